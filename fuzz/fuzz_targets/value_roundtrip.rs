@@ -0,0 +1,115 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use sqltight_core::{InvalidUtf8, Sqlite, Value, blob, int, real};
+
+/// The largest blob `HugeBlob` makes, past the page size so it spills to overflow pages
+const HUGE_LEN: u32 = 16 * 1024 * 1024;
+
+#[derive(Arbitrary, Debug)]
+enum Param {
+    /// Any utf-8, nuls included
+    Text(String),
+    /// Bytes bound as a blob and cast to text in sql, they needn't be utf-8
+    RawText(Vec<u8>),
+    Int(i64),
+    Real(f64),
+    Blob(Vec<u8>),
+    /// `len` copies of `byte`, up to `HUGE_LEN`
+    HugeBlob {
+        byte: u8,
+        len: u32,
+    },
+    Null,
+}
+
+impl Param {
+    fn value(&self) -> Value {
+        match self {
+            Param::Text(text) => text.as_str().into(),
+            Param::RawText(bytes) => blob(bytes.clone()).into(),
+            Param::Int(n) => int(*n).into(),
+            Param::Real(f) => real(*f).into(),
+            Param::Blob(bytes) => blob(bytes.clone()).into(),
+            Param::HugeBlob { byte, len } => blob(vec![*byte; (len % HUGE_LEN) as usize]).into(),
+            Param::Null => Value::Null,
+        }
+    }
+
+    /// `?ix` in sql, cast to text for `RawText`
+    fn sql(&self, ix: usize) -> String {
+        match self {
+            Param::RawText(_) => format!("cast(?{ix} as text)"),
+            _ => format!("?{ix}"),
+        }
+    }
+
+    /// What reading the bound value back should give
+    fn expected(&self, policy: InvalidUtf8) -> Value {
+        match self {
+            Param::RawText(bytes) => match (std::str::from_utf8(bytes), policy) {
+                (Ok(text), _) => text.into(),
+                (Err(_), InvalidUtf8::Bytes) => blob(bytes.clone()).into(),
+                (Err(_), _) => String::from_utf8_lossy(bytes).into_owned().into(),
+            },
+            // sqlite binds nan as null
+            Param::Real(f) if f.is_nan() => Value::Null,
+            _ => self.value(),
+        }
+    }
+}
+
+fuzz_target!(|params: Vec<Param>| {
+    if params.is_empty() || params.len() > 32 {
+        return;
+    }
+    let db = Sqlite::open(":memory:").unwrap();
+    let values = params.iter().map(Param::value).collect::<Vec<_>>();
+    let expected = |policy| {
+        params
+            .iter()
+            .map(|param| param.expected(policy))
+            .collect::<Vec<_>>()
+    };
+
+    // straight from the bound params
+    let columns = (1..=params.len())
+        .zip(&params)
+        .map(|(ix, param)| param.sql(ix))
+        .collect::<Vec<_>>()
+        .join(", ");
+    for policy in [InvalidUtf8::Lossy, InvalidUtf8::Bytes] {
+        db.set_invalid_utf8(policy);
+        let rows = db
+            .prepare(&format!("select {columns}"))
+            .unwrap()
+            .bind(&values)
+            .unwrap()
+            .rows()
+            .unwrap();
+        assert_eq!(rows[0].values(), expected(policy).as_slice());
+    }
+    db.set_invalid_utf8(InvalidUtf8::Lossy);
+
+    // through a table, one row per param, with the bound values dropped before the select
+    let _changes = db.execute("create table t (v)").unwrap();
+    for param in &params {
+        let stmt = db
+            .prepare(&format!("insert into t (v) values ({})", param.sql(1)))
+            .unwrap()
+            .bind(&[param.value()])
+            .unwrap();
+        let _changes = stmt.changes().unwrap();
+    }
+    let rows = db
+        .prepare("select v from t order by rowid")
+        .unwrap()
+        .rows()
+        .unwrap();
+    let stored = rows
+        .iter()
+        .map(|row| row.get::<Value>(0).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(stored, expected(InvalidUtf8::Lossy));
+});
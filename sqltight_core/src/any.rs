@@ -0,0 +1,93 @@
+use crate::{Blob, FromSql, Int, Real, Result, Text, Value, ValueRef};
+
+/// A column declared `Any`, it keeps whichever of integer, real, text or blob was
+/// saved in it, as is
+#[derive(Clone, Debug, PartialEq)]
+pub struct Any(Value);
+
+impl Any {
+    pub fn get(&self) -> &Value {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> Value {
+        self.0
+    }
+
+    /// The value as `T`, e.g. `any.to::<i64>()?`, failing with `Error::InvalidColumnType`
+    /// when it holds another type
+    pub fn to<T: FromSql>(&self) -> Result<T> {
+        T::from_sql(&self.0)
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(
+            self.0,
+            Value::Null
+                | Value::Int(Int(None))
+                | Value::Real(Real(None))
+                | Value::Text(Text(None))
+                | Value::Blob(Blob(None))
+        )
+    }
+}
+
+impl Default for Any {
+    fn default() -> Self {
+        Self(Value::Null)
+    }
+}
+
+impl std::ops::Deref for Any {
+    type Target = Value;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+pub fn any(value: impl Into<Value>) -> Any {
+    Any(value.into())
+}
+
+impl From<Value> for Any {
+    fn from(value: Value) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Option<Value>> for Any {
+    fn from(value: Option<Value>) -> Self {
+        Self(value.unwrap_or(Value::Null))
+    }
+}
+
+impl From<Any> for Value {
+    fn from(value: Any) -> Self {
+        value.0
+    }
+}
+
+macro_rules! from_value {
+    ($($ty:ty),*) => {
+        $(
+            impl From<$ty> for Any {
+                fn from(value: $ty) -> Self {
+                    Self(value.into())
+                }
+            }
+        )*
+    };
+}
+
+from_value!(Int, Real, Text, Blob, i64, f64, String, &str, Vec<u8>);
+
+impl FromSql for Any {
+    fn from_sql(value: &Value) -> Result<Self> {
+        Ok(Self(value.clone()))
+    }
+
+    fn from_sql_ref(value: ValueRef<'_>) -> Result<Self> {
+        Ok(Self(value.into()))
+    }
+}
@@ -0,0 +1,112 @@
+//! Standard workloads for measuring a schema, e.g. before and after a sqltight upgrade
+//!
+//! ```ignore
+//! let db = Database::open(":memory:")?;
+//! let insert = bench::bulk_insert(&db.connection, 10_000, |i| User::new(format!("{i}@example.com")))?;
+//! let reads = bench::point_reads::<User>(&db.connection, "user", 1..=10_000)?;
+//! let hot = bench::hot_query(10_000, || db.connection.prepare("select count(*) from user")?.rows())?;
+//! println!("{insert}\n{reads}\n{hot}");
+//! ```
+use crate::{Crud, Error, FromRow, Result, Sqlite, Value, int};
+use std::time::{Duration, Instant};
+
+/// How long a workload took, `Display` prints one line per report
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub name: &'static str,
+    pub iterations: usize,
+    pub elapsed: Duration,
+}
+
+impl Report {
+    pub fn per_iteration(&self) -> Duration {
+        match u32::try_from(self.iterations) {
+            Ok(0) | Err(_) => Duration::ZERO,
+            Ok(iterations) => self.elapsed / iterations,
+        }
+    }
+
+    pub fn per_second(&self) -> f64 {
+        match self.elapsed.as_secs_f64() {
+            0.0 => 0.0,
+            secs => self.iterations as f64 / secs,
+        }
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} iterations in {:?} ({:?}/iter, {:.0}/s)",
+            self.name,
+            self.iterations,
+            self.elapsed,
+            self.per_iteration(),
+            self.per_second()
+        )
+    }
+}
+
+/// Times `iterations` calls of `f`, stops at the first error
+pub fn run<T>(
+    name: &'static str,
+    iterations: usize,
+    mut f: impl FnMut(usize) -> Result<T>,
+) -> Result<Report> {
+    let started_at = Instant::now();
+    for i in 0..iterations {
+        let _value = std::hint::black_box(f(i)?);
+    }
+    Ok(Report {
+        name,
+        iterations,
+        elapsed: started_at.elapsed(),
+    })
+}
+
+/// Saves `count` rows built by `row` in one transaction, the commit is timed too
+pub fn bulk_insert<T: Crud>(
+    db: &Sqlite,
+    count: usize,
+    mut row: impl FnMut(usize) -> T,
+) -> Result<Report> {
+    let started_at = Instant::now();
+    {
+        // committed on drop
+        let tx = db.transaction()?;
+        for i in 0..count {
+            let _row = row(i).save(&tx)?;
+        }
+    }
+    Ok(Report {
+        name: "bulk insert",
+        iterations: count,
+        elapsed: started_at.elapsed(),
+    })
+}
+
+/// Reads each id from `table` with a freshly prepared `select * ... where id = ?`
+pub fn point_reads<T: FromRow>(
+    db: &Sqlite,
+    table: &str,
+    ids: impl IntoIterator<Item = i64>,
+) -> Result<Report> {
+    let sql = format!("select * from {table} where id = ?");
+    let ids = ids.into_iter().collect::<Vec<_>>();
+    run("point reads", ids.len(), |i| {
+        let rows = db
+            .prepare(&sql)?
+            .bind(&[Value::from(int(ids[i]))])?
+            .rows()?;
+        match rows.first() {
+            Some(row) => Ok(T::from_row(row)),
+            None => Err(Error::RowNotFound),
+        }
+    })
+}
+
+/// Runs the same query `iterations` times, e.g. a generated query method
+pub fn hot_query<T>(iterations: usize, mut query: impl FnMut() -> Result<T>) -> Result<Report> {
+    run("hot query", iterations, |_| query())
+}
@@ -0,0 +1,132 @@
+use crate::{Error, FromSql, Int, Result, Text, Value, ValueRef};
+use chrono::{NaiveDateTime, TimeZone, Utc};
+
+/// A utc timestamp column, written as INTEGER unix epoch seconds.
+/// Reads also accept TEXT in ISO-8601, e.g. rfc 3339 or sqlite's `datetime('now')`
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime(Option<chrono::DateTime<Utc>>);
+
+impl DateTime {
+    pub fn now() -> Self {
+        Self(Some(Utc::now()))
+    }
+
+    pub fn get(&self) -> Option<chrono::DateTime<Utc>> {
+        self.0
+    }
+
+    pub fn timestamp(&self) -> Option<i64> {
+        self.0.map(|datetime| datetime.timestamp())
+    }
+
+    pub fn to_rfc3339(&self) -> Option<String> {
+        self.0.map(|datetime| datetime.to_rfc3339())
+    }
+}
+
+pub fn datetime(value: chrono::DateTime<Utc>) -> DateTime {
+    value.into()
+}
+
+impl From<chrono::DateTime<Utc>> for DateTime {
+    fn from(value: chrono::DateTime<Utc>) -> Self {
+        Self(Some(value))
+    }
+}
+
+impl From<Option<chrono::DateTime<Utc>>> for DateTime {
+    fn from(value: Option<chrono::DateTime<Utc>>) -> Self {
+        Self(value)
+    }
+}
+
+impl From<DateTime> for Value {
+    fn from(value: DateTime) -> Self {
+        Value::Int(value.timestamp().into())
+    }
+}
+
+impl From<Value> for DateTime {
+    fn from(value: Value) -> Self {
+        match DateTime::from_sql(&value) {
+            Ok(datetime) => datetime,
+            Err(_) => unreachable!(),
+        }
+    }
+}
+
+fn parse(text: &str) -> Option<chrono::DateTime<Utc>> {
+    match chrono::DateTime::parse_from_rfc3339(text) {
+        Ok(datetime) => Some(datetime.with_timezone(&Utc)),
+        Err(_) => NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f")
+            .ok()
+            .map(|datetime| datetime.and_utc()),
+    }
+}
+
+impl FromSql for DateTime {
+    fn from_sql(value: &Value) -> Result<Self> {
+        match value {
+            Value::Int(Int(Some(seconds))) => match Utc.timestamp_opt(*seconds, 0).single() {
+                Some(datetime) => Ok(Self(Some(datetime))),
+                None => Err(Error::InvalidDateTime(seconds.to_string())),
+            },
+            Value::Text(Text(Some(text))) => match parse(text) {
+                Some(datetime) => Ok(Self(Some(datetime))),
+                None => Err(Error::InvalidDateTime(text.clone())),
+            },
+            Value::Int(Int(None)) | Value::Text(Text(None)) | Value::Null => Ok(Self(None)),
+            value => Err(Error::InvalidColumnType {
+                column: String::new(),
+                expected: "integer or text",
+                found: value.type_name(),
+            }),
+        }
+    }
+
+    fn from_sql_ref(value: ValueRef<'_>) -> Result<Self> {
+        match value {
+            ValueRef::Text(text) => match parse(&text) {
+                Some(datetime) => Ok(Self(Some(datetime))),
+                None => Err(Error::InvalidDateTime(text.into_owned())),
+            },
+            value => Self::from_sql(&value.into()),
+        }
+    }
+}
+
+impl std::fmt::Display for DateTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Some(value) => write!(f, "{}", value.to_rfc3339()),
+            None => write!(f, ""),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for DateTime {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        self.to_rfc3339().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for DateTime {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(text) => match parse(&text) {
+                Some(datetime) => Ok(Self(Some(datetime))),
+                None => Err(serde::de::Error::custom(format!(
+                    "invalid datetime: {text}"
+                ))),
+            },
+            None => Ok(Self(None)),
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use crate::{Any, Blob, Text, Value};
+use std::fmt::{Debug, Formatter, Result};
+
+/// Text longer than this many chars and blobs longer than this many bytes are cut short
+/// in the `Debug` output of the structs `db!` generates
+pub const DEBUG_MAX_LEN: usize = 64;
+
+/// A text or blob field cut short to `DEBUG_MAX_LEN` in `Debug` output, with its full
+/// length after it, e.g. `"Lorem ipsum dolor"… (52000 chars)` with `DEBUG_MAX_LEN` chars
+pub struct Truncated<'a, T: ?Sized>(pub &'a T);
+
+impl<T: DebugTruncated + ?Sized> Debug for Truncated<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        self.0.fmt_truncated(f)
+    }
+}
+
+/// The text and blob types `Truncated` cuts short
+pub trait DebugTruncated {
+    fn fmt_truncated(&self, f: &mut Formatter<'_>) -> Result;
+}
+
+impl DebugTruncated for str {
+    fn fmt_truncated(&self, f: &mut Formatter<'_>) -> Result {
+        match self.char_indices().nth(DEBUG_MAX_LEN) {
+            Some((end, _c)) => write!(f, "{:?}… ({} chars)", &self[..end], self.chars().count()),
+            None => Debug::fmt(self, f),
+        }
+    }
+}
+
+impl DebugTruncated for [u8] {
+    fn fmt_truncated(&self, f: &mut Formatter<'_>) -> Result {
+        match self.len() > DEBUG_MAX_LEN {
+            true => write!(f, "{:?}… ({} bytes)", &self[..DEBUG_MAX_LEN], self.len()),
+            false => Debug::fmt(self, f),
+        }
+    }
+}
+
+impl DebugTruncated for String {
+    fn fmt_truncated(&self, f: &mut Formatter<'_>) -> Result {
+        self.as_str().fmt_truncated(f)
+    }
+}
+
+impl DebugTruncated for Vec<u8> {
+    fn fmt_truncated(&self, f: &mut Formatter<'_>) -> Result {
+        self.as_slice().fmt_truncated(f)
+    }
+}
+
+impl<T: DebugTruncated> DebugTruncated for Option<T> {
+    fn fmt_truncated(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Some(value) => f.debug_tuple("Some").field(&Truncated(value)).finish(),
+            None => f.write_str("None"),
+        }
+    }
+}
+
+impl DebugTruncated for Text {
+    fn fmt_truncated(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_tuple("Text").field(&Truncated(&self.0)).finish()
+    }
+}
+
+impl DebugTruncated for Blob {
+    fn fmt_truncated(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_tuple("Blob").field(&Truncated(&self.0)).finish()
+    }
+}
+
+impl DebugTruncated for Value {
+    fn fmt_truncated(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Value::Text(text) => f.debug_tuple("Text").field(&Truncated(text)).finish(),
+            Value::Blob(blob) => f.debug_tuple("Blob").field(&Truncated(blob)).finish(),
+            value => Debug::fmt(value, f),
+        }
+    }
+}
+
+impl DebugTruncated for Any {
+    fn fmt_truncated(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_tuple("Any").field(&Truncated(self.get())).finish()
+    }
+}
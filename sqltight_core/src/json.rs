@@ -0,0 +1,105 @@
+use crate::{Error, FromSql, Result, Text, Value, ValueRef};
+use serde::{Serialize, de::DeserializeOwned};
+
+/// A document column, saved as json TEXT and parsed again when read
+#[derive(Clone, Debug, PartialEq)]
+pub struct Json<T>(Option<T>);
+
+impl<T> Json<T> {
+    pub fn get(&self) -> Option<&T> {
+        self.0.as_ref()
+    }
+
+    pub fn into_inner(self) -> Option<T> {
+        self.0
+    }
+}
+
+impl<T> Default for Json<T> {
+    fn default() -> Self {
+        Self(None)
+    }
+}
+
+impl<T> std::ops::Deref for Json<T> {
+    type Target = Option<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+pub fn json<T>(value: T) -> Json<T> {
+    Json(Some(value))
+}
+
+impl<T> From<Option<T>> for Json<T> {
+    fn from(value: Option<T>) -> Self {
+        Self(value)
+    }
+}
+
+impl<T: Serialize> From<Json<T>> for Value {
+    fn from(value: Json<T>) -> Self {
+        match value.0.map(|value| serde_json::to_string(&value)) {
+            Some(Ok(text)) => Value::Text(text.into()),
+            // serializing only fails for maps with non-string keys
+            Some(Err(_)) | None => Value::Null,
+        }
+    }
+}
+
+impl<T: DeserializeOwned> From<Value> for Json<T> {
+    fn from(value: Value) -> Self {
+        match Json::from_sql(&value) {
+            Ok(json) => json,
+            Err(_) => unreachable!(),
+        }
+    }
+}
+
+impl<T: DeserializeOwned> FromSql for Json<T> {
+    fn from_sql(value: &Value) -> Result<Self> {
+        match value {
+            Value::Text(Text(Some(text))) => match serde_json::from_str(text) {
+                Ok(value) => Ok(Self(Some(value))),
+                Err(err) => Err(Error::InvalidJson(err.to_string())),
+            },
+            Value::Text(Text(None)) | Value::Null => Ok(Self(None)),
+            value => Err(Error::InvalidColumnType {
+                column: String::new(),
+                expected: "text",
+                found: value.type_name(),
+            }),
+        }
+    }
+
+    fn from_sql_ref(value: ValueRef<'_>) -> Result<Self> {
+        match value {
+            ValueRef::Text(text) => match serde_json::from_str(&text) {
+                Ok(value) => Ok(Self(Some(value))),
+                Err(err) => Err(Error::InvalidJson(err.to_string())),
+            },
+            value => Self::from_sql(&value.into()),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: Serialize> Serialize for Json<T> {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for Json<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        Option::deserialize(deserializer).map(Json)
+    }
+}
@@ -1,362 +1,133 @@
-use sqltight_ffi::{
-    SQLITE_DONE, SQLITE_OK, SQLITE_ROW, sqlite3, sqlite3_bind_blob, sqlite3_bind_double,
-    sqlite3_bind_int64, sqlite3_bind_null, sqlite3_bind_parameter_count,
-    sqlite3_bind_parameter_name, sqlite3_bind_text, sqlite3_changes, sqlite3_close,
-    sqlite3_column_bytes, sqlite3_column_count, sqlite3_column_decltype, sqlite3_column_double,
-    sqlite3_column_int64, sqlite3_column_name, sqlite3_column_text, sqlite3_column_type,
-    sqlite3_errmsg, sqlite3_exec, sqlite3_finalize, sqlite3_open, sqlite3_prepare_v2, sqlite3_step,
-    sqlite3_stmt,
+mod any;
+#[cfg(feature = "sqlite")]
+pub mod bench;
+#[cfg(feature = "chrono")]
+mod datetime;
+mod debug;
+#[cfg(feature = "json")]
+mod json;
+mod row;
+#[cfg(feature = "serde")]
+mod serialize;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+pub use any::{Any, any};
+#[cfg(feature = "chrono")]
+pub use datetime::{DateTime, datetime};
+pub use debug::{DEBUG_MAX_LEN, DebugTruncated, Truncated};
+#[cfg(feature = "json")]
+pub use json::{Json, json};
+pub use row::{ColumnIndex, FromSql, Row, RowRef, ValueRef};
+#[cfg(feature = "sqlite")]
+pub use sqlite::{
+    AuthAction, Authorization, AutoVacuum, BatchSql, CacheInvalidator, Col, ColumnProfile,
+    ContentionStats, Crud, CsvImport, CsvOptions, CsvRowError, Filter, ForeignKeyViolation,
+    IndexSuggestion, IntegrityError, InterruptGuard, InterruptHandle, InvalidUtf8, InvalidateCache,
+    LastError, LogTable, LongTransaction, MigrationRecord, OnLongTransaction, OnMissing,
+    OpenOptions, Order, PlanNode, QueryPlan, RecordedSql, RepeatedSql, RepeatedSqlDetector,
+    ReplayRate, ReplayedSql, Replica, ReplicaStats, STATEMENT_CACHE_CAPACITY, SchemaDiff, Select,
+    SnapshotScheduler, SnapshotTable, SqlRecording, Sqlite, StatementCache, Stmt, StorageStats,
+    Table, Timeseries, TraceEvent, Transaction, TransactionWatchdog, Tx, UNICODE_COLLATION,
+    VacuumPolicy, VersionedMigration, changes_schema, data_dir, normalize_sql, path_str,
+    read_trace, write_trace,
 };
+#[cfg(all(feature = "sqlite", feature = "dbstat"))]
+pub use sqlite::{DbStat, Fragmentation, TableSize};
 
-use std::{
-    collections::BTreeMap,
-    ffi::{CStr, CString, NulError, c_char, c_int},
-    num::TryFromIntError,
-    ops::Deref,
-    str::Utf8Error,
-};
+use std::{ffi::NulError, num::TryFromIntError, str::Utf8Error};
 
 #[derive(Debug)]
 pub enum Error {
     Io(std::io::Error),
     Null(NulError),
     TryFromInt(TryFromIntError),
-    Sqlite { text: String, code: i32 },
+    Sqlite {
+        text: String,
+        code: i32,
+        extended_code: i32,
+    },
     FailedToPrepare,
-    UniqueConstraint(String),
+    UniqueConstraint {
+        table: String,
+        columns: Vec<String>,
+    },
+    NotNullConstraint {
+        table: String,
+        column: String,
+    },
+    ForeignKeyConstraint,
+    CheckConstraint(String),
+    /// A value of the wrong type for a column of a strict table, e.g. text in an `Int` column
+    DatatypeMismatch {
+        table: String,
+        column: String,
+    },
+    Interrupted,
     ConnectionClosed,
     RowNotFound,
     Utf8Error(Utf8Error),
     DuplicateColumnName(String),
     MutexLockFailed,
+    UnknownEnv(String),
+    ColumnNotFound(String),
+    InvalidColumnType {
+        column: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+    InvalidDateTime(String),
+    InvalidJson(String),
+    InvalidEnum(String),
+    TooManyRows {
+        sql: String,
+        max_rows: usize,
+    },
+    TransactionTimedOut,
+    IrreversibleMigration(i64),
+    /// A row of a `log table` whose hash doesn't match its columns and the row before it
+    BrokenChain {
+        table: String,
+        id: i64,
+    },
+    /// A real too large for an `f32` column, see `FromSql for f32`
+    RealOutOfRange(f64),
+    /// An upsert whose `on conflict where` condition kept the stored row instead
+    UpsertSkipped,
+    /// `open_pooled` on an in-memory database, each pooled connection would open its own
+    /// empty one
+    PooledInMemory,
+    /// `Sqlite::rebuild_table` in a transaction with foreign keys on, it can only turn
+    /// them off outside of one
+    RebuildInTransaction,
+    /// The record of `Sqlite::import_csv` starting on `line` that was one error too many
+    CsvRow {
+        line: usize,
+        error: Box<Error>,
+    },
+    /// Text read with `InvalidUtf8::Error` that is only utf-8 up to byte `valid_up_to`
+    InvalidUtf8 {
+        column: String,
+        valid_up_to: usize,
+    },
+    /// A param sqlite refused to bind, `:name` or `?3`, e.g. a blob past its length limit
+    Bind {
+        param: String,
+        error: Box<Error>,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
-type Row = BTreeMap<String, Value>;
-
-#[derive(Debug, Clone)]
-pub struct Sqlite {
-    db: *mut sqlite3,
-}
-
-impl Sqlite {
-    pub fn open(path: &str) -> Result<Self> {
-        let c_path = CString::new(path)?;
-        let mut db: *mut sqlite3 = core::ptr::null_mut();
-        let result = unsafe { sqlite3_open(c_path.as_ptr(), &mut db) };
-        match result {
-            SQLITE_OK => Ok(Self { db }),
-            code => Err(sqlite_err(code, db)),
-        }
-    }
-
-    pub fn prepare(&self, sql: &str) -> Result<Stmt> {
-        let stmt = Stmt::prepare(self.db, sql, core::ptr::null_mut())?;
-        Ok(stmt)
-    }
-
-    pub fn execute(&self, sql: &str) -> Result<i32> {
-        let c_sql = CString::new(sql)?;
-        let result = unsafe {
-            sqlite3_exec(
-                self.db,
-                c_sql.as_ptr(),
-                None,
-                core::ptr::null_mut(),
-                core::ptr::null_mut(),
-            )
-        };
-        match result {
-            SQLITE_OK => Ok(0),
-            code => Err(sqlite_err(code, self.db)),
-        }
-    }
-
-    pub fn transaction(&self) -> Result<Transaction<'_>> {
-        Transaction::new(self, Tx::Immediate)
-    }
-
-    pub fn migrate(&self, migrations: &[impl ToString]) -> Result<()> {
-        let tx = self.transaction()?;
-        let _result =
-            tx.execute("create table if not exists migrations (sql text unique not null) strict")?;
-        for sql in migrations {
-            let result = tx.execute(&sql.to_string());
-            let _result = match result {
-                Ok(result) => result,
-                Err(Error::DuplicateColumnName(_)) => 0,
-                Err(err) => return Err(err),
-            };
-            let text = Value::Text(sql.to_string().into());
-            let _result = tx
-                .prepare("insert into migrations (sql) values (:sql) on conflict (sql) do update set sql = excluded.sql")?
-                .bind(&[text])?
-                .changes()?;
-        }
-
-        Ok(())
-    }
-}
-
-impl Drop for Sqlite {
-    fn drop(&mut self) {
-        unsafe {
-            sqlite3_close(self.db);
-        }
-    }
-}
-
-#[derive(Clone, Copy)]
-pub struct Stmt {
-    stmt: *mut sqlite3_stmt,
-    db: *mut sqlite3,
-}
-
-impl Stmt {
-    pub(crate) fn prepare(
-        db: *mut sqlite3,
-        sql: &str,
-        mut stmt: *mut sqlite3_stmt,
-    ) -> Result<Self> {
-        let c_sql = CString::new(sql)?;
-        let result =
-            unsafe { sqlite3_prepare_v2(db, c_sql.as_ptr(), -1, &mut stmt, std::ptr::null_mut()) };
-        match result {
-            SQLITE_OK | SQLITE_ROW | SQLITE_DONE => Ok(Self { db, stmt }),
-            code => Err(sqlite_err(code, db)),
-        }
-    }
-
-    fn step(&self) -> Result<i32> {
-        let result = unsafe { sqlite3_step(self.stmt) };
-        match result {
-            SQLITE_OK => Ok(SQLITE_OK),
-            SQLITE_ROW => Ok(SQLITE_ROW),
-            SQLITE_DONE => Ok(SQLITE_DONE),
-            code => Err(sqlite_err(code, self.db)),
-        }
-    }
-
-    fn finalize(&self) -> Result<()> {
-        let result = unsafe { sqlite3_finalize(self.stmt) };
-        match result {
-            SQLITE_OK | SQLITE_ROW | SQLITE_DONE => Ok(()),
-            code => Err(sqlite_err(code, self.db)),
-        }
-    }
-
-    pub fn bind(self, params: &[Value]) -> Result<Self> {
-        params
-            .iter()
-            .enumerate()
-            .for_each(|(ix, param)| match param {
-                Value::Text(Text(Some(val))) => unsafe {
-                    sqlite3_bind_text(
-                        self.stmt,
-                        (ix + 1) as i32,
-                        val.as_ptr() as *const _,
-                        val.len() as c_int,
-                        None,
-                    );
-                },
-                Value::Int(Int(Some(n))) => unsafe {
-                    sqlite3_bind_int64(self.stmt, (ix + 1) as i32, *n);
-                },
-                Value::Real(Real(Some(f))) => unsafe {
-                    sqlite3_bind_double(self.stmt, (ix + 1) as i32, *f);
-                },
-                Value::Blob(Blob(Some(b))) => {
-                    unsafe {
-                        sqlite3_bind_blob(
-                            self.stmt,
-                            (ix + 1) as i32,
-                            b.as_ptr() as *const _,
-                            b.len() as c_int,
-                            None,
-                        )
-                    };
-                }
-                Value::Text(Text(None))
-                | Value::Int(Int(None))
-                | Value::Real(Real(None))
-                | Value::Blob(Blob(None))
-                | Value::Null => {
-                    unsafe { sqlite3_bind_null(self.stmt, (ix + 1) as i32) };
-                }
-            });
-
-        Ok(self)
-    }
-
-    fn column_count(&self) -> i32 {
-        unsafe { sqlite3_column_count(self.stmt) }
-    }
-
-    fn column_name(&self, i: i32) -> String {
-        let result = unsafe { CStr::from_ptr(sqlite3_column_name(self.stmt, i)) };
-        result.to_string_lossy().into_owned()
-    }
-
-    fn column_value(&self, i: i32) -> Value {
-        let result = unsafe { sqlite3_column_type(self.stmt, i) };
-        match result {
-            1 => Value::Int(Int(Some(unsafe { sqlite3_column_int64(self.stmt, i) }))),
-            2 => Value::Real(Real(Some(unsafe { sqlite3_column_double(self.stmt, i) }))),
-            3 => {
-                let result =
-                    unsafe { CStr::from_ptr(sqlite3_column_text(self.stmt, i) as *const c_char) };
-                let text = result.to_string_lossy().into_owned();
-                Value::Text(Text(Some(text)))
-            }
-            4 => {
-                let slice = unsafe {
-                    let len = sqlite3_column_bytes(self.stmt, i) as usize;
-                    let ptr = sqlite3_column_text(self.stmt, i);
-                    std::slice::from_raw_parts(ptr, len)
-                };
-                Value::Blob(Blob(Some(slice.to_vec())))
-            }
-            _ => Value::Null,
-        }
-    }
-
-    pub fn rows(&self) -> Result<Vec<Row>> {
-        let mut rows = Vec::new();
-        while let Ok(sqlite_row) = self.step()
-            && sqlite_row == SQLITE_ROW
-        {
-            let column_count = self.column_count();
-            let mut values: BTreeMap<String, Value> = BTreeMap::new();
-            for i in 0..column_count {
-                let name = self.column_name(i);
-                let value = self.column_value(i);
-                values.insert(name, value);
-            }
-            rows.push(values);
-        }
-        let _result = self.finalize()?;
-        Ok(rows)
-    }
-
-    pub fn changes(&self) -> Result<i32> {
-        while let Ok(result) = self.step()
-            && (result != SQLITE_ROW || result != SQLITE_DONE)
-        {}
-        self.finalize()?;
-        let changes = unsafe { sqlite3_changes(self.db) };
-        Ok(changes)
-    }
-
-    pub fn parameter_names(&self) -> Vec<String> {
-        let mut names = vec![];
-        let parameter_count = unsafe { sqlite3_bind_parameter_count(self.stmt) };
-        for i in 1..=parameter_count {
-            let name = unsafe { CStr::from_ptr(sqlite3_bind_parameter_name(self.stmt, i)) };
-            let name = name.to_string_lossy().to_string();
-            names.push(name);
-        }
-        names
-    }
-
-    pub fn select_column_names(&self) -> Vec<String> {
-        let mut names = vec![];
-        let column_count = unsafe { sqlite3_column_count(self.stmt) };
-        for i in 0..column_count {
-            let name = unsafe { CStr::from_ptr(sqlite3_column_name(self.stmt, i)) };
-            let name = name.to_string_lossy().to_string();
-            names.push(name);
-        }
-        names
-    }
-
-    pub fn select_column_types(&self) -> Vec<String> {
-        let mut types = vec![];
-        let column_count = unsafe { sqlite3_column_count(self.stmt) };
-        for i in 0..column_count {
-            let datatype = unsafe {
-                let value = sqlite3_column_decltype(self.stmt, i);
-                match value.is_null() {
-                    true => CStr::from_bytes_with_nul(b"ANY\0").unwrap(),
-                    false => CStr::from_ptr(value),
-                }
-            };
-            let datatype = datatype.to_string_lossy().to_string();
-            types.push(datatype);
-        }
-        types
-    }
-}
-
-#[derive(Debug)]
-pub struct Transaction<'a> {
-    sqlite: &'a Sqlite,
-}
-
-#[derive(Default)]
-pub enum Tx {
-    #[default]
-    Deferred,
-    Immediate,
-    Exclusive,
-}
-
-impl<'a> Transaction<'a> {
-    pub fn new(sqlite: &'a Sqlite, tx: Tx) -> Result<Transaction<'a>> {
-        let sql = match tx {
-            Tx::Deferred => "begin deferred transaction",
-            Tx::Immediate => "begin immediate transaction",
-            Tx::Exclusive => "begin exclusive transaction",
-        };
-        let _stmt = sqlite.execute(&sql)?;
-        Ok(Self { sqlite })
-    }
-
-    pub fn end(&self) -> Result<i32> {
-        self.execute("end transaction")
-    }
 
-    pub fn rollback(&self) -> Result<i32> {
-        self.execute("rollback transaction")
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
     }
 }
 
-impl<'a> Deref for Transaction<'a> {
-    type Target = Sqlite;
-
-    fn deref(&self) -> &Self::Target {
-        self.sqlite
-    }
-}
-
-impl<'a> Drop for Transaction<'a> {
-    fn drop(&mut self) {
-        match self.end() {
-            Ok(_) => {}
-            Err(_err) => {
-                self.rollback().expect("Rollback failed");
-            }
-        }
-    }
-}
-
-fn sqlite_err(code: i32, db: *mut sqlite3) -> Error {
-    match db.is_null() {
-        true => Error::Sqlite {
-            text: "The sqlite db pointer is null".into(),
-            code: -1,
-        },
-        false => {
-            let text = unsafe { CStr::from_ptr(sqlite3_errmsg(db)) }
-                .to_string_lossy()
-                .into_owned();
-            if text.starts_with("UNIQUE constraint failed: ") {
-                return Error::UniqueConstraint(text.replace("UNIQUE constraint failed: ", ""));
-            } else if text.starts_with("duplicate column name: ") {
-                return Error::DuplicateColumnName(text.replace("duplicate column name: ", ""));
-            } else {
-                return Error::Sqlite { text, code };
-            }
-        }
+impl From<TryFromIntError> for Error {
+    fn from(value: TryFromIntError) -> Self {
+        Self::TryFromInt(value)
     }
 }
 
@@ -474,7 +245,7 @@ impl std::fmt::Display for Real {
         }
     }
 }
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Text(Text),
     Int(Int),
@@ -547,16 +318,137 @@ impl From<&str> for Value {
     }
 }
 
-pub trait FromRow {
-    fn from_row(row: &BTreeMap<String, Value>) -> Self;
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::Text(value.into())
+    }
 }
 
-pub trait Crud {
-    fn save(self, db: &Sqlite) -> Result<Self>
-    where
-        Self: Sized;
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Int(value.into())
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Real(value.into())
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(value: Vec<u8>) -> Self {
+        Value::Blob(value.into())
+    }
+}
+
+// the narrower types of `Int u8` or `Real f32` columns, all of them fit
 
-    fn delete(self, db: &Sqlite) -> Result<Self>
+macro_rules! from_narrow {
+    ($variant:ident, $wide:ty, $($ty:ty),*) => {
+        $(
+            impl From<$ty> for Value {
+                fn from(value: $ty) -> Self {
+                    Value::$variant(<$wide>::from(value).into())
+                }
+            }
+        )*
+    };
+}
+
+from_narrow!(Int, i64, i8, i16, i32, u8, u16, u32);
+from_narrow!(Real, f64, f32);
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        match value {
+            Some(value) => value.into(),
+            None => Value::Null,
+        }
+    }
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Text(_) => "text",
+            Value::Int(_) => "integer",
+            Value::Real(_) => "real",
+            Value::Blob(_) => "blob",
+            Value::Null => "null",
+        }
+    }
+}
+
+pub trait FromRow {
+    fn from_row(row: &Row) -> Self;
+
+    /// Reads a row still borrowed from its statement, by default through a copy of it.
+    /// `db!` and the derive read the columns straight out of `row` instead
+    fn from_row_ref(row: &RowRef) -> Self
     where
-        Self: Sized;
+        Self: Sized,
+    {
+        Self::from_row(&row.to_row())
+    }
+}
+
+/// One result column of the query `Q`, `db!` generates a marker type per column
+pub trait Column<Q> {
+    const NAME: &'static str;
+    type Value;
+
+    fn value(row: &Row) -> Self::Value;
+}
+
+/// A tuple of `Column`s of the query `Q`, selected without the query's other columns
+/// and read into a tuple of their values
+pub trait Columns<Q> {
+    const NAMES: &'static [&'static str];
+    type Values;
+
+    fn values(row: &Row) -> Self::Values;
+}
+
+macro_rules! columns_tuple {
+    ($($column:ident),+) => {
+        impl<Q, $($column: Column<Q>),+> Columns<Q> for ($($column,)+) {
+            const NAMES: &'static [&'static str] = &[$($column::NAME),+];
+            type Values = ($($column::Value,)+);
+
+            fn values(row: &Row) -> Self::Values {
+                ($($column::value(row),)+)
+            }
+        }
+    };
+}
+
+columns_tuple!(A);
+columns_tuple!(A, B);
+columns_tuple!(A, B, C);
+columns_tuple!(A, B, C, D);
+columns_tuple!(A, B, C, D, E);
+columns_tuple!(A, B, C, D, E, F);
+columns_tuple!(A, B, C, D, E, F, G);
+columns_tuple!(A, B, C, D, E, F, G, H);
+
+/// One page of the rows of a `paginate` query
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// The number of rows the query returns across every page
+    pub total: i64,
+    /// The offset of the page after this one, `None` on the last page
+    pub next_offset: Option<i64>,
+}
+
+impl<T> Page<T> {
+    pub fn new(items: Vec<T>, total: i64, offset: i64) -> Self {
+        let next_offset = offset.saturating_add(i64::try_from(items.len()).unwrap_or(i64::MAX));
+        Self {
+            next_offset: (!items.is_empty() && next_offset < total).then_some(next_offset),
+            items,
+            total,
+        }
+    }
 }
@@ -0,0 +1,383 @@
+use crate::{Blob, Error, Int, Real, Result, Text, Value};
+use std::borrow::Cow;
+use std::sync::Arc;
+
+/// One result row, values are kept in select order. The rows of one result share
+/// their column names instead of each holding a copy
+#[derive(Debug, Clone, Default)]
+pub struct Row {
+    columns: Arc<[String]>,
+    values: Vec<Value>,
+}
+
+impl Row {
+    pub fn new(columns: impl Into<Arc<[String]>>, values: Vec<Value>) -> Self {
+        Self {
+            columns: columns.into(),
+            values,
+        }
+    }
+
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The raw value of a column by name or index
+    pub fn value(&self, column: impl ColumnIndex) -> Option<&Value> {
+        column.index(&self.columns).map(|ix| &self.values[ix])
+    }
+
+    /// The value of a column borrowed like `RowRef::value_ref`, so the same code reads both
+    pub fn value_ref(&self, column: impl ColumnIndex) -> Option<ValueRef<'_>> {
+        self.value(column).map(ValueRef::from)
+    }
+
+    /// Converts a column by name or index, e.g. `row.get::<i64>("id")?` or
+    /// `row.get::<Option<String>>(1)?`
+    pub fn get<T: FromSql>(&self, column: impl ColumnIndex) -> Result<T> {
+        let name = column.name(&self.columns);
+        let value = self
+            .value(column)
+            .ok_or_else(|| Error::ColumnNotFound(name.clone()))?;
+        T::from_sql(value).map_err(|err| with_column(err, name))
+    }
+}
+
+/// A result row borrowed from its statement while it's on that row, text and blobs
+/// point into sqlite instead of being copied. See `Stmt::for_each_row`
+#[derive(Debug, Clone, Copy)]
+pub struct RowRef<'a> {
+    columns: &'a [String],
+    values: &'a [ValueRef<'a>],
+}
+
+impl<'a> RowRef<'a> {
+    pub fn new(columns: &'a [String], values: &'a [ValueRef<'a>]) -> Self {
+        Self { columns, values }
+    }
+
+    pub fn columns(&self) -> &'a [String] {
+        self.columns
+    }
+
+    pub fn values(&self) -> &'a [ValueRef<'a>] {
+        self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The borrowed value of a column by name or index
+    pub fn value_ref(&self, column: impl ColumnIndex) -> Option<ValueRef<'a>> {
+        column.index(self.columns).map(|ix| self.values[ix].clone())
+    }
+
+    /// Converts a column like `Row::get`, `String` and `Vec<u8>` are copied once straight
+    /// out of sqlite, `value_ref` borrows them instead
+    pub fn get<T: FromSql>(&self, column: impl ColumnIndex) -> Result<T> {
+        let name = column.name(self.columns);
+        let value = self
+            .value_ref(column)
+            .ok_or_else(|| Error::ColumnNotFound(name.clone()))?;
+        T::from_sql_ref(value).map_err(|err| with_column(err, name))
+    }
+
+    /// Copies the row out of the statement
+    pub fn to_row(&self) -> Row {
+        Row::new(
+            self.columns.to_vec(),
+            self.values.iter().cloned().map(Value::from).collect(),
+        )
+    }
+}
+
+fn with_column(err: Error, column: String) -> Error {
+    match err {
+        Error::InvalidColumnType {
+            expected, found, ..
+        } => Error::InvalidColumnType {
+            column,
+            expected,
+            found,
+        },
+        err => err,
+    }
+}
+
+pub trait ColumnIndex {
+    fn index(&self, columns: &[String]) -> Option<usize>;
+    fn name(&self, columns: &[String]) -> String;
+}
+
+impl ColumnIndex for &str {
+    fn index(&self, columns: &[String]) -> Option<usize> {
+        columns.iter().position(|column| column == self)
+    }
+
+    fn name(&self, _columns: &[String]) -> String {
+        self.to_string()
+    }
+}
+
+impl ColumnIndex for usize {
+    fn index(&self, columns: &[String]) -> Option<usize> {
+        (*self < columns.len()).then_some(*self)
+    }
+
+    fn name(&self, columns: &[String]) -> String {
+        match columns.get(*self) {
+            Some(column) => column.clone(),
+            None => self.to_string(),
+        }
+    }
+}
+
+/// A column value borrowed from a row, only valid until the statement steps again.
+/// Text that isn't valid utf-8 is the one case that's copied, replacing the invalid bytes
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueRef<'a> {
+    Text(Cow<'a, str>),
+    Int(i64),
+    Real(f64),
+    Blob(&'a [u8]),
+    Null,
+}
+
+impl ValueRef<'_> {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            ValueRef::Text(_) => "text",
+            ValueRef::Int(_) => "integer",
+            ValueRef::Real(_) => "real",
+            ValueRef::Blob(_) => "blob",
+            ValueRef::Null => "null",
+        }
+    }
+}
+
+impl<'a> From<&'a Value> for ValueRef<'a> {
+    fn from(value: &'a Value) -> Self {
+        match value {
+            Value::Text(Text(Some(value))) => ValueRef::Text(Cow::Borrowed(value)),
+            Value::Int(Int(Some(value))) => ValueRef::Int(*value),
+            Value::Real(Real(Some(value))) => ValueRef::Real(*value),
+            Value::Blob(Blob(Some(value))) => ValueRef::Blob(value),
+            _ => ValueRef::Null,
+        }
+    }
+}
+
+impl From<ValueRef<'_>> for Value {
+    fn from(value: ValueRef<'_>) -> Self {
+        match value {
+            ValueRef::Text(value) => Value::Text(Text(Some(value.into_owned()))),
+            ValueRef::Int(value) => Value::Int(Int(Some(value))),
+            ValueRef::Real(value) => Value::Real(Real(Some(value))),
+            ValueRef::Blob(value) => Value::Blob(Blob(Some(value.to_vec()))),
+            ValueRef::Null => Value::Null,
+        }
+    }
+}
+
+/// Fallible conversion out of a column value, `Option<T>` maps null to `None`
+pub trait FromSql: Sized {
+    fn from_sql(value: &Value) -> Result<Self>;
+
+    /// Converts a borrowed value, by default through an owned copy of it. Types
+    /// that only need part of a text or blob override it to skip the copy
+    fn from_sql_ref(value: ValueRef<'_>) -> Result<Self> {
+        Self::from_sql(&value.into())
+    }
+}
+
+fn invalid(expected: &'static str, found: &'static str) -> Error {
+    Error::InvalidColumnType {
+        column: String::new(),
+        expected,
+        found,
+    }
+}
+
+impl FromSql for Value {
+    fn from_sql(value: &Value) -> Result<Self> {
+        Ok(value.clone())
+    }
+
+    fn from_sql_ref(value: ValueRef<'_>) -> Result<Self> {
+        Ok(value.into())
+    }
+}
+
+impl<T: FromSql> FromSql for Option<T> {
+    fn from_sql(value: &Value) -> Result<Self> {
+        match value {
+            Value::Null
+            | Value::Int(Int(None))
+            | Value::Real(Real(None))
+            | Value::Text(Text(None))
+            | Value::Blob(Blob(None)) => Ok(None),
+            value => T::from_sql(value).map(Some),
+        }
+    }
+
+    fn from_sql_ref(value: ValueRef<'_>) -> Result<Self> {
+        match value {
+            ValueRef::Null => Ok(None),
+            value => T::from_sql_ref(value).map(Some),
+        }
+    }
+}
+
+impl FromSql for i64 {
+    fn from_sql(value: &Value) -> Result<Self> {
+        match value {
+            Value::Int(Int(Some(value))) => Ok(*value),
+            value => Err(invalid("integer", value.type_name())),
+        }
+    }
+}
+
+macro_rules! from_sql_int {
+    ($($ty:ty),*) => {
+        $(
+            impl FromSql for $ty {
+                fn from_sql(value: &Value) -> Result<Self> {
+                    Ok(<$ty>::try_from(i64::from_sql(value)?)?)
+                }
+            }
+        )*
+    };
+}
+
+from_sql_int!(i8, i16, i32, isize, u8, u16, u32, u64, usize);
+
+impl FromSql for bool {
+    fn from_sql(value: &Value) -> Result<Self> {
+        Ok(i64::from_sql(value)? != 0)
+    }
+}
+
+impl FromSql for f64 {
+    fn from_sql(value: &Value) -> Result<Self> {
+        match value {
+            Value::Real(Real(Some(value))) => Ok(*value),
+            value => Err(invalid("real", value.type_name())),
+        }
+    }
+}
+
+/// Rounds to the nearest `f32`, reals past its largest value fail instead of becoming infinite
+impl FromSql for f32 {
+    fn from_sql(value: &Value) -> Result<Self> {
+        let value = f64::from_sql(value)?;
+        match value.is_finite() && value.abs() > f32::MAX as f64 {
+            true => Err(Error::RealOutOfRange(value)),
+            false => Ok(value as f32),
+        }
+    }
+}
+
+impl FromSql for String {
+    fn from_sql(value: &Value) -> Result<Self> {
+        match value {
+            Value::Text(Text(Some(value))) => Ok(value.clone()),
+            value => Err(invalid("text", value.type_name())),
+        }
+    }
+
+    fn from_sql_ref(value: ValueRef<'_>) -> Result<Self> {
+        match value {
+            ValueRef::Text(value) => Ok(value.into_owned()),
+            value => Err(invalid("text", value.type_name())),
+        }
+    }
+}
+
+impl FromSql for Vec<u8> {
+    fn from_sql(value: &Value) -> Result<Self> {
+        match value {
+            Value::Blob(Blob(Some(value))) => Ok(value.clone()),
+            value => Err(invalid("blob", value.type_name())),
+        }
+    }
+
+    fn from_sql_ref(value: ValueRef<'_>) -> Result<Self> {
+        match value {
+            ValueRef::Blob(value) => Ok(value.to_vec()),
+            value => Err(invalid("blob", value.type_name())),
+        }
+    }
+}
+
+impl FromSql for Int {
+    fn from_sql(value: &Value) -> Result<Self> {
+        match value {
+            Value::Int(value) => Ok(*value),
+            Value::Null => Ok(Int(None)),
+            value => Err(invalid("integer", value.type_name())),
+        }
+    }
+}
+
+impl FromSql for Real {
+    fn from_sql(value: &Value) -> Result<Self> {
+        match value {
+            Value::Real(value) => Ok(*value),
+            Value::Null => Ok(Real(None)),
+            value => Err(invalid("real", value.type_name())),
+        }
+    }
+}
+
+impl FromSql for Text {
+    fn from_sql(value: &Value) -> Result<Self> {
+        match value {
+            Value::Text(value) => Ok(value.clone()),
+            Value::Null => Ok(Text(None)),
+            value => Err(invalid("text", value.type_name())),
+        }
+    }
+
+    fn from_sql_ref(value: ValueRef<'_>) -> Result<Self> {
+        match value {
+            ValueRef::Text(value) => Ok(Text(Some(value.into_owned()))),
+            ValueRef::Null => Ok(Text(None)),
+            value => Err(invalid("text", value.type_name())),
+        }
+    }
+}
+
+impl FromSql for Blob {
+    fn from_sql(value: &Value) -> Result<Self> {
+        match value {
+            Value::Blob(value) => Ok(value.clone()),
+            Value::Null => Ok(Blob(None)),
+            value => Err(invalid("blob", value.type_name())),
+        }
+    }
+
+    fn from_sql_ref(value: ValueRef<'_>) -> Result<Self> {
+        match value {
+            ValueRef::Blob(value) => Ok(Blob(Some(value.to_vec()))),
+            ValueRef::Null => Ok(Blob(None)),
+            value => Err(invalid("blob", value.type_name())),
+        }
+    }
+}
@@ -0,0 +1,1556 @@
+mod advisor;
+mod auth;
+mod batch;
+mod chain;
+mod collation;
+mod contention;
+mod csv;
+#[cfg(feature = "dbstat")]
+mod dbstat;
+mod diff;
+mod dump;
+mod embedded;
+mod explain;
+mod export;
+mod integrity;
+mod invalidate;
+mod last_error;
+mod pool;
+mod prepared;
+mod profile;
+mod record;
+mod relation;
+mod replay;
+mod replica;
+mod select;
+mod snapshot;
+mod storage;
+mod timeseries;
+mod trace;
+mod watchdog;
+
+pub use advisor::IndexSuggestion;
+pub use auth::{AuthAction, Authorization};
+pub use batch::BatchSql;
+pub use chain::LogTable;
+pub use collation::UNICODE_COLLATION;
+pub use contention::ContentionStats;
+pub use csv::{CsvImport, CsvOptions, CsvRowError};
+#[cfg(feature = "dbstat")]
+pub use dbstat::{DbStat, Fragmentation, TableSize};
+pub use diff::SchemaDiff;
+pub use explain::{PlanNode, QueryPlan};
+pub use integrity::{ForeignKeyViolation, IntegrityError};
+pub use invalidate::{CacheInvalidator, InvalidateCache};
+pub use last_error::LastError;
+pub use pool::StatementCache;
+pub use prepared::STATEMENT_CACHE_CAPACITY;
+pub use profile::{ColumnProfile, Table};
+pub use record::{RecordedSql, RepeatedSql, RepeatedSqlDetector, SqlRecording};
+pub use replay::{ReplayRate, ReplayedSql, read_trace, write_trace};
+pub use replica::{Replica, ReplicaStats};
+pub use select::{Col, Filter, Order, Select};
+pub use snapshot::{SnapshotScheduler, SnapshotTable};
+pub use storage::{AutoVacuum, StorageStats, VacuumPolicy};
+pub use timeseries::Timeseries;
+pub use trace::TraceEvent;
+pub use watchdog::{LongTransaction, OnLongTransaction, TransactionWatchdog};
+
+use crate::{Blob, Error, FromRow, Int, Real, Result, Row, RowRef, Text, Value, ValueRef, int};
+use sqltight_ffi::{
+    SQLITE_CONSTRAINT_CHECK, SQLITE_CONSTRAINT_DATATYPE, SQLITE_CONSTRAINT_FOREIGNKEY,
+    SQLITE_CONSTRAINT_NOTNULL, SQLITE_CONSTRAINT_PRIMARYKEY, SQLITE_CONSTRAINT_TRIGGER,
+    SQLITE_CONSTRAINT_UNIQUE, SQLITE_DONE, SQLITE_INTERRUPT, SQLITE_OK, SQLITE_OPEN_CREATE,
+    SQLITE_OPEN_FULLMUTEX, SQLITE_OPEN_READONLY, SQLITE_OPEN_READWRITE, SQLITE_OPEN_URI,
+    SQLITE_ROW, SQLITE_TXN_NONE, SQLITE_UTF8, sqlite3, sqlite3_bind_blob64, sqlite3_bind_double,
+    sqlite3_bind_int64, sqlite3_bind_null, sqlite3_bind_parameter_count,
+    sqlite3_bind_parameter_name, sqlite3_bind_text64, sqlite3_changes, sqlite3_clear_bindings,
+    sqlite3_close_v2, sqlite3_column_blob, sqlite3_column_bytes, sqlite3_column_count,
+    sqlite3_column_decltype, sqlite3_column_double, sqlite3_column_int64, sqlite3_column_name,
+    sqlite3_column_origin_name, sqlite3_column_table_name, sqlite3_column_text,
+    sqlite3_column_type, sqlite3_errmsg, sqlite3_errstr, sqlite3_exec, sqlite3_extended_errcode,
+    sqlite3_finalize, sqlite3_get_autocommit, sqlite3_interrupt, sqlite3_open_v2,
+    sqlite3_prepare_v2, sqlite3_progress_handler, sqlite3_reset, sqlite3_sql, sqlite3_step,
+    sqlite3_stmt, sqlite3_stmt_readonly, sqlite3_system_errno, sqlite3_txn_state,
+};
+use std::{
+    ffi::{CStr, CString, c_int, c_void},
+    ops::Deref,
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicBool, AtomicU8, Ordering},
+    },
+    time::Instant,
+};
+
+/// The raw connection handle, closed once the last `Sqlite` clone and `Stmt` are dropped
+struct Connection {
+    db: *mut sqlite3,
+    progress: Mutex<Option<Box<ProgressHandler>>>,
+    authorizer: Mutex<Option<Box<auth::AuthorizerHandler>>>,
+    trace: Mutex<Option<Box<trace::TraceHandler>>>,
+    invalidation: Mutex<Option<Box<invalidate::InvalidationHandler>>>,
+    recording: AtomicBool,
+    recorded: Mutex<Vec<RecordedSql>>,
+    detecting: AtomicBool,
+    repeats: Mutex<record::RepeatCounter>,
+    watching: AtomicBool,
+    open_transaction: Mutex<watchdog::OpenTransaction>,
+    rolled_back: AtomicBool,
+    last_error: Mutex<Option<LastError>>,
+    contention: contention::Contention,
+    prepared: Mutex<prepared::PreparedCache>,
+    invalid_utf8: AtomicU8,
+}
+
+type ProgressHandler = Box<dyn FnMut() -> bool + Send>;
+
+impl std::fmt::Debug for Connection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Connection").field("db", &self.db).finish()
+    }
+}
+
+// connections are opened with SQLITE_OPEN_FULLMUTEX so sqlite serializes access itself
+unsafe impl Send for Connection {}
+unsafe impl Sync for Connection {}
+
+unsafe extern "C" fn progress_trampoline(handler: *mut c_void) -> c_int {
+    let handler = unsafe { &mut *(handler as *mut ProgressHandler) };
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(handler)) {
+        Ok(true) => 0,
+        Ok(false) | Err(_) => 1,
+    }
+}
+
+impl Connection {
+    fn invalid_utf8(&self) -> InvalidUtf8 {
+        match self.invalid_utf8.load(Ordering::Relaxed) {
+            1 => InvalidUtf8::Error,
+            2 => InvalidUtf8::Bytes,
+            _ => InvalidUtf8::Lossy,
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if let Ok(prepared) = self.prepared.get_mut() {
+            prepared.clear();
+        }
+        unsafe {
+            sqlite3_close_v2(self.db);
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum OnMissing {
+    #[default]
+    CreateNew,
+    Error,
+}
+
+/// What reading text that isn't utf-8 gives, sqlite keeps whatever bytes it was handed
+/// as text, e.g. from `cast(x'ff' as text)`
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum InvalidUtf8 {
+    /// The text with `U+FFFD` in place of each invalid sequence
+    #[default]
+    Lossy,
+    /// `Error::InvalidUtf8` with the column it was read from
+    Error,
+    /// The bytes as they are, read as a blob
+    Bytes,
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct OpenOptions {
+    pub create_parent_dirs: bool,
+    pub on_missing: OnMissing,
+    pub auto_vacuum: Option<AutoVacuum>,
+    pub invalid_utf8: InvalidUtf8,
+}
+
+/// The platform data directory for `app_name`, e.g. `~/.local/share/app_name` with XDG,
+/// `~/Library/Application Support/app_name` on macOS and `%APPDATA%\\app_name` on windows
+pub fn data_dir(app_name: &str) -> Result<std::path::PathBuf> {
+    let var = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty());
+    let base = if cfg!(target_os = "windows") {
+        var("APPDATA").map(std::path::PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|home| std::path::Path::new(&home).join("Library/Application Support"))
+    } else {
+        var("XDG_DATA_HOME")
+            .map(std::path::PathBuf::from)
+            .or_else(|| var("HOME").map(|home| std::path::Path::new(&home).join(".local/share")))
+    };
+    match base {
+        Some(base) => Ok(base.join(app_name)),
+        None => Err(Error::Io(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "could not find a data directory, neither XDG_DATA_HOME, HOME nor APPDATA are set",
+        ))),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Sqlite {
+    connection: Arc<Connection>,
+}
+
+impl Sqlite {
+    pub fn open(path: &str) -> Result<Self> {
+        Self::open_with(path, OpenOptions::default())
+    }
+
+    pub fn open_with(path: &str, options: OpenOptions) -> Result<Self> {
+        let is_file = !path.is_empty() && path != ":memory:" && !path.starts_with("file:");
+        if options.create_parent_dirs && is_file {
+            match std::path::Path::new(path).parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => std::fs::create_dir_all(parent)?,
+                _ => {}
+            }
+        }
+        let flags = match options.on_missing {
+            OnMissing::CreateNew => SQLITE_OPEN_READWRITE | SQLITE_OPEN_CREATE,
+            OnMissing::Error => SQLITE_OPEN_READWRITE,
+        } | SQLITE_OPEN_FULLMUTEX;
+        let sqlite = Self::open_v2(path, flags)?;
+        if let Some(auto_vacuum) = options.auto_vacuum {
+            sqlite.set_auto_vacuum(auto_vacuum)?;
+        }
+        sqlite.set_invalid_utf8(options.invalid_utf8);
+        Ok(sqlite)
+    }
+
+    /// Opens a read only database that nothing writes to while it is open, e.g. a bundled asset.
+    /// Sqlite skips locking and change detection and reads the whole file through mmap
+    pub fn open_immutable(path: &str) -> Result<Self> {
+        let size = std::fs::metadata(path)?.len();
+        // ? and # end the path of a uri, % starts an escape
+        let uri_path = path
+            .replace('%', "%25")
+            .replace('?', "%3f")
+            .replace('#', "%23");
+        let sqlite = Self::open_v2(
+            &format!("file:{uri_path}?immutable=1"),
+            SQLITE_OPEN_READONLY | SQLITE_OPEN_URI | SQLITE_OPEN_FULLMUTEX,
+        )?;
+        let _result = sqlite.execute(&format!("pragma mmap_size = {size}"))?;
+        Ok(sqlite)
+    }
+
+    fn open_v2(path: &str, flags: i32) -> Result<Self> {
+        let c_path = CString::new(path)?;
+        let mut db: *mut sqlite3 = core::ptr::null_mut();
+        let result = unsafe { sqlite3_open_v2(c_path.as_ptr(), &mut db, flags, core::ptr::null()) };
+        match result {
+            SQLITE_OK => Ok(Self {
+                connection: Arc::new(Connection {
+                    db,
+                    progress: Mutex::new(None),
+                    authorizer: Mutex::new(None),
+                    trace: Mutex::new(None),
+                    invalidation: Mutex::new(None),
+                    recording: AtomicBool::new(false),
+                    recorded: Mutex::new(vec![]),
+                    detecting: AtomicBool::new(false),
+                    repeats: Mutex::new(Default::default()),
+                    watching: AtomicBool::new(false),
+                    open_transaction: Mutex::new(Default::default()),
+                    rolled_back: AtomicBool::new(false),
+                    last_error: Mutex::new(None),
+                    contention: Default::default(),
+                    prepared: Default::default(),
+                    invalid_utf8: AtomicU8::new(0),
+                }),
+            })
+            .and_then(Self::with_collations),
+            code => {
+                let err = open_err(code, db, path);
+                // sqlite usually hands back a handle even when open fails
+                unsafe { sqlite3_close_v2(db) };
+                Err(err)
+            }
+        }
+    }
+
+    #[cfg(feature = "unicode_collation")]
+    fn with_collations(self) -> Result<Self> {
+        self.create_unicode_collation()?;
+        Ok(self)
+    }
+
+    #[cfg(not(feature = "unicode_collation"))]
+    fn with_collations(self) -> Result<Self> {
+        Ok(self)
+    }
+
+    /// How rows read on this connection, and its clones, treat text that isn't utf-8
+    pub fn set_invalid_utf8(&self, policy: InvalidUtf8) {
+        let policy = match policy {
+            InvalidUtf8::Lossy => 0,
+            InvalidUtf8::Error => 1,
+            InvalidUtf8::Bytes => 2,
+        };
+        self.connection
+            .invalid_utf8
+            .store(policy, Ordering::Relaxed);
+    }
+
+    pub fn invalid_utf8(&self) -> InvalidUtf8 {
+        self.connection.invalid_utf8()
+    }
+
+    /// Opens `<data dir>/<app_name>/<app_name>.db`, creating the directory when needed
+    pub fn open_in_data_dir(app_name: &str) -> Result<Self> {
+        let path = data_dir(app_name)?.join(format!("{app_name}.db"));
+        let options = OpenOptions {
+            create_parent_dirs: true,
+            ..Default::default()
+        };
+        Self::open_with(&path_str(&path)?, options)
+    }
+
+    fn db(&self) -> *mut sqlite3 {
+        self.connection.db
+    }
+
+    /// Calls `callback` every `n_ops` virtual machine instructions,
+    /// returning false from it aborts the running statement with SQLITE_INTERRUPT
+    pub fn on_progress(
+        &self,
+        n_ops: i32,
+        callback: impl FnMut() -> bool + Send + 'static,
+    ) -> Result<()> {
+        let mut progress = self
+            .connection
+            .progress
+            .lock()
+            .map_err(|_| Error::MutexLockFailed)?;
+        let mut handler: Box<ProgressHandler> = Box::new(Box::new(callback));
+        unsafe {
+            sqlite3_progress_handler(
+                self.db(),
+                n_ops,
+                Some(progress_trampoline),
+                &mut *handler as *mut ProgressHandler as *mut c_void,
+            )
+        };
+        *progress = Some(handler);
+        Ok(())
+    }
+
+    pub fn clear_progress(&self) -> Result<()> {
+        let mut progress = self
+            .connection
+            .progress
+            .lock()
+            .map_err(|_| Error::MutexLockFailed)?;
+        unsafe { sqlite3_progress_handler(self.db(), 0, None, core::ptr::null_mut()) };
+        *progress = None;
+        Ok(())
+    }
+
+    /// A handle that can stop the statement running on this connection from any thread
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        InterruptHandle {
+            connection: Arc::downgrade(&self.connection),
+        }
+    }
+
+    /// Maps the rows of sql written at runtime into `T`, e.g. a `#[derive(FromRow)]` struct,
+    /// binding `params` in order
+    #[track_caller]
+    pub fn query_as<T: FromRow>(&self, sql: &str, params: &[Value]) -> Result<Vec<T>> {
+        self.prepare(sql)?.bind(params)?.rows_as()
+    }
+
+    /// Runs each statement with its params in order in one savepoint, all or nothing,
+    /// e.g. the statements of an `op` in `db!`
+    #[track_caller]
+    pub fn run_all(&self, statements: &[(&str, Vec<Value>)]) -> Result<()> {
+        self.savepoint(|| {
+            for (sql, params) in statements {
+                let _changes = self.prepare(sql)?.bind(params)?.changes()?;
+            }
+            Ok(())
+        })
+    }
+
+    #[track_caller]
+    pub fn execute(&self, sql: &str) -> Result<i32> {
+        self.connection.check_rolled_back()?;
+        self.exec(sql)
+    }
+
+    #[track_caller]
+    fn exec(&self, sql: &str) -> Result<i32> {
+        let c_sql = CString::new(sql)?;
+        let started_at = Instant::now();
+        let result = unsafe {
+            sqlite3_exec(
+                self.db(),
+                c_sql.as_ptr(),
+                None,
+                core::ptr::null_mut(),
+                core::ptr::null_mut(),
+            )
+        };
+        self.connection.record(sql, &[], started_at.elapsed());
+        match result {
+            SQLITE_OK => Ok(0),
+            code => {
+                self.connection.set_last_error(code, sql);
+                Err(sqlite_err(code, self.db()))
+            }
+        }
+    }
+
+    pub fn attach(&self, path: &str, schema_name: &str) -> Result<()> {
+        let _changes = self
+            .prepare("attach database :path as :schema_name")?
+            .bind(&[path.into(), schema_name.into()])?
+            .changes()?;
+        Ok(())
+    }
+
+    pub fn detach(&self, schema_name: &str) -> Result<()> {
+        let _changes = self
+            .prepare("detach database :schema_name")?
+            .bind(&[schema_name.into()])?
+            .changes()?;
+        Ok(())
+    }
+
+    fn pragma_int(&self, name: &str) -> Result<i64> {
+        // bookkeeping pragmas stay out of sql recordings
+        let rows = self
+            .prepare(&format!("pragma {name}"))?
+            .collect_rows(None)?;
+        match rows.first().and_then(|row| row.value(name)) {
+            Some(Value::Int(Int(Some(value)))) => Ok(*value),
+            _ => Err(Error::RowNotFound),
+        }
+    }
+
+    pub fn schema_version(&self) -> Result<i64> {
+        self.pragma_int("schema_version")
+    }
+
+    /// Changes whenever another connection commits to the same database file,
+    /// compare it before and after a write to know a reader can see the write
+    pub fn data_version(&self) -> Result<i64> {
+        self.pragma_int("data_version")
+    }
+
+    /// True while a transaction is open, a reader in a transaction keeps its old snapshot
+    pub fn in_transaction(&self) -> bool {
+        unsafe { sqlite3_get_autocommit(self.db()) == 0 }
+    }
+
+    /// True while this connection reads from a snapshot, in a transaction or while a statement
+    /// on it is still stepping. Outside one, in WAL mode, every statement reads the latest commit
+    pub fn holds_snapshot(&self) -> bool {
+        unsafe { sqlite3_txn_state(self.db(), core::ptr::null()) != SQLITE_TXN_NONE }
+    }
+
+    pub fn transaction(&self) -> Result<Transaction<'_>> {
+        Transaction::new(self, Tx::Immediate)
+    }
+
+    /// A savepoint that is rolled back when dropped instead of committed, even while
+    /// panicking, so tests can write to a shared seeded database without seeing each other's rows.
+    /// Inside a transaction only the savepoint is rolled back
+    pub fn test_transaction(&self) -> Result<Transaction<'_>> {
+        Transaction::test(self)
+    }
+
+    pub fn migrate(&self, migrations: &[impl ToString], app_version: &str) -> Result<()> {
+        let tx = self.transaction()?;
+        let _result =
+            tx.execute("create table if not exists migrations (sql text unique not null) strict")?;
+        for column in [
+            "applied_at integer",
+            "app_version text",
+            "duration_us integer",
+            "checksum text",
+        ] {
+            match tx.execute(&format!("alter table migrations add column {column}")) {
+                Ok(_) | Err(Error::DuplicateColumnName(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        for sql in migrations {
+            let sql = sql.to_string();
+            let started_at = Instant::now();
+            let result = tx.execute(&sql);
+            let _result = match result {
+                Ok(result) => result,
+                Err(Error::DuplicateColumnName(_)) => 0,
+                // renamed and dropped columns are gone after the first run
+                Err(Error::Sqlite { ref text, .. })
+                    if text.starts_with("no such column") && is_column_change(&sql) =>
+                {
+                    0
+                }
+                Err(err) => return Err(err),
+            };
+            let duration_us = started_at.elapsed().as_micros() as i64;
+            let checksum = checksum(&sql);
+            let _result = tx
+                .prepare("insert into migrations (sql, applied_at, app_version, duration_us, checksum) values (:sql, unixepoch(), :app_version, :duration_us, :checksum) on conflict (sql) do nothing")?
+                .bind(&[sql.as_str().into(), app_version.into(), int(duration_us).into(), checksum.as_str().into()])?
+                .changes()?;
+        }
+
+        Ok(())
+    }
+
+    /// Drops the check triggers of every schema that aren't in `keep`, the ones named
+    /// like `user_check_insert_1a2b3c4d` after a check's condition. A changed or removed
+    /// check leaves its old trigger behind otherwise, still aborting writes
+    pub fn drop_stale_checks(&self, keep: &[&str]) -> Result<()> {
+        let schemas = self
+            .prepare("select name from pragma_database_list")?
+            .rows()?
+            .iter()
+            .map(|row| row.get::<String>(0))
+            .collect::<Result<Vec<_>>>()?;
+        for schema in schemas {
+            let schema = schema.replace('"', "\"\"");
+            let triggers = self
+                .prepare(&format!(
+                    "select name from \"{schema}\".sqlite_master where type = 'trigger' and (name glob '*_check_insert_????????' or name glob '*_check_update_????????')"
+                ))?
+                .rows()?
+                .iter()
+                .map(|row| row.get::<String>(0))
+                .collect::<Result<Vec<_>>>()?;
+            for trigger in triggers {
+                if !keep.contains(&trigger.as_str()) {
+                    let trigger = trigger.replace('"', "\"\"");
+                    let _result =
+                        self.execute(&format!("drop trigger \"{schema}\".\"{trigger}\""))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn migration_history(&self) -> Result<Vec<MigrationRecord>> {
+        let rows = self
+            .prepare("select sql, applied_at, app_version, duration_us, checksum from migrations order by rowid")?
+            .rows()?
+            .iter()
+            .map(MigrationRecord::from_row)
+            .collect();
+        Ok(rows)
+    }
+
+    /// The version of the last applied `VersionedMigration`, kept in `pragma user_version`
+    pub fn migration_version(&self) -> Result<i64> {
+        self.pragma_int("user_version")
+    }
+
+    /// Runs the up sql of every migration past the current version through `version`,
+    /// all or nothing, and returns the new version
+    pub fn migrate_to(&self, migrations: &[VersionedMigration], version: i64) -> Result<i64> {
+        self.savepoint(|| {
+            let current = self.migration_version()?;
+            let mut pending = migrations
+                .iter()
+                .filter(|migration| migration.version > current && migration.version <= version)
+                .collect::<Vec<_>>();
+            pending.sort_by_key(|migration| migration.version);
+            let mut version = current;
+            for migration in pending {
+                let _result = self.execute(migration.up)?;
+                version = migration.version;
+            }
+            self.set_migration_version(version)
+        })
+    }
+
+    /// Runs the down sql of every applied migration above `version`, newest first,
+    /// all or nothing, and returns the new version. A migration without down sql
+    /// fails with `Error::IrreversibleMigration`
+    pub fn rollback_to(&self, migrations: &[VersionedMigration], version: i64) -> Result<i64> {
+        self.savepoint(|| {
+            let current = self.migration_version()?;
+            let mut applied = migrations
+                .iter()
+                .filter(|migration| migration.version > version && migration.version <= current)
+                .collect::<Vec<_>>();
+            applied.sort_by_key(|migration| std::cmp::Reverse(migration.version));
+            for migration in applied {
+                if migration.down.trim().is_empty() {
+                    return Err(Error::IrreversibleMigration(migration.version));
+                }
+                let _result = self.execute(migration.down)?;
+            }
+            self.set_migration_version(version.min(current))
+        })
+    }
+
+    fn set_migration_version(&self, version: i64) -> Result<i64> {
+        let _result = self.execute(&format!("pragma user_version = {version}"))?;
+        Ok(version)
+    }
+
+    /// Runs `f` in a savepoint, which begins a transaction when none is open,
+    /// and rolls back everything it did when it fails
+    fn savepoint<T>(&self, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        let _result = self.execute("savepoint sqltight_savepoint")?;
+        let result = f();
+        if result.is_err() {
+            let _result = self.execute("rollback to sqltight_savepoint")?;
+        }
+        let _result = self.execute("release sqltight_savepoint")?;
+        result
+    }
+}
+
+/// A numbered migration for changes the additive schema can't make, like drops and renames
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VersionedMigration<'a> {
+    pub version: i64,
+    pub up: &'a str,
+    pub down: &'a str,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrationRecord {
+    pub sql: Text,
+    pub applied_at: Int,
+    pub app_version: Text,
+    pub duration_us: Int,
+    pub checksum: Text,
+}
+
+impl FromRow for MigrationRecord {
+    fn from_row(row: &Row) -> Self {
+        let get = |key: &str| row.value(key).cloned().unwrap_or(Value::Null);
+        Self {
+            sql: get("sql").into(),
+            applied_at: get("applied_at").into(),
+            app_version: get("app_version").into(),
+            duration_us: get("duration_us").into(),
+            checksum: get("checksum").into(),
+        }
+    }
+}
+
+/// 64-bit FNV-1a, stable across rust versions unlike `DefaultHasher`
+fn checksum(sql: &str) -> String {
+    let hash = sql.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{hash:016x}")
+}
+
+/// Sql that only differs in literals, params, comments, whitespace and the case of words
+/// normalizes to the same string, e.g. `select * from user where id = ?`
+pub fn normalize_sql(sql: &str) -> String {
+    sql_tokens(sql)
+        .into_iter()
+        .filter(|token| token != ";")
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// True when a statement in `sql` creates, alters or drops something, so the schema
+/// changes once it runs
+pub fn changes_schema(sql: &str) -> bool {
+    sql_tokens(sql)
+        .split(|token| token == ";")
+        .any(|statement| {
+            matches!(
+                statement.first().map(String::as_str),
+                Some("create" | "alter" | "drop")
+            )
+        })
+}
+
+/// The words, quoted names, symbols and `;` of `sql` with literals and params as `?`,
+/// whitespace and comments dropped
+fn sql_tokens(sql: &str) -> Vec<String> {
+    let chars = sql.chars().collect::<Vec<_>>();
+    let mut tokens: Vec<String> = vec![];
+    let mut ix = 0;
+    while ix < chars.len() {
+        let c = chars[ix];
+        let next = chars.get(ix + 1).copied();
+        let start = ix;
+        ix += 1;
+        match c {
+            _ if c.is_whitespace() => {}
+            '-' if next == Some('-') => {
+                while ix < chars.len() && chars[ix] != '\n' {
+                    ix += 1;
+                }
+            }
+            '/' if next == Some('*') => {
+                ix += 1;
+                while ix < chars.len() && !(chars[ix - 1] == '*' && chars[ix] == '/') {
+                    ix += 1;
+                }
+                ix += 1;
+            }
+            '\'' | '"' | '`' | '[' => {
+                let close = match c {
+                    '[' => ']',
+                    c => c,
+                };
+                while ix < chars.len() {
+                    ix += 1;
+                    if chars[ix - 1] == close {
+                        // '' and "" escape the quote
+                        match chars.get(ix) {
+                            Some(c) if *c == close && close != ']' => ix += 1,
+                            _ => break,
+                        }
+                    }
+                }
+                match c {
+                    '\'' => tokens.push("?".to_string()),
+                    _ => tokens.push(chars[start..ix.min(chars.len())].iter().collect()),
+                }
+            }
+            'x' | 'X' if next == Some('\'') => {
+                ix += 1;
+                while ix < chars.len() && chars[ix] != '\'' {
+                    ix += 1;
+                }
+                ix += 1;
+                tokens.push("?".to_string());
+            }
+            '?' | ':' | '@' | '$' => {
+                while ix < chars.len() && (chars[ix].is_alphanumeric() || chars[ix] == '_') {
+                    ix += 1;
+                }
+                match c == '?' || ix > start + 1 {
+                    true => tokens.push("?".to_string()),
+                    false => tokens.push(c.to_string()),
+                }
+            }
+            _ if c.is_ascii_digit() || (c == '.' && next.is_some_and(|c| c.is_ascii_digit())) => {
+                while ix < chars.len()
+                    && (chars[ix].is_alphanumeric()
+                        || chars[ix] == '.'
+                        || (matches!(chars[ix], '+' | '-') && matches!(chars[ix - 1], 'e' | 'E')))
+                {
+                    ix += 1;
+                }
+                tokens.push("?".to_string());
+            }
+            _ if c.is_alphabetic() || c == '_' => {
+                while ix < chars.len() && (chars[ix].is_alphanumeric() || chars[ix] == '_') {
+                    ix += 1;
+                }
+                tokens.push(chars[start..ix].iter().collect::<String>().to_lowercase());
+            }
+            _ => tokens.push(c.to_string()),
+        }
+    }
+    tokens
+}
+
+#[derive(Debug, Clone)]
+pub struct InterruptHandle {
+    connection: Weak<Connection>,
+}
+
+impl InterruptHandle {
+    /// Makes the running statement fail with `Error::Interrupted`, does nothing once the
+    /// connection is closed
+    pub fn interrupt(&self) {
+        if let Some(connection) = self.connection.upgrade() {
+            unsafe { sqlite3_interrupt(connection.db) };
+        }
+    }
+
+    /// Interrupts when dropped unless disarmed first, a query future can hold one so that
+    /// cancelling the future also stops the statement on the blocking thread
+    pub fn guard(&self) -> InterruptGuard {
+        InterruptGuard {
+            handle: self.clone(),
+            armed: true,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct InterruptGuard {
+    handle: InterruptHandle,
+    armed: bool,
+}
+
+impl InterruptGuard {
+    pub fn disarm(mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        if self.armed {
+            self.handle.interrupt();
+        }
+    }
+}
+
+/// A prepared statement, reset after each run so it can run again. `Sqlite::prepare` and
+/// `StatementCache` hand it out again once it's dropped, a statement held elsewhere is never shared
+pub struct Stmt {
+    stmt: Arc<RawStmt>,
+    connection: Arc<Connection>,
+    /// only kept while the connection is recording sql
+    params: Vec<Value>,
+}
+
+#[derive(Debug)]
+struct RawStmt(*mut sqlite3_stmt);
+
+// like `Connection`, the statement's connection is opened with SQLITE_OPEN_FULLMUTEX so sqlite
+// serializes every call on it. `Stmt` isn't `Clone`, the `PreparedCache` and `StatementCache`
+// copies are only handed out again once no `Stmt` holds them, and are otherwise only finalized
+// when dropped, so one statement is never bound and stepped from two threads
+unsafe impl Send for RawStmt {}
+unsafe impl Sync for RawStmt {}
+
+impl Drop for RawStmt {
+    fn drop(&mut self) {
+        unsafe { sqlite3_finalize(self.0) };
+    }
+}
+
+impl Stmt {
+    fn new(connection: &Arc<Connection>, stmt: Arc<RawStmt>) -> Self {
+        Self {
+            stmt,
+            connection: connection.clone(),
+            params: vec![],
+        }
+    }
+
+    /// Another `Stmt` for this statement, reset, when nothing else holds it. Only the
+    /// caches hold statements to share, under their lock or on their own thread
+    fn reuse(&self) -> Option<Self> {
+        let stmt = (Arc::strong_count(&self.stmt) == 1)
+            .then(|| Self::new(&self.connection, self.stmt.clone()))?;
+        // a statement that was bound and dropped without running still has its params
+        let _result = stmt.reset();
+        Some(stmt)
+    }
+
+    fn prepare(
+        connection: &Arc<Connection>,
+        sql: &str,
+        mut stmt: *mut sqlite3_stmt,
+    ) -> Result<Self> {
+        let db = connection.db;
+        let c_sql = CString::new(sql)?;
+        let result =
+            unsafe { sqlite3_prepare_v2(db, c_sql.as_ptr(), -1, &mut stmt, std::ptr::null_mut()) };
+        match result {
+            SQLITE_OK | SQLITE_ROW | SQLITE_DONE => {
+                Ok(Self::new(connection, Arc::new(RawStmt(stmt))))
+            }
+            code => {
+                connection.set_last_error(code, sql);
+                Err(sqlite_err(code, db))
+            }
+        }
+    }
+
+    fn db(&self) -> *mut sqlite3 {
+        self.connection.db
+    }
+
+    fn step(&self) -> Result<i32> {
+        let result = unsafe { sqlite3_step(self.stmt.0) };
+        match result {
+            SQLITE_OK => Ok(SQLITE_OK),
+            SQLITE_ROW => Ok(SQLITE_ROW),
+            SQLITE_DONE => Ok(SQLITE_DONE),
+            code => {
+                self.connection.set_last_error(code, &self.sql());
+                Err(sqlite_err(code, self.db()))
+            }
+        }
+    }
+
+    /// Rewinds the statement and unbinds its params for the next run, failing
+    /// with the error of the last step when it failed
+    fn reset(&self) -> Result<()> {
+        let result = unsafe { sqlite3_reset(self.stmt.0) };
+        unsafe { sqlite3_clear_bindings(self.stmt.0) };
+        match result {
+            SQLITE_OK | SQLITE_ROW | SQLITE_DONE => Ok(()),
+            code => Err(sqlite_err(code, self.db())),
+        }
+    }
+
+    pub fn sql(&self) -> String {
+        unsafe { CStr::from_ptr(sqlite3_sql(self.stmt.0)) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// The sql with literals and params replaced by `?`, from `sqlite3_normalized_sql` with
+    /// the `normalize` feature and `normalize_sql` otherwise
+    pub fn normalized_sql(&self) -> String {
+        #[cfg(feature = "normalize")]
+        {
+            let sql = unsafe { sqltight_ffi::sqlite3_normalized_sql(self.stmt.0) };
+            if !sql.is_null() {
+                return unsafe { CStr::from_ptr(sql) }
+                    .to_string_lossy()
+                    .into_owned();
+            }
+        }
+        normalize_sql(&self.sql())
+    }
+
+    /// Binds `params` to `?1`, `?2`, ... in order. Text and blobs are bound by length and
+    /// copied, so nuls inside them are kept and `params` can be dropped before the
+    /// statement runs. A param sqlite refuses, e.g. one past its length limit, fails with
+    /// `Error::Bind` naming it
+    pub fn bind(mut self, params: &[Value]) -> Result<Self> {
+        if self.connection.is_recording() {
+            self.params = params.to_vec();
+        }
+        for (ix, param) in params.iter().enumerate() {
+            // sqlite numbers params from 1 in a c int, past it is a param it doesn't have
+            let ix = c_int::try_from(ix + 1)?;
+            let result = match param {
+                Value::Text(Text(Some(val))) => unsafe {
+                    sqlite3_bind_text64(
+                        self.stmt.0,
+                        ix,
+                        val.as_ptr() as *const _,
+                        u64::try_from(val.len())?,
+                        sqlite_transient(),
+                        SQLITE_UTF8 as u8,
+                    )
+                },
+                Value::Int(Int(Some(n))) => unsafe { sqlite3_bind_int64(self.stmt.0, ix, *n) },
+                // sqlite binds nan as null
+                Value::Real(Real(Some(f))) => unsafe { sqlite3_bind_double(self.stmt.0, ix, *f) },
+                Value::Blob(Blob(Some(b))) => unsafe {
+                    sqlite3_bind_blob64(
+                        self.stmt.0,
+                        ix,
+                        b.as_ptr() as *const _,
+                        u64::try_from(b.len())?,
+                        sqlite_transient(),
+                    )
+                },
+                Value::Text(Text(None))
+                | Value::Int(Int(None))
+                | Value::Real(Real(None))
+                | Value::Blob(Blob(None))
+                | Value::Null => unsafe { sqlite3_bind_null(self.stmt.0, ix) },
+            };
+            if result != SQLITE_OK {
+                self.connection.set_last_error(result, &self.sql());
+                return Err(Error::Bind {
+                    param: self.param_label(ix),
+                    error: Box::new(sqlite_err(result, self.db())),
+                });
+            }
+        }
+        Ok(self)
+    }
+
+    fn column_count(&self) -> i32 {
+        unsafe { sqlite3_column_count(self.stmt.0) }
+    }
+
+    fn column_name(&self, i: i32) -> String {
+        let result = unsafe { CStr::from_ptr(sqlite3_column_name(self.stmt.0, i)) };
+        result.to_string_lossy().into_owned()
+    }
+
+    /// The column of the current row borrowed from sqlite, valid until the next step or reset
+    /// Text is read by its length so nuls inside it are kept, text that isn't utf-8 is
+    /// read the connection's `InvalidUtf8` way
+    fn column_ref(&self, i: i32) -> Result<ValueRef<'_>> {
+        let result = unsafe { sqlite3_column_type(self.stmt.0, i) };
+        let value = match result {
+            1 => ValueRef::Int(unsafe { sqlite3_column_int64(self.stmt.0, i) }),
+            2 => ValueRef::Real(unsafe { sqlite3_column_double(self.stmt.0, i) }),
+            3 => {
+                let ptr = unsafe { sqlite3_column_text(self.stmt.0, i) };
+                let bytes = self.column_bytes(ptr, i);
+                match std::str::from_utf8(bytes) {
+                    Ok(text) => ValueRef::Text(std::borrow::Cow::Borrowed(text)),
+                    Err(err) => match self.connection.invalid_utf8() {
+                        InvalidUtf8::Lossy => ValueRef::Text(String::from_utf8_lossy(bytes)),
+                        InvalidUtf8::Bytes => ValueRef::Blob(bytes),
+                        InvalidUtf8::Error => {
+                            return Err(Error::InvalidUtf8 {
+                                column: self.column_name(i),
+                                valid_up_to: err.valid_up_to(),
+                            });
+                        }
+                    },
+                }
+            }
+            4 => {
+                let ptr = unsafe { sqlite3_column_blob(self.stmt.0, i) } as *const u8;
+                ValueRef::Blob(self.column_bytes(ptr, i))
+            }
+            _ => ValueRef::Null,
+        };
+        Ok(value)
+    }
+
+    /// `:name` for a named param and `?ix` otherwise
+    fn param_label(&self, ix: c_int) -> String {
+        let name = unsafe { sqlite3_bind_parameter_name(self.stmt.0, ix) };
+        match name.is_null() {
+            true => format!("?{ix}"),
+            false => unsafe { CStr::from_ptr(name) }
+                .to_string_lossy()
+                .into_owned(),
+        }
+    }
+
+    /// The bytes at `ptr`, read after it since getting the text or blob can change their length
+    fn column_bytes(&self, ptr: *const u8, i: i32) -> &[u8] {
+        let len = unsafe { sqlite3_column_bytes(self.stmt.0, i) } as usize;
+        // an empty blob is a null pointer
+        match ptr.is_null() {
+            true => &[],
+            false => unsafe { std::slice::from_raw_parts(ptr, len) },
+        }
+    }
+
+    /// The sql to record, read before the statement runs
+    fn recorded_sql(&self) -> Option<String> {
+        self.connection.wants_sql().then(|| self.sql())
+    }
+
+    #[track_caller]
+    /// Unbinds the params and fails when the transaction this would run in was rolled back by the watchdog
+    fn check_rolled_back(&self) -> Result<()> {
+        self.connection.check_rolled_back().inspect_err(|_| {
+            let _result = self.reset();
+        })
+    }
+
+    #[track_caller]
+    fn record(&self, sql: Option<String>, started_at: Instant) {
+        if let Some(sql) = sql {
+            self.connection
+                .record(&sql, &self.params, started_at.elapsed());
+        }
+    }
+
+    #[track_caller]
+    pub fn rows(&self) -> Result<Vec<Row>> {
+        self.check_rolled_back()?;
+        let sql = self.recorded_sql();
+        let started_at = Instant::now();
+        let rows = self.collect_rows(None);
+        self.record(sql, started_at);
+        rows
+    }
+
+    /// Like `rows`, but stops stepping and returns `Error::TooManyRows` past `max_rows` rows
+    #[track_caller]
+    pub fn rows_at_most(&self, max_rows: usize) -> Result<Vec<Row>> {
+        self.check_rolled_back()?;
+        let sql = self.recorded_sql();
+        let started_at = Instant::now();
+        let rows = self.collect_rows(Some(max_rows));
+        self.record(sql, started_at);
+        rows
+    }
+
+    /// Calls `f` with each row borrowed from the statement, text and blobs aren't copied.
+    /// The row is only valid inside `f`, stepping to the next row frees its values
+    #[track_caller]
+    pub fn for_each_row(&self, f: impl FnMut(&RowRef) -> Result<()>) -> Result<()> {
+        self.check_rolled_back()?;
+        let sql = self.recorded_sql();
+        let started_at = Instant::now();
+        let result = self.visit_rows(None, f);
+        self.record(sql, started_at);
+        result
+    }
+
+    /// Like `rows`, reading each row into a `T` without copying it into a `Row` first
+    #[track_caller]
+    pub fn rows_as<T: FromRow>(&self) -> Result<Vec<T>> {
+        let mut rows = Vec::new();
+        self.for_each_row(|row| {
+            rows.push(T::from_row_ref(row));
+            Ok(())
+        })?;
+        Ok(rows)
+    }
+
+    /// Like `rows_as`, but stops stepping and returns `Error::TooManyRows` past `max_rows` rows
+    #[track_caller]
+    pub fn rows_as_at_most<T: FromRow>(&self, max_rows: usize) -> Result<Vec<T>> {
+        self.check_rolled_back()?;
+        let sql = self.recorded_sql();
+        let started_at = Instant::now();
+        let mut rows = Vec::new();
+        let result = self.visit_rows(Some(max_rows), |row| {
+            rows.push(T::from_row_ref(row));
+            Ok(())
+        });
+        self.record(sql, started_at);
+        result.map(|()| rows)
+    }
+
+    fn collect_rows(&self, max_rows: Option<usize>) -> Result<Vec<Row>> {
+        let mut rows = Vec::new();
+        let mut columns: Option<Arc<[String]>> = None;
+        self.visit_rows(max_rows, |row| {
+            let columns = columns.get_or_insert_with(|| row.columns().into());
+            let values = row.values().iter().cloned().map(Value::from).collect();
+            rows.push(Row::new(columns.clone(), values));
+            Ok(())
+        })?;
+        Ok(rows)
+    }
+
+    fn visit_rows(
+        &self,
+        max_rows: Option<usize>,
+        mut f: impl FnMut(&RowRef) -> Result<()>,
+    ) -> Result<()> {
+        let mut visited = 0;
+        let mut columns: Option<Vec<String>> = None;
+        let mut values = Vec::new();
+        // a failed step is reported again by reset, with the statement rewound
+        while let Ok(SQLITE_ROW) = self.step() {
+            if max_rows == Some(visited) {
+                let sql = self.sql();
+                let _result = self.reset();
+                return Err(Error::TooManyRows {
+                    sql,
+                    max_rows: visited,
+                });
+            }
+            let column_count = self.column_count();
+            let columns = columns
+                .get_or_insert_with(|| (0..column_count).map(|i| self.column_name(i)).collect());
+            let result = (0..column_count)
+                .try_for_each(|i| {
+                    values.push(self.column_ref(i)?);
+                    Ok(())
+                })
+                .and_then(|()| f(&RowRef::new(columns, &values)));
+            // the values point into the row, they're gone before the next step
+            values.clear();
+            if let Err(err) = result {
+                let _result = self.reset();
+                return Err(err);
+            }
+            visited += 1;
+        }
+        self.reset()
+    }
+
+    #[track_caller]
+    pub fn changes(&self) -> Result<i32> {
+        self.check_rolled_back()?;
+        let sql = self.recorded_sql();
+        let started_at = Instant::now();
+        while let Ok(SQLITE_ROW) = self.step() {}
+        self.record(sql, started_at);
+        self.reset()?;
+        let changes = unsafe { sqlite3_changes(self.db()) };
+        Ok(changes)
+    }
+
+    pub fn parameter_names(&self) -> Vec<String> {
+        let mut names = vec![];
+        let parameter_count = unsafe { sqlite3_bind_parameter_count(self.stmt.0) };
+        for i in 1..=parameter_count {
+            let name = unsafe { CStr::from_ptr(sqlite3_bind_parameter_name(self.stmt.0, i)) };
+            let name = name.to_string_lossy().to_string();
+            names.push(name);
+        }
+        names
+    }
+
+    /// Whether running the statement leaves the database as it was, true for a `select`
+    pub fn is_readonly(&self) -> bool {
+        unsafe { sqlite3_stmt_readonly(self.stmt.0) != 0 }
+    }
+
+    pub fn select_column_names(&self) -> Vec<String> {
+        let mut names = vec![];
+        let column_count = unsafe { sqlite3_column_count(self.stmt.0) };
+        for i in 0..column_count {
+            let name = unsafe { CStr::from_ptr(sqlite3_column_name(self.stmt.0, i)) };
+            let name = name.to_string_lossy().to_string();
+            names.push(name);
+        }
+        names
+    }
+
+    pub fn select_column_types(&self) -> Vec<String> {
+        let mut types = vec![];
+        let column_count = unsafe { sqlite3_column_count(self.stmt.0) };
+        for i in 0..column_count {
+            let datatype = unsafe {
+                let value = sqlite3_column_decltype(self.stmt.0, i);
+                match value.is_null() {
+                    true => CStr::from_bytes_with_nul(b"ANY\0").unwrap(),
+                    false => CStr::from_ptr(value),
+                }
+            };
+            let datatype = datatype.to_string_lossy().to_string();
+            types.push(datatype);
+        }
+        types
+    }
+
+    /// The table and column each result column comes from, `None` for expressions
+    pub fn select_column_origins(&self) -> Vec<Option<(String, String)>> {
+        let column_count = unsafe { sqlite3_column_count(self.stmt.0) };
+        (0..column_count)
+            .map(|i| {
+                let (table, column) = unsafe {
+                    (
+                        sqlite3_column_table_name(self.stmt.0, i),
+                        sqlite3_column_origin_name(self.stmt.0, i),
+                    )
+                };
+                match table.is_null() || column.is_null() {
+                    true => None,
+                    false => Some(unsafe {
+                        (
+                            CStr::from_ptr(table).to_string_lossy().into_owned(),
+                            CStr::from_ptr(column).to_string_lossy().into_owned(),
+                        )
+                    }),
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct Transaction<'a> {
+    sqlite: &'a Sqlite,
+    scope: Scope,
+}
+
+/// What dropping a `Transaction` does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    /// Commits, or rolls back when the commit fails
+    Transaction,
+    /// Rolls back to the savepoint `test_transaction` opened, `nested` when
+    /// it was opened inside a transaction that stays open
+    TestSavepoint { nested: bool },
+}
+
+#[derive(Default)]
+pub enum Tx {
+    #[default]
+    Deferred,
+    Immediate,
+    Exclusive,
+}
+
+impl<'a> Transaction<'a> {
+    pub fn new(sqlite: &'a Sqlite, tx: Tx) -> Result<Transaction<'a>> {
+        let sql = match tx {
+            Tx::Deferred => "begin deferred transaction",
+            Tx::Immediate => "begin immediate transaction",
+            Tx::Exclusive => "begin exclusive transaction",
+        };
+        let _stmt = sqlite.execute(&sql)?;
+        sqlite.connection.transaction_started();
+        Ok(Self {
+            sqlite,
+            scope: Scope::Transaction,
+        })
+    }
+
+    fn test(sqlite: &'a Sqlite) -> Result<Transaction<'a>> {
+        let nested = sqlite.in_transaction();
+        let _result = sqlite.execute("savepoint sqltight_test")?;
+        if !nested {
+            sqlite.connection.transaction_started();
+        }
+        Ok(Self {
+            sqlite,
+            scope: Scope::TestSavepoint { nested },
+        })
+    }
+
+    pub fn end(&self) -> Result<i32> {
+        self.finish("end transaction")
+    }
+
+    pub fn rollback(&self) -> Result<i32> {
+        self.finish("rollback transaction")
+    }
+
+    /// Runs `f` for every item in its own savepoint, a failed item is rolled back on its own
+    /// and the others are committed with the transaction. Returns one result per item,
+    /// `Err` only when the savepoints themselves fail
+    pub fn try_each<T, R>(
+        &self,
+        items: impl IntoIterator<Item = T>,
+        mut f: impl FnMut(&Self, T) -> Result<R>,
+    ) -> Result<Vec<Result<R>>> {
+        let mut results = vec![];
+        for item in items {
+            let _result = self.execute("savepoint sqltight_item")?;
+            let result = f(self, item);
+            if result.is_err() {
+                let _result = self.execute("rollback to sqltight_item")?;
+            }
+            let _result = self.execute("release sqltight_item")?;
+            results.push(result);
+        }
+        Ok(results)
+    }
+
+    /// Fails with `Error::TransactionTimedOut` instead when the watchdog already rolled it back
+    fn finish(&self, sql: &str) -> Result<i32> {
+        self.connection.transaction_ended()?;
+        self.execute(sql)
+    }
+}
+
+impl<'a> Deref for Transaction<'a> {
+    type Target = Sqlite;
+
+    fn deref(&self) -> &Self::Target {
+        self.sqlite
+    }
+}
+
+impl<'a> Drop for Transaction<'a> {
+    fn drop(&mut self) {
+        if let Scope::TestSavepoint { nested } = self.scope {
+            let _result = self.execute("rollback to sqltight_test");
+            let _result = self.execute("release sqltight_test");
+            if !nested {
+                let _result = self.connection.transaction_ended();
+            }
+            return;
+        }
+        match self.end() {
+            Ok(_) | Err(Error::TransactionTimedOut) => {}
+            Err(_err) => {
+                self.rollback().expect("Rollback failed");
+            }
+        }
+    }
+}
+
+/// `SQLITE_TRANSIENT`, has sqlite copy a text or blob before the bind returns
+fn sqlite_transient() -> Option<unsafe extern "C" fn(*mut c_void)> {
+    // sqlite's own define is the destructor pointer -1
+    Some(unsafe { std::mem::transmute::<isize, unsafe extern "C" fn(*mut c_void)>(-1) })
+}
+
+fn sqlite_err(code: i32, db: *mut sqlite3) -> Error {
+    if code == SQLITE_INTERRUPT {
+        return Error::Interrupted;
+    }
+    match db.is_null() {
+        true => Error::Sqlite {
+            text: errstr(code),
+            code,
+            extended_code: code,
+        },
+        false => {
+            let text = unsafe { CStr::from_ptr(sqlite3_errmsg(db)) }
+                .to_string_lossy()
+                .into_owned();
+            let extended_code = unsafe { sqlite3_extended_errcode(db) };
+            // "UNIQUE constraint failed: user.email, user.name" -> user, [email, name]
+            let detail = text
+                .split_once(": ")
+                .map(|(_, detail)| detail)
+                .unwrap_or("");
+            let columns = detail
+                .split(", ")
+                .filter_map(|column| column.split_once('.'))
+                .collect::<Vec<_>>();
+            let table = columns
+                .first()
+                .map(|(table, _)| table.to_string())
+                .unwrap_or_default();
+            match extended_code {
+                SQLITE_CONSTRAINT_UNIQUE | SQLITE_CONSTRAINT_PRIMARYKEY => {
+                    Error::UniqueConstraint {
+                        table,
+                        columns: columns
+                            .iter()
+                            .map(|(_, column)| column.to_string())
+                            .collect(),
+                    }
+                }
+                SQLITE_CONSTRAINT_NOTNULL => Error::NotNullConstraint {
+                    table,
+                    column: columns
+                        .first()
+                        .map(|(_, column)| column.to_string())
+                        .unwrap_or_default(),
+                },
+                SQLITE_CONSTRAINT_FOREIGNKEY => Error::ForeignKeyConstraint,
+                // "cannot store TEXT value in INTEGER column user.age" -> user, age
+                SQLITE_CONSTRAINT_DATATYPE => {
+                    let (table, column) = text
+                        .rsplit_once(' ')
+                        .and_then(|(_, column)| column.split_once('.'))
+                        .unwrap_or_default();
+                    Error::DatatypeMismatch {
+                        table: table.to_string(),
+                        column: column.to_string(),
+                    }
+                }
+                SQLITE_CONSTRAINT_CHECK => Error::CheckConstraint(detail.to_string()),
+                // db! table checks are triggers raising the check constraint message
+                SQLITE_CONSTRAINT_TRIGGER if text.starts_with("CHECK constraint failed: ") => {
+                    Error::CheckConstraint(detail.to_string())
+                }
+                _ if text.starts_with("duplicate column name: ") => {
+                    Error::DuplicateColumnName(text.replace("duplicate column name: ", ""))
+                }
+                _ => Error::Sqlite {
+                    text,
+                    code,
+                    extended_code,
+                },
+            }
+        }
+    }
+}
+
+/// `alter table ... rename column` or `drop column`, both fail once they ran
+fn is_column_change(sql: &str) -> bool {
+    let sql = sql.to_lowercase();
+    sql.starts_with("alter table")
+        && (sql.contains(" rename column ") || sql.contains(" drop column "))
+}
+
+pub fn path_str(path: &std::path::Path) -> Result<String> {
+    match path.to_str() {
+        Some(path) => Ok(path.to_string()),
+        None => Err(Error::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} is not valid utf-8", path.display()),
+        ))),
+    }
+}
+
+fn errstr(code: i32) -> String {
+    unsafe { CStr::from_ptr(sqlite3_errstr(code)) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Adds the path and the os error to the usual "unable to open database file"
+fn open_err(code: i32, db: *mut sqlite3, path: &str) -> Error {
+    let text = match db.is_null() {
+        true => errstr(code),
+        false => unsafe { CStr::from_ptr(sqlite3_errmsg(db)) }
+            .to_string_lossy()
+            .into_owned(),
+    };
+    let errno = match db.is_null() {
+        true => 0,
+        false => unsafe { sqlite3_system_errno(db) },
+    };
+    let text = match errno {
+        0 => format!("{text}: {path}"),
+        errno => format!(
+            "{text}: {path} ({})",
+            std::io::Error::from_raw_os_error(errno)
+        ),
+    };
+    let extended_code = match db.is_null() {
+        true => code,
+        false => unsafe { sqlite3_extended_errcode(db) },
+    };
+    Error::Sqlite {
+        text,
+        code,
+        extended_code,
+    }
+}
+
+pub trait Crud {
+    fn save(self, db: &Sqlite) -> Result<Self>
+    where
+        Self: Sized;
+
+    fn delete(self, db: &Sqlite) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Saves every row in a single savepoint, all or nothing. Tables override it
+    /// with multi-row upserts that return the rows in the same order, see `Sqlite::save_batch`
+    fn save_all(rows: Vec<Self>, db: &Sqlite) -> Result<Vec<Self>>
+    where
+        Self: Sized,
+    {
+        db.savepoint(|| rows.into_iter().map(|row| row.save(db)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Error, Result, Sqlite, Value, blob, int, real, text};
+
+    // values whose bytes all differ read back the same, lengths included
+    fn binds_round_trip() -> Result<()> {
+        let sqlite = Sqlite::open(":memory:")?;
+        let params: Vec<Value> = vec![
+            int(0x0102_0304_0506_0708).into(),
+            real(-0.1).into(),
+            text("na\0me").into(),
+            blob((0..=255).collect()).into(),
+        ];
+        let rows = sqlite
+            .prepare(
+                "select ?1 as i, ?2 as r, ?3 as t, ?4 as b, length(cast(?3 as blob)) as tl, length(?4) as bl",
+            )?
+            .bind(&params)?
+            .rows()?;
+        let row = rows.first().ok_or(Error::RowNotFound)?;
+        for (ix, column) in ["i", "r", "t", "b"].iter().enumerate() {
+            assert_eq!(row.get::<Value>(*column)?, params[ix]);
+        }
+        assert_eq!(row.get::<i64>("tl")?, 5);
+        assert_eq!(row.get::<i64>("bl")?, 256);
+        Ok(())
+    }
+
+    #[test]
+    fn bind_works() -> Result<()> {
+        binds_round_trip()
+    }
+
+    // text and blob lengths widen from a 32 bit usize to sqlite's u64
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn bind_works_on_32_bit() -> Result<()> {
+        binds_round_trip()
+    }
+
+    // ints and reals are bound by value, sqlite stores them in its own byte order
+    #[cfg(target_endian = "big")]
+    #[test]
+    fn bind_works_on_big_endian() -> Result<()> {
+        binds_round_trip()
+    }
+}
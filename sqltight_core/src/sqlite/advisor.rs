@@ -0,0 +1,140 @@
+use super::Sqlite;
+use crate::{Result, Row};
+use std::collections::BTreeMap;
+
+/// An index that changed a full scan into an index lookup for at least one query
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexSuggestion {
+    pub table: String,
+    pub column: String,
+    /// `create index if not exists ...`, ready for an `index` block or a migration
+    pub sql: String,
+    /// the queries that would use it
+    pub queries: Vec<String>,
+    /// rows in the table right now, a scan reads every one of them
+    pub rows: i64,
+}
+
+impl IndexSuggestion {
+    /// Rows a full scan reads times the queries that scan, the suggestions are sorted by it
+    pub fn benefit(&self) -> i64 {
+        self.rows
+            .saturating_mul(i64::try_from(self.queries.len()).unwrap_or(i64::MAX))
+    }
+}
+
+const CANDIDATE: &str = "sqltight_candidate_ix";
+
+impl Sqlite {
+    /// Looks for full table scans in the `explain query plan` of each query, then tries an index
+    /// on every column of the scanned table in an empty in-memory copy of the schema and keeps
+    /// the ones the planner picks up. Nothing is written to this database
+    pub fn suggest_indexes(&self, queries: &[&str]) -> Result<Vec<IndexSuggestion>> {
+        let scratch = Sqlite::open(":memory:")?;
+        let schema = self
+            .prepare("select sql from sqlite_schema where sql is not null and type in ('table', 'index', 'view') and name not like 'sqlite_%' and sql not like 'create virtual%' order by case type when 'table' then 0 when 'index' then 1 else 2 end")?
+            .collect_rows(None)?;
+        for row in &schema {
+            // anything the copy can't recreate only hides the queries that need it
+            let _result = scratch.execute(&row.get::<String>("sql")?);
+        }
+        let mut suggestions: BTreeMap<(String, String), Vec<String>> = BTreeMap::new();
+        for sql in queries {
+            let Ok(plan) = scratch.query_plan(sql) else {
+                continue;
+            };
+            for table in scanned_tables(&plan) {
+                // the plan names tables the way the query wrote them
+                let Some(table) = scratch.table_name(&table)? else {
+                    continue;
+                };
+                let columns = scratch.columns(&table)?;
+                for column in columns {
+                    let _result = scratch.execute(&format!(
+                        "create index {CANDIDATE} on \"{table}\" (\"{column}\")"
+                    ))?;
+                    let helps = scratch
+                        .query_plan(sql)?
+                        .iter()
+                        .any(|detail| detail.contains(CANDIDATE));
+                    let _result = scratch.execute(&format!("drop index {CANDIDATE}"))?;
+                    if helps {
+                        suggestions
+                            .entry((table.clone(), column))
+                            .or_default()
+                            .push(sql.to_string());
+                    }
+                }
+            }
+        }
+        let mut suggestions = suggestions
+            .into_iter()
+            .map(|((table, column), queries)| {
+                Ok(IndexSuggestion {
+                    sql: format!(
+                        "create index if not exists {table}_{column}_ix on {table} ({column})"
+                    ),
+                    rows: self.row_count(&table)?,
+                    table,
+                    column,
+                    queries,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        suggestions.sort_by_key(|suggestion| std::cmp::Reverse(suggestion.benefit()));
+        Ok(suggestions)
+    }
+
+    fn query_plan(&self, sql: &str) -> Result<Vec<String>> {
+        self.prepare(&format!("explain query plan {sql}"))?
+            .collect_rows(None)?
+            .iter()
+            .map(|row: &Row| row.get::<String>("detail"))
+            .collect()
+    }
+
+    fn table_name(&self, name: &str) -> Result<Option<String>> {
+        let rows = self
+            .prepare(
+                "select name from sqlite_schema where type = 'table' and name = ? collate nocase",
+            )?
+            .bind(&[name.into()])?
+            .collect_rows(None)?;
+        rows.first()
+            .map(|row| row.get::<String>("name"))
+            .transpose()
+    }
+
+    fn columns(&self, table: &str) -> Result<Vec<String>> {
+        self.prepare(&format!("pragma table_info(\"{table}\")"))?
+            .collect_rows(None)?
+            .iter()
+            .filter(|row| !matches!(row.get::<i64>("pk"), Ok(pk) if pk > 0))
+            .map(|row| row.get::<String>("name"))
+            .collect()
+    }
+
+    fn row_count(&self, table: &str) -> Result<i64> {
+        let rows = self
+            .prepare(&format!("select count(*) as count from \"{table}\""))?
+            .collect_rows(None)?;
+        match rows.first() {
+            Some(row) => row.get::<i64>("count"),
+            None => Ok(0),
+        }
+    }
+}
+
+/// `SCAN post` and `SCAN post USING INDEX ...` read the whole table
+fn scanned_tables(plan: &[String]) -> Vec<String> {
+    let mut tables = plan
+        .iter()
+        .filter_map(|detail| detail.strip_prefix("SCAN "))
+        .filter_map(|rest| rest.split_whitespace().next())
+        .filter(|table| *table != "CONSTANT")
+        .map(|table| table.to_string())
+        .collect::<Vec<_>>();
+    tables.sort();
+    tables.dedup();
+    tables
+}
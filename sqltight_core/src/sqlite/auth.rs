@@ -0,0 +1,209 @@
+use super::Sqlite;
+use crate::{Error, Result};
+use sqltight_ffi::{
+    SQLITE_ALTER_TABLE, SQLITE_ANALYZE, SQLITE_ATTACH, SQLITE_CREATE_INDEX, SQLITE_CREATE_TABLE,
+    SQLITE_CREATE_TEMP_INDEX, SQLITE_CREATE_TEMP_TABLE, SQLITE_CREATE_TEMP_TRIGGER,
+    SQLITE_CREATE_TEMP_VIEW, SQLITE_CREATE_TRIGGER, SQLITE_CREATE_VIEW, SQLITE_CREATE_VTABLE,
+    SQLITE_DELETE, SQLITE_DENY, SQLITE_DETACH, SQLITE_DROP_INDEX, SQLITE_DROP_TABLE,
+    SQLITE_DROP_TEMP_INDEX, SQLITE_DROP_TEMP_TABLE, SQLITE_DROP_TEMP_TRIGGER,
+    SQLITE_DROP_TEMP_VIEW, SQLITE_DROP_TRIGGER, SQLITE_DROP_VIEW, SQLITE_DROP_VTABLE,
+    SQLITE_FUNCTION, SQLITE_IGNORE, SQLITE_INSERT, SQLITE_OK, SQLITE_PRAGMA, SQLITE_READ,
+    SQLITE_RECURSIVE, SQLITE_REINDEX, SQLITE_SAVEPOINT, SQLITE_SELECT, SQLITE_TRANSACTION,
+    SQLITE_UPDATE, sqlite3_set_authorizer,
+};
+use std::ffi::{CStr, c_char, c_int, c_void};
+
+/// What a statement wants to do, checked by the authorizer while it is being prepared.
+/// Temp variants of create and drop are reported the same as their regular counterparts.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuthAction {
+    CreateIndex { index: String, table: String },
+    CreateTable { table: String },
+    CreateTrigger { trigger: String, table: String },
+    CreateView { view: String },
+    CreateVirtualTable { table: String, module: String },
+    Delete { table: String },
+    DropIndex { index: String, table: String },
+    DropTable { table: String },
+    DropTrigger { trigger: String, table: String },
+    DropView { view: String },
+    DropVirtualTable { table: String, module: String },
+    Insert { table: String },
+    Pragma { name: String, arg: Option<String> },
+    Read { table: String, column: String },
+    Select,
+    Transaction { operation: String },
+    Update { table: String, column: String },
+    Attach { filename: String },
+    Detach { database: String },
+    AlterTable { database: String, table: String },
+    Reindex { index: String },
+    Analyze { table: String },
+    Function { name: String },
+    Savepoint { operation: String, name: String },
+    Recursive,
+    Other { code: i32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Authorization {
+    Allow,
+    /// Fails the whole statement with an authorization error
+    Deny,
+    /// Reads return null and writes are skipped instead of failing
+    Ignore,
+}
+
+pub(crate) type AuthorizerHandler = Box<dyn FnMut(AuthAction) -> Authorization + Send>;
+
+impl AuthAction {
+    fn new(code: c_int, arg1: Option<String>, arg2: Option<String>) -> Self {
+        let pragma_arg = arg2.clone();
+        let (arg1, arg2) = (arg1.unwrap_or_default(), arg2.unwrap_or_default());
+        match code {
+            SQLITE_CREATE_INDEX | SQLITE_CREATE_TEMP_INDEX => Self::CreateIndex {
+                index: arg1,
+                table: arg2,
+            },
+            SQLITE_CREATE_TABLE | SQLITE_CREATE_TEMP_TABLE => Self::CreateTable { table: arg1 },
+            SQLITE_CREATE_TRIGGER | SQLITE_CREATE_TEMP_TRIGGER => Self::CreateTrigger {
+                trigger: arg1,
+                table: arg2,
+            },
+            SQLITE_CREATE_VIEW | SQLITE_CREATE_TEMP_VIEW => Self::CreateView { view: arg1 },
+            SQLITE_CREATE_VTABLE => Self::CreateVirtualTable {
+                table: arg1,
+                module: arg2,
+            },
+            SQLITE_DELETE => Self::Delete { table: arg1 },
+            SQLITE_DROP_INDEX | SQLITE_DROP_TEMP_INDEX => Self::DropIndex {
+                index: arg1,
+                table: arg2,
+            },
+            SQLITE_DROP_TABLE | SQLITE_DROP_TEMP_TABLE => Self::DropTable { table: arg1 },
+            SQLITE_DROP_TRIGGER | SQLITE_DROP_TEMP_TRIGGER => Self::DropTrigger {
+                trigger: arg1,
+                table: arg2,
+            },
+            SQLITE_DROP_VIEW | SQLITE_DROP_TEMP_VIEW => Self::DropView { view: arg1 },
+            SQLITE_DROP_VTABLE => Self::DropVirtualTable {
+                table: arg1,
+                module: arg2,
+            },
+            SQLITE_INSERT => Self::Insert { table: arg1 },
+            SQLITE_PRAGMA => Self::Pragma {
+                name: arg1,
+                arg: pragma_arg,
+            },
+            SQLITE_READ => Self::Read {
+                table: arg1,
+                column: arg2,
+            },
+            SQLITE_SELECT => Self::Select,
+            SQLITE_TRANSACTION => Self::Transaction { operation: arg1 },
+            SQLITE_UPDATE => Self::Update {
+                table: arg1,
+                column: arg2,
+            },
+            SQLITE_ATTACH => Self::Attach { filename: arg1 },
+            SQLITE_DETACH => Self::Detach { database: arg1 },
+            SQLITE_ALTER_TABLE => Self::AlterTable {
+                database: arg1,
+                table: arg2,
+            },
+            SQLITE_REINDEX => Self::Reindex { index: arg1 },
+            SQLITE_ANALYZE => Self::Analyze { table: arg1 },
+            SQLITE_FUNCTION => Self::Function { name: arg2 },
+            SQLITE_SAVEPOINT => Self::Savepoint {
+                operation: arg1,
+                name: arg2,
+            },
+            SQLITE_RECURSIVE => Self::Recursive,
+            code => Self::Other { code },
+        }
+    }
+}
+
+impl Sqlite {
+    /// Checks every statement prepared from now on with `callback`,
+    /// statements that were already prepared are not re-checked
+    pub fn set_authorizer(
+        &self,
+        callback: impl FnMut(AuthAction) -> Authorization + Send + 'static,
+    ) -> Result<()> {
+        let mut authorizer = self
+            .connection
+            .authorizer
+            .lock()
+            .map_err(|_| Error::MutexLockFailed)?;
+        let mut handler: Box<AuthorizerHandler> = Box::new(Box::new(callback));
+        let result = unsafe {
+            sqlite3_set_authorizer(
+                self.db(),
+                Some(authorizer_trampoline),
+                &mut *handler as *mut AuthorizerHandler as *mut c_void,
+            )
+        };
+        *authorizer = Some(handler);
+        match result {
+            SQLITE_OK => Ok(()),
+            code => Err(super::sqlite_err(code, self.db())),
+        }
+    }
+
+    /// Only allows selects that read from `tables`, everything else is denied
+    pub fn authorize_read_only(&self, tables: &[&str]) -> Result<()> {
+        let tables = tables
+            .iter()
+            .map(|table| table.to_lowercase())
+            .collect::<Vec<_>>();
+        self.set_authorizer(move |action| match action {
+            AuthAction::Select | AuthAction::Function { .. } | AuthAction::Recursive => {
+                Authorization::Allow
+            }
+            AuthAction::Read { table, .. } if tables.contains(&table.to_lowercase()) => {
+                Authorization::Allow
+            }
+            _ => Authorization::Deny,
+        })
+    }
+
+    pub fn clear_authorizer(&self) -> Result<()> {
+        let mut authorizer = self
+            .connection
+            .authorizer
+            .lock()
+            .map_err(|_| Error::MutexLockFailed)?;
+        unsafe { sqlite3_set_authorizer(self.db(), None, core::ptr::null_mut()) };
+        *authorizer = None;
+        Ok(())
+    }
+}
+
+fn opt_string(ptr: *const c_char) -> Option<String> {
+    match ptr.is_null() {
+        true => None,
+        false => Some(
+            unsafe { CStr::from_ptr(ptr) }
+                .to_string_lossy()
+                .into_owned(),
+        ),
+    }
+}
+
+unsafe extern "C" fn authorizer_trampoline(
+    handler: *mut c_void,
+    code: c_int,
+    arg1: *const c_char,
+    arg2: *const c_char,
+    _database: *const c_char,
+    _trigger: *const c_char,
+) -> c_int {
+    let handler = unsafe { &mut *(handler as *mut AuthorizerHandler) };
+    let action = AuthAction::new(code, opt_string(arg1), opt_string(arg2));
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| handler(action))) {
+        Ok(Authorization::Allow) => SQLITE_OK,
+        Ok(Authorization::Ignore) => SQLITE_IGNORE,
+        Ok(Authorization::Deny) | Err(_) => SQLITE_DENY,
+    }
+}
@@ -0,0 +1,124 @@
+use super::Sqlite;
+use crate::{Error, Result, Row, Value};
+
+/// Rows per multi-row upsert, kept well under sqlite's 32766 bound params
+const MAX_BATCH_ROWS: usize = 500;
+pub(super) const MAX_BATCH_PARAMS: usize = 32766;
+
+/// A table's upsert split around its values, `db!` generates one per table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchSql {
+    /// `insert into user (id,email) values`
+    pub insert: &'static str,
+    /// The values of one row with `?` params, e.g. `(?,?)`
+    pub row: &'static str,
+    /// `on conflict (id) do update set ... returning *`
+    pub conflict: &'static str,
+    /// The columns of `insert`, in the order of each row's values
+    pub columns: &'static [&'static str],
+    /// The `on conflict` target besides `id`, like `["email"]`, empty when there's none
+    pub conflict_target: &'static [&'static str],
+}
+
+impl Sqlite {
+    /// Upserts every row in one savepoint, many rows per statement, and returns them
+    /// as saved in the same order. sqlite returns the rows of a `returning` in no particular
+    /// order, so they're matched back to the given rows, see `input_order`
+    pub fn save_batch(&self, sql: BatchSql, rows: Vec<Vec<Value>>) -> Result<Vec<Row>> {
+        let columns = rows.first().map(Vec::len).unwrap_or(1).max(1);
+        let chunk_len = (MAX_BATCH_PARAMS / columns).clamp(1, MAX_BATCH_ROWS);
+        self.savepoint(|| {
+            let mut saved = Vec::with_capacity(rows.len());
+            for chunk in rows.chunks(chunk_len) {
+                let values = vec![sql.row; chunk.len()].join(",");
+                let returned = self
+                    .prepare(&format!("{} {values} {}", sql.insert, sql.conflict))?
+                    .bind(&chunk.concat())?
+                    .rows()?;
+                // only an `on conflict where` skips rows
+                if returned.len() != chunk.len() {
+                    return Err(Error::UpsertSkipped);
+                }
+                saved.extend(input_order(&sql, chunk, returned));
+            }
+            Ok(saved)
+        })
+    }
+}
+
+/// The returned rows in the order of `rows`. A row with an id, or with every column of the
+/// conflict target set, gets the returned row with the same values. The new rows left get
+/// the rest by id, sqlite gives new rows increasing ids in the order they're inserted
+fn input_order(sql: &BatchSql, rows: &[Vec<Value>], returned: Vec<Row>) -> Vec<Row> {
+    let mut returned = returned.into_iter().map(Some).collect::<Vec<_>>();
+    let mut matched = Vec::with_capacity(rows.len());
+    for row in rows {
+        let found = [&["id"][..], sql.conflict_target]
+            .into_iter()
+            .filter(|key| !key.is_empty())
+            .find_map(|key| {
+                let values = key
+                    .iter()
+                    .map(|column| {
+                        let ix = sql.columns.iter().position(|name| name == column)?;
+                        row.get(ix).filter(|value| **value != Value::Null)
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                returned
+                    .iter_mut()
+                    .find(|saved| {
+                        saved.as_ref().is_some_and(|saved| {
+                            key.iter()
+                                .zip(&values)
+                                .all(|(column, value)| saved.value(*column) == Some(*value))
+                        })
+                    })?
+                    .take()
+            });
+        matched.push(found);
+    }
+    let mut rest = returned.into_iter().flatten().collect::<Vec<_>>();
+    rest.sort_by_key(|row| row.get::<i64>("id").ok());
+    let mut rest = rest.into_iter();
+    matched
+        .into_iter()
+        .filter_map(|found| found.or_else(|| rest.next()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BatchSql, input_order};
+    use crate::{Row, Value, int, text};
+
+    fn row(id: i64, email: &str) -> Row {
+        Row::new(
+            vec!["id".to_string(), "email".to_string()],
+            vec![int(id).into(), text(email).into()],
+        )
+    }
+
+    // the rows come back reversed, like sqlite is free to return them
+    #[test]
+    fn input_order_works() {
+        let sql = BatchSql {
+            insert: "",
+            row: "",
+            conflict: "",
+            columns: &["id", "email"],
+            conflict_target: &["email"],
+        };
+        let rows = vec![
+            vec![Value::Null, text("new").into()],
+            vec![int(1).into(), text("a").into()],
+            vec![Value::Null, text("b").into()],
+            vec![Value::Null, text("newer").into()],
+        ];
+        let returned = vec![row(4, "newer"), row(2, "b"), row(1, "a"), row(3, "new")];
+        let ids = input_order(&sql, &rows, returned)
+            .iter()
+            .map(|row| row.get::<i64>("id").ok())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [Some(3), Some(1), Some(2), Some(4)]);
+    }
+}
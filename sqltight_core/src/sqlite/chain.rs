@@ -0,0 +1,272 @@
+use super::{Sqlite, profile::quote};
+use crate::{Blob, Error, FromRow, Int, Real, Result, Row, Table, Text, Value};
+
+/// An append-only table whose rows each carry the sha-256 `hash` of their columns and
+/// the hash of the row before them, `db!` implements it for each `log table`
+pub trait LogTable: Table + FromRow {
+    /// Inserts a row with a null hash and returns it, defaults applied
+    const APPEND_SQL: &'static str;
+
+    /// The params of `APPEND_SQL` in order
+    fn append_values(self) -> Vec<Value>;
+}
+
+impl Sqlite {
+    /// Inserts `row` and chains it to the last row of its table,
+    /// its `hash` covers every other column and the last row's hash. It takes the write lock
+    /// before reading the last hash, so concurrent appends wait for each other instead of
+    /// chaining to the same row. In a deferred transaction that already read, a concurrent
+    /// append fails it with `SQLITE_BUSY_SNAPSHOT` instead
+    pub fn append<T: LogTable>(&self, row: T) -> Result<T> {
+        let table = quote(T::NAME);
+        self.savepoint(|| {
+            // a write that changes nothing, the lock is taken all the same
+            let _changes = self
+                .prepare(&format!("update {table} set hash = hash where 0"))?
+                .changes()?;
+            let last = self
+                .prepare(&format!(
+                    "select hash from {table} order by id desc limit 1"
+                ))?
+                .rows()?;
+            let previous = match last.first() {
+                Some(row) => row.get::<Option<String>>(0)?,
+                None => None,
+            };
+            let id: i64 = self
+                .prepare(T::APPEND_SQL)?
+                .bind(&row.append_values())?
+                .rows()?
+                .first()
+                .ok_or(Error::RowNotFound)?
+                .get("id")?;
+            // hashed as read back, the same way verify_chain reads it
+            let appended = self
+                .prepare(&format!("select * from {table} where id = :id"))?
+                .bind(&[id.into()])?
+                .rows()?
+                .into_iter()
+                .next()
+                .ok_or(Error::RowNotFound)?;
+            let hash = row_hash::<T>(previous.as_deref().unwrap_or_default(), &appended)?;
+            self.prepare(&format!(
+                "update {table} set hash = :hash where id = :id returning *"
+            ))?
+            .bind(&[hash.into(), id.into()])?
+            .rows()?
+            .first()
+            .map(T::from_row)
+            .ok_or(Error::RowNotFound)
+        })
+    }
+
+    /// Recomputes the hash of every row of `T` in order, failing with `Error::BrokenChain`
+    /// at the first row that was changed, or inserted or deleted around the chain.
+    /// Returns the number of rows verified. Deleting the last rows leaves a shorter chain
+    /// that still verifies, compare the count or the last hash against one kept elsewhere
+    /// to catch that
+    pub fn verify_chain<T: LogTable>(&self) -> Result<usize> {
+        let rows = self
+            .prepare(&format!("select * from {} order by id", quote(T::NAME)))?
+            .rows()?;
+        let mut previous = String::new();
+        for row in &rows {
+            let hash: Option<String> = row.get("hash")?;
+            let expected = row_hash::<T>(&previous, row)?;
+            if hash.as_deref() != Some(expected.as_str()) {
+                return Err(Error::BrokenChain {
+                    table: T::NAME.to_string(),
+                    id: row.get("id")?,
+                });
+            }
+            previous = expected;
+        }
+        Ok(rows.len())
+    }
+}
+
+/// The hex sha-256 of the previous hash and each column but `hash`, every value
+/// tagged with its type and length so adjacent values can't run together
+fn row_hash<T: Table>(previous: &str, row: &Row) -> Result<String> {
+    let mut bytes = previous.as_bytes().to_vec();
+    for column in T::COLUMNS.iter().filter(|column| **column != "hash") {
+        match row.get::<Value>(*column)? {
+            Value::Int(Int(Some(value))) => {
+                bytes.push(b'i');
+                bytes.extend(value.to_be_bytes());
+            }
+            Value::Real(Real(Some(value))) => {
+                bytes.push(b'r');
+                bytes.extend(value.to_bits().to_be_bytes());
+            }
+            Value::Text(Text(Some(value))) => {
+                bytes.push(b't');
+                bytes.extend(u64::try_from(value.len())?.to_be_bytes());
+                bytes.extend(value.as_bytes());
+            }
+            Value::Blob(Blob(Some(value))) => {
+                bytes.push(b'b');
+                bytes.extend(u64::try_from(value.len())?.to_be_bytes());
+                bytes.extend(value);
+            }
+            _ => bytes.push(b'n'),
+        }
+    }
+    Ok(sha256(&bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// FIPS 180-4 sha-256, small enough to not need a dependency
+fn sha256(message: &[u8]) -> [u8; 32] {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    let mut padded = message.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend((message.len() as u64 * 8).to_be_bytes());
+    for block in padded.chunks(64) {
+        let mut w = [0u32; 64];
+        for (ix, word) in block.chunks(4).enumerate() {
+            w[ix] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for ix in 16..64 {
+            let s0 = w[ix - 15].rotate_right(7) ^ w[ix - 15].rotate_right(18) ^ (w[ix - 15] >> 3);
+            let s1 = w[ix - 2].rotate_right(17) ^ w[ix - 2].rotate_right(19) ^ (w[ix - 2] >> 10);
+            w[ix] = w[ix - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[ix - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for (k, w) in K.iter().zip(w) {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let temp1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(*k)
+                .wrapping_add(w);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(majority);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(temp1);
+            d = c;
+            c = b;
+            b = a;
+            a = temp1.wrapping_add(temp2);
+        }
+        for (word, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *word = word.wrapping_add(value);
+        }
+    }
+    let mut digest = [0; 32];
+    for (ix, word) in state.iter().enumerate() {
+        digest[ix * 4..ix * 4 + 4].copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{row_hash, sha256};
+    use crate::{Row, Table, Value, blob, int, real, text};
+
+    struct Entry;
+
+    impl Table for Entry {
+        const NAME: &'static str = "entry";
+        const COLUMNS: &'static [&'static str] = &["id", "amount", "note", "data", "hash"];
+    }
+
+    fn entry() -> Row {
+        Row::new(
+            Entry::COLUMNS
+                .iter()
+                .map(|column| String::from(*column))
+                .collect::<Vec<_>>(),
+            vec![
+                int(-2).into(),
+                real(1.5).into(),
+                text("na\0me").into(),
+                blob(vec![0, 255, 1]).into(),
+                Value::Null,
+            ],
+        )
+    }
+
+    // ints, reals and lengths are hashed as big-endian u64s, so every target
+    // chains the same hashes and one target can verify another's chain
+    const ENTRY_HASH: &str = "2795436bf704556e47ae1c0eb6f1eb64e18a08e150ad357dffc65451ff181b40";
+
+    #[test]
+    fn row_hash_works() -> crate::Result<()> {
+        assert_eq!(row_hash::<Entry>("previous", &entry())?, ENTRY_HASH);
+        Ok(())
+    }
+
+    // a 32 bit usize widens to the same 8 length bytes
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn row_hash_works_on_32_bit() -> crate::Result<()> {
+        assert_eq!(row_hash::<Entry>("previous", &entry())?, ENTRY_HASH);
+        assert_eq!(u64::try_from(usize::MAX)?, u64::from(u32::MAX));
+        Ok(())
+    }
+
+    // to_be_bytes is a copy on big-endian targets, not a swap
+    #[cfg(target_endian = "big")]
+    #[test]
+    fn row_hash_works_on_big_endian() -> crate::Result<()> {
+        assert_eq!(row_hash::<Entry>("previous", &entry())?, ENTRY_HASH);
+        assert_eq!((-2i64).to_be_bytes(), (-2i64).to_ne_bytes());
+        Ok(())
+    }
+
+    fn hex(message: &[u8]) -> String {
+        sha256(message)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    // FIPS 180-4 examples, the last one is padded into a second block
+    #[test]
+    fn sha256_works() {
+        assert_eq!(
+            hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        assert_eq!(
+            hex(&[b'a'; 1_000_000]),
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+    }
+}
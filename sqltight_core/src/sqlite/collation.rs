@@ -0,0 +1,80 @@
+use super::Sqlite;
+use crate::Result;
+use sqltight_ffi::{SQLITE_OK, SQLITE_UTF8, sqlite3_create_collation_v2};
+use std::{
+    cmp::Ordering,
+    ffi::{CString, c_int, c_void},
+};
+
+/// The collation `create_unicode_collation` registers, e.g. `order by name collate unicode`
+pub const UNICODE_COLLATION: &str = "unicode";
+
+type CollationHandler = Box<dyn Fn(&str, &str) -> Ordering + Send>;
+
+impl Sqlite {
+    /// Registers `collate {name}` on this connection, replacing a collation with the same name.
+    /// Text that isn't valid utf-8 is compared lossily
+    pub fn create_collation(
+        &self,
+        name: &str,
+        compare: impl Fn(&str, &str) -> Ordering + Send + 'static,
+    ) -> Result<()> {
+        let c_name = CString::new(name)?;
+        let handler: *mut CollationHandler = Box::into_raw(Box::new(Box::new(compare)));
+        let result = unsafe {
+            sqlite3_create_collation_v2(
+                self.db(),
+                c_name.as_ptr(),
+                SQLITE_UTF8 as c_int,
+                handler as *mut c_void,
+                Some(compare_trampoline),
+                Some(destroy_trampoline),
+            )
+        };
+        match result {
+            SQLITE_OK => Ok(()),
+            code => {
+                // sqlite only calls destroy for collations it registered
+                drop(unsafe { Box::from_raw(handler) });
+                Err(super::sqlite_err(code, self.db()))
+            }
+        }
+    }
+
+    /// Registers the `unicode` collation, the unicode collation algorithm with the cldr root
+    /// order, so `é` sorts with `e` instead of after `z`. Connections register it when they open
+    #[cfg(feature = "unicode_collation")]
+    pub fn create_unicode_collation(&self) -> Result<()> {
+        let collator = std::sync::Mutex::new(feruca::Collator::default());
+        self.create_collation(UNICODE_COLLATION, move |a, b| match collator.lock() {
+            Ok(mut collator) => collator.collate(a, b),
+            Err(_) => a.cmp(b),
+        })
+    }
+}
+
+unsafe extern "C" fn compare_trampoline(
+    handler: *mut c_void,
+    a_len: c_int,
+    a: *const c_void,
+    b_len: c_int,
+    b: *const c_void,
+) -> c_int {
+    let handler = unsafe { &*(handler as *const CollationHandler) };
+    let (a, b) = unsafe {
+        (
+            std::slice::from_raw_parts(a as *const u8, a_len.max(0) as usize),
+            std::slice::from_raw_parts(b as *const u8, b_len.max(0) as usize),
+        )
+    };
+    let (a, b) = (String::from_utf8_lossy(a), String::from_utf8_lossy(b));
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| handler(&a, &b))) {
+        Ok(Ordering::Less) => -1,
+        Ok(Ordering::Greater) => 1,
+        Ok(Ordering::Equal) | Err(_) => 0,
+    }
+}
+
+unsafe extern "C" fn destroy_trampoline(handler: *mut c_void) {
+    drop(unsafe { Box::from_raw(handler as *mut CollationHandler) });
+}
@@ -0,0 +1,116 @@
+use super::{Connection, Sqlite};
+use crate::Result;
+use sqltight_ffi::{SQLITE_BUSY, SQLITE_OK, sqlite3_busy_handler};
+use std::{
+    ffi::{c_int, c_void},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// The waits between retries of a locked database, the same as sqlite's own busy handler
+const DELAYS_MS: [u64; 12] = [1, 2, 5, 10, 15, 20, 25, 25, 25, 50, 50, 100];
+
+/// How much a connection waited on other connections' locks, see `Sqlite::contention_stats`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ContentionStats {
+    /// Times a statement found the database locked and waited to try again
+    pub busy_retries: u64,
+    /// Statements that failed with `SQLITE_BUSY` once the busy timeout ran out
+    pub busy_errors: u64,
+    /// Time spent waiting on locks held by other connections
+    pub lock_wait: Duration,
+    /// Frames in the write-ahead log, 0 outside of wal mode
+    pub wal_frames: u64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Contention {
+    busy_timeout_ms: AtomicU64,
+    busy_retries: AtomicU64,
+    busy_errors: AtomicU64,
+    lock_wait_us: AtomicU64,
+}
+
+impl Connection {
+    pub(crate) fn count_busy_error(&self, code: i32) {
+        if code & 0xff == SQLITE_BUSY {
+            self.contention.busy_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Sqlite {
+    /// Replaces sqlite's busy handler with one that waits the same way, up to the current
+    /// `pragma busy_timeout`, and counts the waits for `contention_stats`.
+    /// A later `pragma busy_timeout` puts sqlite's handler back, use `set_busy_timeout` instead
+    pub fn track_contention(&self) -> Result<()> {
+        // its one column is named `timeout`, not `busy_timeout`
+        let rows = self.prepare("pragma busy_timeout")?.collect_rows(None)?;
+        let busy_timeout: i64 = match rows.first() {
+            Some(row) => row.get(0)?,
+            None => 0,
+        };
+        self.set_busy_timeout(Duration::from_millis(busy_timeout.max(0) as u64))
+    }
+
+    /// How long statements wait for other connections' locks before failing with `SQLITE_BUSY`,
+    /// with the waits counted in `contention_stats`
+    pub fn set_busy_timeout(&self, timeout: Duration) -> Result<()> {
+        self.connection.contention.busy_timeout_ms.store(
+            u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+        let result = unsafe {
+            sqlite3_busy_handler(
+                self.db(),
+                Some(busy_trampoline),
+                &self.connection.contention as *const Contention as *mut c_void,
+            )
+        };
+        match result {
+            SQLITE_OK => Ok(()),
+            code => Err(super::sqlite_err(code, self.db())),
+        }
+    }
+
+    pub fn contention_stats(&self) -> Result<ContentionStats> {
+        let contention = &self.connection.contention;
+        let storage = self.storage_stats()?;
+        // a 32 byte header, then each frame's 24 byte header and page
+        let wal_frames = match storage.wal_size {
+            0..32 => 0,
+            wal_size => (wal_size - 32) / (storage.page_size.max(1) as u64 + 24),
+        };
+        Ok(ContentionStats {
+            busy_retries: contention.busy_retries.load(Ordering::Relaxed),
+            busy_errors: contention.busy_errors.load(Ordering::Relaxed),
+            lock_wait: Duration::from_micros(contention.lock_wait_us.load(Ordering::Relaxed)),
+            wal_frames,
+        })
+    }
+}
+
+/// Sqlite calls it with the number of times it already waited for the same lock,
+/// returning 0 gives up with `SQLITE_BUSY`
+unsafe extern "C" fn busy_trampoline(contention: *mut c_void, count: c_int) -> c_int {
+    let contention = unsafe { &*(contention as *const Contention) };
+    let count = count.max(0) as usize;
+    let waited_ms = DELAYS_MS.iter().take(count).sum::<u64>()
+        + count.saturating_sub(DELAYS_MS.len()) as u64 * DELAYS_MS[DELAYS_MS.len() - 1];
+    let timeout_ms = contention.busy_timeout_ms.load(Ordering::Relaxed);
+    if waited_ms >= timeout_ms {
+        return 0;
+    }
+    let delay_ms = DELAYS_MS
+        .get(count)
+        .copied()
+        .unwrap_or(DELAYS_MS[DELAYS_MS.len() - 1])
+        .min(timeout_ms - waited_ms);
+    let started_at = std::time::Instant::now();
+    std::thread::sleep(Duration::from_millis(delay_ms));
+    contention.busy_retries.fetch_add(1, Ordering::Relaxed);
+    contention
+        .lock_wait_us
+        .fetch_add(started_at.elapsed().as_micros() as u64, Ordering::Relaxed);
+    1
+}
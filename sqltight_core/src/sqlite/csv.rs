@@ -0,0 +1,328 @@
+use super::{Sqlite, Table, batch::MAX_BATCH_PARAMS, profile::quote, storage::split_name};
+use crate::{Error, Result, Value};
+use std::io::{BufRead, BufReader, Read};
+
+/// How `Sqlite::import_csv` reads its input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvOptions {
+    /// `b','` unless it's set, e.g. `b'\t'` for tab separated files
+    pub delimiter: u8,
+    /// The first record names the columns, in any order. Without it each record
+    /// holds every column of the table in declared order
+    pub has_header: bool,
+    /// Records per insert statement
+    pub batch_size: usize,
+    /// Records that can't be converted or inserted are skipped and reported, up to this
+    /// many. One more rolls the whole import back
+    pub max_errors: usize,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            has_header: true,
+            batch_size: 500,
+            max_errors: 0,
+        }
+    }
+}
+
+/// What `Sqlite::import_csv` inserted and the records it skipped
+#[derive(Debug, Default)]
+pub struct CsvImport {
+    pub rows: usize,
+    pub errors: Vec<CsvRowError>,
+}
+
+/// A skipped record, `line` is where it starts in the input counting from 1
+#[derive(Debug)]
+pub struct CsvRowError {
+    pub line: usize,
+    pub error: Error,
+}
+
+impl Sqlite {
+    /// Streams the records of `reader` into `T` in one savepoint, converting each field
+    /// to its column's declared type. Empty fields are null, quoted empty fields are
+    /// empty text. Fails with `Error::CsvRow` at the first record past `max_errors`
+    pub fn import_csv<T: Table>(
+        &self,
+        reader: impl Read,
+        options: CsvOptions,
+    ) -> Result<CsvImport> {
+        let mut records = Records::new(BufReader::new(reader), options.delimiter);
+        let declared = self.declared_types(T::NAME)?;
+        let columns = match options.has_header {
+            true => match records.next()? {
+                Some((_line, fields)) => fields?
+                    .into_iter()
+                    .map(|field| field.text)
+                    .collect::<Vec<_>>(),
+                None => return Ok(CsvImport::default()),
+            },
+            false => T::COLUMNS.iter().map(|column| column.to_string()).collect(),
+        };
+        let affinities = columns
+            .iter()
+            .map(
+                |column| match declared.iter().find(|(name, _ty)| name == column) {
+                    Some((_name, ty)) => Ok(Affinity::of(ty)),
+                    None => Err(Error::ColumnNotFound(column.clone())),
+                },
+            )
+            .collect::<Result<Vec<_>>>()?;
+        let insert = format!(
+            "insert into {} ({}) values",
+            quote(T::NAME),
+            columns
+                .iter()
+                .map(|column| quote(column))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let row_sql = format!("({})", vec!["?"; columns.len()].join(", "));
+        let batch_size = options
+            .batch_size
+            .clamp(1, (MAX_BATCH_PARAMS / columns.len().max(1)).max(1));
+        self.savepoint(|| {
+            let mut import = CsvImport::default();
+            let mut batch = Vec::with_capacity(batch_size);
+            let skip = |import: &mut CsvImport, line: usize, error: Error| {
+                if import.errors.len() >= options.max_errors {
+                    return Err(Error::CsvRow {
+                        line,
+                        error: Box::new(error),
+                    });
+                }
+                import.errors.push(CsvRowError { line, error });
+                Ok(())
+            };
+            loop {
+                let record = records.next()?;
+                let done = record.is_none();
+                if let Some((line, fields)) = record {
+                    match fields.and_then(|fields| convert(&columns, &affinities, fields)) {
+                        Ok(values) => batch.push((line, values)),
+                        Err(error) => skip(&mut import, line, error)?,
+                    }
+                }
+                if batch.len() == batch_size || (done && !batch.is_empty()) {
+                    let values = vec![row_sql.as_str(); batch.len()].join(", ");
+                    let params = batch
+                        .iter()
+                        .flat_map(|(_line, values)| values.iter().cloned())
+                        .collect::<Vec<_>>();
+                    match self
+                        .prepare(&format!("{insert} {values}"))?
+                        .bind(&params)?
+                        .changes()
+                    {
+                        Ok(_changes) => import.rows += batch.len(),
+                        // a failed statement inserts nothing, so each row is tried on its own
+                        // to find the ones that failed
+                        Err(_error) => {
+                            for (line, values) in &batch {
+                                let inserted = self
+                                    .prepare(&format!("{insert} {row_sql}"))?
+                                    .bind(values)?
+                                    .changes();
+                                match inserted {
+                                    Ok(_changes) => import.rows += 1,
+                                    Err(error) => skip(&mut import, *line, error)?,
+                                }
+                            }
+                        }
+                    }
+                    batch.clear();
+                }
+                if done {
+                    return Ok(import);
+                }
+            }
+        })
+    }
+
+    /// The declared type of each column of `table`, by name
+    fn declared_types(&self, table: &str) -> Result<Vec<(String, String)>> {
+        let (schema, table) = split_name(table);
+        self.prepare("select name, type from pragma_table_info(:table, :schema)")?
+            .bind(&[table.into(), schema.into()])?
+            .rows()?
+            .iter()
+            .map(|row| Ok((row.get("name")?, row.get("type")?)))
+            .collect()
+    }
+}
+
+/// Which type a field is converted to, from the declared type like sqlite's column affinity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Affinity {
+    Integer,
+    Text,
+    Blob,
+    Real,
+    Numeric,
+}
+
+impl Affinity {
+    fn of(declared: &str) -> Self {
+        let declared = declared.to_ascii_lowercase();
+        if declared.contains("int") {
+            Affinity::Integer
+        } else if ["char", "clob", "text"]
+            .iter()
+            .any(|ty| declared.contains(ty))
+        {
+            Affinity::Text
+        } else if declared.contains("blob") {
+            Affinity::Blob
+        } else if ["real", "floa", "doub"]
+            .iter()
+            .any(|ty| declared.contains(ty))
+        {
+            Affinity::Real
+        } else {
+            Affinity::Numeric
+        }
+    }
+}
+
+fn convert(columns: &[String], affinities: &[Affinity], fields: Vec<Field>) -> Result<Vec<Value>> {
+    if fields.len() != columns.len() {
+        return Err(Error::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("expected {} fields, found {}", columns.len(), fields.len()),
+        )));
+    }
+    columns
+        .iter()
+        .zip(affinities)
+        .zip(fields)
+        .map(|((column, affinity), field)| {
+            if field.text.is_empty() && !field.quoted {
+                return Ok(Value::Null);
+            }
+            let text = field.text.trim();
+            let invalid = |expected| Error::InvalidColumnType {
+                column: column.clone(),
+                expected,
+                found: "text",
+            };
+            Ok(match affinity {
+                Affinity::Integer => text.parse::<i64>().map_err(|_| invalid("integer"))?.into(),
+                Affinity::Real => text.parse::<f64>().map_err(|_| invalid("real"))?.into(),
+                Affinity::Numeric => match (text.parse::<i64>(), text.parse::<f64>()) {
+                    (Ok(value), _) => value.into(),
+                    (_, Ok(value)) => value.into(),
+                    _ => field.text.into(),
+                },
+                Affinity::Text => field.text.into(),
+                Affinity::Blob => field.text.into_bytes().into(),
+            })
+        })
+        .collect()
+}
+
+/// One field of a record, a quoted empty field is empty text instead of null
+#[derive(Debug)]
+struct Field {
+    text: String,
+    quoted: bool,
+}
+
+/// Splits rfc 4180 csv into records, a quoted field can hold delimiters, `""` and newlines
+struct Records<R> {
+    reader: R,
+    delimiter: u8,
+    /// The last line read so far
+    line: usize,
+}
+
+impl<R: BufRead> Records<R> {
+    fn new(reader: R, delimiter: u8) -> Self {
+        Self {
+            reader,
+            delimiter,
+            line: 0,
+        }
+    }
+
+    /// The next record that isn't a blank line with the line it starts on, the inner
+    /// error is a record that isn't valid csv
+    fn next(&mut self) -> Result<Option<(usize, Result<Vec<Field>>)>> {
+        loop {
+            let start = self.line + 1;
+            let mut bytes = vec![];
+            // a record goes on past the end of a line while a quote is open
+            while self.reader.read_until(b'\n', &mut bytes)? > 0 {
+                self.line += 1;
+                if bytes.iter().filter(|byte| **byte == b'"').count() % 2 == 0 {
+                    break;
+                }
+            }
+            if bytes.is_empty() {
+                return Ok(None);
+            }
+            if start == 1 && bytes.starts_with(b"\xef\xbb\xbf") {
+                bytes.drain(..3);
+            }
+            while matches!(bytes.last(), Some(b'\n' | b'\r')) {
+                bytes.pop();
+            }
+            if bytes.is_empty() {
+                continue;
+            }
+            return Ok(Some((start, self.split(&bytes))));
+        }
+    }
+
+    fn split(&self, bytes: &[u8]) -> Result<Vec<Field>> {
+        let invalid = |message: &str| {
+            Error::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                message.to_string(),
+            ))
+        };
+        let mut fields = vec![];
+        let mut ix = 0;
+        loop {
+            let mut text = vec![];
+            let quoted = bytes.get(ix) == Some(&b'"');
+            if quoted {
+                ix += 1;
+                loop {
+                    match (bytes.get(ix), bytes.get(ix + 1)) {
+                        (Some(b'"'), Some(b'"')) => {
+                            text.push(b'"');
+                            ix += 2;
+                        }
+                        (Some(b'"'), _) => {
+                            ix += 1;
+                            break;
+                        }
+                        (Some(byte), _) => {
+                            text.push(*byte);
+                            ix += 1;
+                        }
+                        (None, _) => return Err(invalid("unterminated quoted field")),
+                    }
+                }
+            }
+            while let Some(byte) = bytes.get(ix).filter(|byte| **byte != self.delimiter) {
+                if quoted {
+                    return Err(invalid("text after a quoted field"));
+                }
+                text.push(*byte);
+                ix += 1;
+            }
+            let text = String::from_utf8(text).map_err(|err| Error::Utf8Error(err.utf8_error()))?;
+            fields.push(Field { text, quoted });
+            if ix >= bytes.len() {
+                return Ok(fields);
+            }
+            // past the delimiter
+            ix += 1;
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use super::{Sqlite, Table, profile::quote, storage::split_name};
+use crate::{FromRow, Result, Row};
+
+/// The pages of one table or index summed from the `dbstat` table, see `Sqlite::dbstat`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DbStat {
+    /// The table or index
+    pub name: String,
+    /// The table `name` belongs to, `name` itself for a table
+    pub table: String,
+    pub is_index: bool,
+    pub pages: i64,
+    /// Rows or index entries in the leaf pages, plus the cells of the interior pages
+    pub cells: i64,
+    pub payload_bytes: i64,
+    /// Bytes of the pages that hold nothing, mostly left behind by deletes
+    pub unused_bytes: i64,
+    pub bytes: i64,
+    /// Levels of the b-tree, 1 when the root page holds everything
+    pub depth: i64,
+}
+
+impl DbStat {
+    /// The share of `bytes` that's unused, what a `vacuum` would give back at most
+    pub fn fragmentation(&self) -> f64 {
+        match self.bytes {
+            0 => 0.0,
+            bytes => self.unused_bytes as f64 / bytes as f64,
+        }
+    }
+}
+
+impl FromRow for DbStat {
+    fn from_row(row: &Row) -> Self {
+        let get = |column: &str| row.get::<i64>(column).unwrap_or_default();
+        Self {
+            name: row.get("name").unwrap_or_default(),
+            table: row.get("tbl_name").unwrap_or_default(),
+            is_index: row.get("is_index").unwrap_or_default(),
+            pages: get("pages"),
+            cells: get("cells"),
+            payload_bytes: get("payload_bytes"),
+            unused_bytes: get("unused_bytes"),
+            bytes: get("bytes"),
+            depth: get("depth"),
+        }
+    }
+}
+
+/// The unused bytes of one table and its indexes, see `Sqlite::fragmentation_report`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Fragmentation {
+    pub table: String,
+    pub bytes: i64,
+    pub unused_bytes: i64,
+}
+
+impl Fragmentation {
+    /// The share of `bytes` that's unused, what `Sqlite::rebuild_table` gives back at most
+    pub fn ratio(&self) -> f64 {
+        match self.bytes {
+            0 => 0.0,
+            bytes => self.unused_bytes as f64 / bytes as f64,
+        }
+    }
+}
+
+/// How much of the file one table and its indexes take, see `Sqlite::table_size`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TableSize {
+    /// bytes of the pages holding the rows
+    pub table_bytes: u64,
+    /// bytes of the pages holding the table's indexes
+    pub index_bytes: u64,
+}
+
+impl Sqlite {
+    /// Every table and index of the attached database `schema`, `main` for the main one,
+    /// read from the `dbstat` table sqlite builds with `SQLITE_ENABLE_DBSTAT_VTAB`.
+    /// It reads every page, so it takes as long as a scan of the whole file
+    pub fn dbstat(&self, schema: &str) -> Result<Vec<DbStat>> {
+        // the path of a page has a `/` per level, `/` for the root and `/000/` below it
+        let sql = format!(
+            "select
+              d.name,
+              coalesce(s.tbl_name, d.name) as tbl_name,
+              coalesce(s.type = 'index', 0) as is_index,
+              count(*) as pages,
+              sum(d.ncell) as cells,
+              sum(d.payload) as payload_bytes,
+              sum(d.unused) as unused_bytes,
+              sum(d.pgsize) as bytes,
+              max(length(d.path) - length(replace(d.path, '/', ''))) as depth
+            from dbstat(:schema) d
+            left join {}.sqlite_schema s on s.name = d.name
+            group by d.name
+            order by d.name",
+            quote(schema)
+        );
+        self.prepare(&sql)?.bind(&[schema.into()])?.rows_as()
+    }
+
+    /// `T` and its indexes from `dbstat`
+    pub fn table_stats<T: Table>(&self) -> Result<Vec<DbStat>> {
+        let (schema, table) = split_name(T::NAME);
+        Ok(self
+            .dbstat(schema)?
+            .into_iter()
+            .filter(|stat| stat.table.eq_ignore_ascii_case(table))
+            .collect())
+    }
+
+    /// Each table of `schema` with its indexes, the most unused bytes first, to pick
+    /// the tables worth a `rebuild_table`. Sqlite's own tables are left out
+    pub fn fragmentation_report(&self, schema: &str) -> Result<Vec<Fragmentation>> {
+        let mut report: Vec<Fragmentation> = vec![];
+        for stat in self.dbstat(schema)? {
+            if stat.table.starts_with("sqlite_") {
+                continue;
+            }
+            let ix = match report.iter().position(|table| table.table == stat.table) {
+                Some(ix) => ix,
+                None => {
+                    report.push(Fragmentation {
+                        table: stat.table.clone(),
+                        ..Default::default()
+                    });
+                    report.len() - 1
+                }
+            };
+            report[ix].bytes += stat.bytes;
+            report[ix].unused_bytes += stat.unused_bytes;
+        }
+        report.sort_by_key(|stat| std::cmp::Reverse(stat.unused_bytes));
+        Ok(report)
+    }
+
+    /// The bytes of the pages `T` and its indexes take, from `table_stats`
+    pub fn table_size<T: Table>(&self) -> Result<TableSize> {
+        let mut size = TableSize::default();
+        for stat in self.table_stats::<T>()? {
+            let bytes = stat.bytes.max(0) as u64;
+            match stat.is_index {
+                true => size.index_bytes += bytes,
+                false => size.table_bytes += bytes,
+            }
+        }
+        Ok(size)
+    }
+}
@@ -0,0 +1,152 @@
+use super::Sqlite;
+use crate::{Result, Row};
+
+/// A way a live database falls short of the schema it is expected to have
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaDiff {
+    MissingTable {
+        table: String,
+    },
+    MissingColumn {
+        table: String,
+        column: String,
+        ty: String,
+    },
+    TypeMismatch {
+        table: String,
+        column: String,
+        expected: String,
+        found: String,
+    },
+    MissingIndex {
+        table: String,
+        index: String,
+    },
+}
+
+impl std::fmt::Display for SchemaDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchemaDiff::MissingTable { table } => write!(f, "missing table {table}"),
+            SchemaDiff::MissingColumn { table, column, ty } => {
+                write!(f, "missing column {table}.{column} {ty}")
+            }
+            SchemaDiff::TypeMismatch {
+                table,
+                column,
+                expected,
+                found,
+            } => write!(f, "{table}.{column} is {found}, expected {expected}"),
+            SchemaDiff::MissingIndex { table, index } => {
+                write!(f, "missing index {index} on {table}")
+            }
+        }
+    }
+}
+
+impl Sqlite {
+    /// Compares the main schema of this database against `expected`, e.g. a fresh in-memory
+    /// database the declared migrations ran on. Extra tables, columns and indexes are fine,
+    /// and so are types with the same affinity like `INT` and `INTEGER`
+    pub fn schema_diff(&self, expected: &Sqlite) -> Result<Vec<SchemaDiff>> {
+        let mut diffs = vec![];
+        for table in expected.schema_names("table")? {
+            let Some(live_columns) = self.table_columns(&table)? else {
+                diffs.push(SchemaDiff::MissingTable { table });
+                continue;
+            };
+            for (column, ty) in expected.table_columns(&table)?.unwrap_or_default() {
+                match live_columns
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(&column))
+                {
+                    None => diffs.push(SchemaDiff::MissingColumn {
+                        table: table.clone(),
+                        column,
+                        ty,
+                    }),
+                    Some((_, found)) if affinity(found) != affinity(&ty) => {
+                        diffs.push(SchemaDiff::TypeMismatch {
+                            table: table.clone(),
+                            column,
+                            expected: ty,
+                            found: found.clone(),
+                        })
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+        let live_indexes = self.schema_names("index")?;
+        for index in expected.schema_names("index")? {
+            if !live_indexes
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(&index))
+            {
+                let table = expected.index_table(&index)?;
+                diffs.push(SchemaDiff::MissingIndex { table, index });
+            }
+        }
+        Ok(diffs)
+    }
+
+    /// Tables or indexes of the main schema, without sqlite's own and the migration bookkeeping
+    fn schema_names(&self, ty: &str) -> Result<Vec<String>> {
+        let rows = self
+            .prepare(
+                "select name from main.sqlite_schema where type = :type and sql is not null and name not like 'sqlite_%' and name != 'migrations' order by name",
+            )?
+            .bind(&[ty.into()])?
+            .collect_rows(None)?;
+        rows.iter().map(|row| row.get("name")).collect()
+    }
+
+    /// The name and declared type of each column, `None` when the table doesn't exist
+    fn table_columns(&self, table: &str) -> Result<Option<Vec<(String, String)>>> {
+        let rows = self
+            .prepare("select name, type from pragma_table_info(:table, 'main')")?
+            .bind(&[table.into()])?
+            .collect_rows(None)?;
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        let columns = rows
+            .iter()
+            .map(|row: &Row| Ok((row.get("name")?, row.get("type")?)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Some(columns))
+    }
+
+    fn index_table(&self, index: &str) -> Result<String> {
+        let rows = self
+            .prepare("select tbl_name from main.sqlite_schema where name = :name")?
+            .bind(&[index.into()])?
+            .collect_rows(None)?;
+        match rows.first() {
+            Some(row) => row.get("tbl_name"),
+            None => Ok(String::new()),
+        }
+    }
+}
+
+/// The column affinity sqlite derives from a declared type
+fn affinity(ty: &str) -> &'static str {
+    let ty = ty.to_uppercase();
+    if ty.contains("INT") {
+        "INTEGER"
+    } else if ["CHAR", "CLOB", "TEXT"]
+        .iter()
+        .any(|name| ty.contains(name))
+    {
+        "TEXT"
+    } else if ty.is_empty() || ty.contains("BLOB") {
+        "BLOB"
+    } else if ["REAL", "FLOA", "DOUB"]
+        .iter()
+        .any(|name| ty.contains(name))
+    {
+        "REAL"
+    } else {
+        "NUMERIC"
+    }
+}
@@ -0,0 +1,147 @@
+use super::Sqlite;
+use crate::{Result, ValueRef};
+use std::io::{BufWriter, Read, Write};
+
+impl Sqlite {
+    /// Streams the main database as a sql script like the sqlite shell's `.dump`, the
+    /// tables with their rows first, then the indexes, triggers and views, all in one
+    /// transaction. Fts tables with external content are rebuilt from it instead of copied,
+    /// the tables sqlite makes for them and `sqlite_stat1` are left out
+    pub fn dump(&self, writer: impl Write) -> Result<()> {
+        let mut writer = BufWriter::new(writer);
+        let entries = self
+            .prepare(
+                "select type, name, sql from main.sqlite_schema
+                where sql is not null
+                order by type != 'table', rowid",
+            )?
+            .rows()?;
+        let shadow = self
+            .prepare(
+                "select name from pragma_table_list where schema = 'main' and type = 'shadow'",
+            )?
+            .rows()?
+            .iter()
+            .map(|row| row.get::<String>("name"))
+            .collect::<Result<Vec<_>>>()?;
+        writeln!(writer, "PRAGMA foreign_keys=OFF;")?;
+        writeln!(writer, "BEGIN TRANSACTION;")?;
+        let mut rebuilds = vec![];
+        for entry in &entries {
+            let kind = entry.get::<String>("type")?;
+            let name = entry.get::<String>("name")?;
+            let sql = entry.get::<String>("sql")?;
+            if kind != "table" {
+                writeln!(writer, "{sql};")?;
+                continue;
+            }
+            if shadow.contains(&name) {
+                continue;
+            }
+            let lower = sql.to_ascii_lowercase();
+            let is_virtual = lower.starts_with("create virtual table");
+            match name.as_str() {
+                // made by the first autoincrement table, only its rows can be written
+                "sqlite_sequence" => writeln!(writer, "DELETE FROM sqlite_sequence;")?,
+                name if name.starts_with("sqlite_") => continue,
+                _ => writeln!(writer, "{sql};")?,
+            }
+            // contentless tables have nothing to copy or rebuild from
+            if is_virtual && lower.replace(' ', "").contains("content=''") {
+                continue;
+            }
+            if is_virtual && lower.contains("content=") {
+                rebuilds.push(name);
+                continue;
+            }
+            self.dump_rows(&mut writer, &name, is_virtual)?;
+        }
+        for name in rebuilds {
+            let name = quote_name(&name);
+            writeln!(writer, "INSERT INTO {name}({name}) VALUES('rebuild');")?;
+        }
+        match self.pragma_int("user_version")? {
+            0 => {}
+            version => writeln!(writer, "PRAGMA user_version={version};")?,
+        }
+        writeln!(writer, "COMMIT;")?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Runs a script from `dump`, read into memory first, on a database without the tables
+    /// it creates. A failed script is rolled back, and foreign keys are turned back on when
+    /// they were on before
+    pub fn restore(&self, mut reader: impl Read) -> Result<()> {
+        let mut sql = String::new();
+        let _len = reader.read_to_string(&mut sql)?;
+        let foreign_keys = self.pragma_int("foreign_keys")? == 1;
+        let in_transaction = self.in_transaction();
+        let result = self.execute(&sql);
+        if result.is_err() && !in_transaction && self.in_transaction() {
+            let _result = self.execute("rollback")?;
+        }
+        if foreign_keys {
+            let _result = self.execute("pragma foreign_keys = on")?;
+        }
+        result.map(|_| ())
+    }
+
+    /// An insert per row of `table`, naming the columns so generated ones are skipped.
+    /// Virtual tables keep their rowids, an fts index is matched up by them
+    fn dump_rows(&self, writer: &mut impl Write, table: &str, is_virtual: bool) -> Result<()> {
+        let mut columns = self
+            .prepare("select name from pragma_table_xinfo(:table, 'main') where hidden = 0")?
+            .bind(&[table.into()])?
+            .rows()?
+            .iter()
+            .map(|row| row.get::<String>("name").map(|name| quote_name(&name)))
+            .collect::<Result<Vec<_>>>()?;
+        if is_virtual {
+            columns.insert(0, "rowid".to_string());
+        }
+        let columns = columns.join(",");
+        let insert = format!("INSERT INTO {}({columns}) VALUES(", quote_name(table));
+        self.prepare(&format!("select {columns} from main.{}", quote_name(table)))?
+            .for_each_row(|row| {
+                writer.write_all(insert.as_bytes())?;
+                for (ix, value) in row.values().iter().enumerate() {
+                    if ix > 0 {
+                        writer.write_all(b",")?;
+                    }
+                    write_literal(writer, value)?;
+                }
+                writeln!(writer, ");")?;
+                Ok(())
+            })
+    }
+}
+
+/// A name as one quoted identifier, even with dots in it
+fn quote_name(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// `value` as a sql literal that reads back as the same value and type
+fn write_literal(writer: &mut impl Write, value: &ValueRef) -> std::io::Result<()> {
+    match value {
+        ValueRef::Int(value) => write!(writer, "{value}"),
+        ValueRef::Real(value) if value.is_nan() => writer.write_all(b"NULL"),
+        // past the largest double, sqlite reads these back as infinity
+        ValueRef::Real(value) if value.is_infinite() => {
+            writer.write_all(if *value > 0.0 { b"1e999" } else { b"-1e999" })
+        }
+        ValueRef::Real(value) => write!(writer, "{value:?}"),
+        // a nul would end the script early, the bytes are cast back to text instead
+        ValueRef::Text(text) if text.contains('\0') => {
+            write!(writer, "CAST(X'{}' AS TEXT)", hex(text.as_bytes()))
+        }
+        ValueRef::Text(text) => write!(writer, "'{}'", text.replace('\'', "''")),
+        ValueRef::Blob(value) => write!(writer, "X'{}'", hex(value)),
+        ValueRef::Null => writer.write_all(b"NULL"),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02X}")).collect()
+}
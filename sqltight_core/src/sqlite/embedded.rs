@@ -0,0 +1,72 @@
+use super::{Sqlite, sqlite_err};
+use crate::{Error, Result};
+use sqltight_ffi::{
+    SQLITE_DESERIALIZE_FREEONCLOSE, SQLITE_DESERIALIZE_READONLY, SQLITE_NOMEM, SQLITE_OK,
+    SQLITE_OPEN_CREATE, SQLITE_OPEN_FULLMUTEX, SQLITE_OPEN_READWRITE, sqlite3_deserialize,
+    sqlite3_malloc64,
+};
+
+impl Sqlite {
+    /// Opens a database file compiled into the binary, e.g. `include_bytes!("../assets/words.db")`,
+    /// read only and without copying it. Databases saved in wal mode are copied, see `open_bytes`
+    pub fn open_static_bytes(bytes: &'static [u8]) -> Result<Self> {
+        if is_wal(bytes) {
+            return Self::open_bytes(bytes);
+        }
+        let sqlite = Self::open_in_memory()?;
+        // sqlite never writes to a read only buffer and doesn't free it without FREEONCLOSE
+        sqlite.deserialize(
+            bytes.as_ptr().cast_mut(),
+            bytes.len(),
+            SQLITE_DESERIALIZE_READONLY,
+        )?;
+        Ok(sqlite)
+    }
+
+    /// Opens a read only in-memory copy of a database file, e.g. one read out of an asset archive
+    pub fn open_bytes(bytes: &[u8]) -> Result<Self> {
+        let sqlite = Self::open_in_memory()?;
+        let data = unsafe { sqlite3_malloc64(bytes.len().max(1) as u64) } as *mut u8;
+        if data.is_null() {
+            return Err(sqlite_err(SQLITE_NOMEM, core::ptr::null_mut()));
+        }
+        unsafe { core::ptr::copy_nonoverlapping(bytes.as_ptr(), data, bytes.len()) };
+        // the in-memory vfs can't read wal, the rollback journal header reads the same pages
+        if is_wal(bytes) {
+            unsafe {
+                *data.add(18) = 1;
+                *data.add(19) = 1;
+            }
+        }
+        // sqlite frees the copy, even when deserializing fails
+        sqlite.deserialize(
+            data,
+            bytes.len(),
+            SQLITE_DESERIALIZE_READONLY | SQLITE_DESERIALIZE_FREEONCLOSE,
+        )?;
+        Ok(sqlite)
+    }
+
+    fn open_in_memory() -> Result<Self> {
+        Self::open_v2(
+            ":memory:",
+            SQLITE_OPEN_READWRITE | SQLITE_OPEN_CREATE | SQLITE_OPEN_FULLMUTEX,
+        )
+    }
+
+    fn deserialize(&self, data: *mut u8, len: usize, flags: i32) -> Result<()> {
+        let len = i64::try_from(len).map_err(Error::TryFromInt)?;
+        let result = unsafe {
+            sqlite3_deserialize(self.db(), c"main".as_ptr(), data, len, len, flags as u32)
+        };
+        match result {
+            SQLITE_OK => Ok(()),
+            code => Err(sqlite_err(code, self.db())),
+        }
+    }
+}
+
+/// Bytes 18 and 19 of the header are 2 for wal databases
+fn is_wal(bytes: &[u8]) -> bool {
+    bytes.len() > 19 && bytes[18] == 2 && bytes[19] == 2
+}
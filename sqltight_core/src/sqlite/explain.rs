@@ -0,0 +1,129 @@
+use super::Stmt;
+use crate::{Result, Row};
+use std::fmt::{Display, Formatter};
+
+/// One step of a query plan, a row of `explain query plan` with the steps under it
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanNode {
+    pub id: i64,
+    /// The `id` of the step this one is part of, 0 for the top steps
+    pub parent: i64,
+    /// e.g. `SEARCH Post USING INDEX Post_user_id_ix (user_id=?)`
+    pub detail: String,
+    pub children: Vec<PlanNode>,
+}
+
+/// The plan sqlite picks for a statement as a tree, see `Stmt::explain`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct QueryPlan {
+    /// The top steps, in the order they run
+    pub nodes: Vec<PlanNode>,
+}
+
+impl QueryPlan {
+    /// Every step, depth first
+    pub fn steps(&self) -> Vec<&PlanNode> {
+        fn walk<'a>(nodes: &'a [PlanNode], steps: &mut Vec<&'a PlanNode>) {
+            for node in nodes {
+                steps.push(node);
+                walk(&node.children, steps);
+            }
+        }
+        let mut steps = vec![];
+        walk(&self.nodes, &mut steps);
+        steps
+    }
+
+    /// Whether a step looks rows up with an index or the primary key instead of reading
+    /// them all. An automatic index sqlite builds for the one statement doesn't count
+    pub fn uses_index(&self) -> bool {
+        self.steps()
+            .iter()
+            .any(|step| step.detail.starts_with("SEARCH ") && !step.detail.contains(" AUTOMATIC "))
+    }
+
+    /// The indexes the steps read, searched or scanned, in order
+    pub fn index_names(&self) -> Vec<&str> {
+        self.steps()
+            .iter()
+            .filter_map(|step| {
+                let words = step.detail.split_whitespace().collect::<Vec<_>>();
+                let ix = words.iter().position(|word| *word == "INDEX")?;
+                match words.get(ix.checked_sub(1)?) {
+                    Some(&"AUTOMATIC") => None,
+                    _ => words.get(ix + 1).copied(),
+                }
+            })
+            .collect()
+    }
+
+    /// The tables read row by row, `SCAN Post` and `SCAN Post USING INDEX ...` both read
+    /// every row
+    pub fn full_scans(&self) -> Vec<&str> {
+        self.steps()
+            .iter()
+            .filter_map(|step| step.detail.strip_prefix("SCAN "))
+            .filter_map(|rest| rest.split_whitespace().next())
+            .filter(|table| *table != "CONSTANT" && !table.starts_with('('))
+            .collect()
+    }
+}
+
+/// The tree the sqlite shell prints for `.eqp on`
+impl Display for QueryPlan {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fn write_nodes(
+            f: &mut Formatter<'_>,
+            nodes: &[PlanNode],
+            indent: &str,
+        ) -> std::fmt::Result {
+            for (ix, node) in nodes.iter().enumerate() {
+                let last = ix + 1 == nodes.len();
+                writeln!(
+                    f,
+                    "{indent}{}{}",
+                    if last { "`--" } else { "|--" },
+                    node.detail
+                )?;
+                let indent = format!("{indent}{}", if last { "   " } else { "|  " });
+                write_nodes(f, &node.children, &indent)?;
+            }
+            Ok(())
+        }
+        writeln!(f, "QUERY PLAN")?;
+        write_nodes(f, &self.nodes, "")
+    }
+}
+
+impl Stmt {
+    /// The `explain query plan` of this statement as a tree. Params don't need to be bound,
+    /// the plan doesn't depend on their values
+    #[track_caller]
+    pub fn explain(&self) -> Result<QueryPlan> {
+        let sql = format!("explain query plan {}", self.sql());
+        let rows = Stmt::prepare(&self.connection, &sql, std::ptr::null_mut())?.rows()?;
+        let nodes = plan_nodes(&rows, 0)?;
+        Ok(QueryPlan { nodes })
+    }
+}
+
+fn plan_nodes(rows: &[Row], parent: i64) -> Result<Vec<PlanNode>> {
+    let mut nodes = vec![];
+    for row in rows {
+        if row.get::<i64>("parent")? != parent {
+            continue;
+        }
+        let id = row.get::<i64>("id")?;
+        // a step can't be its own parent, it would never end
+        if id == parent {
+            continue;
+        }
+        nodes.push(PlanNode {
+            id,
+            parent,
+            detail: row.get("detail")?,
+            children: plan_nodes(rows, id)?,
+        });
+    }
+    Ok(nodes)
+}
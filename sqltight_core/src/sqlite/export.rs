@@ -0,0 +1,109 @@
+use super::Stmt;
+use crate::{Result, ValueRef};
+use std::io::{BufWriter, Write};
+
+impl Stmt {
+    /// Streams the rows as csv with a header of the column names, one row at a time
+    /// without collecting them. Nulls are empty fields and empty text is `""`, the way
+    /// `Sqlite::import_csv` tells them apart, blobs are hex like sqlite's `hex()`.
+    /// Returns the number of rows written
+    #[track_caller]
+    pub fn write_csv(&self, writer: impl Write) -> Result<usize> {
+        let mut writer = BufWriter::new(writer);
+        let columns = self.select_column_names();
+        let mut rows = 0;
+        // written with the first row, a statement that fails to step writes nothing
+        let header = |writer: &mut BufWriter<_>| -> std::io::Result<()> {
+            for (ix, column) in columns.iter().enumerate() {
+                write_csv_value(writer, ix, &ValueRef::Text(column.into()))?;
+            }
+            writeln!(writer)
+        };
+        self.for_each_row(|row| {
+            if rows == 0 {
+                header(&mut writer)?;
+            }
+            for (ix, value) in row.values().iter().enumerate() {
+                write_csv_value(&mut writer, ix, value)?;
+            }
+            writeln!(writer)?;
+            rows += 1;
+            Ok(())
+        })?;
+        if rows == 0 {
+            header(&mut writer)?;
+        }
+        writer.flush()?;
+        Ok(rows)
+    }
+
+    /// Streams the rows as a json array with an object per row, keyed by column name.
+    /// Blobs are hex strings and reals that aren't finite are null. Returns the number
+    /// of rows written
+    #[track_caller]
+    pub fn write_json(&self, writer: impl Write) -> Result<usize> {
+        let mut writer = BufWriter::new(writer);
+        let mut rows = 0;
+        self.for_each_row(|row| {
+            writer.write_all(if rows == 0 { b"[\n{" } else { b",\n{" })?;
+            for (ix, (column, value)) in row.columns().iter().zip(row.values()).enumerate() {
+                if ix > 0 {
+                    writer.write_all(b",")?;
+                }
+                write_json_string(&mut writer, column)?;
+                writer.write_all(b":")?;
+                match value {
+                    ValueRef::Int(value) => write!(writer, "{value}")?,
+                    ValueRef::Real(value) if value.is_finite() => write!(writer, "{value:?}")?,
+                    ValueRef::Text(value) => write_json_string(&mut writer, value)?,
+                    ValueRef::Blob(value) => write!(writer, "\"{}\"", hex(value))?,
+                    ValueRef::Real(_) | ValueRef::Null => writer.write_all(b"null")?,
+                }
+            }
+            writer.write_all(b"}")?;
+            rows += 1;
+            Ok(())
+        })?;
+        writer.write_all(if rows == 0 { b"[]\n" } else { b"\n]\n" })?;
+        writer.flush()?;
+        Ok(rows)
+    }
+}
+
+/// The `ix`th field of a record, quoted when it holds a delimiter, quote or newline, or
+/// is empty text
+fn write_csv_value(writer: &mut impl Write, ix: usize, value: &ValueRef) -> std::io::Result<()> {
+    if ix > 0 {
+        writer.write_all(b",")?;
+    }
+    match value {
+        ValueRef::Int(value) => write!(writer, "{value}"),
+        ValueRef::Real(value) => write!(writer, "{value:?}"),
+        ValueRef::Text(text) if text.is_empty() || text.contains([',', '"', '\n', '\r']) => {
+            write!(writer, "\"{}\"", text.replace('"', "\"\""))
+        }
+        ValueRef::Text(text) => writer.write_all(text.as_bytes()),
+        ValueRef::Blob(value) => writer.write_all(hex(value).as_bytes()),
+        ValueRef::Null => Ok(()),
+    }
+}
+
+fn write_json_string(writer: &mut impl Write, text: &str) -> std::io::Result<()> {
+    writer.write_all(b"\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => writer.write_all(b"\\\"")?,
+            '\\' => writer.write_all(b"\\\\")?,
+            '\n' => writer.write_all(b"\\n")?,
+            '\r' => writer.write_all(b"\\r")?,
+            '\t' => writer.write_all(b"\\t")?,
+            c if (c as u32) < 0x20 => write!(writer, "\\u{:04x}", c as u32)?,
+            c => writer.write_all(c.encode_utf8(&mut [0; 4]).as_bytes())?,
+        }
+    }
+    writer.write_all(b"\"")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02X}")).collect()
+}
@@ -0,0 +1,102 @@
+use super::Sqlite;
+use crate::{Result, int};
+
+/// One problem `Sqlite::integrity_check` found in the file
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntegrityError {
+    /// The table or index it's in, when sqlite names one
+    pub table: Option<String>,
+    pub rowid: Option<i64>,
+    /// Sqlite's message, e.g. `row 5 missing from index post_user_id`
+    pub description: String,
+}
+
+/// A row whose foreign key has no parent row, see `Sqlite::foreign_key_check`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForeignKeyViolation {
+    pub table: String,
+    /// `None` for without rowid tables
+    pub rowid: Option<i64>,
+    /// The table the foreign key references
+    pub parent: String,
+    /// The foreign key, e.g. `user_id references User(id)`
+    pub description: String,
+}
+
+impl Sqlite {
+    /// Reads every page, index and constraint of the file like `pragma integrity_check`,
+    /// stopping after `max_errors` problems. Empty when the file is fine
+    pub fn integrity_check(&self, max_errors: usize) -> Result<Vec<IntegrityError>> {
+        // sqlite reads a count past a c int as the name of a table to check
+        let max_errors = int(i32::try_from(max_errors).unwrap_or(i32::MAX).max(1).into());
+        let mut errors = vec![];
+        for row in self
+            .prepare("select * from pragma_integrity_check(:max_errors)")?
+            .bind(&[max_errors.into()])?
+            .rows()?
+        {
+            let description = row.get::<String>(0)?;
+            if description != "ok" {
+                errors.push(integrity_error(description));
+            }
+        }
+        Ok(errors)
+    }
+
+    /// Every row of the main database whose foreign key points at a missing row, like
+    /// `pragma foreign_key_check`. Runs whether or not foreign keys are on
+    pub fn foreign_key_check(&self) -> Result<Vec<ForeignKeyViolation>> {
+        self.prepare(
+            "select c.\"table\", c.rowid, c.parent,
+              group_concat(l.\"from\", ', ') as from_columns,
+              group_concat(coalesce(l.\"to\", ''), ', ') as to_columns
+            from pragma_foreign_key_check c
+            join pragma_foreign_key_list(c.\"table\") l on l.id = c.fkid
+            group by c.\"table\", c.rowid, c.parent, c.fkid
+            order by c.\"table\", c.rowid, c.fkid",
+        )?
+        .rows()?
+        .iter()
+        .map(|row| {
+            let parent = row.get::<String>("parent")?;
+            let from = row.get::<String>("from_columns")?;
+            // without `to` columns the key references the parent's primary key
+            let to = row.get::<String>("to_columns")?;
+            let description = match to.trim_matches([',', ' ']).is_empty() {
+                true => format!("{from} references {parent}"),
+                false => format!("{from} references {parent}({to})"),
+            };
+            Ok(ForeignKeyViolation {
+                table: row.get("table")?,
+                rowid: row.get("rowid")?,
+                parent,
+                description,
+            })
+        })
+        .collect()
+    }
+}
+
+/// Picks the row and the table or index out of messages like `row 5 missing from index i`,
+/// `NULL value in Post.user_id` or `non-unique entry in index i`
+fn integrity_error(description: String) -> IntegrityError {
+    let words = description.split_whitespace().collect::<Vec<_>>();
+    let rowid = match words.as_slice() {
+        ["row", rowid, ..] => rowid.parse().ok(),
+        _ => None,
+    };
+    let table = words
+        .windows(2)
+        .rev()
+        .find(|pair| matches!(pair[0], "index" | "in") && pair[1] != "database")
+        .map(|pair| match pair[0] {
+            "index" => pair[1].to_string(),
+            // `in Post.user_id` names a column
+            _ => pair[1].split('.').next().unwrap_or(pair[1]).to_string(),
+        });
+    IntegrityError {
+        table,
+        rowid,
+        description,
+    }
+}
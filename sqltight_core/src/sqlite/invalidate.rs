@@ -0,0 +1,186 @@
+use super::Sqlite;
+use crate::{Error, Result};
+use sqltight_ffi::{
+    sqlite3_commit_hook, sqlite3_int64, sqlite3_rollback_hook, sqlite3_update_hook,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::{CStr, c_char, c_int, c_void},
+    hash::Hash,
+    sync::{Arc, Mutex},
+};
+
+/// A cache entries can be dropped from by key. Implemented for `Mutex<HashMap>`, and for
+/// moka's sync cache and `Mutex<lru::LruCache>` behind the `moka` and `lru` features
+pub trait InvalidateCache<K>: Send + Sync {
+    fn invalidate(&self, key: &K);
+}
+
+impl<K: Hash + Eq + Send, V: Send> InvalidateCache<K> for Mutex<HashMap<K, V>> {
+    fn invalidate(&self, key: &K) {
+        if let Ok(mut cache) = self.lock() {
+            cache.remove(key);
+        }
+    }
+}
+
+impl<K, C: InvalidateCache<K>> InvalidateCache<K> for Arc<C> {
+    fn invalidate(&self, key: &K) {
+        C::invalidate(self, key)
+    }
+}
+
+#[cfg(feature = "moka")]
+impl<K, V, S> InvalidateCache<K> for moka::sync::Cache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: std::hash::BuildHasher + Clone + Send + Sync + 'static,
+{
+    fn invalidate(&self, key: &K) {
+        moka::sync::Cache::invalidate(self, key)
+    }
+}
+
+#[cfg(feature = "lru")]
+impl<K: Hash + Eq + Send, V: Send> InvalidateCache<K> for Mutex<lru::LruCache<K, V>> {
+    fn invalidate(&self, key: &K) {
+        if let Ok(mut cache) = self.lock() {
+            cache.pop(key);
+        }
+    }
+}
+
+type KeysFn<K> = Box<dyn Fn(i64) -> Vec<K> + Send>;
+
+/// Maps tables to the cache keys that go stale when their rows change,
+/// see `Sqlite::invalidate_caches`
+pub struct CacheInvalidator<K> {
+    cache: Box<dyn InvalidateCache<K>>,
+    tables: HashMap<String, Vec<KeysFn<K>>>,
+    pending: HashSet<K>,
+}
+
+impl<K: Hash + Eq + Clone + Send + 'static> CacheInvalidator<K> {
+    pub fn new(cache: impl InvalidateCache<K> + 'static) -> Self {
+        Self {
+            cache: Box::new(cache),
+            tables: HashMap::new(),
+            pending: HashSet::new(),
+        }
+    }
+
+    /// Drops `keys` whenever any row of `table` changes, e.g. the keys of list queries
+    pub fn table(self, table: &str, keys: impl IntoIterator<Item = K>) -> Self {
+        let keys = keys.into_iter().collect::<Vec<_>>();
+        self.keys(table, move |_rowid| keys.clone())
+    }
+
+    /// Drops the key built from the id of each changed row, e.g. `format!("user:{id}")`
+    pub fn rows(self, table: &str, key: impl Fn(i64) -> K + Send + 'static) -> Self {
+        self.keys(table, move |rowid| vec![key(rowid)])
+    }
+
+    fn keys(mut self, table: &str, keys: impl Fn(i64) -> Vec<K> + Send + 'static) -> Self {
+        self.tables
+            .entry(table.to_lowercase())
+            .or_default()
+            .push(Box::new(keys));
+        self
+    }
+}
+
+/// The type erased side of a `CacheInvalidator` the hooks call into
+pub(crate) trait Invalidation: Send {
+    fn changed(&mut self, table: &str, rowid: i64);
+    fn committed(&mut self);
+    fn rolled_back(&mut self);
+}
+
+impl<K: Hash + Eq + Send> Invalidation for CacheInvalidator<K> {
+    fn changed(&mut self, table: &str, rowid: i64) {
+        if let Some(mappings) = self.tables.get(&table.to_lowercase()) {
+            for keys in mappings {
+                self.pending.extend(keys(rowid));
+            }
+        }
+    }
+
+    fn committed(&mut self) {
+        for key in self.pending.drain() {
+            self.cache.invalidate(&key);
+        }
+    }
+
+    fn rolled_back(&mut self) {
+        self.pending.clear();
+    }
+}
+
+pub(crate) type InvalidationHandler = Box<dyn Invalidation>;
+
+impl Sqlite {
+    /// Collects the keys of rows changed on this connection and drops them from the cache
+    /// as their transaction commits, or forgets them when it rolls back.
+    /// Writes from other connections and processes aren't seen
+    pub fn invalidate_caches<K: Hash + Eq + Clone + Send + 'static>(
+        &self,
+        invalidator: CacheInvalidator<K>,
+    ) -> Result<()> {
+        let mut invalidation = self
+            .connection
+            .invalidation
+            .lock()
+            .map_err(|_| Error::MutexLockFailed)?;
+        let mut handler: Box<InvalidationHandler> = Box::new(Box::new(invalidator));
+        let handler_ptr = &mut *handler as *mut InvalidationHandler as *mut c_void;
+        unsafe {
+            sqlite3_update_hook(self.db(), Some(update_trampoline), handler_ptr);
+            sqlite3_commit_hook(self.db(), Some(commit_trampoline), handler_ptr);
+            sqlite3_rollback_hook(self.db(), Some(rollback_trampoline), handler_ptr);
+        }
+        *invalidation = Some(handler);
+        Ok(())
+    }
+
+    pub fn clear_cache_invalidation(&self) -> Result<()> {
+        let mut invalidation = self
+            .connection
+            .invalidation
+            .lock()
+            .map_err(|_| Error::MutexLockFailed)?;
+        unsafe {
+            sqlite3_update_hook(self.db(), None, core::ptr::null_mut());
+            sqlite3_commit_hook(self.db(), None, core::ptr::null_mut());
+            sqlite3_rollback_hook(self.db(), None, core::ptr::null_mut());
+        }
+        *invalidation = None;
+        Ok(())
+    }
+}
+
+unsafe extern "C" fn update_trampoline(
+    handler: *mut c_void,
+    _op: c_int,
+    _db_name: *const c_char,
+    table: *const c_char,
+    rowid: sqlite3_int64,
+) {
+    let handler = unsafe { &mut *(handler as *mut InvalidationHandler) };
+    let table = unsafe { CStr::from_ptr(table) }.to_string_lossy();
+    let _result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        handler.changed(&table, rowid)
+    }));
+}
+
+unsafe extern "C" fn commit_trampoline(handler: *mut c_void) -> c_int {
+    let handler = unsafe { &mut *(handler as *mut InvalidationHandler) };
+    let _result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| handler.committed()));
+    // non-zero would turn the commit into a rollback
+    0
+}
+
+unsafe extern "C" fn rollback_trampoline(handler: *mut c_void) {
+    let handler = unsafe { &mut *(handler as *mut InvalidationHandler) };
+    let _result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| handler.rolled_back()));
+}
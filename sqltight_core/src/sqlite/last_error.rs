@@ -0,0 +1,50 @@
+use super::{Connection, Sqlite};
+use sqltight_ffi::{sqlite3_errmsg, sqlite3_extended_errcode};
+use std::ffi::CStr;
+
+/// The last statement sqlite failed on a connection, see `Sqlite::last_error`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LastError {
+    pub code: i32,
+    pub extended_code: i32,
+    pub message: String,
+    pub sql: String,
+}
+
+impl Connection {
+    /// Keeps sqlite's error for `sql`, read right away since the next call on the
+    /// connection replaces it
+    pub(crate) fn set_last_error(&self, code: i32, sql: &str) {
+        self.count_busy_error(code);
+        let message = unsafe { CStr::from_ptr(sqlite3_errmsg(self.db)) }
+            .to_string_lossy()
+            .into_owned();
+        let extended_code = unsafe { sqlite3_extended_errcode(self.db) };
+        if let Ok(mut last_error) = self.last_error.lock() {
+            *last_error = Some(LastError {
+                code,
+                extended_code,
+                message,
+                sql: sql.to_string(),
+            });
+        }
+    }
+}
+
+impl Sqlite {
+    /// The error of the last statement that failed to prepare or run on this connection,
+    /// it stays until the next failure or `clear_last_error`
+    pub fn last_error(&self) -> Option<LastError> {
+        self.connection
+            .last_error
+            .lock()
+            .ok()
+            .and_then(|last_error| last_error.clone())
+    }
+
+    pub fn clear_last_error(&self) {
+        if let Ok(mut last_error) = self.connection.last_error.lock() {
+            *last_error = None;
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use super::{Sqlite, Stmt};
+use crate::Result;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::{
+        Arc, Weak,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+type Opener = dyn Fn() -> Result<Sqlite> + Send + Sync;
+
+/// Prepared statements cached per thread, so threads never wait on each other for a statement.
+/// A pooled cache also prepares them on a connection of their own per thread
+pub struct StatementCache {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    id: u64,
+    generation: AtomicU64,
+    open: Option<Box<Opener>>,
+}
+
+/// What one thread holds for one cache
+struct ThreadCache {
+    shared: Weak<Shared>,
+    generation: u64,
+    connection: Option<Sqlite>,
+    statements: HashMap<&'static str, Stmt>,
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static CACHES: RefCell<HashMap<u64, ThreadCache>> = RefCell::new(HashMap::new());
+}
+
+impl StatementCache {
+    /// Caches statements prepared on the connection passed to `get`, `statements` are
+    /// cached for the calling thread
+    pub fn new(statements: impl IntoIterator<Item = (&'static str, Stmt)>) -> Result<Self> {
+        let cache = Self::with_opener(None);
+        cache.with_thread(|thread| -> Result<()> {
+            for (name, stmt) in statements {
+                // only this cache hands it out again, `Sqlite::prepare` could while it's in use
+                stmt.uncache()?;
+                thread.statements.insert(name, stmt);
+            }
+            Ok(())
+        })?;
+        Ok(cache)
+    }
+
+    /// Opens a connection with `open` the first time a thread needs a statement, and prepares
+    /// that thread's statements on it. Only useful for file databases, `:memory:` is one database per connection
+    pub fn pooled(open: impl Fn() -> Result<Sqlite> + Send + Sync + 'static) -> Self {
+        Self::with_opener(Some(Box::new(open)))
+    }
+
+    fn with_opener(open: Option<Box<Opener>>) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+                generation: AtomicU64::new(0),
+                open,
+            }),
+        }
+    }
+
+    pub fn is_pooled(&self) -> bool {
+        self.shared.open.is_some()
+    }
+
+    /// This thread's statement for `name`, prepared from `sql` when it isn't cached yet.
+    /// While the cached one is still in use, e.g. by an unfinished iterator, another one is
+    /// prepared. While a transaction is open on `connection` a pooled cache prepares on
+    /// `connection` too, the transaction's writes can't be seen from any other connection
+    pub fn get(&self, connection: &Sqlite, name: &'static str, sql: &str) -> Result<Stmt> {
+        if self.is_pooled() && connection.in_transaction() {
+            return connection.prepare(sql);
+        }
+        self.with_thread(|thread| {
+            if thread.connection.is_none() {
+                thread.connection = self.shared.open.as_ref().map(|open| open()).transpose()?;
+            }
+            let connection = thread.connection.as_ref().unwrap_or(connection);
+            match thread.statements.get(name) {
+                Some(stmt) => match stmt.reuse() {
+                    Some(stmt) => Ok(stmt),
+                    None => Stmt::prepare(&connection.connection, sql, core::ptr::null_mut()),
+                },
+                None => {
+                    let stmt = Stmt::prepare(&connection.connection, sql, core::ptr::null_mut())?;
+                    let shared = Stmt::new(&stmt.connection, stmt.stmt.clone());
+                    thread.statements.insert(name, stmt);
+                    Ok(shared)
+                }
+            }
+        })
+    }
+
+    /// Makes this thread's next reads see every write committed so far. In WAL mode a pooled
+    /// connection already reads the latest commit with each statement, it only misses writes
+    /// while it holds an older snapshot, e.g. when a query reads from inside another query's
+    /// rows. That connection is left to the statements still using it and this thread's next
+    /// reads open a new one
+    pub fn read_after_write(&self) {
+        self.with_thread(|thread| {
+            if thread
+                .connection
+                .as_ref()
+                .is_some_and(Sqlite::holds_snapshot)
+            {
+                thread.statements.clear();
+                thread.connection = None;
+            }
+        });
+    }
+
+    /// Drops the statements of every thread, each thread prepares them again on next use
+    pub fn clear(&self) {
+        self.shared.generation.fetch_add(1, Ordering::Release);
+    }
+
+    fn with_thread<T>(&self, f: impl FnOnce(&mut ThreadCache) -> T) -> T {
+        let generation = self.shared.generation.load(Ordering::Acquire);
+        CACHES.with(|caches| {
+            let mut caches = caches.borrow_mut();
+            if !caches.contains_key(&self.shared.id) {
+                // drops what dropped caches left behind on this thread, connections included
+                caches.retain(|_, thread| thread.shared.strong_count() > 0);
+            }
+            let thread = caches.entry(self.shared.id).or_insert_with(|| ThreadCache {
+                shared: Arc::downgrade(&self.shared),
+                generation,
+                connection: None,
+                statements: HashMap::new(),
+            });
+            if thread.generation != generation {
+                thread.statements.clear();
+                thread.generation = generation;
+            }
+            f(thread)
+        })
+    }
+}
+
+impl Drop for StatementCache {
+    fn drop(&mut self) {
+        let id = self.shared.id;
+        let _result = CACHES.try_with(|caches| {
+            let removed = caches.borrow_mut().remove(&id);
+            drop(removed);
+        });
+    }
+}
+
+impl std::fmt::Debug for StatementCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StatementCache")
+            .field("pooled", &self.is_pooled())
+            .finish()
+    }
+}
@@ -0,0 +1,113 @@
+use super::{RawStmt, Sqlite, Stmt};
+use crate::{Error, Result};
+use std::sync::Arc;
+
+/// How many statements `Sqlite::prepare` keeps by default
+pub const STATEMENT_CACHE_CAPACITY: usize = 64;
+
+/// The statements `Sqlite::prepare` keeps by their sql, least recently used first
+#[derive(Debug)]
+pub(crate) struct PreparedCache {
+    capacity: usize,
+    statements: Vec<(String, Arc<RawStmt>)>,
+}
+
+impl Default for PreparedCache {
+    fn default() -> Self {
+        Self {
+            capacity: STATEMENT_CACHE_CAPACITY,
+            statements: vec![],
+        }
+    }
+}
+
+impl PreparedCache {
+    /// The statement for `sql` when it's cached and no `Stmt` is using it,
+    /// so two callers never bind the same statement
+    fn get(&mut self, sql: &str) -> Option<Arc<RawStmt>> {
+        let ix = self
+            .statements
+            .iter()
+            .position(|(cached, _stmt)| cached == sql)?;
+        let entry = self.statements.remove(ix);
+        let stmt = (Arc::strong_count(&entry.1) == 1).then(|| entry.1.clone());
+        self.statements.push(entry);
+        stmt
+    }
+
+    fn insert(&mut self, sql: &str, stmt: Arc<RawStmt>) {
+        if self.capacity == 0 || self.statements.iter().any(|(cached, _stmt)| cached == sql) {
+            return;
+        }
+        if self.statements.len() >= self.capacity {
+            self.statements.remove(0);
+        }
+        self.statements.push((sql.to_string(), stmt));
+    }
+
+    fn remove(&mut self, stmt: &Arc<RawStmt>) {
+        self.statements
+            .retain(|(_sql, cached)| !Arc::ptr_eq(cached, stmt));
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        let excess = self.statements.len().saturating_sub(capacity);
+        self.statements.drain(..excess);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.statements.clear();
+    }
+}
+
+impl Sqlite {
+    /// Prepares `sql`, or reuses the statement prepared for it before while it is one of the
+    /// `set_statement_cache_capacity` most recently prepared. A statement still held
+    /// elsewhere isn't reused, `sql` is prepared again instead
+    pub fn prepare(&self, sql: &str) -> Result<Stmt> {
+        let cached = self.prepared()?.get(sql);
+        if let Some(stmt) = cached {
+            let stmt = Stmt::new(&self.connection, stmt);
+            // a statement that was bound and dropped without running still has its params
+            let _result = stmt.reset();
+            return Ok(stmt);
+        }
+        // not locked while preparing, an authorizer may prepare statements of its own
+        let stmt = Stmt::prepare(&self.connection, sql, core::ptr::null_mut())?;
+        self.prepared()?.insert(sql, stmt.stmt.clone());
+        Ok(stmt)
+    }
+
+    /// How many statements `prepare` keeps, `STATEMENT_CACHE_CAPACITY` unless it's set,
+    /// 0 prepares every statement again
+    pub fn set_statement_cache_capacity(&self, capacity: usize) -> Result<()> {
+        self.prepared()?.set_capacity(capacity);
+        Ok(())
+    }
+
+    /// Finalizes the statements `prepare` kept, the ones still in use stay until they're dropped
+    pub fn clear_statement_cache(&self) -> Result<()> {
+        self.prepared()?.clear();
+        Ok(())
+    }
+
+    fn prepared(&self) -> Result<std::sync::MutexGuard<'_, PreparedCache>> {
+        self.connection
+            .prepared
+            .lock()
+            .map_err(|_| Error::MutexLockFailed)
+    }
+}
+
+impl Stmt {
+    /// Takes this statement out of the ones `Sqlite::prepare` kept, for a cache that shares it itself
+    pub(crate) fn uncache(&self) -> Result<()> {
+        self.connection
+            .prepared
+            .lock()
+            .map_err(|_| Error::MutexLockFailed)?
+            .remove(&self.stmt);
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+use super::Sqlite;
+use crate::{Result, Value};
+
+/// A declared table and its columns in order, `db!` implements it for each table struct
+pub trait Table {
+    const NAME: &'static str;
+    const COLUMNS: &'static [&'static str];
+    /// `soft_delete` tables, whose rows with `deleted_at` set are left out of reads
+    const SOFT_DELETE: bool = false;
+}
+
+/// What the rows of a table hold in one column, see `Sqlite::profile_table`
+#[derive(Debug, Clone)]
+pub struct ColumnProfile {
+    pub column: String,
+    pub rows: i64,
+    pub nulls: i64,
+    pub distinct: i64,
+    pub min: Value,
+    pub max: Value,
+    /// The average `length()` of the non-null values, characters for text and bytes for blobs
+    pub avg_length: Option<f64>,
+}
+
+impl Sqlite {
+    /// Null and distinct counts, min, max and average length of each column,
+    /// computed in a single scan of `table`
+    pub fn profile_table(&self, table: &str, columns: &[&str]) -> Result<Vec<ColumnProfile>> {
+        let aggregates = columns
+            .iter()
+            .map(|column| {
+                let column = quote(column);
+                format!(
+                    ", count(*) - count({column}), count(distinct {column}), min({column}), max({column}), avg(length({column}))"
+                )
+            })
+            .collect::<String>();
+        let sql = format!("select count(*){aggregates} from {}", quote(table));
+        let rows = self.prepare(&sql)?.rows()?;
+        let Some(row) = rows.first() else {
+            return Ok(vec![]);
+        };
+        let total = row.get(0)?;
+        columns
+            .iter()
+            .enumerate()
+            .map(|(ix, column)| {
+                let offset = 1 + ix * 5;
+                Ok(ColumnProfile {
+                    column: column.to_string(),
+                    rows: total,
+                    nulls: row.get(offset)?,
+                    distinct: row.get(offset + 1)?,
+                    min: row.get(offset + 2)?,
+                    max: row.get(offset + 3)?,
+                    avg_length: row.get(offset + 4)?,
+                })
+            })
+            .collect()
+    }
+
+    pub fn profile<T: Table>(&self) -> Result<Vec<ColumnProfile>> {
+        self.profile_table(T::NAME, T::COLUMNS)
+    }
+}
+
+/// `schema.table` becomes `"schema"."table"`
+pub(super) fn quote(name: &str) -> String {
+    name.split('.')
+        .map(|part| format!("\"{}\"", part.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(".")
+}
@@ -0,0 +1,180 @@
+use super::{Connection, Sqlite};
+use crate::Value;
+use std::{
+    collections::HashMap,
+    panic::Location,
+    sync::{Arc, atomic::Ordering},
+    time::Duration,
+};
+
+#[derive(Debug, Clone)]
+pub struct RecordedSql {
+    pub sql: String,
+    pub params: Vec<Value>,
+    pub elapsed: Duration,
+}
+
+/// Collects every statement run on the connection until it is dropped,
+/// meant for tests that assert how many queries a code path performs
+#[derive(Debug)]
+pub struct SqlRecording {
+    connection: Arc<Connection>,
+}
+
+impl SqlRecording {
+    pub fn statements(&self) -> Vec<RecordedSql> {
+        match self.connection.recorded.lock() {
+            Ok(recorded) => recorded.clone(),
+            Err(_) => vec![],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self.connection.recorded.lock() {
+            Ok(recorded) => recorded.len(),
+            Err(_) => 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut recorded) = self.connection.recorded.lock() {
+            recorded.clear();
+        }
+    }
+}
+
+impl Drop for SqlRecording {
+    fn drop(&mut self) {
+        self.connection.recording.store(false, Ordering::Relaxed);
+        self.clear();
+    }
+}
+
+/// A statement that ran more than the detector's threshold, usually a query inside a loop
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepeatedSql {
+    pub sql: String,
+    pub count: usize,
+    /// Where the statement crossed the threshold
+    pub location: &'static Location<'static>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct RepeatCounter {
+    threshold: usize,
+    counts: HashMap<String, usize>,
+    reports: Vec<RepeatedSql>,
+}
+
+/// Counts statements until it is dropped, every statement run more than
+/// `threshold` times in that scope is reported once with its call site
+#[derive(Debug)]
+pub struct RepeatedSqlDetector {
+    connection: Arc<Connection>,
+}
+
+impl RepeatedSqlDetector {
+    pub fn reports(&self) -> Vec<RepeatedSql> {
+        match self.connection.repeats.lock() {
+            Ok(repeats) => repeats.reports.clone(),
+            Err(_) => vec![],
+        }
+    }
+
+    /// Starts a new scope, e.g. once per request or transaction
+    pub fn reset(&self) {
+        if let Ok(mut repeats) = self.connection.repeats.lock() {
+            repeats.counts.clear();
+            repeats.reports.clear();
+        }
+    }
+}
+
+impl Drop for RepeatedSqlDetector {
+    fn drop(&mut self) {
+        self.connection.detecting.store(false, Ordering::Relaxed);
+        self.reset();
+    }
+}
+
+impl Sqlite {
+    /// Starts recording sql, params and durations, replacing any earlier recording's log
+    pub fn record_sql(&self) -> SqlRecording {
+        let recording = SqlRecording {
+            connection: self.connection.clone(),
+        };
+        recording.clear();
+        self.connection.recording.store(true, Ordering::Relaxed);
+        recording
+    }
+
+    /// Opt-in N+1 query detection, replacing any earlier detector's counts
+    pub fn detect_repeated_sql(&self, threshold: usize) -> RepeatedSqlDetector {
+        let detector = RepeatedSqlDetector {
+            connection: self.connection.clone(),
+        };
+        if let Ok(mut repeats) = self.connection.repeats.lock() {
+            *repeats = RepeatCounter {
+                threshold,
+                ..Default::default()
+            };
+        }
+        self.connection.detecting.store(true, Ordering::Relaxed);
+        detector
+    }
+}
+
+impl Connection {
+    pub(crate) fn is_recording(&self) -> bool {
+        self.recording.load(Ordering::Relaxed)
+    }
+
+    /// Whether statements need their sql read before they run
+    pub(crate) fn wants_sql(&self) -> bool {
+        self.is_recording() || self.detecting.load(Ordering::Relaxed) || self.is_watching()
+    }
+
+    #[track_caller]
+    pub(crate) fn record(&self, sql: &str, params: &[Value], elapsed: Duration) {
+        if self.detecting.load(Ordering::Relaxed) {
+            self.count(sql, Location::caller());
+        }
+        if self.is_watching() {
+            self.watch(sql);
+        }
+        if !self.is_recording() {
+            return;
+        }
+        if let Ok(mut recorded) = self.recorded.lock() {
+            recorded.push(RecordedSql {
+                sql: sql.to_string(),
+                params: params.to_vec(),
+                elapsed,
+            });
+        }
+    }
+}
+
+impl Connection {
+    fn count(&self, sql: &str, location: &'static Location<'static>) {
+        let Ok(mut repeats) = self.repeats.lock() else {
+            return;
+        };
+        let count = repeats.counts.entry(sql.to_string()).or_default();
+        *count += 1;
+        let count = *count;
+        if count == repeats.threshold + 1 {
+            repeats.reports.push(RepeatedSql {
+                sql: sql.to_string(),
+                count,
+                location,
+            });
+        } else if let Some(report) = repeats.reports.iter_mut().find(|report| report.sql == sql) {
+            report.count = count;
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use super::Sqlite;
+use crate::{Blob, FromRow, Int, Real, Result, Row, Text, Value};
+use std::collections::HashMap;
+
+/// A column value that can key a `HashMap`, null keys match nothing like in sql
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum Key {
+    Int(i64),
+    Real(u64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl Sqlite {
+    /// Runs `parents_sql` and then `children_sql` once for all the parents, instead of once
+    /// per parent, giving each parent the children whose `child_key` equals its `parent_key`
+    /// in the order `children_sql` returns them
+    pub fn load_children<P: FromRow, C: FromRow>(
+        &self,
+        parents_sql: &str,
+        children_sql: &str,
+        parent_key: &str,
+        child_key: &str,
+    ) -> Result<Vec<(P, Vec<C>)>> {
+        let parents = self.prepare(parents_sql)?.rows()?;
+        let mut children = HashMap::<Key, Vec<C>>::new();
+        for row in self.prepare(children_sql)?.rows()? {
+            if let Some(key) = key(&row, child_key)? {
+                children.entry(key).or_default().push(C::from_row(&row));
+            }
+        }
+        parents
+            .iter()
+            .map(|row| {
+                let children = match key(row, parent_key)? {
+                    Some(key) => children.remove(&key).unwrap_or_default(),
+                    None => vec![],
+                };
+                Ok((P::from_row(row), children))
+            })
+            .collect()
+    }
+
+    /// Runs `children_sql` and then `parents_sql` once for all the children, giving each
+    /// child the parent whose `parent_key` equals its `child_key`, if any
+    pub fn load_parents<C: FromRow, P: FromRow>(
+        &self,
+        children_sql: &str,
+        parents_sql: &str,
+        child_key: &str,
+        parent_key: &str,
+    ) -> Result<Vec<(C, Option<P>)>> {
+        let children = self.prepare(children_sql)?.rows()?;
+        let mut parents = HashMap::<Key, Row>::new();
+        for row in self.prepare(parents_sql)?.rows()? {
+            if let Some(key) = key(&row, parent_key)? {
+                parents.insert(key, row);
+            }
+        }
+        children
+            .iter()
+            .map(|row| {
+                let parent = key(row, child_key)?
+                    .and_then(|key| parents.get(&key))
+                    .map(P::from_row);
+                Ok((C::from_row(row), parent))
+            })
+            .collect()
+    }
+}
+
+fn key(row: &Row, column: &str) -> Result<Option<Key>> {
+    Ok(match row.get::<Value>(column)? {
+        Value::Int(Int(Some(value))) => Some(Key::Int(value)),
+        Value::Real(Real(Some(value))) => Some(Key::Real(value.to_bits())),
+        Value::Text(Text(Some(value))) => Some(Key::Text(value)),
+        Value::Blob(Blob(Some(value))) => Some(Key::Blob(value)),
+        _ => None,
+    })
+}
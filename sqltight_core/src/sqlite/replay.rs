@@ -0,0 +1,178 @@
+use super::{RecordedSql, SqlRecording, Sqlite};
+use crate::{Blob, Error, Int, Real, Result, Text, Value, blob, int, real, text};
+use std::{
+    fmt::Write as _,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// How fast `Sqlite::replay` runs a trace
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ReplayRate {
+    #[default]
+    Unlimited,
+    PerSecond(f64),
+}
+
+/// One replayed statement next to how long it took when it was recorded
+#[derive(Debug)]
+pub struct ReplayedSql {
+    pub sql: String,
+    pub recorded: Duration,
+    pub elapsed: Duration,
+    pub result: Result<usize>,
+}
+
+impl SqlRecording {
+    /// Writes the statements recorded so far to `path`, see `write_trace`
+    pub fn export(&self, path: impl AsRef<Path>) -> Result<()> {
+        write_trace(path, &self.statements())
+    }
+}
+
+impl Sqlite {
+    /// Runs every statement of a trace in order, a failing statement is reported
+    /// in its `ReplayedSql` and the replay moves on
+    pub fn replay(&self, trace: &[RecordedSql], rate: ReplayRate) -> Vec<ReplayedSql> {
+        let interval = match rate {
+            ReplayRate::PerSecond(per_second) if per_second > 0.0 => {
+                Some(Duration::from_secs_f64(1.0 / per_second))
+            }
+            ReplayRate::PerSecond(_) | ReplayRate::Unlimited => None,
+        };
+        let replay_started_at = Instant::now();
+        trace
+            .iter()
+            .enumerate()
+            .map(|(ix, recorded)| {
+                if let Some(interval) = interval {
+                    let due = interval.saturating_mul(u32::try_from(ix).unwrap_or(u32::MAX));
+                    std::thread::sleep(due.saturating_sub(replay_started_at.elapsed()));
+                }
+                let started_at = Instant::now();
+                let result = self
+                    .prepare(&recorded.sql)
+                    .and_then(|stmt| stmt.bind(&recorded.params))
+                    .and_then(|stmt| stmt.rows())
+                    .map(|rows| rows.len());
+                ReplayedSql {
+                    sql: recorded.sql.clone(),
+                    recorded: recorded.elapsed,
+                    elapsed: started_at.elapsed(),
+                    result,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Saves a trace as text, one statement per line:
+/// elapsed microseconds, the sql and then each param, separated by tabs.
+/// Params are written as `n`, `i:1`, `r:1.5`, `t:text` or `b:<hex>`
+pub fn write_trace(path: impl AsRef<Path>, trace: &[RecordedSql]) -> Result<()> {
+    let mut out = String::new();
+    for recorded in trace {
+        let _result = write!(
+            out,
+            "{}\t{}",
+            recorded.elapsed.as_micros(),
+            escape(&recorded.sql)
+        );
+        for param in &recorded.params {
+            out.push('\t');
+            out.push_str(&encode(param));
+        }
+        out.push('\n');
+    }
+    std::fs::write(path, out)?;
+    Ok(())
+}
+
+pub fn read_trace(path: impl AsRef<Path>) -> Result<Vec<RecordedSql>> {
+    std::fs::read_to_string(path)?
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(ix, line)| parse_line(line).ok_or_else(|| invalid_trace(ix + 1)))
+        .collect()
+}
+
+fn invalid_trace(line: usize) -> Error {
+    Error::Io(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("invalid trace on line {line}"),
+    ))
+}
+
+fn parse_line(line: &str) -> Option<RecordedSql> {
+    let mut parts = line.split('\t');
+    let elapsed = Duration::from_micros(parts.next()?.parse().ok()?);
+    let sql = unescape(parts.next()?)?;
+    let params = parts.map(decode).collect::<Option<Vec<_>>>()?;
+    Some(RecordedSql {
+        sql,
+        params,
+        elapsed,
+    })
+}
+
+fn encode(value: &Value) -> String {
+    match value {
+        Value::Int(Int(Some(value))) => format!("i:{value}"),
+        Value::Real(Real(Some(value))) => format!("r:{value:?}"),
+        Value::Text(Text(Some(value))) => format!("t:{}", escape(value)),
+        Value::Blob(Blob(Some(value))) => format!(
+            "b:{}",
+            value
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<String>()
+        ),
+        Value::Int(_) | Value::Real(_) | Value::Text(_) | Value::Blob(_) | Value::Null => {
+            "n".to_string()
+        }
+    }
+}
+
+fn decode(param: &str) -> Option<Value> {
+    match param.split_once(':') {
+        Some(("i", value)) => Some(int(value.parse().ok()?).into()),
+        Some(("r", value)) => Some(real(value.parse().ok()?).into()),
+        Some(("t", value)) => Some(text(unescape(value)?).into()),
+        Some(("b", value)) => {
+            let bytes = (0..value.len())
+                .step_by(2)
+                .map(|ix| u8::from_str_radix(value.get(ix..ix + 2)?, 16).ok())
+                .collect::<Option<Vec<_>>>()?;
+            Some(blob(bytes).into())
+        }
+        _ if param == "n" => Some(Value::Null),
+        _ => None,
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn unescape(value: &str) -> Option<String> {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next()? {
+                '\\' => out.push('\\'),
+                't' => out.push('\t'),
+                'n' => out.push('\n'),
+                'r' => out.push('\r'),
+                _ => return None,
+            },
+            c => out.push(c),
+        }
+    }
+    Some(out)
+}
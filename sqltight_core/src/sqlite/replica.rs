@@ -0,0 +1,177 @@
+use super::{Sqlite, sqlite_err};
+use crate::{Error, Result};
+use sqltight_ffi::{
+    SQLITE_BUSY, SQLITE_LOCKED, SQLITE_OK, SQLITE_OPEN_CREATE, SQLITE_OPEN_FULLMUTEX,
+    SQLITE_OPEN_READONLY, SQLITE_OPEN_READWRITE, sqlite3_backup_finish, sqlite3_backup_init,
+    sqlite3_backup_step, sqlite3_extended_errcode, sqlite3_libversion_number,
+};
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// How long a backup waits on readers of the replica or the primary's writer before giving up
+const BACKUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How far behind its primary a replica is, see `Replica::stats`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplicaStats {
+    /// The time since the copy the replica holds now was taken
+    pub staleness: Duration,
+    pub refreshes: u64,
+    pub failed_refreshes: u64,
+    /// How long the last successful refresh took to copy
+    pub last_refresh: Duration,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug)]
+struct ReplicaState {
+    refreshed_at: Instant,
+    stats: ReplicaStats,
+}
+
+/// Refreshes a replica file from its primary from a background thread until it is dropped
+#[derive(Debug)]
+pub struct Replica {
+    state: Arc<Mutex<ReplicaState>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Replica {
+    pub fn stats(&self) -> Result<ReplicaStats> {
+        let state = self.state.lock().map_err(|_| Error::MutexLockFailed)?;
+        Ok(ReplicaStats {
+            staleness: state.refreshed_at.elapsed(),
+            ..state.stats.clone()
+        })
+    }
+}
+
+impl Drop for Replica {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _result = thread.join();
+        }
+    }
+}
+
+impl Sqlite {
+    /// Copies every page of this database over `dest` with the backup api, waiting
+    /// for locks on either side for a few seconds
+    pub fn backup_into(&self, dest: &Sqlite) -> Result<()> {
+        let backup = unsafe {
+            sqlite3_backup_init(dest.db(), c"main".as_ptr(), self.db(), c"main".as_ptr())
+        };
+        if backup.is_null() {
+            let code = unsafe { sqlite3_extended_errcode(dest.db()) };
+            return Err(sqlite_err(code, dest.db()));
+        }
+        let started_at = Instant::now();
+        loop {
+            match unsafe { sqlite3_backup_step(backup, -1) } {
+                SQLITE_BUSY | SQLITE_LOCKED if started_at.elapsed() < BACKUP_TIMEOUT => {
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                _ => break,
+            }
+        }
+        match unsafe { sqlite3_backup_finish(backup) } {
+            SQLITE_OK => Ok(()),
+            code => Err(sqlite_err(code, dest.db())),
+        }
+    }
+
+    /// Writes a compacted copy of the database to a new file at `path` with `vacuum into`,
+    /// or with `backup_into` before sqlite 3.27. A wal database is copied as its readers see
+    /// it, without a checkpoint. Fails inside a transaction. Returns the size of the copy
+    pub fn backup_to(&self, path: &str) -> Result<u64> {
+        // `vacuum into` won't overwrite a file either
+        if std::fs::metadata(path).is_ok_and(|metadata| metadata.len() > 0) {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("{path} already exists"),
+            )));
+        }
+        match unsafe { sqlite3_libversion_number() } < 3_027_000 {
+            true => self.backup_into(&Sqlite::open(path)?)?,
+            false => {
+                let _changes = self
+                    .prepare("vacuum main into :path")?
+                    .bind(&[path.into()])?
+                    .changes()?;
+            }
+        }
+        Ok(std::fs::metadata(path)?.len())
+    }
+
+    /// Copies `primary_path` to `replica_path` and opens the copy read only, then copies it
+    /// again every `interval` from a background thread. Readers of the replica never hold
+    /// up the primary's writer, they only see its changes `interval` late
+    pub fn open_replica(
+        primary_path: &str,
+        replica_path: &str,
+        interval: Duration,
+    ) -> Result<(Sqlite, Replica)> {
+        let primary = Self::open_v2(primary_path, SQLITE_OPEN_READONLY | SQLITE_OPEN_FULLMUTEX)?;
+        let writer = Self::open_v2(
+            replica_path,
+            SQLITE_OPEN_READWRITE | SQLITE_OPEN_CREATE | SQLITE_OPEN_FULLMUTEX,
+        )?;
+        let started_at = Instant::now();
+        primary.backup_into(&writer)?;
+        let state = Arc::new(Mutex::new(ReplicaState {
+            refreshed_at: started_at,
+            stats: ReplicaStats {
+                refreshes: 1,
+                last_refresh: started_at.elapsed(),
+                ..Default::default()
+            },
+        }));
+        let replica = Self::open_v2(replica_path, SQLITE_OPEN_READONLY | SQLITE_OPEN_FULLMUTEX)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let refreshed = state.clone();
+        let thread = std::thread::spawn(move || {
+            let mut next_at = Instant::now() + interval;
+            while !stopped.load(Ordering::Relaxed) {
+                let now = Instant::now();
+                if now < next_at {
+                    std::thread::park_timeout(next_at - now);
+                    continue;
+                }
+                let result = primary.backup_into(&writer);
+                if let Ok(mut state) = refreshed.lock() {
+                    match result {
+                        Ok(()) => {
+                            state.refreshed_at = now;
+                            state.stats.refreshes += 1;
+                            state.stats.last_refresh = now.elapsed();
+                            state.stats.last_error = None;
+                        }
+                        Err(err) => {
+                            state.stats.failed_refreshes += 1;
+                            state.stats.last_error = Some(format!("{err:?}"));
+                        }
+                    }
+                }
+                next_at += interval;
+            }
+        });
+        Ok((
+            replica,
+            Replica {
+                state,
+                stop,
+                thread: Some(thread),
+            },
+        ))
+    }
+}
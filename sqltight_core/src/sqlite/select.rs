@@ -0,0 +1,329 @@
+use super::{Sqlite, Transaction, profile::quote};
+use crate::{Error, FromRow, Result, Table, Value, int};
+use std::marker::PhantomData;
+
+/// The column `name` of the table `T` holding `V`s, `db!` generates one per field,
+/// e.g. `User::EMAIL` for `email` in `table User`
+#[derive(Debug)]
+pub struct Col<T, V> {
+    name: &'static str,
+    marker: PhantomData<fn() -> (T, V)>,
+}
+
+impl<T, V> Clone for Col<T, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, V> Copy for Col<T, V> {}
+
+impl<T, V: Into<Value>> Col<T, V> {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn eq(self, value: impl Into<V>) -> Filter<T> {
+        self.compare("=", value)
+    }
+
+    pub fn ne(self, value: impl Into<V>) -> Filter<T> {
+        self.compare("!=", value)
+    }
+
+    pub fn lt(self, value: impl Into<V>) -> Filter<T> {
+        self.compare("<", value)
+    }
+
+    pub fn le(self, value: impl Into<V>) -> Filter<T> {
+        self.compare("<=", value)
+    }
+
+    pub fn gt(self, value: impl Into<V>) -> Filter<T> {
+        self.compare(">", value)
+    }
+
+    pub fn ge(self, value: impl Into<V>) -> Filter<T> {
+        self.compare(">=", value)
+    }
+
+    pub fn like(self, pattern: impl Into<String>) -> Filter<T> {
+        Filter::new(
+            format!("{} like ?", quote(self.name)),
+            vec![pattern.into().into()],
+        )
+    }
+
+    pub fn glob(self, pattern: impl Into<String>) -> Filter<T> {
+        Filter::new(
+            format!("{} glob ?", quote(self.name)),
+            vec![pattern.into().into()],
+        )
+    }
+
+    /// Matches none of the rows when `values` is empty, like sql's `in ()`
+    pub fn is_in(self, values: impl IntoIterator<Item = impl Into<V>>) -> Filter<T> {
+        let params = values
+            .into_iter()
+            .map(|value| value.into().into())
+            .collect::<Vec<Value>>();
+        let placeholders = vec!["?"; params.len()].join(", ");
+        Filter::new(format!("{} in ({placeholders})", quote(self.name)), params)
+    }
+
+    pub fn is_null(self) -> Filter<T> {
+        Filter::new(format!("{} is null", quote(self.name)), vec![])
+    }
+
+    pub fn is_not_null(self) -> Filter<T> {
+        Filter::new(format!("{} is not null", quote(self.name)), vec![])
+    }
+
+    pub fn asc(self) -> Order<T> {
+        Order::new(format!("{} asc", quote(self.name)))
+    }
+
+    pub fn desc(self) -> Order<T> {
+        Order::new(format!("{} desc", quote(self.name)))
+    }
+
+    fn compare(self, operator: &str, value: impl Into<V>) -> Filter<T> {
+        Filter::new(
+            format!("{} {operator} ?", quote(self.name)),
+            vec![value.into().into()],
+        )
+    }
+}
+
+/// A where condition on the table `T` with its params, see `Col`
+#[derive(Debug)]
+pub struct Filter<T> {
+    sql: String,
+    params: Vec<Value>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Filter<T> {
+    fn new(sql: String, params: Vec<Value>) -> Self {
+        Self {
+            sql,
+            params,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn and(mut self, other: Filter<T>) -> Self {
+        self.sql = format!("({}) and ({})", self.sql, other.sql);
+        self.params.extend(other.params);
+        self
+    }
+
+    pub fn or(mut self, other: Filter<T>) -> Self {
+        self.sql = format!("({}) or ({})", self.sql, other.sql);
+        self.params.extend(other.params);
+        self
+    }
+}
+
+impl<T> std::ops::Not for Filter<T> {
+    type Output = Self;
+
+    fn not(mut self) -> Self {
+        self.sql = format!("not ({})", self.sql);
+        self
+    }
+}
+
+impl<T> Clone for Filter<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.sql.clone(), self.params.clone())
+    }
+}
+
+/// An order by term of the table `T`, a `Col` on its own sorts ascending
+#[derive(Debug)]
+pub struct Order<T> {
+    sql: String,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Order<T> {
+    fn new(sql: String) -> Self {
+        Self {
+            sql,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Clone for Order<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.sql.clone())
+    }
+}
+
+impl<T, V: Into<Value>> From<Col<T, V>> for Order<T> {
+    fn from(col: Col<T, V>) -> Self {
+        col.asc()
+    }
+}
+
+/// A select built at runtime, for queries that can't be declared in `db!` like
+/// optional search params. `db!` adds `query()` to each table to start one
+#[derive(Debug)]
+pub struct Select<T> {
+    filters: Vec<Filter<T>>,
+    order: Vec<Order<T>>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+impl<T> Clone for Select<T> {
+    fn clone(&self) -> Self {
+        Self {
+            filters: self.filters.clone(),
+            order: self.order.clone(),
+            limit: self.limit,
+            offset: self.offset,
+        }
+    }
+}
+
+impl<T> Default for Select<T> {
+    fn default() -> Self {
+        Self {
+            filters: vec![],
+            order: vec![],
+            limit: None,
+            offset: None,
+        }
+    }
+}
+
+impl<T: Table + FromRow> Select<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rows have to match every filter
+    pub fn filter(mut self, filter: Filter<T>) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    pub fn order_by(mut self, order: impl Into<Order<T>>) -> Self {
+        self.order.push(order.into());
+        self
+    }
+
+    pub fn limit(mut self, limit: i64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn offset(mut self, offset: i64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// The select with `?` params, `soft_delete` tables skip deleted rows
+    pub fn sql(&self) -> String {
+        self.sql_for("*", true)
+    }
+
+    pub fn params(&self) -> Vec<Value> {
+        let mut params = self
+            .filters
+            .iter()
+            .flat_map(|filter| filter.params.iter().cloned())
+            .collect::<Vec<_>>();
+        // sqlite needs a limit before an offset, -1 is no limit
+        if self.limit.is_some() || self.offset.is_some() {
+            params.push(int(self.limit.unwrap_or(-1)).into());
+        }
+        if let Some(offset) = self.offset {
+            params.push(int(offset).into());
+        }
+        params
+    }
+
+    pub fn fetch(&self, db: &impl AsRef<Sqlite>) -> Result<Vec<T>> {
+        db.as_ref()
+            .prepare(&self.sql())?
+            .bind(&self.params())?
+            .rows_as()
+    }
+
+    /// The first row, with the limit set to 1
+    pub fn first(&self, db: &impl AsRef<Sqlite>) -> Result<Option<T>> {
+        Ok(self.clone().limit(1).fetch(db)?.into_iter().next())
+    }
+
+    /// The number of rows matching the filters, ignoring order, limit and offset
+    pub fn count(&self, db: &impl AsRef<Sqlite>) -> Result<i64> {
+        let params = self
+            .filters
+            .iter()
+            .flat_map(|filter| filter.params.iter().cloned())
+            .collect::<Vec<_>>();
+        db.as_ref()
+            .prepare(&self.sql_for("count(*)", false))?
+            .bind(&params)?
+            .rows()?
+            .first()
+            .ok_or(Error::RowNotFound)?
+            .get(0)
+    }
+
+    fn sql_for(&self, columns: &str, paged: bool) -> String {
+        let mut conditions = self
+            .filters
+            .iter()
+            .map(|filter| format!("({})", filter.sql))
+            .collect::<Vec<_>>();
+        if T::SOFT_DELETE {
+            conditions.push("deleted_at is null".to_string());
+        }
+        let mut sql = format!("select {columns} from {}", quote(T::NAME));
+        if !conditions.is_empty() {
+            sql.push_str(&format!(" where {}", conditions.join(" and ")));
+        }
+        if !paged {
+            return sql;
+        }
+        if !self.order.is_empty() {
+            let order = self
+                .order
+                .iter()
+                .map(|order| order.sql.as_str())
+                .collect::<Vec<_>>();
+            sql.push_str(&format!(" order by {}", order.join(", ")));
+        }
+        if self.limit.is_some() || self.offset.is_some() {
+            sql.push_str(" limit ?");
+        }
+        if self.offset.is_some() {
+            sql.push_str(" offset ?");
+        }
+        sql
+    }
+}
+
+impl AsRef<Sqlite> for Sqlite {
+    fn as_ref(&self) -> &Sqlite {
+        self
+    }
+}
+
+impl AsRef<Sqlite> for Transaction<'_> {
+    fn as_ref(&self) -> &Sqlite {
+        self
+    }
+}
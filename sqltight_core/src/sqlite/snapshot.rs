@@ -0,0 +1,193 @@
+use super::{Sqlite, profile::quote};
+use crate::{Error, FromRow, Result, Table, int};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// A table to copy into its `{table}_snapshots` shadow table, see `Sqlite::snapshot_tables`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotTable {
+    pub name: &'static str,
+    pub columns: &'static [&'static str],
+}
+
+impl SnapshotTable {
+    pub fn of<T: Table>() -> Self {
+        Self {
+            name: T::NAME,
+            columns: T::COLUMNS,
+        }
+    }
+}
+
+/// Snapshots tables from a background thread until it is dropped
+#[derive(Debug)]
+pub struct SnapshotScheduler {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for SnapshotScheduler {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _result = thread.join();
+        }
+    }
+}
+
+impl Sqlite {
+    /// Copies every row of each table into `{table}_snapshots`, stamped with `at` in unix
+    /// seconds, all or nothing. A second snapshot at the same second replaces the first.
+    /// Returns the number of rows copied
+    pub fn snapshot_tables(&self, tables: &[SnapshotTable], at: i64) -> Result<usize> {
+        self.savepoint(|| {
+            let mut copied = 0;
+            for table in tables {
+                copied += self.snapshot_table(table, at)?;
+            }
+            Ok(copied)
+        })
+    }
+
+    pub fn snapshot<T: Table>(&self, at: i64) -> Result<usize> {
+        self.snapshot_tables(&[SnapshotTable::of::<T>()], at)
+    }
+
+    /// The rows of `T` in the latest snapshot taken at or before `at`,
+    /// empty when there is none
+    pub fn as_of<T: Table + FromRow>(&self, at: i64) -> Result<Vec<T>> {
+        let shadow = shadow_name(T::NAME);
+        let sql = format!(
+            "select {} from {shadow} where snapshot_at = (select max(snapshot_at) from {shadow} where snapshot_at <= :at)",
+            column_list(T::COLUMNS)
+        );
+        let stmt = match self.prepare(&sql) {
+            Ok(stmt) => stmt,
+            Err(Error::Sqlite { ref text, .. }) if text.starts_with("no such table") => {
+                return Ok(vec![]);
+            }
+            Err(err) => return Err(err),
+        };
+        let rows = stmt.bind(&[int(at).into()])?.rows()?;
+        Ok(rows.iter().map(T::from_row).collect())
+    }
+
+    /// Deletes the snapshots of `T` taken before `at`, returns the number of rows deleted
+    pub fn delete_snapshots<T: Table>(&self, before: i64) -> Result<usize> {
+        let sql = format!(
+            "delete from {} where snapshot_at < :before",
+            shadow_name(T::NAME)
+        );
+        match self.prepare(&sql) {
+            Ok(stmt) => Ok(usize::try_from(
+                stmt.bind(&[int(before).into()])?.changes()?,
+            )?),
+            Err(Error::Sqlite { ref text, .. }) if text.starts_with("no such table") => Ok(0),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Snapshots `tables` right away and then every `interval` from a background thread.
+    /// The thread has its own connection to the same file, so it waits for the app's
+    /// transactions instead of running inside them. Failed snapshots go to `on_error`
+    pub fn snapshot_every(
+        &self,
+        interval: Duration,
+        tables: Vec<SnapshotTable>,
+        on_error: impl Fn(Error) + Send + 'static,
+    ) -> Result<SnapshotScheduler> {
+        let path = self.main_path().ok_or_else(|| {
+            Error::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "snapshots need a database file, not an in-memory database",
+            ))
+        })?;
+        let sqlite = Sqlite::open(&path)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let thread = std::thread::spawn(move || {
+            let mut next_at = Instant::now();
+            while !stopped.load(Ordering::Relaxed) {
+                let now = Instant::now();
+                if now < next_at {
+                    std::thread::park_timeout(next_at - now);
+                    continue;
+                }
+                if let Err(err) = sqlite.snapshot_tables(&tables, unix_now()) {
+                    on_error(err);
+                }
+                next_at += interval;
+            }
+        });
+        Ok(SnapshotScheduler {
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    fn snapshot_table(&self, table: &SnapshotTable, at: i64) -> Result<usize> {
+        let shadow = shadow_name(table.name);
+        let bare_shadow = quote(&format!("{}_snapshots", bare_name(table.name)));
+        let index = quote(&format!("{}_snapshots_at", table.name));
+        let _result = self.execute(&format!(
+            "create table if not exists {shadow} (snapshot_at integer not null)"
+        ))?;
+        let _result = self.execute(&format!(
+            "create index if not exists {index} on {bare_shadow} (snapshot_at)"
+        ))?;
+        // columns added to the table later show up as nulls in older snapshots
+        for column in table.columns {
+            match self.execute(&format!(
+                "alter table {shadow} add column {}",
+                quote(column)
+            )) {
+                Ok(_) | Err(Error::DuplicateColumnName(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        let _changes = self
+            .prepare(&format!("delete from {shadow} where snapshot_at = :at"))?
+            .bind(&[int(at).into()])?
+            .changes()?;
+        let columns = column_list(table.columns);
+        let copied = self
+            .prepare(&format!(
+                "insert into {shadow} (snapshot_at, {columns}) select :at, {columns} from {}",
+                quote(table.name)
+            ))?
+            .bind(&[int(at).into()])?
+            .changes()?;
+        Ok(usize::try_from(copied)?)
+    }
+}
+
+/// `schema.User` snapshots into `"schema"."User_snapshots"`
+fn shadow_name(table: &str) -> String {
+    quote(&format!("{table}_snapshots"))
+}
+
+fn bare_name(table: &str) -> &str {
+    table.rsplit('.').next().unwrap_or(table)
+}
+
+fn column_list(columns: &[&str]) -> String {
+    columns
+        .iter()
+        .map(|column| quote(column))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,286 @@
+use super::{Sqlite, Table, profile::quote};
+use crate::{Error, Result};
+use sqltight_ffi::{sqlite3_db_filename, sqlite3_filename_wal};
+use std::ffi::{CStr, c_char};
+
+/// How much space the main database takes, e.g. for a "storage used" screen
+/// or to decide when to vacuum
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct StorageStats {
+    /// bytes on disk, 0 for in-memory databases
+    pub file_size: u64,
+    /// bytes in the write-ahead log, 0 outside of wal mode
+    pub wal_size: u64,
+    pub page_size: i64,
+    pub page_count: i64,
+    /// pages left empty by deletes, reused by later writes or given back by a vacuum
+    pub freelist_count: i64,
+}
+
+impl StorageStats {
+    pub fn free_bytes(&self) -> i64 {
+        self.freelist_count * self.page_size
+    }
+}
+
+/// `pragma auto_vacuum`, set with `OpenOptions::auto_vacuum` before the first table is created.
+/// Switching an existing database between `None` and the others takes a full `vacuum`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutoVacuum {
+    None,
+    /// gives free pages back on every commit
+    Full,
+    /// keeps free pages until `incremental_vacuum` or `reclaim_space`
+    Incremental,
+}
+
+impl AutoVacuum {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AutoVacuum::None => "none",
+            AutoVacuum::Full => "full",
+            AutoVacuum::Incremental => "incremental",
+        }
+    }
+}
+
+/// When `Sqlite::reclaim_space` gives free pages back, call it after deletes or on a timer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VacuumPolicy {
+    /// free pages are left alone until they are at least this fraction of the file
+    pub min_free_ratio: f64,
+    /// pages given back per call so the write lock is only held briefly, 0 gives back every free page
+    pub max_pages: u32,
+}
+
+impl Default for VacuumPolicy {
+    fn default() -> Self {
+        Self {
+            min_free_ratio: 0.25,
+            max_pages: 1000,
+        }
+    }
+}
+
+impl Sqlite {
+    pub fn auto_vacuum(&self) -> Result<AutoVacuum> {
+        match self.pragma_int("auto_vacuum")? {
+            1 => Ok(AutoVacuum::Full),
+            2 => Ok(AutoVacuum::Incremental),
+            _ => Ok(AutoVacuum::None),
+        }
+    }
+
+    pub(super) fn set_auto_vacuum(&self, auto_vacuum: AutoVacuum) -> Result<()> {
+        let _result = self.execute(&format!("pragma auto_vacuum = {}", auto_vacuum.as_str()))?;
+        Ok(())
+    }
+
+    /// Gives up to `pages` free pages back to the file system, 0 for all of them, and returns
+    /// how many were given back. Only incremental auto vacuum databases have any to give
+    pub fn incremental_vacuum(&self, pages: u32) -> Result<i64> {
+        let before = self.pragma_int("freelist_count")?;
+        let _result = self.execute(&format!("pragma incremental_vacuum({pages})"))?;
+        Ok(before - self.pragma_int("freelist_count")?)
+    }
+
+    /// Runs `incremental_vacuum` when `policy` says the free pages are worth it
+    pub fn reclaim_space(&self, policy: VacuumPolicy) -> Result<i64> {
+        let stats = self.storage_stats()?;
+        let free_ratio = match stats.page_count {
+            0 => 0.0,
+            page_count => stats.freelist_count as f64 / page_count as f64,
+        };
+        match stats.freelist_count > 0 && free_ratio >= policy.min_free_ratio {
+            true => self.incremental_vacuum(policy.max_pages),
+            false => Ok(0),
+        }
+    }
+
+    /// Compacts `T` and its indexes without a `vacuum` of the whole file: its rows are
+    /// copied into a new table that takes its place, in one savepoint. The pages it frees
+    /// are reused by later writes or given back by `reclaim_space`. Foreign keys are off
+    /// while it runs and checked before it's done, so it can't run inside a transaction
+    /// with them on, `Error::RebuildInTransaction`
+    pub fn rebuild_table<T: Table>(&self) -> Result<()> {
+        let foreign_keys = self.pragma_int("foreign_keys")? == 1;
+        if foreign_keys && self.in_transaction() {
+            // dropping the old table would run the on delete actions of the tables referencing it
+            return Err(Error::RebuildInTransaction);
+        }
+        let (schema, table) = split_name(T::NAME);
+        let entries = self
+            .prepare(&format!(
+                "select type, sql from {}.sqlite_schema
+                where tbl_name = :table collate nocase and sql is not null
+                order by type = 'table' desc, rowid",
+                quote(schema)
+            ))?
+            .bind(&[table.into()])?
+            .rows()?;
+        let create = match entries.first() {
+            Some(row) if row.get::<String>("type")? == "table" => row.get::<String>("sql")?,
+            _ => return Err(Error::RowNotFound),
+        };
+        // generated columns are computed again, they can't be copied
+        let columns = self
+            .prepare("select name from pragma_table_xinfo(:table, :schema) where hidden = 0")?
+            .bind(&[table.into(), schema.into()])?
+            .rows()?
+            .iter()
+            .map(|row| row.get::<String>("name").map(|name| quote(&name)))
+            .collect::<Result<Vec<_>>>()?;
+        let mut columns = columns.join(", ");
+        // without rowid tables have none to keep
+        if self
+            .prepare(&format!("select rowid from {} limit 0", quote(T::NAME)))
+            .is_ok()
+        {
+            columns = format!("rowid, {columns}");
+        }
+        let rebuilt = format!("sqltight_rebuild_{table}");
+        // everything after the name is the table's definition
+        let definition = &create[create.find('(').ok_or(Error::RowNotFound)?..];
+        if foreign_keys {
+            let _result = self.execute("pragma foreign_keys = off")?;
+        }
+        let result = self.savepoint(|| {
+            let _result = self.execute(&format!(
+                "create table {}.{} {definition}",
+                quote(schema),
+                quote(&rebuilt)
+            ))?;
+            let _result = self.execute(&format!(
+                "insert into {}.{} ({columns}) select {columns} from {}",
+                quote(schema),
+                quote(&rebuilt),
+                quote(T::NAME)
+            ))?;
+            let _result = self.execute(&format!("drop table {}", quote(T::NAME)))?;
+            // views naming the table would fail to parse while it's missing otherwise
+            let _result = self.execute("pragma legacy_alter_table = on")?;
+            let renamed = self.execute(&format!(
+                "alter table {}.{} rename to {}",
+                quote(schema),
+                quote(&rebuilt),
+                quote(table)
+            ));
+            let _result = self.execute("pragma legacy_alter_table = off")?;
+            let _result = renamed?;
+            // the indexes and triggers went with the old table
+            for row in &entries[1..] {
+                let _result = self.execute(&row.get::<String>("sql")?)?;
+            }
+            if foreign_keys
+                && !self
+                    .prepare("select * from pragma_foreign_key_check(:table, :schema)")?
+                    .bind(&[table.into(), schema.into()])?
+                    .rows()?
+                    .is_empty()
+            {
+                return Err(Error::ForeignKeyConstraint);
+            }
+            Ok(())
+        });
+        if foreign_keys {
+            let _result = self.execute("pragma foreign_keys = on")?;
+        }
+        result
+    }
+
+    /// The path of the main database file, `None` for in-memory databases
+    pub(crate) fn main_path(&self) -> Option<String> {
+        filename_str(unsafe { sqlite3_db_filename(self.db(), c"main".as_ptr()) })
+    }
+
+    pub fn storage_stats(&self) -> Result<StorageStats> {
+        let filename = unsafe { sqlite3_db_filename(self.db(), c"main".as_ptr()) };
+        let (file_size, wal_size) = match filename_str(filename) {
+            Some(path) => (
+                file_size(&path),
+                filename_str(unsafe { sqlite3_filename_wal(filename) })
+                    .map(|wal| file_size(&wal))
+                    .unwrap_or_default(),
+            ),
+            None => (0, 0),
+        };
+        Ok(StorageStats {
+            file_size,
+            wal_size,
+            page_size: self.pragma_int("page_size")?,
+            page_count: self.pragma_int("page_count")?,
+            freelist_count: self.pragma_int("freelist_count")?,
+        })
+    }
+}
+
+impl Sqlite {
+    /// About how many rows `T` has, without counting them: the count the last `analyze`
+    /// stored in `sqlite_stat1`, or the largest rowid when it hasn't run, which counts
+    /// deleted rows too
+    pub fn estimate_rows<T: Table>(&self) -> Result<i64> {
+        let (schema, table) = split_name(T::NAME);
+        let analyzed = self
+            .prepare(&format!(
+                "select count(*) from {}.sqlite_schema where name = 'sqlite_stat1'",
+                quote(schema)
+            ))?
+            .rows()?
+            .first()
+            .map(|row| row.get::<i64>(0))
+            .transpose()?
+            .unwrap_or_default()
+            > 0;
+        if analyzed {
+            // each row's stat starts with the row count, one row per index
+            let stats = self
+                .prepare(&format!(
+                    "select stat from {}.sqlite_stat1 where tbl = :table collate nocase",
+                    quote(schema)
+                ))?
+                .bind(&[table.into()])?
+                .rows()?;
+            let estimate = stats
+                .iter()
+                .filter_map(|row| row.get::<String>("stat").ok())
+                .filter_map(|stat| stat.split_whitespace().next()?.parse::<i64>().ok())
+                .max();
+            if let Some(estimate) = estimate {
+                return Ok(estimate);
+            }
+        }
+        self.prepare(&format!(
+            "select coalesce(max(rowid), 0) from {}",
+            quote(T::NAME)
+        ))?
+        .rows()?
+        .first()
+        .map(|row| row.get(0))
+        .unwrap_or(Ok(0))
+    }
+}
+
+/// `archive.Event` is `Event` in the attached `archive`, `Event` is in `main`
+pub(super) fn split_name(name: &str) -> (&str, &str) {
+    name.split_once('.').unwrap_or(("main", name))
+}
+
+/// Sqlite gives in-memory and temp databases an empty name
+fn filename_str(filename: *const c_char) -> Option<String> {
+    match filename.is_null() {
+        true => None,
+        false => Some(
+            unsafe { CStr::from_ptr(filename) }
+                .to_string_lossy()
+                .into_owned(),
+        )
+        .filter(|path| !path.is_empty()),
+    }
+}
+
+/// A missing file, e.g. a wal that was checkpointed away, takes no space
+fn file_size(path: &str) -> u64 {
+    std::fs::metadata(path)
+        .map(|metadata| metadata.len())
+        .unwrap_or_default()
+}
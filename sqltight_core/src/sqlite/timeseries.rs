@@ -0,0 +1,207 @@
+use super::{Sqlite, profile::quote};
+use crate::{Error, FromRow, FromSql, Result, Value, int};
+
+/// A table split into one `{name}_{yyyy}_{mm}` table per month of its `BY` column,
+/// `db!` implements it for each `timeseries table`
+pub trait Timeseries: FromRow {
+    const NAME: &'static str;
+    /// The unix seconds column that picks a row's partition
+    const BY: &'static str;
+    const COLUMNS: &'static [&'static str];
+    /// The definition of each column in `COLUMNS`, e.g. `value real`
+    const DEFINITIONS: &'static [&'static str];
+
+    /// The values of `COLUMNS` in order
+    fn values(self) -> Vec<Value>;
+}
+
+impl Sqlite {
+    /// Inserts `row` into the partition of its month, creating the partition first
+    /// when it's the month's first row and adding the columns declared since it was created
+    pub fn insert_series<T: Timeseries>(&self, row: T) -> Result<()> {
+        let values = row.values();
+        let at = T::COLUMNS
+            .iter()
+            .position(|column| *column == T::BY)
+            .and_then(|ix| values.get(ix))
+            .map(i64::from_sql)
+            .unwrap_or(Ok(0))?;
+        let (year, month) = month_of(at);
+        let partition = partition_name(T::NAME, year, month);
+        let sql = format!(
+            "insert into {} ({}) values ({})",
+            quote(&partition),
+            column_list(T::COLUMNS),
+            vec!["?"; T::COLUMNS.len()].join(", ")
+        );
+        let stmt = match self.prepare(&sql) {
+            Ok(stmt) => stmt,
+            Err(Error::Sqlite { ref text, .. }) if text.starts_with("no such table") => {
+                self.create_partition::<T>(&partition)?;
+                self.prepare(&sql)?
+            }
+            Err(Error::Sqlite { ref text, .. }) if text.contains("has no column named") => {
+                for definition in T::DEFINITIONS {
+                    match self.execute(&format!(
+                        "alter table {} add column {definition}",
+                        quote(&partition)
+                    )) {
+                        Ok(_) | Err(Error::DuplicateColumnName(_)) => {}
+                        Err(err) => return Err(err),
+                    }
+                }
+                self.prepare(&sql)?
+            }
+            Err(err) => return Err(err),
+        };
+        let _changes = stmt.bind(&values)?.changes()?;
+        Ok(())
+    }
+
+    /// The rows with `from <= BY < to` in order, read from the partitions of those months
+    /// with a single `union all`
+    pub fn series_between<T: Timeseries>(&self, from: i64, to: i64) -> Result<Vec<T>> {
+        let by = quote(T::BY);
+        let columns = column_list(T::COLUMNS);
+        let selects = self
+            .series_partitions::<T>()?
+            .into_iter()
+            .filter(|(_name, start, end)| *start < to && *end > from)
+            .map(|(name, ..)| {
+                format!(
+                    "select {columns} from {} where {by} >= :from and {by} < :to",
+                    quote(&name)
+                )
+            })
+            .collect::<Vec<_>>();
+        if selects.is_empty() {
+            return Ok(vec![]);
+        }
+        let sql = format!("{} order by {by}", selects.join(" union all "));
+        let rows = self
+            .prepare(&sql)?
+            .bind(&[int(from).into(), int(to).into()])?
+            .rows()?;
+        Ok(rows.iter().map(T::from_row).collect())
+    }
+
+    /// Drops the partitions that end before `before` and deletes the older rows of the one
+    /// it falls in, all or nothing. Returns the number of rows removed
+    pub fn sweep_series<T: Timeseries>(&self, before: i64) -> Result<usize> {
+        self.savepoint(|| {
+            let mut removed = 0;
+            for (name, start, end) in self.series_partitions::<T>()? {
+                let table = quote(&name);
+                if end <= before {
+                    let rows = self
+                        .prepare(&format!("select count(*) from {table}"))?
+                        .rows()?;
+                    removed += match rows.first() {
+                        Some(row) => usize::try_from(row.get::<i64>(0)?)?,
+                        None => 0,
+                    };
+                    let _result = self.execute(&format!("drop table {table}"))?;
+                } else if start < before {
+                    let deleted = self
+                        .prepare(&format!(
+                            "delete from {table} where {} < :before",
+                            quote(T::BY)
+                        ))?
+                        .bind(&[int(before).into()])?
+                        .changes()?;
+                    removed += usize::try_from(deleted)?;
+                }
+            }
+            Ok(removed)
+        })
+    }
+
+    /// The partitions of `T` in month order, with the unix seconds each month starts and ends at
+    pub fn series_partitions<T: Timeseries>(&self) -> Result<Vec<(String, i64, i64)>> {
+        let pattern = format!("{}_[0-9][0-9][0-9][0-9]_[0-9][0-9]", T::NAME);
+        let rows = self
+            .prepare(
+                "select name from sqlite_schema where type = 'table' and name glob :pattern order by name",
+            )?
+            .bind(&[pattern.into()])?
+            .rows()?;
+        let mut partitions = vec![];
+        for row in rows {
+            let name: String = row.get(0)?;
+            let suffix = &name[T::NAME.len() + 1..];
+            let (Ok(year), Ok(month)) = (suffix[..4].parse::<i64>(), suffix[5..].parse::<i64>())
+            else {
+                continue;
+            };
+            if !(1..=12).contains(&month) {
+                continue;
+            }
+            let (next_year, next_month) = match month {
+                12 => (year + 1, 1),
+                _ => (year, month + 1),
+            };
+            partitions.push((
+                name,
+                month_start(year, month),
+                month_start(next_year, next_month),
+            ));
+        }
+        Ok(partitions)
+    }
+
+    fn create_partition<T: Timeseries>(&self, partition: &str) -> Result<()> {
+        let table = quote(partition);
+        let _result = self.execute(&format!(
+            "create table if not exists {table} ({}) strict",
+            T::DEFINITIONS.join(", ")
+        ))?;
+        let _result = self.execute(&format!(
+            "create index if not exists {} on {table} ({})",
+            quote(&format!("{partition}_{}", T::BY)),
+            quote(T::BY)
+        ))?;
+        Ok(())
+    }
+}
+
+fn partition_name(name: &str, year: i64, month: i64) -> String {
+    format!("{name}_{year:04}_{month:02}")
+}
+
+fn column_list(columns: &[&str]) -> String {
+    columns
+        .iter()
+        .map(|column| quote(column))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The utc year and month of unix seconds, from Howard Hinnant's `civil_from_days`
+fn month_of(at: i64) -> (i64, i64) {
+    let days = at.div_euclid(86_400) + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let month = match shifted_month {
+        0..=9 => shifted_month + 3,
+        _ => shifted_month - 9,
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month)
+}
+
+/// The unix seconds at the start of a utc month, from `days_from_civil`
+fn month_start(year: i64, month: i64) -> i64 {
+    let year = match month {
+        1 | 2 => year - 1,
+        _ => year,
+    };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    (era * 146_097 + day_of_era - 719_468) * 86_400
+}
@@ -0,0 +1,90 @@
+use super::Sqlite;
+use crate::{Error, Result};
+use sqltight_ffi::{
+    SQLITE_OK, SQLITE_TRACE_PROFILE, sqlite3_expanded_sql, sqlite3_free, sqlite3_sql, sqlite3_stmt,
+    sqlite3_trace_v2,
+};
+use std::{
+    ffi::{CStr, c_int, c_uint, c_void},
+    time::Duration,
+};
+
+/// A statement that finished running, with its parameters expanded into the sql
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEvent {
+    pub sql: String,
+    pub elapsed: Duration,
+}
+
+pub(crate) type TraceHandler = Box<dyn FnMut(TraceEvent) + Send>;
+
+impl Sqlite {
+    /// Calls `callback` after every statement on this connection runs to completion
+    pub fn on_trace(&self, callback: impl FnMut(TraceEvent) + Send + 'static) -> Result<()> {
+        let mut trace = self
+            .connection
+            .trace
+            .lock()
+            .map_err(|_| Error::MutexLockFailed)?;
+        let mut handler: Box<TraceHandler> = Box::new(Box::new(callback));
+        let result = unsafe {
+            sqlite3_trace_v2(
+                self.db(),
+                SQLITE_TRACE_PROFILE as c_uint,
+                Some(trace_trampoline),
+                &mut *handler as *mut TraceHandler as *mut c_void,
+            )
+        };
+        *trace = Some(handler);
+        match result {
+            SQLITE_OK => Ok(()),
+            code => Err(super::sqlite_err(code, self.db())),
+        }
+    }
+
+    pub fn clear_trace(&self) -> Result<()> {
+        let mut trace = self
+            .connection
+            .trace
+            .lock()
+            .map_err(|_| Error::MutexLockFailed)?;
+        unsafe { sqlite3_trace_v2(self.db(), 0, None, core::ptr::null_mut()) };
+        *trace = None;
+        Ok(())
+    }
+}
+
+fn expanded_sql(stmt: *mut sqlite3_stmt) -> String {
+    unsafe {
+        let expanded = sqlite3_expanded_sql(stmt);
+        match expanded.is_null() {
+            // expanding fails when out of memory or the result is too long
+            true => CStr::from_ptr(sqlite3_sql(stmt))
+                .to_string_lossy()
+                .into_owned(),
+            false => {
+                let sql = CStr::from_ptr(expanded).to_string_lossy().into_owned();
+                sqlite3_free(expanded as *mut c_void);
+                sql
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn trace_trampoline(
+    event: c_uint,
+    handler: *mut c_void,
+    stmt: *mut c_void,
+    nanos: *mut c_void,
+) -> c_int {
+    if event != SQLITE_TRACE_PROFILE as c_uint {
+        return 0;
+    }
+    let handler = unsafe { &mut *(handler as *mut TraceHandler) };
+    let event = TraceEvent {
+        sql: expanded_sql(stmt as *mut sqlite3_stmt),
+        elapsed: Duration::from_nanos(unsafe { nanos.cast::<i64>().read() }.max(0) as u64),
+    };
+    let _result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| handler(event)));
+    0
+}
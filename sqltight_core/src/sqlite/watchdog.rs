@@ -0,0 +1,162 @@
+use super::{Connection, Sqlite};
+use crate::{Error, Result};
+use sqltight_ffi::sqlite3_interrupt;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// The transaction `Transaction` has open on a watched connection
+#[derive(Debug, Default)]
+pub(crate) struct OpenTransaction {
+    started_at: Option<Instant>,
+    statements: Vec<String>,
+    reported: bool,
+}
+
+/// What the watchdog does once a transaction is open too long
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OnLongTransaction {
+    #[default]
+    Report,
+    /// interrupts and rolls the transaction back, its next statement and its commit
+    /// fail with `Error::TransactionTimedOut`
+    Rollback,
+}
+
+/// A transaction open longer than the watchdog allows, with the statements it ran so far
+#[derive(Debug, Clone, PartialEq)]
+pub struct LongTransaction {
+    pub elapsed: Duration,
+    pub statements: Vec<String>,
+    pub rolled_back: bool,
+}
+
+/// Watches transactions from a background thread until it is dropped
+#[derive(Debug)]
+pub struct TransactionWatchdog {
+    connection: Arc<Connection>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for TransactionWatchdog {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.connection.watching.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _result = thread.join();
+        }
+    }
+}
+
+impl Sqlite {
+    /// Calls `report` once for every transaction open longer than `max_open`, e.g. to log what
+    /// holds the write lock while other writers get "database is locked"
+    pub fn watch_transactions(
+        &self,
+        max_open: Duration,
+        on_long_transaction: OnLongTransaction,
+        report: impl Fn(LongTransaction) + Send + 'static,
+    ) -> TransactionWatchdog {
+        self.connection.watching.store(true, Ordering::Relaxed);
+        let stop = Arc::new(AtomicBool::new(false));
+        let interval = (max_open / 4).clamp(Duration::from_millis(1), Duration::from_millis(100));
+        let connection = Arc::downgrade(&self.connection);
+        let stopped = stop.clone();
+        let thread = std::thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                std::thread::park_timeout(interval);
+                let Some(connection) = connection.upgrade() else {
+                    break;
+                };
+                let Some((elapsed, statements)) = connection.long_transaction(max_open) else {
+                    continue;
+                };
+                let rolled_back = on_long_transaction == OnLongTransaction::Rollback;
+                if rolled_back {
+                    Sqlite { connection }.force_rollback();
+                }
+                report(LongTransaction {
+                    elapsed,
+                    statements,
+                    rolled_back,
+                });
+            }
+        });
+        TransactionWatchdog {
+            connection: self.connection.clone(),
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    fn force_rollback(&self) {
+        // set first, so the transaction's next statement can't run outside of it
+        self.connection.rolled_back.store(true, Ordering::SeqCst);
+        unsafe { sqlite3_interrupt(self.db()) };
+        // an interrupted write may have rolled the transaction back already
+        let _result = self.exec("rollback transaction");
+    }
+}
+
+impl Connection {
+    /// The elapsed time and statements of a transaction past `max_open`, once per transaction
+    fn long_transaction(&self, max_open: Duration) -> Option<(Duration, Vec<String>)> {
+        let mut open = self.open_transaction.lock().ok()?;
+        let elapsed = open.started_at?.elapsed();
+        match !open.reported && elapsed >= max_open {
+            true => {
+                open.reported = true;
+                Some((elapsed, open.statements.clone()))
+            }
+            false => None,
+        }
+    }
+
+    pub(crate) fn transaction_started(&self) {
+        self.rolled_back.store(false, Ordering::SeqCst);
+        if let Ok(mut open) = self.open_transaction.lock() {
+            *open = OpenTransaction {
+                started_at: Some(Instant::now()),
+                ..Default::default()
+            };
+        }
+    }
+
+    /// Fails when the watchdog rolled the transaction back, the flag is cleared either way
+    pub(crate) fn transaction_ended(&self) -> Result<()> {
+        if let Ok(mut open) = self.open_transaction.lock() {
+            *open = OpenTransaction::default();
+        }
+        match self.rolled_back.swap(false, Ordering::SeqCst) {
+            true => Err(Error::TransactionTimedOut),
+            false => Ok(()),
+        }
+    }
+
+    pub(crate) fn check_rolled_back(&self) -> Result<()> {
+        match self.rolled_back.load(Ordering::SeqCst) {
+            true => Err(Error::TransactionTimedOut),
+            false => Ok(()),
+        }
+    }
+
+    pub(crate) fn is_watching(&self) -> bool {
+        self.watching.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn watch(&self, sql: &str) {
+        let Ok(mut open) = self.open_transaction.lock() else {
+            return;
+        };
+        if open.started_at.is_some() {
+            open.statements.push(sql.to_string());
+        }
+    }
+}
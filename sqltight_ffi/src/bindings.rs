@@ -540,6 +540,7 @@ unsafe extern "C" {
 pub struct sqlite3_file {
     pub pMethods: *const sqlite3_io_methods,
 }
+#[cfg(target_pointer_width = "64")]
 #[allow(clippy::unnecessary_operation, clippy::identity_op)]
 const _: () = {
     ["Size of sqlite3_file"][::std::mem::size_of::<sqlite3_file>() - 8usize];
@@ -656,6 +657,7 @@ pub struct sqlite3_io_methods {
         ) -> ::std::os::raw::c_int,
     >,
 }
+#[cfg(target_pointer_width = "64")]
 #[allow(clippy::unnecessary_operation, clippy::identity_op)]
 const _: () = {
     ["Size of sqlite3_io_methods"][::std::mem::size_of::<sqlite3_io_methods>() - 152usize];
@@ -830,6 +832,7 @@ pub struct sqlite3_vfs {
         ) -> *const ::std::os::raw::c_char,
     >,
 }
+#[cfg(target_pointer_width = "64")]
 #[allow(clippy::unnecessary_operation, clippy::identity_op)]
 const _: () = {
     ["Size of sqlite3_vfs"][::std::mem::size_of::<sqlite3_vfs>() - 168usize];
@@ -922,6 +925,7 @@ pub struct sqlite3_mem_methods {
     pub xShutdown: ::std::option::Option<unsafe extern "C" fn(arg1: *mut ::std::os::raw::c_void)>,
     pub pAppData: *mut ::std::os::raw::c_void,
 }
+#[cfg(target_pointer_width = "64")]
 #[allow(clippy::unnecessary_operation, clippy::identity_op)]
 const _: () = {
     ["Size of sqlite3_mem_methods"][::std::mem::size_of::<sqlite3_mem_methods>() - 64usize];
@@ -2313,6 +2317,7 @@ pub struct sqlite3_module {
         ) -> ::std::os::raw::c_int,
     >,
 }
+#[cfg(target_pointer_width = "64")]
 #[allow(clippy::unnecessary_operation, clippy::identity_op)]
 const _: () = {
     ["Size of sqlite3_module"][::std::mem::size_of::<sqlite3_module>() - 200usize];
@@ -2393,6 +2398,7 @@ pub struct sqlite3_index_info_sqlite3_index_constraint {
     pub usable: ::std::os::raw::c_uchar,
     pub iTermOffset: ::std::os::raw::c_int,
 }
+#[cfg(target_pointer_width = "64")]
 #[allow(clippy::unnecessary_operation, clippy::identity_op)]
 const _: () = {
     ["Size of sqlite3_index_info_sqlite3_index_constraint"]
@@ -2414,6 +2420,7 @@ pub struct sqlite3_index_info_sqlite3_index_orderby {
     pub iColumn: ::std::os::raw::c_int,
     pub desc: ::std::os::raw::c_uchar,
 }
+#[cfg(target_pointer_width = "64")]
 #[allow(clippy::unnecessary_operation, clippy::identity_op)]
 const _: () = {
     ["Size of sqlite3_index_info_sqlite3_index_orderby"]
@@ -2431,6 +2438,7 @@ pub struct sqlite3_index_info_sqlite3_index_constraint_usage {
     pub argvIndex: ::std::os::raw::c_int,
     pub omit: ::std::os::raw::c_uchar,
 }
+#[cfg(target_pointer_width = "64")]
 #[allow(clippy::unnecessary_operation, clippy::identity_op)]
 const _: () = {
     ["Size of sqlite3_index_info_sqlite3_index_constraint_usage"]
@@ -2444,6 +2452,7 @@ const _: () = {
     ["Offset of field: sqlite3_index_info_sqlite3_index_constraint_usage::omit"]
         [::std::mem::offset_of!(sqlite3_index_info_sqlite3_index_constraint_usage, omit) - 4usize];
 };
+#[cfg(target_pointer_width = "64")]
 #[allow(clippy::unnecessary_operation, clippy::identity_op)]
 const _: () = {
     ["Size of sqlite3_index_info"][::std::mem::size_of::<sqlite3_index_info>() - 96usize];
@@ -2505,6 +2514,7 @@ pub struct sqlite3_vtab {
     pub nRef: ::std::os::raw::c_int,
     pub zErrMsg: *mut ::std::os::raw::c_char,
 }
+#[cfg(target_pointer_width = "64")]
 #[allow(clippy::unnecessary_operation, clippy::identity_op)]
 const _: () = {
     ["Size of sqlite3_vtab"][::std::mem::size_of::<sqlite3_vtab>() - 24usize];
@@ -2520,6 +2530,7 @@ const _: () = {
 pub struct sqlite3_vtab_cursor {
     pub pVtab: *mut sqlite3_vtab,
 }
+#[cfg(target_pointer_width = "64")]
 #[allow(clippy::unnecessary_operation, clippy::identity_op)]
 const _: () = {
     ["Size of sqlite3_vtab_cursor"][::std::mem::size_of::<sqlite3_vtab_cursor>() - 8usize];
@@ -2632,6 +2643,7 @@ pub struct sqlite3_mutex_methods {
         unsafe extern "C" fn(arg1: *mut sqlite3_mutex) -> ::std::os::raw::c_int,
     >,
 }
+#[cfg(target_pointer_width = "64")]
 #[allow(clippy::unnecessary_operation, clippy::identity_op)]
 const _: () = {
     ["Size of sqlite3_mutex_methods"][::std::mem::size_of::<sqlite3_mutex_methods>() - 72usize];
@@ -2785,6 +2797,7 @@ pub struct sqlite3_pcache_page {
     pub pBuf: *mut ::std::os::raw::c_void,
     pub pExtra: *mut ::std::os::raw::c_void,
 }
+#[cfg(target_pointer_width = "64")]
 #[allow(clippy::unnecessary_operation, clippy::identity_op)]
 const _: () = {
     ["Size of sqlite3_pcache_page"][::std::mem::size_of::<sqlite3_pcache_page>() - 16usize];
@@ -2844,6 +2857,7 @@ pub struct sqlite3_pcache_methods2 {
     pub xDestroy: ::std::option::Option<unsafe extern "C" fn(arg1: *mut sqlite3_pcache)>,
     pub xShrink: ::std::option::Option<unsafe extern "C" fn(arg1: *mut sqlite3_pcache)>,
 }
+#[cfg(target_pointer_width = "64")]
 #[allow(clippy::unnecessary_operation, clippy::identity_op)]
 const _: () = {
     ["Size of sqlite3_pcache_methods2"]
@@ -2924,6 +2938,7 @@ pub struct sqlite3_pcache_methods {
     >,
     pub xDestroy: ::std::option::Option<unsafe extern "C" fn(arg1: *mut sqlite3_pcache)>,
 }
+#[cfg(target_pointer_width = "64")]
 #[allow(clippy::unnecessary_operation, clippy::identity_op)]
 const _: () = {
     ["Size of sqlite3_pcache_methods"][::std::mem::size_of::<sqlite3_pcache_methods>() - 88usize];
@@ -3139,6 +3154,7 @@ unsafe extern "C" {
 pub struct sqlite3_snapshot {
     pub hidden: [::std::os::raw::c_uchar; 48usize],
 }
+#[cfg(target_pointer_width = "64")]
 #[allow(clippy::unnecessary_operation, clippy::identity_op)]
 const _: () = {
     ["Size of sqlite3_snapshot"][::std::mem::size_of::<sqlite3_snapshot>() - 48usize];
@@ -3218,6 +3234,7 @@ pub struct sqlite3_rtree_geometry {
     pub pUser: *mut ::std::os::raw::c_void,
     pub xDelUser: ::std::option::Option<unsafe extern "C" fn(arg1: *mut ::std::os::raw::c_void)>,
 }
+#[cfg(target_pointer_width = "64")]
 #[allow(clippy::unnecessary_operation, clippy::identity_op)]
 const _: () = {
     ["Size of sqlite3_rtree_geometry"][::std::mem::size_of::<sqlite3_rtree_geometry>() - 40usize];
@@ -3265,6 +3282,7 @@ pub struct sqlite3_rtree_query_info {
     pub rScore: sqlite3_rtree_dbl,
     pub apSqlParam: *mut *mut sqlite3_value,
 }
+#[cfg(target_pointer_width = "64")]
 #[allow(clippy::unnecessary_operation, clippy::identity_op)]
 const _: () = {
     ["Size of sqlite3_rtree_query_info"]
@@ -1,7 +1,14 @@
 #![allow(non_upper_case_globals, non_camel_case_types, non_snake_case)]
 
+// generated on x86_64, the structs are repr(C) and lay out for whatever they're built for,
+// but the layout checks bindgen wrote have 64 bit sizes in them and only run there
 include!("bindings.rs");
 
+#[cfg(feature = "normalize")]
+unsafe extern "C" {
+    pub fn sqlite3_normalized_sql(pStmt: *mut sqlite3_stmt) -> *const ::std::os::raw::c_char;
+}
+
 pub fn sqlite_version() -> String {
     unsafe {
         let version = sqlite3_libversion();
@@ -0,0 +1,471 @@
+use crate::{
+    Error,
+    parser::{DatabaseSchema, Fts, Query, SchemaPart, Table, View},
+};
+use sqltight_core::Sqlite;
+
+/// The declared schema as the compile time database sees it after the migrations ran
+struct SchemaDocs {
+    tables: Vec<TableDoc>,
+    views: Vec<QueryDoc>,
+    fts: Vec<FtsDoc>,
+    queries: Vec<QueryDoc>,
+}
+
+struct TableDoc {
+    name: String,
+    /// name, declared type and constraints
+    columns: Vec<(String, String, String)>,
+    indexes: Vec<String>,
+    primary_key: Vec<String>,
+    foreign_keys: Vec<ForeignKeyDoc>,
+}
+
+struct ForeignKeyDoc {
+    column: String,
+    /// The referenced table, qualified like the table the key is declared on
+    table: String,
+    not_null: bool,
+}
+
+struct FtsDoc {
+    name: String,
+    content: Option<String>,
+    columns: Vec<String>,
+}
+
+struct QueryDoc {
+    name: String,
+    params: Vec<String>,
+    /// name and declared type, empty for expressions
+    columns: Vec<(String, String)>,
+    sql: String,
+}
+
+/// Renders the tables, views, fts tables and queries as markdown
+pub fn markdown(db: &Sqlite, schema: &DatabaseSchema) -> Result<String, Error> {
+    let docs = SchemaDocs::new(db, schema)?;
+    let mut out = String::from("# Database\n");
+    if !docs.tables.is_empty() {
+        out.push_str("\n## Tables\n");
+    }
+    for table in &docs.tables {
+        out.push_str(&format!(
+            "\n### {}\n\n| Column | Type | Constraints |\n| --- | --- | --- |\n",
+            table.name
+        ));
+        for (name, ty, constraints) in &table.columns {
+            out.push_str(&format!("| {name} | {ty} | {constraints} |\n"));
+        }
+        if !table.indexes.is_empty() {
+            out.push_str("\nIndexes:\n\n");
+            for index in &table.indexes {
+                out.push_str(&format!("- `{index}`\n"));
+            }
+        }
+    }
+    if !docs.views.is_empty() {
+        out.push_str("\n## Views\n");
+    }
+    for view in &docs.views {
+        out.push_str(&markdown_query(view));
+    }
+    if !docs.fts.is_empty() {
+        out.push_str("\n## Full text search\n");
+    }
+    for fts in &docs.fts {
+        out.push_str(&format!("\n### {}\n\n", fts.name));
+        if let Some(content) = &fts.content {
+            out.push_str(&format!("Indexes `{content}`.\n\n"));
+        }
+        out.push_str(&format!("Columns: {}\n", fts.columns.join(", ")));
+    }
+    if !docs.queries.is_empty() {
+        out.push_str("\n## Queries\n");
+    }
+    for query in &docs.queries {
+        out.push_str(&markdown_query(query));
+    }
+    Ok(out)
+}
+
+fn markdown_query(query: &QueryDoc) -> String {
+    let mut out = format!("\n### {}\n\n", query.name);
+    if !query.params.is_empty() {
+        out.push_str(&format!("Parameters: {}\n\n", code_list(&query.params)));
+    }
+    out.push_str("| Column | Type |\n| --- | --- |\n");
+    for (name, ty) in &query.columns {
+        out.push_str(&format!("| {name} | {ty} |\n"));
+    }
+    out.push_str(&format!("\n```sql\n{}\n```\n", query.sql.trim()));
+    out
+}
+
+/// Renders the tables and their foreign keys as a mermaid `erDiagram`
+pub fn mermaid(db: &Sqlite, schema: &DatabaseSchema) -> Result<String, Error> {
+    let docs = SchemaDocs::new(db, schema)?;
+    // mermaid entity names can't have dots, `archive.Event` becomes `archive_Event`
+    let entity = |name: &str| name.replace('.', "_");
+    let mut out = String::from("erDiagram\n");
+    for table in &docs.tables {
+        out.push_str(&format!("    {} {{\n", entity(&table.name)));
+        for (name, ty, _constraints) in &table.columns {
+            let keys = [
+                table.primary_key.contains(name).then_some("PK"),
+                table
+                    .foreign_keys
+                    .iter()
+                    .any(|foreign_key| foreign_key.column == *name)
+                    .then_some("FK"),
+            ]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(", ");
+            let ty = ty.split_whitespace().next().unwrap_or("ANY");
+            out.push_str(format!("        {ty} {name} {keys}").trim_end());
+            out.push('\n');
+        }
+        out.push_str("    }\n");
+    }
+    for table in &docs.tables {
+        for foreign_key in &table.foreign_keys {
+            let parent = match foreign_key.not_null {
+                true => "||",
+                false => "|o",
+            };
+            out.push_str(&format!(
+                "    {} {parent}--o{{ {} : {}\n",
+                entity(&foreign_key.table),
+                entity(&table.name),
+                foreign_key.column
+            ));
+        }
+    }
+    Ok(out)
+}
+
+/// Renders the tables and their foreign keys as a graphviz digraph, one record node per table
+pub fn dot(db: &Sqlite, schema: &DatabaseSchema) -> Result<String, Error> {
+    let docs = SchemaDocs::new(db, schema)?;
+    let record = |text: &str| {
+        text.chars()
+            .flat_map(|c| match c {
+                '{' | '}' | '|' | '<' | '>' | '"' | '\\' => vec!['\\', c],
+                c => vec![c],
+            })
+            .collect::<String>()
+    };
+    let mut out = String::from("digraph schema {\n    rankdir=LR;\n    node [shape=record];\n");
+    for table in &docs.tables {
+        let columns = table
+            .columns
+            .iter()
+            .map(|(name, ty, _constraints)| format!("{} {}\\l", record(name), record(ty)))
+            .collect::<String>();
+        out.push_str(&format!(
+            "    \"{}\" [label=\"{{{}|{columns}}}\"];\n",
+            table.name,
+            record(&table.name)
+        ));
+    }
+    for table in &docs.tables {
+        for foreign_key in &table.foreign_keys {
+            out.push_str(&format!(
+                "    \"{}\" -> \"{}\" [label=\"{}\"];\n",
+                table.name, foreign_key.table, foreign_key.column
+            ));
+        }
+    }
+    out.push_str("}\n");
+    Ok(out)
+}
+
+/// Renders the same as `markdown`, as a standalone html page
+pub fn html(db: &Sqlite, schema: &DatabaseSchema) -> Result<String, Error> {
+    let docs = SchemaDocs::new(db, schema)?;
+    let mut out = String::from(
+        "<!doctype html>\n<html>\n<head><meta charset=\"utf-8\"><title>Database</title></head>\n<body>\n<h1>Database</h1>\n",
+    );
+    if !docs.tables.is_empty() {
+        out.push_str("<h2>Tables</h2>\n");
+    }
+    for table in &docs.tables {
+        out.push_str(&format!("<h3>{}</h3>\n", escape(&table.name)));
+        let rows = table
+            .columns
+            .iter()
+            .map(|(name, ty, constraints)| vec![name.as_str(), ty, constraints])
+            .collect::<Vec<_>>();
+        out.push_str(&html_table(&["Column", "Type", "Constraints"], &rows));
+        if !table.indexes.is_empty() {
+            out.push_str("<p>Indexes:</p>\n<ul>\n");
+            for index in &table.indexes {
+                out.push_str(&format!("<li><code>{}</code></li>\n", escape(index)));
+            }
+            out.push_str("</ul>\n");
+        }
+    }
+    if !docs.views.is_empty() {
+        out.push_str("<h2>Views</h2>\n");
+    }
+    for view in &docs.views {
+        out.push_str(&html_query(view));
+    }
+    if !docs.fts.is_empty() {
+        out.push_str("<h2>Full text search</h2>\n");
+    }
+    for fts in &docs.fts {
+        out.push_str(&format!("<h3>{}</h3>\n", escape(&fts.name)));
+        if let Some(content) = &fts.content {
+            out.push_str(&format!(
+                "<p>Indexes <code>{}</code>.</p>\n",
+                escape(content)
+            ));
+        }
+        out.push_str(&format!(
+            "<p>Columns: {}</p>\n",
+            escape(&fts.columns.join(", "))
+        ));
+    }
+    if !docs.queries.is_empty() {
+        out.push_str("<h2>Queries</h2>\n");
+    }
+    for query in &docs.queries {
+        out.push_str(&html_query(query));
+    }
+    out.push_str("</body>\n</html>\n");
+    Ok(out)
+}
+
+fn html_query(query: &QueryDoc) -> String {
+    let mut out = format!("<h3>{}</h3>\n", escape(&query.name));
+    if !query.params.is_empty() {
+        let params = query
+            .params
+            .iter()
+            .map(|param| format!("<code>{}</code>", escape(param)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        out.push_str(&format!("<p>Parameters: {params}</p>\n"));
+    }
+    let rows = query
+        .columns
+        .iter()
+        .map(|(name, ty)| vec![name.as_str(), ty])
+        .collect::<Vec<_>>();
+    out.push_str(&html_table(&["Column", "Type"], &rows));
+    out.push_str(&format!(
+        "<pre><code>{}</code></pre>\n",
+        escape(query.sql.trim())
+    ));
+    out
+}
+
+fn html_table(headers: &[&str], rows: &[Vec<&str>]) -> String {
+    let cells = |tag: &str, cells: &[&str]| {
+        cells
+            .iter()
+            .map(|cell| format!("<{tag}>{}</{tag}>", escape(cell)))
+            .collect::<String>()
+    };
+    let mut out = format!("<table>\n<tr>{}</tr>\n", cells("th", headers));
+    for row in rows {
+        out.push_str(&format!("<tr>{}</tr>\n", cells("td", row)));
+    }
+    out.push_str("</table>\n");
+    out
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn code_list(items: &[String]) -> String {
+    items
+        .iter()
+        .map(|item| format!("`{item}`"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl SchemaDocs {
+    fn new(db: &Sqlite, schema: &DatabaseSchema) -> Result<Self, Error> {
+        let mut docs = SchemaDocs {
+            tables: vec![],
+            views: vec![],
+            fts: vec![],
+            queries: vec![],
+        };
+        for part in &schema.parts {
+            match part {
+                SchemaPart::Table(table) => docs.tables.push(table_doc(db, table)?),
+                SchemaPart::View(View { name, sql, .. }) => {
+                    docs.views.push(query_doc(db, &name.to_string(), sql)?)
+                }
+                SchemaPart::Fts(Fts {
+                    name,
+                    content,
+                    columns,
+                    ..
+                }) => docs.fts.push(FtsDoc {
+                    name: name.to_string(),
+                    content: content.as_ref().map(|content| content.to_string()),
+                    columns: columns.iter().map(|column| column.to_string()).collect(),
+                }),
+                SchemaPart::Query(Query { fn_name, sql, .. }) => {
+                    docs.queries.push(query_doc(db, &fn_name.to_string(), sql)?)
+                }
+                SchemaPart::Attach(_)
+                | SchemaPart::Pragma(_)
+                | SchemaPart::Env(_)
+                | SchemaPart::DebugOutput(_)
+                | SchemaPart::SchemaDiff(_)
+                | SchemaPart::DevDatabase(_)
+                | SchemaPart::Docs(_)
+                | SchemaPart::Visibility(_)
+                | SchemaPart::MaxRows(_)
+                | SchemaPart::Op(_)
+                | SchemaPart::Execute(_)
+                | SchemaPart::Index(_)
+                | SchemaPart::Check(_)
+                | SchemaPart::Timeseries(_)
+                | SchemaPart::Migration(_)
+                | SchemaPart::MigrationDir(_) => {}
+            }
+        }
+        Ok(docs)
+    }
+}
+
+fn table_doc(db: &Sqlite, table: &Table) -> Result<TableDoc, Error> {
+    let name = table.name.to_string();
+    let schema = table
+        .schema
+        .as_ref()
+        .map(|schema| schema.to_string())
+        .unwrap_or_else(|| "main".to_string());
+    let foreign_keys = db
+        .prepare(
+            "select \"from\", \"table\", \"to\", on_delete, on_update from pragma_foreign_key_list(:table, :schema)",
+        )?
+        .bind(&[name.as_str().into(), schema.as_str().into()])?
+        .rows()?;
+    let columns = db
+        .prepare(
+            "select name, type, \"notnull\", dflt_value, pk from pragma_table_info(:table, :schema) order by cid",
+        )?
+        .bind(&[name.as_str().into(), schema.as_str().into()])?
+        .rows()?
+        .iter()
+        .map(|row| {
+            let column: String = row.get("name")?;
+            let mut constraints = vec![];
+            if row.get::<i64>("pk")? > 0 {
+                constraints.push("primary key".to_string());
+            }
+            if row.get::<i64>("notnull")? == 1 {
+                constraints.push("not null".to_string());
+            }
+            if let Some(default) = row.get::<Option<String>>("dflt_value")? {
+                constraints.push(format!("default {default}"));
+            }
+            for foreign_key in &foreign_keys {
+                if foreign_key.get::<String>("from")? != column {
+                    continue;
+                }
+                let mut references = format!(
+                    "references {}({})",
+                    foreign_key.get::<String>("table")?,
+                    foreign_key.get::<String>("to")?
+                );
+                for (event, key) in [("delete", "on_delete"), ("update", "on_update")] {
+                    let action = foreign_key.get::<String>(key)?.to_lowercase();
+                    if action != "no action" {
+                        references.push_str(&format!(" on {event} {action}"));
+                    }
+                }
+                constraints.push(references);
+            }
+            Ok((column, row.get("type")?, constraints.join(", ")))
+        })
+        .collect::<sqltight_core::Result<Vec<_>>>()?;
+    let primary_key = db
+        .prepare("select name from pragma_table_info(:table, :schema) where pk > 0 order by pk")?
+        .bind(&[name.as_str().into(), schema.as_str().into()])?
+        .rows()?
+        .iter()
+        .map(|row| row.get("name"))
+        .collect::<sqltight_core::Result<Vec<String>>>()?;
+    let foreign_keys = foreign_keys
+        .iter()
+        .map(|foreign_key| {
+            let column: String = foreign_key.get("from")?;
+            let table: String = foreign_key.get("table")?;
+            let not_null = columns.iter().any(|(name, _ty, constraints)| {
+                *name == column && constraints.contains("not null")
+            });
+            Ok(ForeignKeyDoc {
+                column,
+                table: qualified(&schema, &table),
+                not_null,
+            })
+        })
+        .collect::<sqltight_core::Result<Vec<_>>>()?;
+    let indexes = db
+        .prepare(
+            "select name, \"unique\" from pragma_index_list(:table, :schema) where origin = 'c' order by name",
+        )?
+        .bind(&[name.as_str().into(), schema.as_str().into()])?
+        .rows()?
+        .iter()
+        .map(|row| {
+            let index: String = row.get("name")?;
+            let columns = db
+                .prepare("select name from pragma_index_info(:index, :schema) order by seqno")?
+                .bind(&[index.as_str().into(), schema.as_str().into()])?
+                .rows()?
+                .iter()
+                .map(|row| row.get("name"))
+                .collect::<sqltight_core::Result<Vec<String>>>()?;
+            let unique = match row.get::<i64>("unique")? {
+                1 => " unique",
+                _ => "",
+            };
+            Ok(format!("{index}{unique} ({})", columns.join(", ")))
+        })
+        .collect::<sqltight_core::Result<Vec<_>>>()?;
+    Ok(TableDoc {
+        name: qualified(&schema, &name),
+        columns,
+        indexes,
+        primary_key,
+        foreign_keys,
+    })
+}
+
+fn qualified(schema: &str, name: &str) -> String {
+    match schema {
+        "main" => name.to_string(),
+        schema => format!("{schema}.{name}"),
+    }
+}
+
+fn query_doc(db: &Sqlite, name: &str, sql: &str) -> Result<QueryDoc, Error> {
+    let stmt = db.prepare(sql)?;
+    let columns = stmt
+        .select_column_names()
+        .into_iter()
+        .zip(stmt.select_column_types())
+        .collect();
+    Ok(QueryDoc {
+        name: name.to_string(),
+        params: stmt.parameter_names(),
+        columns,
+        sql: sql.to_string(),
+    })
+}
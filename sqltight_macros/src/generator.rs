@@ -1,445 +1,4337 @@
 use crate::{
-    Error,
-    parser::{DatabaseSchema, Field, Index, Query, SchemaPart, Table},
+    Error, docs,
+    parser::{
+        Attach, Check, DatabaseSchema, DebugOutput, DeriveStruct, DevDatabase, Docs, Env, Execute,
+        Field, Fts, Index, MaxRows, Migration, MigrationDir, Op, Pragma, Query, References,
+        SchemaDiffCheck, SchemaPart, Table, Timeseries, View, Visibility,
+    },
+};
+use proc_macro::{
+    Delimiter, Diagnostic, Ident, Level, Literal, Spacing, Span, TokenStream, TokenTree, quote,
 };
-use proc_macro::{Diagnostic, Ident, Level, Span, TokenStream, quote};
 
 pub fn generate(schema: &DatabaseSchema) -> Result<TokenStream, Error> {
     let db = sqltight_core::Sqlite::open(":memory:").unwrap();
+    let attachments = schema
+        .parts
+        .iter()
+        .filter_map(|part| match part {
+            SchemaPart::Attach(attach) => Some(attach),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    for Attach { schema } in &attachments {
+        db.attach(":memory:", &schema.to_string())?;
+    }
     let migrations = schema.parts.iter().flat_map(migration).collect::<Vec<_>>();
-    let _result = db.migrate(&migrations)?;
+    let envs = envs(schema);
+    for (_name, pragmas, env_migrations) in &envs {
+        let _result = db.execute(pragmas)?;
+        let env_migrations = [migrations.clone(), env_migrations.clone()].concat();
+        db.migrate(&env_migrations, env!("CARGO_PKG_VERSION"))?;
+    }
+    let versioned_migrations = versioned_migrations(schema)?;
+    let versioned = versioned_migrations
+        .iter()
+        .map(
+            |Migration { version, up, down }| sqltight_core::VersionedMigration {
+                version: *version,
+                up,
+                down,
+            },
+        )
+        .collect::<Vec<_>>();
+    // every down down to the last irreversible migration is checked too,
+    // then the queries below are checked against the latest version
+    let irreversible = versioned
+        .iter()
+        .filter(|migration| migration.down.trim().is_empty())
+        .map(|migration| migration.version)
+        .max()
+        .unwrap_or_default();
+    let _version = db.migrate_to(&versioned, i64::MAX)?;
+    let _version = db.rollback_to(&versioned, irreversible)?;
+    let _version = db.migrate_to(&versioned, i64::MAX)?;
+    for part in &schema.parts {
+        if let SchemaPart::SchemaDiff(check) = part {
+            warn_schema_diff(check, &attachments, &migrations, &versioned)?;
+        }
+    }
+    check_conflict_targets(&db, schema)?;
+    let dev_db = match schema.parts.iter().find_map(|part| match part {
+        SchemaPart::DevDatabase(dev_database) => Some(dev_database),
+        _ => None,
+    }) {
+        Some(dev_database) => open_dev_database(dev_database, &attachments)?,
+        None => None,
+    };
+    // queries are prepared against the dev database when there is one,
+    // so their result types come from what it really has
+    let query_db = dev_db.as_ref().unwrap_or(&db);
+    let vis = default_vis(schema);
+    let max_rows = default_max_rows(schema);
+    let tables = schema
+        .parts
+        .iter()
+        .filter_map(|part| match part {
+            SchemaPart::Table(table) => Some(table),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let declared = declared_reads(schema);
     let table_tokens = schema
         .parts
         .iter()
         .filter_map(|part| match part {
-            SchemaPart::Table(table) => Some(generate_table(table)),
+            SchemaPart::Attach(_attach) => None,
+            SchemaPart::Pragma(_pragma) => None,
+            SchemaPart::Env(_env) => None,
+            SchemaPart::DebugOutput(_debug_output) => None,
+            SchemaPart::SchemaDiff(_schema_diff) => None,
+            SchemaPart::DevDatabase(_dev_database) => None,
+            SchemaPart::Docs(_docs) => None,
+            SchemaPart::Visibility(_visibility) => None,
+            SchemaPart::MaxRows(_max_rows) => None,
+            SchemaPart::Table(table) => Some(
+                generate_table_queries(query_db, &tables, table, schema, &declared, &vis, max_rows)
+                    .and_then(|queries| generate_table_module(table, &vis, queries)),
+            ),
             SchemaPart::Index(_index) => None,
+            SchemaPart::Check(_check) => None,
             SchemaPart::Query(_select) => None,
+            SchemaPart::Op(_op) => None,
+            SchemaPart::Execute(_execute) => None,
+            SchemaPart::View(_view) => None,
+            SchemaPart::Fts(_fts) => None,
+            SchemaPart::Timeseries(timeseries) => {
+                Some(Ok(generate_timeseries_module(timeseries, &vis)))
+            }
+            SchemaPart::Migration(_migration) => None,
+            SchemaPart::MigrationDir(_migration_dir) => None,
         })
         .collect::<Result<TokenStream, Error>>()?;
+    let relation_tokens = generate_relations(&tables, &vis);
+    let query_plan_tests = generate_query_plan_tests(schema, &attachments);
+    let relation_read_tokens = generate_relation_reads(&tables, &declared, &vis);
     let select_tokens = schema
         .parts
         .iter()
         .filter_map(|part| match part {
+            SchemaPart::Attach(_attach) => None,
+            SchemaPart::Pragma(_pragma) => None,
+            SchemaPart::Env(_env) => None,
+            SchemaPart::DebugOutput(_debug_output) => None,
+            SchemaPart::SchemaDiff(_schema_diff) => None,
+            SchemaPart::DevDatabase(_dev_database) => None,
+            SchemaPart::Docs(_docs) => None,
+            SchemaPart::Visibility(_visibility) => None,
+            SchemaPart::MaxRows(_max_rows) => None,
             SchemaPart::Table(_table) => None,
             SchemaPart::Index(_index) => None,
-            SchemaPart::Query(select) => Some(generate_select(&db, select)),
+            SchemaPart::Check(_check) => None,
+            SchemaPart::Query(select) => match owning_table(&select.sql, &tables) {
+                Some(_table) => None,
+                None => Some(generate_select(
+                    query_db, &tables, select, &vis, max_rows, true,
+                )),
+            },
+            SchemaPart::Op(op) => Some(generate_op(query_db, &tables, op, &vis)),
+            SchemaPart::Execute(execute) => {
+                Some(generate_execute(query_db, &tables, execute, &vis))
+            }
+            SchemaPart::View(view) => Some(generate_select(
+                query_db,
+                &tables,
+                &view_query(view),
+                &vis,
+                max_rows,
+                true,
+            )),
+            SchemaPart::Fts(fts) => Some(generate_select(
+                query_db,
+                &tables,
+                &fts_query(fts),
+                &vis,
+                max_rows,
+                false,
+            )),
+            SchemaPart::Timeseries(_timeseries) => None,
+            SchemaPart::Migration(_migration) => None,
+            SchemaPart::MigrationDir(_migration_dir) => None,
         })
         .collect::<Result<TokenStream, Error>>()?;
+    check_defaults(&db, &tables)?;
+    warn_duplicate_queries(query_db, schema)?;
+    check_references(&tables)?;
+    check_conditions(&db, schema)?;
+    check_fts_content(&tables, schema)?;
+    let docs_db = default_schema_db(&attachments, &migrations, &versioned)?;
+    let schema_markdown = docs::markdown(&docs_db, schema)?;
+    let schema_html = docs::html(&docs_db, schema)?;
+    let schema_mermaid = docs::mermaid(&docs_db, schema)?;
+    let schema_dot = docs::dot(&docs_db, schema)?;
+    for part in &schema.parts {
+        if let SchemaPart::Docs(docs) = part {
+            let output = match std::path::Path::new(&docs.path)
+                .extension()
+                .and_then(|ext| ext.to_str())
+            {
+                Some("html" | "htm") => &schema_html,
+                Some("mmd" | "mermaid") => &schema_mermaid,
+                Some("dot" | "gv") => &schema_dot,
+                _ => &schema_markdown,
+            };
+            write_docs(docs, output)?;
+        }
+    }
     let select_struct_tokens = schema
         .parts
         .iter()
         .filter_map(|part| match part {
+            SchemaPart::Attach(_attach) => None,
+            SchemaPart::Pragma(_pragma) => None,
+            SchemaPart::Env(_env) => None,
+            SchemaPart::DebugOutput(_debug_output) => None,
+            SchemaPart::SchemaDiff(_schema_diff) => None,
+            SchemaPart::DevDatabase(_dev_database) => None,
+            SchemaPart::Docs(_docs) => None,
+            SchemaPart::Visibility(_visibility) => None,
+            SchemaPart::MaxRows(_max_rows) => None,
             SchemaPart::Table(_table) => None,
             SchemaPart::Index(_index) => None,
-            SchemaPart::Query(select) => Some(generate_select_struct(&db, select)),
+            SchemaPart::Check(_check) => None,
+            SchemaPart::Query(select) => match owning_table(&select.sql, &tables) {
+                Some(_table) => None,
+                None => Some(generate_select_struct(query_db, &tables, select, &vis)),
+            },
+            SchemaPart::Op(_op) => None,
+            SchemaPart::Execute(_execute) => None,
+            SchemaPart::View(view) => Some(generate_select_struct(
+                query_db,
+                &tables,
+                &view_query(view),
+                &vis,
+            )),
+            SchemaPart::Fts(fts) => Some(Ok(generate_fts_struct(fts, &vis))),
+            SchemaPart::Timeseries(_timeseries) => None,
+            SchemaPart::Migration(_migration) => None,
+            SchemaPart::MigrationDir(_migration_dir) => None,
         })
         .collect::<Result<TokenStream, Error>>()?;
+    let check_trigger_tokens = schema
+        .parts
+        .iter()
+        .filter_map(|part| match part {
+            SchemaPart::Check(check) => Some(check_triggers(check)),
+            _ => None,
+        })
+        .flatten()
+        .map(|(_event, name)| quote! { $name, })
+        .collect::<TokenStream>();
     let migration_tokens = migrations
         .iter()
         .map(|mig| quote! { $mig, })
         .collect::<TokenStream>();
+    let versioned_migration_tokens = versioned_migrations
+        .iter()
+        .map(|migration| {
+            let version = Literal::i64_unsuffixed(migration.version);
+            let up = Literal::string(&migration.up);
+            let down = Literal::string(&migration.down);
+            quote! { sqltight::VersionedMigration { version: $version, up: $up, down: $down }, }
+        })
+        .collect::<TokenStream>();
+    // include_str! makes cargo rebuild when a migration file changes
+    let tracked_migration_files = schema
+        .parts
+        .iter()
+        .filter_map(|part| match part {
+            SchemaPart::MigrationDir(dir) => Some(migration_files(dir)),
+            _ => None,
+        })
+        .collect::<Result<Vec<_>, Error>>()?
+        .into_iter()
+        .flatten()
+        .map(|file| {
+            let file = file.to_string_lossy().into_owned();
+            quote! { const _: &str = include_str!($file); }
+        })
+        .collect::<TokenStream>();
+    let attach_args = attachments
+        .iter()
+        .map(|Attach { schema }| quote! { $schema: &str, })
+        .collect::<TokenStream>();
+    let attach_statements = attachments
+        .iter()
+        .map(|Attach { schema }| {
+            let schema_name = schema.to_string();
+            quote! { let _result = connection.attach($schema, $schema_name)?; }
+        })
+        .collect::<TokenStream>();
+    let expected_attach_statements = attachments
+        .iter()
+        .map(|Attach { schema }| {
+            let schema_name = schema.to_string();
+            quote! { let _result = expected.attach(":memory:", $schema_name)?; }
+        })
+        .collect::<TokenStream>();
+    let attach_pairs = attachments
+        .iter()
+        .map(|Attach { schema }| {
+            let schema_name = schema.to_string();
+            quote! { ($schema.to_string(), $schema_name), }
+        })
+        .collect::<TokenStream>();
+    let attach_names = attachments
+        .iter()
+        .map(|Attach { schema }| quote! { $schema, })
+        .collect::<TokenStream>();
+    let env_arms = envs
+        .iter()
+        .map(|(name, pragmas, env_migrations)| {
+            let env_migrations = env_migrations
+                .iter()
+                .map(|mig| quote! { $mig, })
+                .collect::<TokenStream>();
+            quote! { $name => Ok(($pragmas, vec![$env_migrations])), }
+        })
+        .collect::<TokenStream>();
+    let open_env_name = match envs.iter().any(|(name, ..)| name == "test") {
+        true => quote! { if cfg!(test) { "test" } else { "default" } },
+        false => quote! { "default" },
+    };
     let statements = schema
         .parts
         .iter()
-        .map(statement_from_part)
+        .map(|part| statement_from_part(part, &declared))
+        .collect::<TokenStream>();
+    let advised_sql = schema
+        .parts
+        .iter()
+        .filter_map(|part| match part {
+            SchemaPart::Query(select) => Some(select.sql.clone()),
+            SchemaPart::View(view) => Some(view_query(view).sql),
+            _ => None,
+        })
+        .map(|sql| quote! { $sql, })
         .collect::<TokenStream>();
+    let table_size_fn = match cfg!(feature = "dbstat") {
+        true => {
+            let table_size = Ident::new("table_size", Span::call_site());
+            let table_stats = Ident::new("table_stats", Span::call_site());
+            let fragmentation_report = Ident::new("fragmentation_report", Span::call_site());
+            let connection = Ident::new("connection", Span::call_site());
+            quote! {
+                /// Bytes of the pages `T` and its indexes take, see `Sqlite::table_size`
+                pub fn $table_size<T: sqltight::Table>(&self) -> sqltight::Result<sqltight::TableSize> {
+                    self.$connection.table_size::<T>()
+                }
+
+                /// Pages, unused bytes and depth of `T` and each of its indexes, see `Sqlite::dbstat`
+                pub fn $table_stats<T: sqltight::Table>(&self) -> sqltight::Result<Vec<sqltight::DbStat>> {
+                    self.$connection.table_stats::<T>()
+                }
+
+                /// The unused bytes of each table and its indexes, the most first,
+                /// see `Sqlite::fragmentation_report`
+                pub fn $fragmentation_report(&self) -> sqltight::Result<Vec<sqltight::Fragmentation>> {
+                    self.$connection.fragmentation_report("main")
+                }
+            }
+        }
+        false => TokenStream::new(),
+    };
     // HACK: call_site spans for each ident
     let database = Ident::new("Database", Span::call_site());
+    let connection = Ident::new("connection", Span::call_site());
     let open_fn = Ident::new("open", Span::call_site());
+    let open_env = Ident::new("open_env", Span::call_site());
+    let open_with = Ident::new("open_with", Span::call_site());
+    let open_env_with = Ident::new("open_env_with", Span::call_site());
+    let open_in_data_dir = Ident::new("open_in_data_dir", Span::call_site());
+    let open_immutable = Ident::new("open_immutable", Span::call_site());
+    let open_replica = Ident::new("open_replica", Span::call_site());
+    let open_static_bytes = Ident::new("open_static_bytes", Span::call_site());
+    let open_bytes = Ident::new("open_bytes", Span::call_site());
+    let open_pooled = Ident::new("open_pooled", Span::call_site());
+    let open_pooled_env_with = Ident::new("open_pooled_env_with", Span::call_site());
+    let read_after_write = Ident::new("read_after_write", Span::call_site());
     let transaction = Ident::new("transaction", Span::call_site());
+    let test_transaction = Ident::new("test_transaction", Span::call_site());
+    let last_error = Ident::new("last_error", Span::call_site());
+    let contention_stats = Ident::new("contention_stats", Span::call_site());
     let execute = Ident::new("execute", Span::call_site());
+    let query_as = Ident::new("query_as", Span::call_site());
     let save = Ident::new("save", Span::call_site());
     let delete = Ident::new("delete", Span::call_site());
+    let save_all = Ident::new("save_all", Span::call_site());
+    let attach = Ident::new("attach", Span::call_site());
+    let detach = Ident::new("detach", Span::call_site());
+    let migration_history = Ident::new("migration_history", Span::call_site());
+    let storage_stats = Ident::new("storage_stats", Span::call_site());
+    let profile_table = Ident::new("profile_table", Span::call_site());
+    let estimate_rows = Ident::new("estimate_rows", Span::call_site());
+    let import_csv = Ident::new("import_csv", Span::call_site());
+    let dump = Ident::new("dump", Span::call_site());
+    let backup_to = Ident::new("backup_to", Span::call_site());
+    let integrity_check = Ident::new("integrity_check", Span::call_site());
+    let foreign_key_check = Ident::new("foreign_key_check", Span::call_site());
+    let rebuild_table = Ident::new("rebuild_table", Span::call_site());
+    let schema_markdown_const = Ident::new("SCHEMA_MARKDOWN", Span::call_site());
+    let schema_html_const = Ident::new("SCHEMA_HTML", Span::call_site());
+    let schema_mermaid_const = Ident::new("SCHEMA_MERMAID", Span::call_site());
+    let schema_dot_const = Ident::new("SCHEMA_DOT", Span::call_site());
+    let snapshot = Ident::new("snapshot", Span::call_site());
+    let snapshot_every = Ident::new("snapshot_every", Span::call_site());
+    let as_of = Ident::new("as_of", Span::call_site());
+    let delete_snapshots = Ident::new("delete_snapshots", Span::call_site());
+    let verify_chain = Ident::new("verify_chain", Span::call_site());
+    let insert_series = Ident::new("insert_series", Span::call_site());
+    let series_between = Ident::new("series_between", Span::call_site());
+    let sweep_series = Ident::new("sweep_series", Span::call_site());
+    let suggest_indexes = Ident::new("suggest_indexes", Span::call_site());
+    let incremental_vacuum = Ident::new("incremental_vacuum", Span::call_site());
+    let reclaim_space = Ident::new("reclaim_space", Span::call_site());
+    let invalidate_statements = Ident::new("invalidate_statements", Span::call_site());
+    let record_sql = Ident::new("record_sql", Span::call_site());
+    let detect_repeated_sql = Ident::new("detect_repeated_sql", Span::call_site());
+    let watch_transactions = Ident::new("watch_transactions", Span::call_site());
+    let migrate_to = Ident::new("migrate_to", Span::call_site());
+    let invalidate_caches = Ident::new("invalidate_caches", Span::call_site());
+    let schema_diff = Ident::new("schema_diff", Span::call_site());
+    let rollback_to = Ident::new("rollback_to", Span::call_site());
 
-    Ok(quote! {
+    let prelude = schema
+        .parts
+        .iter()
+        .filter_map(|part| match part {
+            SchemaPart::Table(table) | SchemaPart::Timeseries(Timeseries { table, .. }) => {
+                let module = table_module(table);
+                Some(quote! { pub use super::$module::*; })
+            }
+            _ => None,
+        })
+        .collect::<TokenStream>();
+    let db_module = Ident::new("db", Span::call_site());
+    let queries = Ident::new("queries", Span::call_site());
+    // each table's queries are also in db::queries, next to the ones not on a table
+    let table_queries = tables
+        .iter()
+        .map(|table| {
+            let module = table_module(table);
+            quote! { pub use super::$module::$queries::*; }
+        })
+        .collect::<TokenStream>();
+    let prelude_module = Ident::new("prelude", Span::call_site());
+    let use_invoking_module = use_invoking_module();
+    let database_tokens = quote! {
         #[allow(unused)]
-        pub struct $database {
-            pub connection: sqltight::Sqlite,
-            pub statements: std::collections::HashMap<&'static str, sqltight::Stmt>,
+        $vis struct $database {
+            pub $connection: sqltight::Sqlite,
+            pub statements: sqltight::StatementCache,
+        }
+
+        impl AsRef<sqltight::Sqlite> for $database {
+            fn as_ref(&self) -> &sqltight::Sqlite {
+                &self.$connection
+            }
         }
 
         impl $database {
+            /// The tables, views, fts tables and queries as markdown, `docs = "..."` writes it to a file
+            pub const $schema_markdown_const: &'static str = $schema_markdown;
+            /// The same as `SCHEMA_MARKDOWN` as a standalone html page
+            pub const $schema_html_const: &'static str = $schema_html;
+            /// The tables and their foreign keys as a mermaid `erDiagram`
+            pub const $schema_mermaid_const: &'static str = $schema_mermaid;
+            /// The tables and their foreign keys as a graphviz digraph
+            pub const $schema_dot_const: &'static str = $schema_dot;
+
             pub fn $transaction<'a>(&'a self) -> sqltight::Result<sqltight::Transaction<'a>> {
-                let tx = self.connection.transaction()?;
+                let tx = self.$connection.transaction()?;
                 Ok(sqltight::Transaction(tx))
             }
 
+            /// Runs `f` in a transaction that is always rolled back, whatever `f` returns,
+            /// see `Sqlite::test_transaction`
+            pub fn $test_transaction<T>(&self, f: impl FnOnce(&sqltight::Transaction) -> T) -> sqltight::Result<T> {
+                let tx = sqltight::Transaction(self.$connection.test_transaction()?);
+                Ok(f(&tx))
+            }
+
+            #[track_caller]
             pub fn $execute(&self, sql: &str) -> sqltight::Result<i32> {
-                self.connection.execute(sql)
+                let result = self.$connection.execute(sql)?;
+                // only ddl changes what the cached statements read and write
+                if sqltight::changes_schema(sql) {
+                    self.$invalidate_statements()?;
+                }
+                Ok(result)
+            }
+
+            /// Maps the rows of sql written at runtime into `T`, see `Sqlite::query_as`
+            #[track_caller]
+            pub fn $query_as<T: sqltight::FromRow>(
+                &self,
+                sql: &str,
+                params: &[sqltight::Value],
+            ) -> sqltight::Result<Vec<T>> {
+                self.$connection.query_as(sql, params)
+            }
+
+            /// The error of the last statement that failed on `connection`, e.g. for error reporting
+            /// middleware, see `Sqlite::last_error`
+            pub fn $last_error(&self) -> Option<sqltight::LastError> {
+                self.$connection.last_error()
             }
 
+            /// Busy retries, lock waits and the wal size of `connection`, see `Sqlite::contention_stats`
+            pub fn $contention_stats(&self) -> sqltight::Result<sqltight::ContentionStats> {
+                self.$connection.contention_stats()
+            }
+
+            /// Drops every cached statement, they are prepared again on next use
+            pub fn $invalidate_statements(&self) -> sqltight::Result<()> {
+                self.statements.clear();
+                self.$connection.clear_statement_cache()
+            }
+
+            /// With `open_pooled`, makes this thread's next queries see every save, delete and
+            /// transaction committed so far, see `StatementCache::read_after_write`.
+            /// Queries always see an open transaction's writes, they run on `connection` until it ends
+            pub fn $read_after_write(&self) {
+                self.statements.read_after_write()
+            }
+
+            fn statement(&self, name: &'static str, sql: &str) -> sqltight::Result<sqltight::Stmt> {
+                self.statements.get(&self.$connection, name, sql)
+            }
+
+            #[track_caller]
             pub fn $save<T: sqltight::Crud>(&self, row: T) -> sqltight::Result<T> {
-                row.save(&self.connection)
+                row.save(&self.$connection)
             }
 
+            #[track_caller]
             pub fn $delete<T: sqltight::Crud>(&self, row: T) -> sqltight::Result<T> {
-                row.delete(&self.connection)
+                row.delete(&self.$connection)
+            }
+
+            /// Saves every row in one transaction, all or nothing, many rows per upsert
+            #[track_caller]
+            pub fn $save_all<T: sqltight::Crud>(&self, rows: Vec<T>) -> sqltight::Result<Vec<T>> {
+                T::save_all(rows, &self.$connection)
+            }
+
+            pub fn $attach(&self, path: &str, schema_name: &str) -> sqltight::Result<()> {
+                self.$connection.attach(path, schema_name)
+            }
+
+            pub fn $detach(&self, schema_name: &str) -> sqltight::Result<()> {
+                self.$connection.detach(schema_name)
+            }
+
+            /// Records every statement until the returned recording is dropped, see `Sqlite::record_sql`
+            pub fn $record_sql(&self) -> sqltight::SqlRecording {
+                self.$connection.record_sql()
+            }
+
+            /// Reports statements run more than `threshold` times, see `Sqlite::detect_repeated_sql`
+            pub fn $detect_repeated_sql(&self, threshold: usize) -> sqltight::RepeatedSqlDetector {
+                self.$connection.detect_repeated_sql(threshold)
+            }
+
+            /// Reports transactions open longer than `max_open` until the returned watchdog is dropped,
+            /// see `Sqlite::watch_transactions`
+            pub fn $watch_transactions(
+                &self,
+                max_open: std::time::Duration,
+                on_long_transaction: sqltight::OnLongTransaction,
+                report: impl Fn(sqltight::LongTransaction) + Send + 'static,
+            ) -> sqltight::TransactionWatchdog {
+                self.$connection.watch_transactions(max_open, on_long_transaction, report)
+            }
+
+            /// Drops cache keys as writes to their tables commit, see `Sqlite::invalidate_caches`
+            pub fn $invalidate_caches<K: std::hash::Hash + Eq + Clone + Send + 'static>(
+                &self,
+                invalidator: sqltight::CacheInvalidator<K>,
+            ) -> sqltight::Result<()> {
+                self.$connection.invalidate_caches(invalidator)
+            }
+
+            /// File, wal and page sizes of the main database, see `Sqlite::storage_stats`
+            pub fn $storage_stats(&self) -> sqltight::Result<sqltight::StorageStats> {
+                self.$connection.storage_stats()
+            }
+
+            /// Null and distinct counts, min, max and average length of each column of `T`,
+            /// see `Sqlite::profile_table`
+            pub fn $profile_table<T: sqltight::Table>(
+                &self,
+            ) -> sqltight::Result<Vec<sqltight::ColumnProfile>> {
+                self.$connection.profile::<T>()
+            }
+
+            /// About how many rows `T` has without a count(*) scan, see `Sqlite::estimate_rows`
+            pub fn $estimate_rows<T: sqltight::Table>(&self) -> sqltight::Result<i64> {
+                self.$connection.estimate_rows::<T>()
+            }
+
+            $table_size_fn
+
+            /// Compacts `T` and its indexes by copying it into a new table, without a `vacuum`
+            /// of the whole file, see `Sqlite::rebuild_table`
+            pub fn $rebuild_table<T: sqltight::Table>(&self) -> sqltight::Result<()> {
+                self.$connection.rebuild_table::<T>()
+            }
+
+            /// Loads the csv records of `reader` into `T` in one savepoint, see `Sqlite::import_csv`
+            pub fn $import_csv<T: sqltight::Table>(
+                &self,
+                reader: impl std::io::Read,
+                options: sqltight::CsvOptions,
+            ) -> sqltight::Result<sqltight::CsvImport> {
+                self.$connection.import_csv::<T>(reader, options)
+            }
+
+            /// Writes the schema and rows as a sql script, see `Sqlite::dump` and `Sqlite::restore`
+            pub fn $dump(&self, writer: impl std::io::Write) -> sqltight::Result<()> {
+                self.$connection.dump(writer)
+            }
+
+            /// Copies the database to a new file at `path` and returns its size, see
+            /// `Sqlite::backup_to`
+            pub fn $backup_to(&self, path: &str) -> sqltight::Result<u64> {
+                self.$connection.backup_to(path)
+            }
+
+            /// Up to `max_errors` problems in the file, empty when it's fine, see
+            /// `Sqlite::integrity_check`
+            pub fn $integrity_check(&self, max_errors: usize) -> sqltight::Result<Vec<sqltight::IntegrityError>> {
+                self.$connection.integrity_check(max_errors)
+            }
+
+            /// The rows whose foreign keys point at missing rows, see `Sqlite::foreign_key_check`
+            pub fn $foreign_key_check(&self) -> sqltight::Result<Vec<sqltight::ForeignKeyViolation>> {
+                self.$connection.foreign_key_check()
+            }
+
+            /// Copies the rows of `T` into its snapshot table stamped with `at` in unix seconds,
+            /// see `Sqlite::snapshot_tables`
+            pub fn $snapshot<T: sqltight::Table>(&self, at: i64) -> sqltight::Result<usize> {
+                self.$connection.snapshot::<T>(at)
+            }
+
+            /// Snapshots `tables` every `interval` until the scheduler is dropped,
+            /// see `Sqlite::snapshot_every`
+            pub fn $snapshot_every(
+                &self,
+                interval: std::time::Duration,
+                tables: Vec<sqltight::SnapshotTable>,
+                on_error: impl Fn(sqltight::Error) + Send + 'static,
+            ) -> sqltight::Result<sqltight::SnapshotScheduler> {
+                self.$connection.snapshot_every(interval, tables, on_error)
+            }
+
+            /// The rows of `T` in the latest snapshot taken at or before `at`
+            pub fn $as_of<T: sqltight::Table + sqltight::FromRow>(
+                &self,
+                at: i64,
+            ) -> sqltight::Result<Vec<T>> {
+                self.$connection.as_of::<T>(at)
+            }
+
+            pub fn $delete_snapshots<T: sqltight::Table>(
+                &self,
+                before: i64,
+            ) -> sqltight::Result<usize> {
+                self.$connection.delete_snapshots::<T>(before)
+            }
+
+            /// Checks the hash chain of the log table `T`, see `Sqlite::verify_chain`
+            pub fn $verify_chain<T: sqltight::LogTable>(&self) -> sqltight::Result<usize> {
+                self.$connection.verify_chain::<T>()
+            }
+
+            /// Inserts `row` into the partition of its month, see `Sqlite::insert_series`
+            #[track_caller]
+            pub fn $insert_series<T: sqltight::Timeseries>(&self, row: T) -> sqltight::Result<()> {
+                self.$connection.insert_series(row)
+            }
+
+            /// The rows of `T` from `from` up to `to` in unix seconds, see `Sqlite::series_between`
+            pub fn $series_between<T: sqltight::Timeseries>(
+                &self,
+                from: i64,
+                to: i64,
+            ) -> sqltight::Result<Vec<T>> {
+                self.$connection.series_between::<T>(from, to)
+            }
+
+            /// Removes the rows of `T` older than `before`, see `Sqlite::sweep_series`
+            pub fn $sweep_series<T: sqltight::Timeseries>(&self, before: i64) -> sqltight::Result<usize> {
+                self.$connection.sweep_series::<T>(before)
+            }
+
+            /// Gives up to `pages` free pages back, see `Sqlite::incremental_vacuum`
+            pub fn $incremental_vacuum(&self, pages: u32) -> sqltight::Result<i64> {
+                self.$connection.incremental_vacuum(pages)
+            }
+
+            /// Gives free pages back when `policy` says so, see `Sqlite::reclaim_space`
+            pub fn $reclaim_space(&self, policy: sqltight::VacuumPolicy) -> sqltight::Result<i64> {
+                self.$connection.reclaim_space(policy)
+            }
+
+            /// Indexes that would turn full scans in the declared queries into lookups,
+            /// see `Sqlite::suggest_indexes`
+            pub fn $suggest_indexes(&self) -> sqltight::Result<Vec<sqltight::IndexSuggestion>> {
+                self.$connection.suggest_indexes(&[$advised_sql])
+            }
+
+            const VERSIONED_MIGRATIONS: &'static [sqltight::VersionedMigration<'static>] = &[$versioned_migration_tokens];
+
+            /// Runs the declared migrations past the current version through `version`,
+            /// see `Sqlite::migrate_to`
+            pub fn $migrate_to(&self, version: i64) -> sqltight::Result<i64> {
+                let version = self.$connection.migrate_to(Self::VERSIONED_MIGRATIONS, version)?;
+                self.$invalidate_statements()?;
+                Ok(version)
+            }
+
+            /// Runs the down sql of the applied migrations above `version`, see `Sqlite::rollback_to`
+            pub fn $rollback_to(&self, version: i64) -> sqltight::Result<i64> {
+                let version = self.$connection.rollback_to(Self::VERSIONED_MIGRATIONS, version)?;
+                self.$invalidate_statements()?;
+                Ok(version)
+            }
+
+            pub fn $migration_history(&self) -> sqltight::Result<Vec<sqltight::MigrationRecord>> {
+                self.$connection.migration_history()
+            }
+
+            pub fn $open_fn(path: &str, $attach_args) -> sqltight::Result<Self> {
+                Self::$open_env_with(path, $open_env_name, sqltight::OpenOptions::default(), $attach_names)
+            }
+
+            pub fn $open_with(path: &str, options: sqltight::OpenOptions, $attach_args) -> sqltight::Result<Self> {
+                Self::$open_env_with(path, $open_env_name, options, $attach_names)
+            }
+
+            /// Opens and migrates `<data dir>/<app_name>/<app_name>.db`, see `sqltight::data_dir`
+            pub fn $open_in_data_dir(app_name: &str, $attach_args) -> sqltight::Result<Self> {
+                let path = sqltight::data_dir(app_name)?.join(format!("{app_name}.db"));
+                let options = sqltight::OpenOptions {
+                    create_parent_dirs: true,
+                    ..Default::default()
+                };
+                Self::$open_with(&sqltight::path_str(&path)?, options, $attach_names)
+            }
+
+            /// Opens a read only database as is, without pragmas or migrations, see `Sqlite::open_immutable`
+            pub fn $open_immutable(path: &str, $attach_args) -> sqltight::Result<Self> {
+                Self::read_only(sqltight::Sqlite::open_immutable(path)?, $attach_names)
+            }
+
+            /// Opens a read only copy of `primary_path` at `{primary_path}-replica`, refreshed every
+            /// `interval` while the `Replica` is alive, see `Sqlite::open_replica`
+            pub fn $open_replica(primary_path: &str, interval: std::time::Duration, $attach_args) -> sqltight::Result<(Self, sqltight::Replica)> {
+                let replica_path = format!("{primary_path}-replica");
+                let (connection, replica) = sqltight::Sqlite::open_replica(primary_path, &replica_path, interval)?;
+                Ok((Self::read_only(connection, $attach_names)?, replica))
+            }
+
+            /// Opens a database compiled in with `include_bytes!`, see `Sqlite::open_static_bytes`
+            pub fn $open_static_bytes(bytes: &'static [u8], $attach_args) -> sqltight::Result<Self> {
+                Self::read_only(sqltight::Sqlite::open_static_bytes(bytes)?, $attach_names)
+            }
+
+            /// Opens a read only copy of a database file's bytes, see `Sqlite::open_bytes`
+            pub fn $open_bytes(bytes: &[u8], $attach_args) -> sqltight::Result<Self> {
+                Self::read_only(sqltight::Sqlite::open_bytes(bytes)?, $attach_names)
+            }
+
+            /// Compares the declared schema with the database at `path` without migrating it,
+            /// see `Sqlite::schema_diff`
+            pub fn $schema_diff(path: &str) -> sqltight::Result<Vec<sqltight::SchemaDiff>> {
+                let expected = sqltight::Sqlite::open(":memory:")?;
+                $expected_attach_statements
+                let (_pragmas, env_migrations) = Self::env($open_env_name)?;
+                let migrations = [&[$migration_tokens][..], &env_migrations[..]].concat();
+                let _result = expected.migrate(&migrations, env!("CARGO_PKG_VERSION"))?;
+                let _version = expected.migrate_to(Self::VERSIONED_MIGRATIONS, i64::MAX)?;
+                let options = sqltight::OpenOptions {
+                    on_missing: sqltight::OnMissing::Error,
+                    ..Default::default()
+                };
+                let live = sqltight::Sqlite::open_with(path, options)?;
+                live.schema_diff(&expected)
+            }
+
+            fn read_only(connection: sqltight::Sqlite, $attach_args) -> sqltight::Result<Self> {
+                $attach_statements
+                let statements = sqltight::StatementCache::new(vec![$statements])?;
+                Ok(Self { $connection: connection, statements })
+            }
+
+            /// Opens and migrates like `open`, then runs queries on a connection per thread
+            /// while saves, deletes and transactions stay on `connection`, see `StatementCache::pooled`
+            pub fn $open_pooled(path: &str, $attach_args) -> sqltight::Result<Self> {
+                Self::$open_pooled_env_with(path, $open_env_name, sqltight::OpenOptions::default(), $attach_names)
+            }
+
+            /// Like `open_pooled`, every connection opened with `env`'s pragmas and `options`.
+            /// An in-memory database is private to its connection, so it can't be pooled
+            pub fn $open_pooled_env_with(path: &str, env: &str, options: sqltight::OpenOptions, $attach_args) -> sqltight::Result<Self> {
+                if path.is_empty() || path == ":memory:" {
+                    return Err(sqltight::Error::PooledInMemory);
+                }
+                let mut db = Self::$open_env_with(path, env, options, $attach_names)?;
+                let (pragmas, _env_migrations) = Self::env(env)?;
+                let path = path.to_string();
+                let attachments: Vec<(String, &'static str)> = vec![$attach_pairs];
+                db.statements = sqltight::StatementCache::pooled(move || {
+                    let connection = sqltight::Sqlite::open_with(&path, options)?;
+                    let _result = connection.execute(pragmas)?;
+                    connection.track_contention()?;
+                    for (path, schema_name) in &attachments {
+                        let _result = connection.attach(path, schema_name)?;
+                    }
+                    Ok(connection)
+                });
+                Ok(db)
+            }
+
+            pub fn $open_env(path: &str, env: &str, $attach_args) -> sqltight::Result<Self> {
+                Self::$open_env_with(path, env, sqltight::OpenOptions::default(), $attach_names)
+            }
+
+            pub fn $open_env_with(path: &str, env: &str, options: sqltight::OpenOptions, $attach_args) -> sqltight::Result<Self> {
+                let (pragmas, env_migrations) = Self::env(env)?;
+                let connection = sqltight::Sqlite::open_with(path, options)?;
+                let _result = connection.execute(pragmas)?;
+                connection.track_contention()?;
+                $attach_statements
+                let migrations = [&[$migration_tokens][..], &env_migrations[..]].concat();
+                let _result = connection.migrate(&migrations, env!("CARGO_PKG_VERSION"))?;
+                connection.drop_stale_checks(&[$check_trigger_tokens])?;
+                let _version = connection.migrate_to(Self::VERSIONED_MIGRATIONS, i64::MAX)?;
+                let statements = sqltight::StatementCache::new(vec![$statements])?;
+                Ok(Self { $connection: connection, statements })
             }
 
-            pub fn $open_fn(path: &str) -> sqltight::Result<Self> {
-                let connection = sqltight::Sqlite::open(path)?;
-                let _result = connection.execute(
-                    "PRAGMA journal_mode = WAL;
-                    PRAGMA busy_timeout = 5000;
-                    PRAGMA synchronous = NORMAL;
-                    PRAGMA cache_size = 1000000000;
-                    PRAGMA foreign_keys = true;
-                    PRAGMA temp_store = memory;",
-                )?;
-                let _result = connection.migrate(&[$migration_tokens])?;
-                let statements: std::collections::HashMap<&'static str, sqltight::Stmt> = vec![$statements].into_iter().collect();
-                Ok(Self { connection, statements })
+            fn env(env: &str) -> sqltight::Result<(&'static str, Vec<&'static str>)> {
+                match env {
+                    $env_arms
+                    _ => Err(sqltight::Error::UnknownEnv(env.to_string())),
+                }
             }
 
             $select_tokens
+
+            $relation_read_tokens
         }
 
-        $table_tokens
-        $select_struct_tokens
-    })
+    };
+    let tokens = quote! {
+        pub mod $db_module {
+            $use_invoking_module
+
+            $tracked_migration_files
+
+            $database_tokens
+
+            $table_tokens
+
+            $relation_tokens
+
+            $query_plan_tests
+
+            pub mod $queries {
+                $use_invoking_module
+
+                $table_queries
+
+                $select_struct_tokens
+            }
+
+            pub mod $prelude_module {
+                $vis use super::$database;
+                pub use super::$queries::*;
+                $prelude
+            }
+        }
+
+        // a glob so items declared next to db! shadow generated ones instead of clashing
+        pub use $db_module::$prelude_module::*;
+    };
+    for part in &schema.parts {
+        if let SchemaPart::DebugOutput(debug_output) = part {
+            write_debug_output(debug_output, &tokens, &migrations, &envs)?;
+        }
+    }
+    Ok(tokens)
 }
 
-fn migration(part: &SchemaPart) -> Vec<String> {
-    match part {
-        SchemaPart::Table(table) => table_migrations(table),
-        SchemaPart::Index(index) => index_migrations(index),
-        SchemaPart::Query(_select) => vec![],
+/// Warns about every way the database at `schema_diff = "..."`, relative to the invoking
+/// crate's manifest dir, differs from the declared schema. A missing file is skipped,
+/// so builds without a local database still work
+fn warn_schema_diff(
+    SchemaDiffCheck { path, span }: &SchemaDiffCheck,
+    attachments: &[&Attach],
+    migrations: &[String],
+    versioned: &[sqltight_core::VersionedMigration],
+) -> Result<(), Error> {
+    let path = manifest_path(path);
+    if !path.exists() {
+        return Ok(());
     }
+    let expected = default_schema_db(attachments, migrations, versioned)?;
+    let live = open_existing(&path)?;
+    for diff in live.schema_diff(&expected)? {
+        Diagnostic::spanned(*span, Level::Warning, format!("{}: {diff}", path.display())).emit();
+    }
+    Ok(())
 }
 
-fn table_migrations(table: &Table) -> Vec<String> {
-    let table_name = table.name.to_string();
-    let columns = table
-        .fields
-        .iter()
-        .filter(|field| field.name.to_string() != "id");
-    let mut migrations = vec![format!(
-        "create table if not exists {table_name} ( id integer primary key ) strict"
-    )];
-    migrations.extend(columns.map(|Field { name, ty }| {
-        format!("alter table {} add column {} {}", table_name, name, ty)
-    }));
-    migrations
+/// Opens `path = "..."` relative to the invoking crate's manifest dir, without creating
+/// or migrating it. A missing file is skipped, so builds without a local database still work
+fn open_dev_database(
+    DevDatabase { path, span }: &DevDatabase,
+    attachments: &[&Attach],
+) -> Result<Option<sqltight_core::Sqlite>, Error> {
+    if !attachments.is_empty() {
+        let text = "path can't be combined with attach, the attached databases aren't known at compile time";
+        Diagnostic::spanned(*span, Level::Error, text).emit();
+        return Err(Error::Generate(text.to_string()));
+    }
+    let path = manifest_path(path);
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(open_existing(&path)?))
 }
 
-fn index_migrations(index: &Index) -> Vec<String> {
-    index
-        .fields
-        .iter()
-        .map(|field| {
-            format!(
-                "create {} index if not exists {}_{}_ix on {} ({})",
-                match field.ty.to_string().as_str() {
-                    "Unique" => "unique",
-                    _ => "",
-                },
-                index.name,
-                field.name,
-                index.name,
-                field.name
-            )
+/// A path from the macro, relative to the invoking crate's manifest dir
+fn manifest_path(path: &str) -> std::path::PathBuf {
+    match std::env::var("CARGO_MANIFEST_DIR") {
+        Ok(dir) => std::path::Path::new(&dir).join(path),
+        Err(_) => std::path::PathBuf::from(path),
+    }
+}
+
+/// Opens a database file that has to exist already, it is never created at compile time
+fn open_existing(path: &std::path::Path) -> Result<sqltight_core::Sqlite, Error> {
+    let options = sqltight_core::OpenOptions {
+        on_missing: sqltight_core::OnMissing::Error,
+        ..Default::default()
+    };
+    Ok(sqltight_core::Sqlite::open_with(
+        &sqltight_core::path_str(path)?,
+        options,
+    )?)
+}
+
+/// A fresh in-memory database with the default schema, without any env's extra migrations
+fn default_schema_db(
+    attachments: &[&Attach],
+    migrations: &[String],
+    versioned: &[sqltight_core::VersionedMigration],
+) -> Result<sqltight_core::Sqlite, Error> {
+    let db = sqltight_core::Sqlite::open(":memory:")?;
+    for Attach { schema } in attachments {
+        db.attach(":memory:", &schema.to_string())?;
+    }
+    db.migrate(migrations, env!("CARGO_PKG_VERSION"))?;
+    let _version = db.migrate_to(versioned, i64::MAX)?;
+    Ok(db)
+}
+
+/// Writes the schema docs relative to the invoking crate's manifest dir
+fn write_docs(Docs { path }: &Docs, output: &str) -> Result<(), Error> {
+    let path = manifest_path(path);
+    // unchanged docs aren't rewritten, so tools watching the file don't see a change every build
+    if std::fs::read_to_string(&path).is_ok_and(|existing| existing == output) {
+        return Ok(());
+    }
+    path.parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(&path, output))
+        .map_err(|err| {
+            Error::Generate(format!("Could not write docs to {}: {err}", path.display()))
         })
-        .collect()
 }
 
-fn generate_table(table: &Table) -> Result<TokenStream, Error> {
-    let name = &table.name;
-    let fields = table
-        .fields
+/// Writes the migrations as comments followed by the generated code, relative to the
+/// invoking crate's manifest dir. rustfmt is run on it when it is installed.
+fn write_debug_output(
+    DebugOutput { path }: &DebugOutput,
+    tokens: &TokenStream,
+    migrations: &[String],
+    envs: &[(String, String, Vec<String>)],
+) -> Result<(), Error> {
+    let path = manifest_path(path);
+    let comment = |sql: &str| {
+        sql.lines()
+            .map(|line| format!("// {}\n", line.trim()))
+            .collect::<String>()
+    };
+    let mut output = String::from("// generated by sqltight::db!\n\n// migrations\n");
+    for sql in migrations {
+        output.push_str(&comment(&format!("{sql};")));
+    }
+    for (name, pragmas, env_migrations) in envs {
+        output.push_str(&format!("\n// env {name}\n"));
+        output.push_str(&comment(pragmas));
+        for sql in env_migrations {
+            output.push_str(&comment(&format!("{sql};")));
+        }
+    }
+    output.push_str(&format!("\n{tokens}\n"));
+    let result = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(&path, output));
+    if let Err(err) = result {
+        return Err(Error::Generate(format!(
+            "Could not write debug_output to {}: {err}",
+            path.display()
+        )));
+    }
+    let _result = std::process::Command::new("rustfmt")
+        .args(["--edition", "2024"])
+        .arg(&path)
+        .output();
+    Ok(())
+}
+
+const DEFAULT_PRAGMAS: [(&str, &str); 6] = [
+    ("journal_mode", "WAL"),
+    ("busy_timeout", "5000"),
+    ("synchronous", "NORMAL"),
+    ("cache_size", "1000000000"),
+    ("foreign_keys", "true"),
+    ("temp_store", "memory"),
+];
+
+fn merge_pragmas(pragmas: &mut Vec<(String, String)>, parts: &[SchemaPart]) {
+    for part in parts {
+        if let SchemaPart::Pragma(Pragma { name, value }) = part {
+            let name = name.to_string();
+            match pragmas.iter_mut().find(|(existing, _)| *existing == name) {
+                Some(pragma) => pragma.1 = value.clone(),
+                None => pragmas.push((name, value.clone())),
+            }
+        }
+    }
+}
+
+/// every env with its pragma block and extra migrations, "default" always comes first
+fn envs(schema: &DatabaseSchema) -> Vec<(String, String, Vec<String>)> {
+    let declared = schema
+        .parts
+        .iter()
+        .filter_map(|part| match part {
+            SchemaPart::Env(env) => Some(env),
+            _ => None,
+        })
+        .collect::<Vec<&Env>>();
+    let mut names = vec!["default".to_string()];
+    for env in &declared {
+        let name = env.name.to_string();
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+        .into_iter()
+        .map(|name| {
+            let mut pragmas = DEFAULT_PRAGMAS
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<Vec<_>>();
+            merge_pragmas(&mut pragmas, &schema.parts);
+            let mut migrations = vec![];
+            for env in declared.iter().filter(|env| env.name.to_string() == name) {
+                merge_pragmas(&mut pragmas, &env.parts);
+                migrations.extend(env.parts.iter().flat_map(migration));
+            }
+            let pragmas = pragmas
+                .iter()
+                .map(|(name, value)| format!("PRAGMA {name} = {value};"))
+                .collect::<Vec<_>>()
+                .join("\n");
+            (name, pragmas, migrations)
+        })
+        .collect()
+}
+
+fn migration(part: &SchemaPart) -> Vec<String> {
+    match part {
+        SchemaPart::Attach(_attach) => vec![],
+        SchemaPart::Pragma(_pragma) => vec![],
+        SchemaPart::Env(_env) => vec![],
+        SchemaPart::DebugOutput(_debug_output) => vec![],
+        SchemaPart::SchemaDiff(_schema_diff) => vec![],
+        SchemaPart::DevDatabase(_dev_database) => vec![],
+        SchemaPart::Docs(_docs) => vec![],
+        SchemaPart::Visibility(_visibility) => vec![],
+        SchemaPart::MaxRows(_max_rows) => vec![],
+        SchemaPart::Table(table) => table_migrations(table),
+        SchemaPart::Index(index) => index_migrations(index),
+        SchemaPart::Check(check) => check_migrations(check),
+        SchemaPart::Query(_select) => vec![],
+        SchemaPart::Op(_op) => vec![],
+        SchemaPart::Execute(_execute) => vec![],
+        SchemaPart::View(view) => view_migrations(view),
+        SchemaPart::Fts(fts) => fts_migrations(fts),
+        // partitions are created as their first row is inserted
+        SchemaPart::Timeseries(_timeseries) => vec![],
+        SchemaPart::Migration(_migration) => vec![],
+        SchemaPart::MigrationDir(_migration_dir) => vec![],
+    }
+}
+
+/// The numbered migrations, declared or read from `migrations` dirs, in version order
+/// with each version declared once
+fn versioned_migrations(schema: &DatabaseSchema) -> Result<Vec<Migration>, Error> {
+    let mut migrations = vec![];
+    for part in &schema.parts {
+        match part {
+            SchemaPart::Migration(migration) => migrations.push(migration.clone()),
+            SchemaPart::MigrationDir(dir) => migrations.extend(read_migration_dir(dir)?),
+            _ => {}
+        }
+    }
+    migrations.sort_by_key(|migration| migration.version);
+    match migrations
+        .windows(2)
+        .find(|pair| pair[0].version == pair[1].version)
+    {
+        Some(pair) => Err(Error::Parse(format!(
+            "migration {} is declared more than once",
+            pair[0].version
+        ))),
+        None => Ok(migrations),
+    }
+}
+
+/// The .sql files of a `migrations` dir, relative to the invoking crate's manifest dir
+fn migration_files(MigrationDir { path }: &MigrationDir) -> Result<Vec<std::path::PathBuf>, Error> {
+    let dir = manifest_path(path);
+    let entries = std::fs::read_dir(&dir).map_err(|err| {
+        Error::Generate(format!(
+            "Could not read migrations from {}: {err}",
+            dir.display()
+        ))
+    })?;
+    let mut files = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "sql"))
+        .collect::<Vec<_>>();
+    files.sort();
+    Ok(files)
+}
+
+/// Reads `0001_name.sql` or `0001_name.up.sql` as the up sql of migration 1,
+/// and `0001_name.down.sql` as its down sql. Without one the migration can't be rolled back
+fn read_migration_dir(dir: &MigrationDir) -> Result<Vec<Migration>, Error> {
+    let mut migrations = std::collections::BTreeMap::<i64, (Option<String>, String)>::new();
+    for file in migration_files(dir)? {
+        let name = file
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let version = name
+            .split(|c: char| !c.is_ascii_digit())
+            .next()
+            .and_then(|digits| digits.parse::<i64>().ok())
+            .filter(|version| *version > 0)
+            .ok_or_else(|| {
+                Error::Generate(format!(
+                    "Expected a numbered migration file like 0001_create_user.sql, but got: {name}"
+                ))
+            })?;
+        let sql = std::fs::read_to_string(&file).map_err(|err| {
+            Error::Generate(format!(
+                "Could not read migration {}: {err}",
+                file.display()
+            ))
+        })?;
+        let (up, down) = migrations.entry(version).or_default();
+        match name.ends_with(".down.sql") {
+            true => *down = sql,
+            false if up.is_some() => {
+                return Err(Error::Generate(format!(
+                    "migration {version} has more than one up file in {}",
+                    dir.path
+                )));
+            }
+            false => *up = Some(sql),
+        }
+    }
+    migrations
+        .into_iter()
+        .map(|(version, (up, down))| match up {
+            Some(up) => Ok(Migration { version, up, down }),
+            None => Err(Error::Generate(format!(
+                "migration {version} in {} has a down file but no up file",
+                dir.path
+            ))),
+        })
+        .collect()
+}
+
+/// Views are recreated on every open, so a changed select replaces the old one
+fn view_migrations(view: &View) -> Vec<String> {
+    vec![
+        format!("drop view if exists {}", view.name),
+        format!("create view {} as {}", view.name, view.sql),
+    ]
+}
+
+/// A view is read like `query name "select * from name"`
+fn view_query(view: &View) -> Query {
+    Query {
+        vis: view.vis.clone(),
+        fn_name: view.name.clone(),
+        max_rows: None,
+        paginate: false,
+        uses_index: None,
+        sql: format!("select * from {}", view.name),
+        param_types: vec![],
+        literal: None,
+        with_deleted: false,
+    }
+}
+
+/// The fts5 table, plus triggers that copy every write to the `for` table into it.
+/// Rows written before the fts table existed are indexed by a rebuild
+fn fts_migrations(fts: &Fts) -> Vec<String> {
+    let name = &fts.name;
+    let columns = fts
+        .columns
+        .iter()
+        .map(|column| column.to_string())
+        .collect::<Vec<_>>();
+    let Some(content) = &fts.content else {
+        return vec![format!(
+            "create virtual table if not exists {name} using fts5({})",
+            columns.join(", ")
+        )];
+    };
+    let prefixed = |prefix: &str| {
+        columns
+            .iter()
+            .map(|column| format!("{prefix}.{column}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let insert = format!(
+        "insert into {name} (rowid, {}) values (new.id, {});",
+        columns.join(", "),
+        prefixed("new")
+    );
+    let delete = format!(
+        "insert into {name} ({name}, rowid, {}) values ('delete', old.id, {});",
+        columns.join(", "),
+        prefixed("old")
+    );
+    vec![
+        format!(
+            "create virtual table if not exists {name} using fts5({}, content='{content}', content_rowid='id')",
+            columns.join(", ")
+        ),
+        format!(
+            "create trigger if not exists {name}_insert after insert on {content} begin {insert} end"
+        ),
+        format!(
+            "create trigger if not exists {name}_delete after delete on {content} begin {delete} end"
+        ),
+        format!(
+            "create trigger if not exists {name}_update after update on {content} begin {delete} {insert} end"
+        ),
+        format!(
+            "insert into {name} ({name}) select 'rebuild' where not exists (select 1 from {name}_docsize) and exists (select 1 from {content})"
+        ),
+    ]
+}
+
+/// An fts table is searched like `query post_search "... where PostSearch match :query order by rank"`
+fn fts_query(fts: &Fts) -> Query {
+    let name = &fts.name;
+    let columns = fts
+        .columns
+        .iter()
+        .map(|column| column.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    Query {
+        vis: fts.vis.clone(),
+        fn_name: Ident::new(&snake_case(&name.to_string()), name.span()),
+        max_rows: None,
+        paginate: false,
+        uses_index: None,
+        sql: format!(
+            "select rowid as id, {columns}, rank from {name} where {name} match :query order by rank"
+        ),
+        param_types: vec![],
+        literal: None,
+        with_deleted: false,
+    }
+}
+
+fn qualified_name(schema: &Option<Ident>, name: &Ident) -> String {
+    match schema {
+        Some(schema) => format!("{schema}.{name}"),
+        None => name.to_string(),
+    }
+}
+
+fn table_migrations(table: &Table) -> Vec<String> {
+    let table_name = qualified_name(&table.schema, &table.name);
+    let columns = table
+        .fields
+        .iter()
+        .filter(|field| field.name.to_string() != "id");
+    let mut migrations = vec![format!(
+        "create table if not exists {table_name} ( id integer primary key ) strict"
+    )];
+    for field in columns {
+        // the rename goes first so the add column below finds the data already there
+        if let Some(old_name) = &field.renamed_from {
+            migrations.push(format!(
+                "alter table {table_name} rename column {old_name} to {}",
+                field.name
+            ));
+        }
+        migrations.push(format!(
+            "alter table {} add column {} {}{}",
+            table_name,
+            field.name,
+            column_type(field),
+            references(field)
+        ));
+    }
+    migrations.extend(
+        table
+            .dropped
+            .iter()
+            .map(|column| format!("alter table {table_name} drop column {column}")),
+    );
+    if table.log {
+        migrations.extend(log_migrations(table));
+    }
+    migrations
+}
+
+/// Rows of a log table can't be deleted, or updated once `append` set their hash
+fn log_migrations(table: &Table) -> Vec<String> {
+    let name = &table.name;
+    let trigger = |event: &str| {
+        qualified_name(
+            &table.schema,
+            &Ident::new(&format!("{name}_append_only_{event}"), Span::call_site()),
+        )
+    };
+    vec![
+        format!(
+            "create trigger if not exists {} before update on {name} when old.hash is not null begin select raise(abort, '{name} is append-only'); end",
+            trigger("update")
+        ),
+        format!(
+            "create trigger if not exists {} before delete on {name} begin select raise(abort, '{name} is append-only'); end",
+            trigger("delete")
+        ),
+    ]
+}
+
+/// Most dsl types are valid strict column types as is. Sqlite only adds not null
+/// columns with a default, so they get the zero value of their type
+fn column_type(field: &Field) -> String {
+    let ty = sql_type(field);
+    let default = field.default.as_ref().filter(|sql| is_constant(sql));
+    match (field.not_null, default) {
+        (_, Some(default)) => match field.not_null {
+            true => format!("{ty} not null default {default}"),
+            false => format!("{ty} default {default}"),
+        },
+        (true, None) => {
+            let default = match field.variants.first() {
+                Some(variant) => match variant.value {
+                    Some(value) => value.to_string(),
+                    None => format!("'{}'", variant.name),
+                },
+                None => match field.ty.to_string().as_str() {
+                    "Text" => "''".to_string(),
+                    "Real" => "0.0".to_string(),
+                    "Blob" => "x''".to_string(),
+                    _ => "0".to_string(),
+                },
+            };
+            format!("{ty} not null default {default}")
+        }
+        (false, None) => ty,
+    }
+}
+
+fn references(field: &Field) -> String {
+    match &field.references {
+        Some(References {
+            table,
+            column,
+            actions,
+        }) => match actions.is_empty() {
+            true => format!(" references {table}({column})"),
+            false => format!(" references {table}({column}) {actions}"),
+        },
+        None => String::new(),
+    }
+}
+
+/// Alter table only takes constant defaults, expressions like `unixepoch()` are
+/// applied by the generated upsert instead
+fn is_constant(sql: &str) -> bool {
+    sql.parse::<f64>().is_ok()
+        || (sql.len() > 1 && sql.starts_with('\'') && sql.ends_with('\''))
+        || matches!(sql.to_lowercase().as_str(), "null" | "true" | "false")
+}
+
+fn sql_type(field: &Field) -> String {
+    match field.ty.to_string().as_str() {
+        "DateTime" => "integer".to_string(),
+        "Json" => "text".to_string(),
+        "Enum" => {
+            let values = field
+                .variants
+                .iter()
+                .map(|variant| match variant.value {
+                    Some(value) => value.to_string(),
+                    None => format!("'{}'", variant.name),
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            let ty = match field.variants.iter().any(|variant| variant.value.is_some()) {
+                true => "integer",
+                false => "text",
+            };
+            format!("{ty} check ({} in ({values}))", field.name)
+        }
+        ty => ty.to_string(),
+    }
+}
+
+fn index_migrations(index: &Index) -> Vec<String> {
+    index
+        .columns
+        .iter()
+        .map(|columns| {
+            let names = columns
+                .names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>();
+            format!(
+                "create {} index if not exists {}_{}_ix on {} ({})",
+                match columns.unique {
+                    true => "unique",
+                    false => "",
+                },
+                qualified_name(&index.schema, &index.name),
+                names.join("_"),
+                index.name,
+                names.join(", ")
+            )
+        })
+        .collect()
+}
+
+/// Alter table can't add table constraints, so checks are triggers that abort
+/// with the same message as a check constraint
+fn check_migrations(check: &Check) -> Vec<String> {
+    let sql = sql_text(check.condition.clone(), None);
+    let condition = sql_text(check.condition.clone(), Some("new"));
+    let table = check.name.to_string().to_lowercase();
+    check_triggers(check)
+        .into_iter()
+        .map(|(event, name)| {
+            let trigger = qualified_name(&check.schema, &Ident::new(&name, Span::call_site()));
+            format!(
+                "create trigger if not exists {trigger} before {event} on {table} when not ({condition}) begin select raise(abort, 'CHECK constraint failed: {}'); end",
+                sql.replace('\'', "''")
+            )
+        })
+        .collect()
+}
+
+/// The insert and update trigger of a check, named after the condition so a changed
+/// condition becomes a new trigger and `Sqlite::drop_stale_checks` drops the old one
+fn check_triggers(check: &Check) -> [(&'static str, String); 2] {
+    let sql = sql_text(check.condition.clone(), None);
+    let hash = sql.bytes().fold(0x811c9dc5u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    });
+    let table = check.name.to_string().to_lowercase();
+    ["insert", "update"].map(|event| (event, format!("{table}_check_{event}_{hash:08x}")))
+}
+
+/// Rust tokens as sql, rust strings become sql strings. With a `prefix`
+/// bare column names become `new.column` for use inside a trigger
+fn sql_text(tokens: TokenStream, prefix: Option<&str>) -> String {
+    let mut tokens = tokens.into_iter().peekable();
+    let mut sql = String::new();
+    let mut qualified = false;
+    while let Some(token) = tokens.next() {
+        let text = match &token {
+            TokenTree::Group(group) => {
+                let inner = sql_text(group.stream(), prefix);
+                match group.delimiter() {
+                    Delimiter::Parenthesis => format!("({inner}) "),
+                    Delimiter::Bracket => format!("[{inner}] "),
+                    Delimiter::Brace => format!("{{{inner}}} "),
+                    Delimiter::None => format!("{inner} "),
+                }
+            }
+            TokenTree::Literal(literal) => {
+                let literal = literal.to_string();
+                match literal
+                    .strip_prefix('"')
+                    .and_then(|text| text.strip_suffix('"'))
+                {
+                    Some(text) => format!("'{}' ", text.replace("\\\"", "\"").replace('\'', "''")),
+                    None => format!("{literal} "),
+                }
+            }
+            TokenTree::Punct(punct) => match punct.spacing() {
+                Spacing::Joint => punct.to_string(),
+                Spacing::Alone if punct.as_char() == '.' => ".".to_string(),
+                Spacing::Alone => format!("{punct} "),
+            },
+            TokenTree::Ident(ident) => {
+                let is_call = matches!(tokens.peek(), Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Parenthesis);
+                let is_keyword = matches!(
+                    ident.to_string().to_lowercase().as_str(),
+                    "and"
+                        | "or"
+                        | "not"
+                        | "is"
+                        | "null"
+                        | "in"
+                        | "like"
+                        | "glob"
+                        | "between"
+                        | "case"
+                        | "when"
+                        | "then"
+                        | "else"
+                        | "end"
+                        | "true"
+                        | "false"
+                        | "collate"
+                        | "escape"
+                        | "regexp"
+                        | "match"
+                        | "exists"
+                        | "cast"
+                        | "as"
+                );
+                let is_table = matches!(tokens.peek(), Some(TokenTree::Punct(punct)) if punct.as_char() == '.');
+                match prefix {
+                    Some(prefix) if !is_call && !is_keyword && !is_table && !qualified => {
+                        format!("{prefix}.{ident} ")
+                    }
+                    _ => format!("{ident} "),
+                }
+            }
+        };
+        qualified = matches!(&token, TokenTree::Punct(punct) if punct.as_char() == '.');
+        sql.push_str(&text);
+    }
+    sql.trim().to_string()
+}
+
+/// Glob imports the invoking module into generated modules. With call_site spans `self`
+/// skips modules created by the expansion, so this is the module db! was called in.
+fn use_invoking_module() -> TokenStream {
+    "use self::*;".parse().unwrap()
+}
+
+fn table_module(table: &Table) -> Ident {
+    Ident::new(&snake_case(&table.name.to_string()), Span::call_site())
+}
+
+/// Gives the leading `pub` the same span quote! would, derives such as serde's
+/// resolve their helper paths at the span of a field's first token.
+/// `(crate)` keeps the user's span so it still names the user's crate
+fn generated_span(tokens: TokenStream) -> TokenStream {
+    let Some(span) = quote!(pub).into_iter().next().map(|token| token.span()) else {
+        return tokens;
+    };
+    let mut tokens = tokens.into_iter();
+    let first = tokens.next().map(|mut token| {
+        token.set_span(span);
+        token
+    });
+    first.into_iter().chain(tokens).collect()
+}
+
+/// The last `visibility = ...` declared, `pub` otherwise
+fn default_vis(schema: &DatabaseSchema) -> TokenStream {
+    schema
+        .parts
+        .iter()
+        .rev()
+        .find_map(|part| match part {
+            SchemaPart::Visibility(Visibility { vis }) => Some(generated_span(vis.clone())),
+            _ => None,
+        })
+        .unwrap_or(quote!(pub))
+}
+
+/// The last `max_rows = ...` declared, no limit otherwise
+fn default_max_rows(schema: &DatabaseSchema) -> Option<usize> {
+    schema.parts.iter().rev().find_map(|part| match part {
+        SchemaPart::MaxRows(MaxRows { rows }) => Some(*rows),
+        _ => None,
+    })
+}
+
+fn generate_table_module(
+    table: &Table,
+    vis: &TokenStream,
+    queries: TokenStream,
+) -> Result<TokenStream, Error> {
+    let module = table_module(table);
+    let use_invoking_module = use_invoking_module();
+    let tokens = generate_table(table, vis)?;
+    Ok(quote! {
+        pub mod $module {
+            $use_invoking_module
+
+            $tokens
+
+            $queries
+        }
+    })
+}
+
+/// `db::user::queries`, the reads of `User` and the declared queries that select from it,
+/// their methods are still on `Database`
+fn generate_table_queries(
+    db: &sqltight_core::Sqlite,
+    tables: &[&Table],
+    table: &Table,
+    schema: &DatabaseSchema,
+    declared: &[String],
+    vis: &TokenStream,
+    max_rows: Option<usize>,
+) -> Result<TokenStream, Error> {
+    let selects = schema
+        .parts
+        .iter()
+        .filter_map(|part| match part {
+            SchemaPart::Query(select) => owning_table(&select.sql, tables)
+                .filter(|owner| std::ptr::eq(*owner, table))
+                .map(|_owner| select),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let select_structs = selects
+        .iter()
+        .map(|select| generate_select_struct(db, tables, select, vis))
+        .collect::<Result<TokenStream, Error>>()?;
+    let select_fns = selects
+        .iter()
+        .map(|select| generate_select(db, tables, select, vis, max_rows, true))
+        .collect::<Result<TokenStream, Error>>()?;
+    let reads = generate_table_reads(table, declared, vis, max_rows);
+    let database = Ident::new("Database", Span::call_site());
+    let queries = Ident::new("queries", Span::call_site());
+    // the paths the methods use, like `user::User` and `queries::UserById`, are db's.
+    // `self` is the invoking module, like `use_invoking_module`
+    let use_db_module: TokenStream = "use self::db::*;".parse().unwrap();
+    Ok(quote! {
+        pub mod $queries {
+            $use_db_module
+
+            $select_structs
+
+            impl $database {
+                $reads
+
+                $select_fns
+            }
+        }
+    })
+}
+
+/// The table a query selects from first, `None` for queries starting with a `with`
+/// or reading a view, an fts table or a timeseries
+fn owning_table<'a>(sql: &str, tables: &[&'a Table]) -> Option<&'a Table> {
+    let tokens = sql_tokens(sql);
+    if tokens.first().is_some_and(|token| token == "with") {
+        return None;
+    }
+    let from = tokens.iter().position(|token| token == "from")?;
+    let (schema, name) = match tokens.get(from + 2).map(String::as_str) {
+        Some(".") => (tokens.get(from + 1), tokens.get(from + 3)?),
+        _ => (None, tokens.get(from + 1)?),
+    };
+    tables.iter().copied().find(|table| {
+        table.name.to_string().to_lowercase() == *name
+            && table
+                .schema
+                .as_ref()
+                .map(|schema| schema.to_string().to_lowercase())
+                == schema.cloned()
+    })
+}
+
+fn generate_timeseries_module(timeseries: &Timeseries, vis: &TokenStream) -> TokenStream {
+    let module = table_module(&timeseries.table);
+    let use_invoking_module = use_invoking_module();
+    let tokens = generate_timeseries(timeseries, vis);
+    quote! {
+        pub mod $module {
+            $use_invoking_module
+
+            $tokens
+        }
+    }
+}
+
+/// A struct per row like a table's, without `Crud` since each row's table depends on its `by`
+fn generate_timeseries(Timeseries { table, by }: &Timeseries, vis: &TokenStream) -> TokenStream {
+    let name = &table.name;
+    let vis = table.vis.clone().map(generated_span).unwrap_or(vis.clone());
+    let fields = table
+        .fields
+        .iter()
+        .map(|field| {
+            let vis = field.vis.clone().map(generated_span).unwrap_or(quote!(pub));
+            let name = &field.name;
+            let ty = field_type(table, field);
+            quote! { $vis $name: $ty, }
+        })
+        .collect::<TokenStream>();
+    let enums = table
+        .fields
+        .iter()
+        .filter(|field| !field.variants.is_empty())
+        .map(|field| generate_enum(table, field, &vis))
+        .collect::<TokenStream>();
+    let from_row_fields = table
+        .fields
+        .iter()
+        .map(|field| from_row_field(&field.name, Some(field)))
+        .collect::<TokenStream>();
+    let table_name = name.to_string();
+    let by = by.to_string();
+    let columns = table
+        .fields
+        .iter()
+        .map(|field| {
+            let column = field.name.to_string();
+            quote!($column,)
+        })
+        .collect::<TokenStream>();
+    let definitions = table
+        .fields
+        .iter()
+        .map(|field| {
+            let definition = format!("{} {}", field.name, column_type(field));
+            quote!($definition,)
+        })
+        .collect::<TokenStream>();
+    let values = table
+        .fields
+        .iter()
+        .map(|Field { name, .. }| quote!(sqltight::Value::from(self.$name),))
+        .collect::<TokenStream>();
+    let debug = table_debug_impl(table);
+    let serde = serde_derive();
+    quote! {
+        $enums
+
+        #[derive(Default)]
+        $serde
+        $vis struct $name {
+            $fields
+        }
+
+        $debug
+
+        impl sqltight::Timeseries for $name {
+            const NAME: &'static str = $table_name;
+            const BY: &'static str = $by;
+            const COLUMNS: &'static [&'static str] = &[$columns];
+            const DEFINITIONS: &'static [&'static str] = &[$definitions];
+
+            fn values(self) -> Vec<sqltight::Value> {
+                vec![$values]
+            }
+        }
+
+        impl sqltight::FromRow for $name {
+            fn from_row(row: &sqltight::Row) -> Self {
+                Self {
+                    $from_row_fields
+                }
+            }
+
+            fn from_row_ref(row: &sqltight::RowRef) -> Self {
+                Self {
+                    $from_row_fields
+                }
+            }
+        }
+    }
+}
+
+fn generate_table(table: &Table, vis: &TokenStream) -> Result<TokenStream, Error> {
+    let name = &table.name;
+    let vis = table.vis.clone().map(generated_span).unwrap_or(vis.clone());
+    let id_field = match table
+        .fields
+        .iter()
+        .find(|field| field.name.to_string() == "id")
+    {
+        Some(id_field) => id_field,
+        None => {
+            Diagnostic::spanned(
+                table.name.span(),
+                Level::Error,
+                "Missing required column: id",
+            )
+            .emit();
+            return Err(Error::Generate("Missing required column: id".to_string()));
+        }
+    };
+    let id = &id_field.name;
+    let id_ty = id_name(table);
+    let fields = table
+        .fields
+        .iter()
+        .map(|field| {
+            let vis = field.vis.clone().map(generated_span).unwrap_or(quote!(pub));
+            let name = &field.name;
+            let ty = match field.name.to_string() == "id" {
+                true => quote!($id_ty),
+                false => field_type(table, field),
+            };
+            quote! { $vis $name: $ty, }
+        })
+        .collect::<TokenStream>();
+    let id_tokens = generate_id(table, id_field, &vis);
+    let enums = table
+        .fields
+        .iter()
+        .filter(|field| !field.variants.is_empty())
+        .map(|field| generate_enum(table, field, &vis))
+        .collect::<TokenStream>();
+    let (upsert_sql, upsert_params) = upsert_sql(table);
+    let delete_sql = match table.soft_delete {
+        // deleting again keeps when it was first deleted
+        true => format!(
+            "update {} set deleted_at = coalesce(deleted_at, unixepoch()) where id = :id returning *",
+            qualified_name(&table.schema, name)
+        ),
+        false => format!(
+            "delete from {} where id = :id returning *",
+            qualified_name(&table.schema, name)
+        ),
+    };
+    let from_row_fields = table
+        .fields
+        .iter()
+        .map(|field| match field.name.to_string() == "id" {
+            true => {
+                let value = from_row_value("id", Some(field));
+                quote! { $id: $id_ty($value), }
+            }
+            false => from_row_field(&field.name, Some(field)),
+        })
+        .collect::<TokenStream>();
+    let new_fields = table
+        .fields
+        .iter()
+        .filter(|field| {
+            field.name.to_string() != "id"
+                && field.name.to_string() != "created_at"
+                && field.name.to_string() != "updated_at"
+                && !(table.log && field.name.to_string() == "hash")
+                && !(table.soft_delete && field.name.to_string() == "deleted_at")
+                && (field.default.is_none() || field.not_null)
+        })
+        .collect::<Vec<&Field>>();
+    let new_args = new_fields
+        .iter()
+        .map(|field| {
+            let field_name = &field.name;
+            let ty = field_type(table, field);
+            quote! { $field_name: impl Into<$ty>, }
+        })
+        .collect::<TokenStream>();
+
+    let new_struct_fields = new_fields
+        .iter()
+        .map(|Field { name, .. }| {
+            quote! { $name: $name.into(), }
+        })
+        .collect::<TokenStream>();
+    let new_fn = Ident::new("new", name.span());
+    let save_sql = Ident::new("SAVE_SQL", Span::call_site());
+    let debug = table_debug_impl(table);
+    let serde = serde_derive();
+    let delete_sql_const = Ident::new("DELETE_SQL", Span::call_site());
+    let table_name = qualified_name(&table.schema, name);
+    let columns = table
+        .fields
+        .iter()
+        .map(|field| {
+            let column = field.name.to_string();
+            quote!($column,)
+        })
+        .collect::<TokenStream>();
+    let column_consts = table
+        .fields
+        .iter()
+        .map(|field| {
+            let column = field.name.to_string();
+            let name = Ident::new(&column.to_uppercase(), field.name.span());
+            let ty = match column == "id" {
+                true => quote!($id_ty),
+                false => field_type(table, field),
+            };
+            quote! {
+                pub const $name: sqltight::Col<Self, $ty> = sqltight::Col::new($column);
+            }
+        })
+        .collect::<TokenStream>();
+    let query_fn = Ident::new("query", Span::call_site());
+    let soft_delete = Ident::new(&table.soft_delete.to_string(), Span::call_site());
+    // a batch's update only sees the values as inserted, defaults and all,
+    // so those tables save one row at a time
+    let batched = !table.log
+        && !table
+            .fields
+            .iter()
+            .any(|field| defaults_on_insert(table, field));
+    let save_all = match batched {
+        true => {
+            let (insert, row, conflict) = batch_sql(table);
+            let conflict_target = table
+                .conflict
+                .iter()
+                .map(|column| column.to_string())
+                .filter(|column| column != "id")
+                .map(|column| quote!($column,))
+                .collect::<TokenStream>();
+            let batch_params = table
+                .fields
+                .iter()
+                .map(|field| save_param(table, field, quote!(row)))
+                .collect::<TokenStream>();
+            quote! {
+                #[track_caller]
+                fn save_all(rows: Vec<Self>, db: &sqltight::Sqlite) -> sqltight::Result<Vec<Self>> {
+                    let sql = sqltight::BatchSql {
+                        insert: $insert,
+                        row: $row,
+                        conflict: $conflict,
+                        columns: &[$columns],
+                        conflict_target: &[$conflict_target],
+                    };
+                    let params = rows.into_iter().map(|row| vec![$batch_params]).collect();
+                    Ok(db.save_batch(sql, params)?.iter().map(Self::from_row).collect())
+                }
+            }
+        }
+        false => TokenStream::new(),
+    };
+    // the upsert returns no row when the `on conflict where` kept the stored one
+    let not_saved = match table.conflict_where {
+        Some(_) => quote!(sqltight::Error::UpsertSkipped),
+        None => quote!(sqltight::Error::RowNotFound),
+    };
+    let (save, log_table) = match table.log {
+        true => {
+            let (append_sql, append_params) = append_sql(table);
+            (
+                quote! {
+                    db.append(self)
+                },
+                quote! {
+                    impl sqltight::LogTable for $name {
+                        const APPEND_SQL: &'static str = $append_sql;
+
+                        fn append_values(self) -> Vec<sqltight::Value> {
+                            vec![$append_params]
+                        }
+                    }
+                },
+            )
+        }
+        false => (
+            quote! {
+                let sql = Self::$save_sql;
+                let params = vec![$upsert_params];
+                let row = db.prepare(sql)?
+                    .bind(&params)?
+                    .rows()?
+                    .into_iter()
+                    .nth(0)
+                    .ok_or($not_saved)?;
+                Ok(Self::from_row(&row))
+            },
+            TokenStream::new(),
+        ),
+    };
+
+    Ok(quote! {
+        $enums
+
+        $id_tokens
+
+        #[derive(Default)]
+        $serde
+        $vis struct $name {
+            $fields
+        }
+
+        $debug
+
+        impl $name {
+            #[doc = $upsert_sql]
+            pub const $save_sql: &'static str = $upsert_sql;
+            #[doc = $delete_sql]
+            pub const $delete_sql_const: &'static str = $delete_sql;
+
+            pub fn $new_fn($new_args) -> Self {
+                Self { $new_struct_fields ..Default::default() }
+            }
+        }
+        impl $name {
+            $column_consts
+
+            /// Starts a select of this table built at runtime, see `sqltight::Select`
+            pub fn $query_fn() -> sqltight::Select<Self> {
+                sqltight::Select::new()
+            }
+        }
+        impl sqltight::Table for $name {
+            const NAME: &'static str = $table_name;
+            const COLUMNS: &'static [&'static str] = &[$columns];
+            const SOFT_DELETE: bool = $soft_delete;
+        }
+        impl sqltight::Crud for $name {
+            #[track_caller]
+            fn save(self, db: &sqltight::Sqlite) -> sqltight::Result<Self> {
+                $save
+            }
+
+            #[track_caller]
+            fn delete(self, db: &sqltight::Sqlite) -> sqltight::Result<Self> {
+                let sql = Self::$delete_sql_const;
+                let params = vec![self.$id.into()];
+                let row = db
+                    .prepare(sql)?
+                    .bind(&params)?
+                    .rows()?
+                    .into_iter()
+                    .nth(0)
+                    .ok_or(sqltight::Error::RowNotFound)?;
+                Ok(Self::from_row(&row))
+            }
+
+            $save_all
+        }
+
+        impl sqltight::FromRow for $name {
+            fn from_row(row: &sqltight::Row) -> Self {
+                Self {
+                    $from_row_fields
+                }
+            }
+
+            fn from_row_ref(row: &sqltight::RowRef) -> Self {
+                Self {
+                    $from_row_fields
+                }
+            }
+        }
+
+        $log_table
+    })
+}
+
+/// `UserId` for the id of `table User`
+fn id_name(table: &Table) -> Ident {
+    Ident::new(&format!("{}Id", table.name), Span::call_site())
+}
+
+/// The id of a table as its own type, so a `PostId` can't be passed where a `UserId`
+/// goes. It converts to and from the column's type and compares with it
+fn generate_id(table: &Table, field: &Field, vis: &TokenStream) -> TokenStream {
+    let name = id_name(table);
+    let ty = field_type(table, field);
+    let serde = match cfg!(feature = "serde") {
+        true => {
+            let serde = serde_derive();
+            quote!($serde #[serde(transparent)])
+        }
+        false => TokenStream::new(),
+    };
+    let plain = field.variants.is_empty() && field.args.is_empty();
+    let number =
+        plain && (field.width.is_some() || matches!(field.ty.to_string().as_str(), "Int" | "Real"));
+    let copy = match number {
+        true => quote!(Copy,),
+        false => TokenStream::new(),
+    };
+    // `find_user(1)` like `find_user(user.id)`
+    let from_plain = match (plain && field.width.is_none()).then(|| field.ty.to_string()) {
+        Some(ty) if ty == "Int" => quote! {
+            impl From<i64> for $name {
+                fn from(value: i64) -> Self {
+                    Self(value.into())
+                }
+            }
+        },
+        Some(ty) if ty == "Text" => quote! {
+            impl From<&str> for $name {
+                fn from(value: &str) -> Self {
+                    Self(value.into())
+                }
+            }
+        },
+        _ => TokenStream::new(),
+    };
+    let display = match number || (plain && field.ty.to_string() == "Text") {
+        true => quote! {
+            impl std::fmt::Display for $name {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    std::fmt::Display::fmt(&self.0, f)
+                }
+            }
+        },
+        false => TokenStream::new(),
+    };
+    quote! {
+        #[derive(Default, Clone, $copy PartialEq)]
+        $serde
+        $vis struct $name(pub $ty);
+
+        impl std::fmt::Debug for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                std::fmt::Debug::fmt(&self.0, f)
+            }
+        }
+
+        $display
+
+        impl From<$ty> for $name {
+            fn from(value: $ty) -> Self {
+                Self(value)
+            }
+        }
+
+        $from_plain
+
+        impl From<$name> for $ty {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+
+        impl From<$name> for sqltight::Value {
+            fn from(value: $name) -> Self {
+                Self::from(value.0)
+            }
+        }
+
+        impl PartialEq<$ty> for $name {
+            fn eq(&self, other: &$ty) -> bool {
+                self.0 == *other
+            }
+        }
+
+        impl PartialEq<$name> for $ty {
+            fn eq(&self, other: &$name) -> bool {
+                *self == other.0
+            }
+        }
+    }
+}
+
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (ix, c) in name.chars().enumerate() {
+        if c.is_uppercase() && ix > 0 {
+            snake.push('_');
+        }
+        snake.extend(c.to_lowercase());
+    }
+    snake
+}
+
+fn pascal_case(name: &str) -> String {
+    name.split("_")
+        .map(|x| {
+            format!(
+                "{}{}",
+                x.chars().nth(0).unwrap().to_uppercase(),
+                x.chars().skip(1).collect::<String>()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("")
+}
+
+fn generate_select(
+    db: &sqltight_core::Sqlite,
+    tables: &[&Table],
+    select: &Query,
+    vis: &TokenStream,
+    max_rows: Option<usize>,
+    fields: bool,
+) -> Result<TokenStream, Error> {
+    let vis = select
+        .vis
+        .clone()
+        .map(generated_span)
+        .unwrap_or(vis.clone());
+    let sql = &select.sql;
+    let fn_name = &select.fn_name;
+    let return_ident = Ident::new(&pascal_case(&fn_name.to_string()), fn_name.span());
+    let queries = Ident::new("queries", Span::call_site());
+    let return_ident = quote!($queries::$return_ident);
+    let (return_ty, fields_return_ty, return_val) = match sql.contains("limit 1") {
+        false => (
+            quote!(Vec<$return_ident>),
+            quote!(Vec<S::Values>),
+            quote!(Ok(rows)),
+        ),
+        true => (
+            quote!($return_ident),
+            quote!(S::Values),
+            quote!(rows.into_iter().nth(0).ok_or(sqltight::Error::RowNotFound)),
+        ),
+    };
+    let stmt = match db.prepare(sql) {
+        Ok(stmt) => stmt,
+        Err(err) => {
+            let text = error_text(err);
+            Diagnostic::spanned(fn_name.span(), Level::Error, &text).emit();
+            return Err(Error::Generate(text));
+        }
+    };
+    let param_names = stmt.parameter_names();
+    check_params(
+        fn_name,
+        select.literal.as_ref(),
+        sql,
+        &param_names,
+        &select.param_types,
+        tables,
+    )?;
+    let param_names = param_names
+        .iter()
+        .map(|x| x.trim_start_matches(":"))
+        .collect::<Vec<_>>();
+    let param_idents = param_names
+        .iter()
+        .map(|name| Ident::new(name, fn_name.span()))
+        .collect::<Vec<_>>();
+    for (name, _ty) in &select.param_types {
+        if !param_names.contains(&name.as_str()) {
+            let text = format!("{fn_name} has no parameter :{name} to annotate");
+            Diagnostic::spanned(fn_name.span(), Level::Error, &text).emit();
+            return Err(Error::Generate(text));
+        }
+    }
+    let param_types = param_names
+        .iter()
+        .map(|name| {
+            select
+                .param_types
+                .iter()
+                .find(|(annotated, _ty)| annotated == name)
+                .map(|(_name, ty)| ty.clone())
+                .or_else(|| inferred_param_type(sql, name, tables))
+        })
+        .collect::<Vec<_>>();
+    let fn_args = param_idents
+        .iter()
+        .zip(&param_types)
+        .map(|(arg, ty)| match ty {
+            Some(ty) => quote!($arg: impl Into<sqltight::$ty>,),
+            None => quote!($arg: impl Into<sqltight::Value>,),
+        })
+        .collect::<TokenStream>();
+    let params = param_idents
+        .iter()
+        .zip(&param_types)
+        .map(|(arg, ty)| match ty {
+            Some(ty) => quote!(sqltight::Value::from(Into::<sqltight::$ty>::into($arg)),),
+            None => quote!($arg.into(),),
+        })
+        .collect::<TokenStream>();
+    let params = quote!(&[$params]);
+    let rows = match select.max_rows.or(max_rows) {
+        Some(max_rows) => quote!(rows_at_most($max_rows)),
+        None => quote!(rows()),
+    };
+    // read straight into the return type, without a `Row` per row in between
+    let rows_as = match select.max_rows.or(max_rows) {
+        Some(max_rows) => quote!(rows_as_at_most::<$return_ident>($max_rows)),
+        None => quote!(rows_as::<$return_ident>()),
+    };
+    let fn_name_str = fn_name.to_string();
+    let sql_const = Ident::new(
+        &format!("{}_SQL", fn_name_str.to_uppercase()),
+        Span::call_site(),
+    );
+    // streamed to a writer, every row of the query without collecting them
+    let exports = [("csv", "write_csv"), ("json", "write_json")]
+        .into_iter()
+        .map(|(format, write)| {
+            let export_fn = Ident::new(&format!("export_{fn_name_str}_{format}"), fn_name.span());
+            let write = Ident::new(write, Span::call_site());
+            let doc = format!("Writes the rows of `{fn_name_str}` to `writer` as {format}, see `Stmt::{write}`");
+            quote! {
+                #[doc = $doc]
+                #[track_caller]
+                $vis fn $export_fn(&self, $fn_args mut writer: impl std::io::Write) -> sqltight::Result<usize> {
+                    let params: &[sqltight::Value] = $params;
+                    self.statement($fn_name_str, Self::$sql_const)?.bind(params)?.$write(&mut writer)
+                }
+            }
+        })
+        .collect::<TokenStream>();
+    if select.paginate {
+        let page_select = generate_page_select(
+            select,
+            &SelectParts {
+                vis: &vis,
+                return_ident: &return_ident,
+                param_names: &param_names,
+                fn_args: &fn_args,
+                params: &params,
+                rows: &rows,
+                rows_as: &rows_as,
+            },
+        )?;
+        return Ok(quote!($exports $page_select));
+    }
+    let fields_fn = match fields {
+        true => {
+            let fields_fn = Ident::new(&format!("{fn_name_str}_fields"), fn_name.span());
+            let doc = format!(
+                "Like `{fn_name_str}`, reading only the columns `S` selects, e.g. `{fn_name_str}_fields::<({fn_name_str}::Id,)>`"
+            );
+            quote! {
+                #[doc = $doc]
+                #[track_caller]
+                $vis fn $fields_fn<S: sqltight::Columns<$return_ident>>(&self, $fn_args) -> sqltight::Result<$fields_return_ty> {
+                    let params: &[sqltight::Value] = $params;
+                    let columns = S::NAMES
+                        .iter()
+                        .map(|name| format!("\"{name}\""))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    let sql = format!("select {columns} from ({})", Self::$sql_const);
+                    // the selection's type name is unique per query and column list
+                    let name = std::any::type_name::<S>();
+                    let rows = self.statement(name, &sql)?.bind(params)?.$rows?;
+                    let rows = rows.iter().map(S::values).collect::<Vec<S::Values>>();
+                    $return_val
+                }
+            }
+        }
+        false => TokenStream::new(),
+    };
+    Ok(quote!(
+        $fields_fn
+
+        $exports
+
+        #[doc = $sql]
+        $vis const $sql_const: &'static str = $sql;
+
+        #[doc = $sql]
+        #[track_caller]
+        $vis fn $fn_name(&self, $fn_args) -> sqltight::Result<$return_ty> {
+            let params: &[sqltight::Value] = $params;
+            let rows = self.statement($fn_name_str, Self::$sql_const)?.bind(params)?.$rows_as?;
+            $return_val
+        }
+    ))
+}
+
+/// The pieces of a select method `generate_select` has already worked out
+struct SelectParts<'a> {
+    vis: &'a TokenStream,
+    return_ident: &'a TokenStream,
+    param_names: &'a [&'a str],
+    fn_args: &'a TokenStream,
+    params: &'a TokenStream,
+    rows: &'a TokenStream,
+    rows_as: &'a TokenStream,
+}
+
+/// `query name paginate "..."` reads `limit` rows starting at `offset` into a `Page`
+/// that also holds the total number of rows and where the next page starts
+fn generate_page_select(select: &Query, parts: &SelectParts) -> Result<TokenStream, Error> {
+    let SelectParts {
+        vis,
+        return_ident,
+        param_names,
+        fn_args,
+        params,
+        rows,
+        rows_as,
+    } = parts;
+    let fn_name = &select.fn_name;
+    if let Some(name) = param_names
+        .iter()
+        .find(|name| ["limit", "offset"].contains(name))
+    {
+        let text =
+            format!("{fn_name} paginates with limit and offset, rename the parameter :{name}");
+        Diagnostic::spanned(fn_name.span(), Level::Error, &text).emit();
+        return Err(Error::Generate(text));
+    }
+    let sql = &select.sql;
+    let (page_sql, count_sql) = page_sql(sql);
+    let fn_name_str = fn_name.to_string();
+    let count_name = format!("{fn_name_str}_count");
+    let sql_const = Ident::new(
+        &format!("{}_SQL", fn_name_str.to_uppercase()),
+        Span::call_site(),
+    );
+    let doc = format!("{sql}\n\nOne page of at most `limit` rows, skipping the first `offset`");
+    Ok(quote!(
+        #[doc = $sql]
+        $vis const $sql_const: &'static str = $sql;
+
+        #[doc = $doc]
+        #[track_caller]
+        $vis fn $fn_name(&self, $fn_args limit: i64, offset: i64) -> sqltight::Result<sqltight::Page<$return_ident>> {
+            let params: &[sqltight::Value] = $params;
+            let read = |name: &'static str, sql: &str, params: &[sqltight::Value]| {
+                self.statement(name, sql)?.bind(params)?.$rows
+            };
+            let total = match read($count_name, $count_sql, params)?.first() {
+                Some(row) => row.get::<i64>(0)?,
+                None => 0,
+            };
+            let page_params = params
+                .iter()
+                .cloned()
+                .chain([sqltight::Value::from(limit), sqltight::Value::from(offset)])
+                .collect::<Vec<sqltight::Value>>();
+            let items = self.statement($fn_name_str, $page_sql)?.bind(&page_params)?.$rows_as?;
+            Ok(sqltight::Page::new(items, total, offset))
+        }
+    ))
+}
+
+/// `op name { ... }` runs its statements in order in one savepoint, all or nothing, each
+/// bound to the shared params it names
+fn generate_op(
+    db: &sqltight_core::Sqlite,
+    tables: &[&Table],
+    op: &Op,
+    vis: &TokenStream,
+) -> Result<TokenStream, Error> {
+    let vis = op.vis.clone().map(generated_span).unwrap_or(vis.clone());
+    let fn_name = &op.fn_name;
+    let error = |text: String| {
+        Diagnostic::spanned(fn_name.span(), Level::Error, &text).emit();
+        Err(Error::Generate(text))
+    };
+    let mut param_names: Vec<String> = vec![];
+    let mut statement_params = vec![];
+    for sql in &op.statements {
+        let stmt = match db.prepare(sql) {
+            Ok(stmt) => stmt,
+            Err(sqltight_core::Error::Sqlite { text, .. }) => {
+                return error(format!("{fn_name}: {text}"));
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let names = stmt
+            .parameter_names()
+            .iter()
+            .map(|name| name.trim_start_matches(':').to_string())
+            .collect::<Vec<_>>();
+        if names
+            .iter()
+            .any(|name| name.is_empty() || name.starts_with('?'))
+        {
+            return error(format!(
+                "{fn_name} shares its params by name, use :name instead of ?"
+            ));
+        }
+        for name in &names {
+            if !param_names.contains(name) {
+                param_names.push(name.clone());
+            }
+        }
+        statement_params.push(names);
+    }
+    for (name, _ty) in &op.param_types {
+        if !param_names.contains(name) {
+            return error(format!("{fn_name} has no parameter :{name} to annotate"));
+        }
+    }
+    let param_idents = param_names
+        .iter()
+        .map(|name| Ident::new(name, fn_name.span()))
+        .collect::<Vec<_>>();
+    let param_types = param_names
+        .iter()
+        .map(|name| {
+            op.param_types
+                .iter()
+                .find(|(annotated, _ty)| annotated == name)
+                .map(|(_name, ty)| ty.clone())
+                .or_else(|| {
+                    op.statements
+                        .iter()
+                        .find_map(|sql| inferred_param_type(sql, name, tables))
+                })
+        })
+        .collect::<Vec<_>>();
+    let fn_args = param_idents
+        .iter()
+        .zip(&param_types)
+        .map(|(arg, ty)| match ty {
+            Some(ty) => quote!($arg: impl Into<sqltight::$ty>,),
+            None => quote!($arg: impl Into<sqltight::Value>,),
+        })
+        .collect::<TokenStream>();
+    let values = param_idents
+        .iter()
+        .zip(&param_types)
+        .map(|(arg, ty)| match ty {
+            Some(ty) => {
+                quote!(let $arg = sqltight::Value::from(Into::<sqltight::$ty>::into($arg));)
+            }
+            None => quote!(let $arg: sqltight::Value = $arg.into();),
+        })
+        .collect::<TokenStream>();
+    let statements = op
+        .statements
+        .iter()
+        .zip(&statement_params)
+        .map(|(sql, names)| {
+            let params = names
+                .iter()
+                .map(|name| {
+                    let arg = Ident::new(name, fn_name.span());
+                    quote!($arg.clone(),)
+                })
+                .collect::<TokenStream>();
+            quote!(($sql, vec![$params]),)
+        })
+        .collect::<TokenStream>();
+    let doc = op.statements.join(";\n");
+    let connection = Ident::new("connection", Span::call_site());
+    Ok(quote!(
+        #[doc = $doc]
+        #[track_caller]
+        $vis fn $fn_name(&self, $fn_args) -> sqltight::Result<()> {
+            $values
+            self.$connection.run_all(&[$statements])
+        }
+    ))
+}
+
+/// `execute name "..."` runs one write statement and returns the rows it changed, or with
+/// `-> T` the rows of its returning clause read into `T`
+fn generate_execute(
+    db: &sqltight_core::Sqlite,
+    tables: &[&Table],
+    execute: &Execute,
+    vis: &TokenStream,
+) -> Result<TokenStream, Error> {
+    let vis = execute
+        .vis
+        .clone()
+        .map(generated_span)
+        .unwrap_or(vis.clone());
+    let fn_name = &execute.fn_name;
+    let sql = &execute.sql;
+    let error = |text: String| {
+        Diagnostic::spanned(fn_name.span(), Level::Error, &text).emit();
+        Err(Error::Generate(text))
+    };
+    let stmt = match db.prepare(sql) {
+        Ok(stmt) => stmt,
+        Err(sqltight_core::Error::Sqlite { text, .. }) => {
+            return error(format!("{fn_name}: {text}"));
+        }
+        Err(err) => return Err(err.into()),
+    };
+    if stmt.is_readonly() {
+        return error(format!(
+            "{fn_name} only reads, declare it with query instead of execute"
+        ));
+    }
+    let returning = !stmt.select_column_names().is_empty();
+    match (&execute.returns, returning) {
+        (Some(ty), false) => {
+            return error(format!(
+                "{fn_name} reads into {ty} but its sql has no returning clause"
+            ));
+        }
+        (None, true) => {
+            return error(format!(
+                "{fn_name} has a returning clause, name the type to read it into, e.g. execute {fn_name} -> Row"
+            ));
+        }
+        _ => {}
+    }
+    check_params(
+        fn_name,
+        Some(&execute.literal),
+        sql,
+        &stmt.parameter_names(),
+        &execute.param_types,
+        tables,
+    )?;
+    let param_names = stmt
+        .parameter_names()
+        .iter()
+        .map(|name| name.trim_start_matches(':').to_string())
+        .collect::<Vec<_>>();
+    for (name, _ty) in &execute.param_types {
+        if !param_names.contains(name) {
+            return error(format!("{fn_name} has no parameter :{name} to annotate"));
+        }
+    }
+    let param_idents = param_names
+        .iter()
+        .map(|name| Ident::new(name, fn_name.span()))
+        .collect::<Vec<_>>();
+    let param_types = param_names
+        .iter()
+        .map(|name| {
+            execute
+                .param_types
+                .iter()
+                .find(|(annotated, _ty)| annotated == name)
+                .map(|(_name, ty)| ty.clone())
+                .or_else(|| inferred_param_type(sql, name, tables))
+        })
+        .collect::<Vec<_>>();
+    let fn_args = param_idents
         .iter()
-        .map(|Field { name, ty }| quote! { pub $name: $ty, })
+        .zip(&param_types)
+        .map(|(arg, ty)| match ty {
+            Some(ty) => quote!($arg: impl Into<sqltight::$ty>,),
+            None => quote!($arg: impl Into<sqltight::Value>,),
+        })
         .collect::<TokenStream>();
-    let (upsert_sql, upsert_params) = upsert_sql(table);
-    let delete_sql = format!("delete from {name} where id = :id returning *");
-    let from_row_fields = table
-        .fields
+    let params = param_idents
         .iter()
-        .map(|field| {
-            let field_name = &field.name;
-            let key = field.name.to_string();
-            quote!($field_name: match row.get($key) { Some(val) => val.clone().into(), None => None.into() },)
+        .zip(&param_types)
+        .map(|(arg, ty)| match ty {
+            Some(ty) => quote!(sqltight::Value::from(Into::<sqltight::$ty>::into($arg)),),
+            None => quote!($arg.into(),),
         })
         .collect::<TokenStream>();
-    let id = match table
-        .fields
+    let sql_const = Ident::new(
+        &format!("{}_SQL", fn_name.to_string().to_uppercase()),
+        Span::call_site(),
+    );
+    // writes prepare on the connection saves run on, like ops
+    let connection = Ident::new("connection", Span::call_site());
+    let (return_ty, run) = match &execute.returns {
+        Some(ty) => (quote!(Vec<$ty>), quote!(rows_as::<$ty>())),
+        None => (
+            quote!(usize),
+            quote!(changes().and_then(|changes| Ok(usize::try_from(changes)?))),
+        ),
+    };
+    Ok(quote!(
+        #[doc = $sql]
+        $vis const $sql_const: &'static str = $sql;
+
+        #[doc = $sql]
+        #[track_caller]
+        $vis fn $fn_name(&self, $fn_args) -> sqltight::Result<$return_ty> {
+            let params: &[sqltight::Value] = &[$params];
+            self.$connection.prepare(Self::$sql_const)?.bind(params)?.$run
+        }
+    ))
+}
+
+/// Fails on params that can't become arguments, `?` or `@name`, and warns about the ones
+/// that are easy to get wrong: a `:name` in a string or comment that isn't bound, one
+/// annotated with two types, or one named after a column it isn't compared with
+fn check_params(
+    fn_name: &Ident,
+    literal: Option<&Literal>,
+    sql: &str,
+    param_names: &[String],
+    param_types: &[(String, Ident)],
+    tables: &[&Table],
+) -> Result<(), Error> {
+    let span = |name: &str| param_span(literal, fn_name.span(), name);
+    let warn =
+        |name: &str, text: String| Diagnostic::spanned(span(name), Level::Warning, text).emit();
+    if let Some(name) = param_names.iter().find(|name| !is_named_param(name)) {
+        let text = format!("{fn_name} binds its params by name, use :name instead of {name}");
+        Diagnostic::spanned(span(name), Level::Error, &text).emit();
+        return Err(Error::Generate(text));
+    }
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut mentioned: Vec<&str> = vec![];
+    for (ix, _colon) in sql.match_indices(':') {
+        let rest = &sql[ix + 1..];
+        let name = &rest[..rest.find(|c| !is_ident(c)).unwrap_or(rest.len())];
+        let starts_ident = name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+        if starts_ident && !sql[..ix].ends_with(is_ident) && !mentioned.contains(&name) {
+            mentioned.push(name);
+        }
+    }
+    for name in mentioned {
+        if !param_names.iter().any(|param| param[1..] == *name) {
+            warn(
+                &format!(":{name}"),
+                format!(":{name} is inside a string or comment, it isn't a param of {fn_name}"),
+            );
+        }
+    }
+    for (ix, (name, ty)) in param_types.iter().enumerate() {
+        if let Some((_name, first)) = param_types[..ix]
+            .iter()
+            .find(|(annotated, first)| annotated == name && first.to_string() != ty.to_string())
+        {
+            warn(
+                &format!(":{name}"),
+                format!(
+                    "{fn_name} annotates :{name} as both {first} and {ty}, it's one {first} argument"
+                ),
+            );
+        }
+    }
+    // `where user_id = :id` reads like it compares the id column
+    let tokens = sql_tokens(sql);
+    let columns = tables
         .iter()
-        .find(|field| field.name.to_string() == "id")
-        .map(|field| &field.name)
-    {
-        Some(id) => id,
-        None => {
-            Diagnostic::spanned(
-                table.name.span(),
-                Level::Error,
-                "Missing required column: id",
-            )
-            .emit();
-            return Err(Error::Generate("Missing required column: id".to_string()));
+        .filter(|table| tokens.contains(&table.name.to_string().to_lowercase()))
+        .flat_map(|table| {
+            table
+                .fields
+                .iter()
+                .map(|field| field.name.to_string().to_lowercase())
+        })
+        .collect::<Vec<_>>();
+    const OPERATORS: [&str; 6] = ["=", "==", "!=", "<>", "is", "like"];
+    let mut warned: Vec<&str> = vec![];
+    for (ix, token) in tokens.iter().enumerate() {
+        let Some(name) = token.strip_prefix(':') else {
+            continue;
+        };
+        let name_lower = name.to_lowercase();
+        if warned.contains(&name) || !columns.contains(&name_lower) {
+            continue;
         }
+        let before = ix
+            .checked_sub(1)
+            .filter(|op| OPERATORS.contains(&tokens[*op].as_str()))
+            .and_then(|op| column_before(&tokens, op));
+        let after = tokens
+            .get(ix + 1)
+            .filter(|op| OPERATORS.contains(&op.as_str()))
+            .and_then(|_op| column_after(&tokens, ix + 2));
+        if let Some((_table, column)) = before
+            .or(after)
+            .filter(|(_table, column)| *column != name_lower)
+        {
+            warn(
+                token,
+                format!(
+                    "{fn_name} compares :{name} with {column}, but :{name} is the name of another column"
+                ),
+            );
+            warned.push(name);
+        }
+    }
+    Ok(())
+}
+
+/// `:name`, the only params that can become arguments, not `?`, `?1`, `@name` or `$name`
+fn is_named_param(name: &str) -> bool {
+    name.strip_prefix(':').is_some_and(|name| {
+        name.chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+    })
+}
+
+/// The first `param` in `literal`, or all of `literal` when it isn't found or can't be
+/// split, or `fallback` without a literal
+fn param_span(literal: Option<&Literal>, fallback: Span, param: &str) -> Span {
+    let Some(literal) = literal else {
+        return fallback;
     };
-    let new_fields = table
-        .fields
+    let text = literal.to_string();
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    text.match_indices(param)
+        .find(|(ix, _param)| {
+            !text[ix + param.len()..].starts_with(is_ident)
+                && (!param.starts_with(is_ident) || !text[..*ix].ends_with(is_ident))
+        })
+        .and_then(|(ix, _param)| literal.subspan(ix..ix + param.len()))
+        .unwrap_or(literal.span())
+}
+
+/// `count_users`, `user_exists`, `find_user` and `all_users` for `table User`, with their sql
+fn table_reads(table: &Table) -> [(Ident, String); 4] {
+    let snake = snake_case(&table.name.to_string());
+    let table_name = qualified_name(&table.schema, &table.name);
+    let span = table.name.span();
+    let (live, and_live) = live(table);
+    [
+        (
+            Ident::new(&format!("count_{snake}s"), span),
+            format!("select count(*) from {table_name}{live}"),
+        ),
+        (
+            Ident::new(&format!("{snake}_exists"), span),
+            format!("select exists (select 1 from {table_name} where id = :id{and_live})"),
+        ),
+        (
+            Ident::new(&format!("find_{snake}"), span),
+            format!("select * from {table_name} where id = :id{and_live}"),
+        ),
+        (
+            Ident::new(&format!("all_{snake}s"), span),
+            format!("select * from {table_name}{live} order by id"),
+        ),
+    ]
+}
+
+/// The names of the declared queries, views and fts tables, a table read with the same
+/// name is left to them
+fn declared_reads(schema: &DatabaseSchema) -> Vec<String> {
+    schema
+        .parts
         .iter()
-        .filter(|field| {
-            field.name.to_string() != "id"
-                && field.name.to_string() != "created_at"
-                && field.name.to_string() != "updated_at"
+        .filter_map(|part| match part {
+            SchemaPart::Query(select) => Some(select.fn_name.to_string()),
+            SchemaPart::View(view) => Some(view_query(view).fn_name.to_string()),
+            SchemaPart::Fts(fts) => Some(fts_query(fts).fn_name.to_string()),
+            _ => None,
         })
-        .collect::<Vec<&Field>>();
-    let new_args = new_fields
+        .collect()
+}
+
+/// Row counts, id lookups and full reads for every table, without declaring a query for each
+fn generate_table_reads(
+    table: &Table,
+    declared: &[String],
+    vis: &TokenStream,
+    max_rows: Option<usize>,
+) -> TokenStream {
+    let vis = table.vis.clone().map(generated_span).unwrap_or(vis.clone());
+    let [
+        (count_fn, count_sql),
+        (exists_fn, exists_sql),
+        (find_fn, find_sql),
+        (all_fn, all_sql),
+    ] = table_reads(table);
+    let count_name = count_fn.to_string();
+    let exists_name = exists_fn.to_string();
+    let find_name = find_fn.to_string();
+    let all_name = all_fn.to_string();
+    let module = table_module(table);
+    let name = &table.name;
+    let row_ty = quote!($module::$name);
+    let id_name = id_name(table);
+    let id_ty = quote!($module::$id_name);
+    let all_rows = match max_rows {
+        Some(max_rows) => quote!(rows_as_at_most::<$row_ty>($max_rows)),
+        None => quote!(rows_as::<$row_ty>()),
+    };
+    let count = quote! {
+        #[doc = $count_sql]
+        #[track_caller]
+        $vis fn $count_fn(&self) -> sqltight::Result<i64> {
+            let rows = self.statement($count_name, $count_sql)?.rows()?;
+            rows.first().ok_or(sqltight::Error::RowNotFound)?.get(0)
+        }
+    };
+    let exists = quote! {
+        #[doc = $exists_sql]
+        #[track_caller]
+        $vis fn $exists_fn(&self, id: impl Into<$id_ty>) -> sqltight::Result<bool> {
+            let params: &[sqltight::Value] = &[sqltight::Value::from(Into::<$id_ty>::into(id))];
+            let rows = self.statement($exists_name, $exists_sql)?.bind(params)?.rows()?;
+            rows.first().ok_or(sqltight::Error::RowNotFound)?.get(0)
+        }
+    };
+    let find = quote! {
+        #[doc = $find_sql]
+        #[track_caller]
+        $vis fn $find_fn(&self, id: impl Into<$id_ty>) -> sqltight::Result<Option<$row_ty>> {
+            let params: &[sqltight::Value] = &[sqltight::Value::from(Into::<$id_ty>::into(id))];
+            let rows = self.statement($find_name, $find_sql)?.bind(params)?.rows_as::<$row_ty>()?;
+            Ok(rows.into_iter().next())
+        }
+    };
+    let all = quote! {
+        #[doc = $all_sql]
+        #[track_caller]
+        $vis fn $all_fn(&self) -> sqltight::Result<Vec<$row_ty>> {
+            let rows = self.statement($all_name, $all_sql)?.$all_rows?;
+            Ok(rows)
+        }
+    };
+    [
+        (count_name, count),
+        (exists_name, exists),
+        (find_name, find),
+        (all_name, all),
+    ]
+    .into_iter()
+    .filter(|(name, _tokens)| !declared.contains(name))
+    .map(|(_name, tokens)| tokens)
+    .collect()
+}
+
+/// A `references` column and the table it points at, `post.user_id references User(id)`
+/// gives `post.user(&db)` and `user.posts(&db)`
+struct Relation<'a> {
+    child: &'a Table,
+    field: &'a Field,
+    parent: &'a Table,
+    column: &'a Field,
+    parent_fn: Ident,
+    /// Prefixed with the column, like `sender_messages`, when the child references
+    /// the parent more than once
+    children_fn: Ident,
+}
+
+fn relations<'a>(tables: &[&'a Table]) -> Vec<Relation<'a>> {
+    tables
+        .iter()
+        .flat_map(|child| {
+            child.fields.iter().filter_map(|field| {
+                let References { table, column, .. } = field.references.as_ref()?;
+                // undeclared tables are reported by check_references
+                let (parent, column) =
+                    table_field(tables, (table.to_string(), column.to_string()))?;
+                let name = field.name.to_string();
+                let stem = name.strip_suffix("_id").unwrap_or(&name);
+                let references = child
+                    .fields
+                    .iter()
+                    .filter(|f| {
+                        f.references
+                            .as_ref()
+                            .is_some_and(|r| r.table.to_string() == table.to_string())
+                    })
+                    .count();
+                let children = format!("{}s", snake_case(&child.name.to_string()));
+                let children_fn = match references {
+                    1 => children,
+                    _ => format!("{stem}_{children}"),
+                };
+                Some(Relation {
+                    child,
+                    field,
+                    parent,
+                    column,
+                    parent_fn: Ident::new(stem, field.name.span()),
+                    children_fn: Ident::new(&children_fn, field.name.span()),
+                })
+            })
+        })
+        .collect()
+}
+
+/// ` where deleted_at is null` and ` and deleted_at is null` for `soft_delete` tables
+fn live(table: &Table) -> (&'static str, &'static str) {
+    match table.soft_delete {
+        true => (" where deleted_at is null", " and deleted_at is null"),
+        false => ("", ""),
+    }
+}
+
+/// A test per `query name uses_index "..."` that explains the query on a fresh in-memory
+/// database and fails when the plan searches no index, or not the one named
+fn generate_query_plan_tests(schema: &DatabaseSchema, attachments: &[&Attach]) -> TokenStream {
+    let database = Ident::new("Database", Span::call_site());
+    let connection = Ident::new("connection", Span::call_site());
+    let open = Ident::new("open", Span::call_site());
+    let memory_attachments = attachments
+        .iter()
+        .map(|_attach| quote! { ":memory:", })
+        .collect::<TokenStream>();
+    let tests = schema
+        .parts
+        .iter()
+        .filter_map(|part| match part {
+            SchemaPart::Query(select) => select.uses_index.as_ref().map(|index| (select, index)),
+            _ => None,
+        })
+        .map(|(select, index)| {
+            let fn_name = &select.fn_name;
+            let test_name = Ident::new(&format!("{fn_name}_uses_index"), fn_name.span());
+            let sql_const = Ident::new(
+                &format!("{}_SQL", fn_name.to_string().to_uppercase()),
+                Span::call_site(),
+            );
+            let (check, message) = match index {
+                Some(index) => {
+                    let name = index.to_string();
+                    (
+                        quote!(plan.index_names().contains(&$name)),
+                        format!("{fn_name} doesn't use the index {name}"),
+                    )
+                }
+                None => (
+                    quote!(plan.uses_index()),
+                    format!("{fn_name} doesn't search an index"),
+                ),
+            };
+            quote! {
+                #[test]
+                fn $test_name() -> sqltight::Result<()> {
+                    let db = super::$database::$open(":memory:", $memory_attachments)?;
+                    let plan = db.$connection.prepare(super::$database::$sql_const)?.explain()?;
+                    assert!($check, "{}\n{}", $message, plan);
+                    Ok(())
+                }
+            }
+        })
+        .collect::<TokenStream>();
+    if tests.is_empty() {
+        return tests;
+    }
+    let query_plans = Ident::new("query_plans", Span::call_site());
+    quote! {
+        #[cfg(test)]
+        mod $query_plans {
+            $tests
+        }
+    }
+}
+
+/// `post.user(&db)` and `user.posts(&db)` for each `references` column,
+/// one query per call
+fn generate_relations(tables: &[&Table], vis: &TokenStream) -> TokenStream {
+    let database = Ident::new("Database", Span::call_site());
+    let connection = Ident::new("connection", Span::call_site());
+    relations(tables)
         .iter()
         .map(
-            |Field {
-                 name: field_name,
-                 ty,
+            |Relation {
+                 child,
+                 field,
+                 parent,
+                 column,
+                 parent_fn,
+                 children_fn,
              }| {
-                quote! { $field_name: impl Into<$ty>, }
+                let vis = child.vis.clone().map(generated_span).unwrap_or(vis.clone());
+                let child_module = table_module(child);
+                let child_name = &child.name;
+                let child_ty = quote!($child_module::$child_name);
+                let parent_module = table_module(parent);
+                let parent_name = &parent.name;
+                let parent_ty = quote!($parent_module::$parent_name);
+                let field_name = &field.name;
+                let column_name = &column.name;
+                let parent_sql = format!(
+                    "select * from {} where {column_name} = :{field_name}{}",
+                    qualified_name(&parent.schema, parent_name),
+                    live(parent).1
+                );
+                let children_sql = format!(
+                    "select * from {} where {field_name} = :{column_name}{} order by id",
+                    qualified_name(&child.schema, child_name),
+                    live(child).1
+                );
+                quote! {
+                    impl $child_ty {
+                        #[doc = $parent_sql]
+                        #[track_caller]
+                        $vis fn $parent_fn(&self, db: &$database) -> sqltight::Result<Option<$parent_ty>> {
+                            let params = [sqltight::Value::from(self.$field_name.clone())];
+                            let rows = db.$connection.prepare($parent_sql)?.bind(&params)?.rows()?;
+                            Ok(rows.first().map(<$parent_ty as sqltight::FromRow>::from_row))
+                        }
+                    }
+
+                    impl $parent_ty {
+                        #[doc = $children_sql]
+                        #[track_caller]
+                        $vis fn $children_fn(&self, db: &$database) -> sqltight::Result<Vec<$child_ty>> {
+                            let params = [sqltight::Value::from(self.$column_name.clone())];
+                            let rows = db.$connection.prepare($children_sql)?.bind(&params)?.rows()?;
+                            Ok(rows.iter().map(<$child_ty as sqltight::FromRow>::from_row).collect())
+                        }
+                    }
+                }
             },
         )
-        .collect::<TokenStream>();
+        .collect()
+}
 
-    let new_struct_fields = new_fields
+/// `users_with_posts` and `posts_with_user` for each `references` column, two queries
+/// each however many rows they load, see `Sqlite::load_children`
+fn generate_relation_reads(
+    tables: &[&Table],
+    declared: &[String],
+    vis: &TokenStream,
+) -> TokenStream {
+    let connection = Ident::new("connection", Span::call_site());
+    relations(tables)
         .iter()
-        .map(|Field { name, .. }| {
-            quote! { $name: $name.into(), }
+        .flat_map(
+            |Relation {
+                 child,
+                 field,
+                 parent,
+                 column,
+                 parent_fn,
+                 children_fn,
+             }| {
+                let vis = child.vis.clone().map(generated_span).unwrap_or(vis.clone());
+                let child_module = table_module(child);
+                let child_name = &child.name;
+                let child_ty = quote!($child_module::$child_name);
+                let child_table = qualified_name(&child.schema, child_name);
+                let parent_module = table_module(parent);
+                let parent_name = &parent.name;
+                let parent_ty = quote!($parent_module::$parent_name);
+                let parent_table = qualified_name(&parent.schema, parent_name);
+                let field_name = field.name.to_string();
+                let column_name = column.name.to_string();
+                let (parent_live, parent_and_live) = live(parent);
+                let (child_live, child_and_live) = live(child);
+                let span = field.name.span();
+
+                let with_children_fn = Ident::new(
+                    &format!("{}s_with_{children_fn}", snake_case(&parent_name.to_string())),
+                    span,
+                );
+                let parents_sql =
+                    format!("select * from {parent_table}{parent_live} order by id");
+                let children_sql = format!(
+                    "select * from {child_table} where {field_name} in (select {column_name} from {parent_table}{parent_live}){child_and_live} order by id"
+                );
+                let with_children_doc = format!("{parents_sql}\n\n{children_sql}");
+                let with_children = quote! {
+                    #[doc = $with_children_doc]
+                    #[track_caller]
+                    $vis fn $with_children_fn(&self) -> sqltight::Result<Vec<($parent_ty, Vec<$child_ty>)>> {
+                        self.$connection.load_children($parents_sql, $children_sql, $column_name, $field_name)
+                    }
+                };
+
+                let with_parent_fn = Ident::new(
+                    &format!("{}s_with_{parent_fn}", snake_case(&child_name.to_string())),
+                    span,
+                );
+                let children_sql = format!("select * from {child_table}{child_live} order by id");
+                let parents_sql = format!(
+                    "select * from {parent_table} where {column_name} in (select {field_name} from {child_table}{child_live}){parent_and_live}"
+                );
+                let with_parent_doc = format!("{children_sql}\n\n{parents_sql}");
+                let with_parent = quote! {
+                    #[doc = $with_parent_doc]
+                    #[track_caller]
+                    $vis fn $with_parent_fn(&self) -> sqltight::Result<Vec<($child_ty, Option<$parent_ty>)>> {
+                        self.$connection.load_parents($children_sql, $parents_sql, $field_name, $column_name)
+                    }
+                };
+                [
+                    (with_children_fn.to_string(), with_children),
+                    (with_parent_fn.to_string(), with_parent),
+                ]
+            },
+        )
+        .filter(|(name, _tokens)| !declared.contains(name))
+        .map(|(_name, tokens)| tokens)
+        .collect()
+}
+
+/// The type of the column a param is compared to, like `Int` for `post.user_id = :user_id`.
+/// `None` for params that aren't compared to exactly one kind of declared
+/// `Int`, `Text`, `Real` or `Blob` column, those take any value
+fn inferred_param_type(sql: &str, param: &str, tables: &[&Table]) -> Option<Ident> {
+    const OPERATORS: [&str; 11] = [
+        "=", "==", "!=", "<>", "<", "<=", ">", ">=", "like", "glob", "is",
+    ];
+    let tokens = sql_tokens(sql);
+    let param = format!(":{param}");
+    let mut types = vec![];
+    for (ix, token) in tokens.iter().enumerate() {
+        if *token != param {
+            continue;
+        }
+        let before = ix
+            .checked_sub(1)
+            .filter(|op| OPERATORS.contains(&tokens[*op].as_str()))
+            .and_then(|op| column_before(&tokens, op));
+        let after = tokens
+            .get(ix + 1)
+            .filter(|op| OPERATORS.contains(&op.as_str()))
+            .and_then(|_op| column_after(&tokens, ix + 2));
+        match before.or(after) {
+            Some((table, column)) => types.push(column_type_name(tables, table, column)?),
+            None => return None,
+        }
+    }
+    let ty = types.first()?.clone();
+    match types.iter().all(|other| *other == ty) {
+        true => Some(Ident::new(&ty, Span::call_site())),
+        false => None,
+    }
+}
+
+/// `table.column` or `column` ending right before `tokens[end]`
+fn column_before(tokens: &[String], end: usize) -> Option<(Option<&str>, &str)> {
+    let column = tokens.get(end.checked_sub(1)?)?;
+    match end.checked_sub(3) {
+        Some(table) if tokens[end - 2] == "." => {
+            Some((Some(tokens[table].as_str()), column.as_str()))
+        }
+        _ => Some((None, column.as_str())),
+    }
+    .filter(|(_table, column)| is_sql_ident(column))
+}
+
+/// `table.column` or `column` starting at `tokens[start]`
+fn column_after(tokens: &[String], start: usize) -> Option<(Option<&str>, &str)> {
+    let first = tokens.get(start)?;
+    match (tokens.get(start + 1), tokens.get(start + 2)) {
+        (Some(dot), Some(column)) if dot == "." => Some((Some(first.as_str()), column.as_str())),
+        _ => Some((None, first.as_str())),
+    }
+    .filter(|(_table, column)| is_sql_ident(column))
+}
+
+fn is_sql_ident(token: &str) -> bool {
+    token
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+}
+
+/// The dsl type of a declared column, looked up in the named table or, for aliases and
+/// unqualified columns, in every table that has it. `None` when the tables disagree
+fn column_type_name(tables: &[&Table], table: Option<&str>, column: &str) -> Option<String> {
+    let named = tables
+        .iter()
+        .filter(|candidate| {
+            table.is_some_and(|table| candidate.name.to_string().eq_ignore_ascii_case(table))
+        })
+        .collect::<Vec<_>>();
+    let candidates = match named.is_empty() {
+        true => tables.iter().collect::<Vec<_>>(),
+        false => named,
+    };
+    let types = candidates
+        .iter()
+        .filter_map(|table| {
+            table
+                .fields
+                .iter()
+                .find(|field| field.name.to_string().eq_ignore_ascii_case(column))
+        })
+        .map(|field| field.ty.to_string())
+        .collect::<Vec<_>>();
+    let ty = types.first()?.clone();
+    match ["Int", "Text", "Real", "Blob"].contains(&ty.as_str())
+        && types.iter().all(|other| *other == ty)
+    {
+        true => Some(ty),
+        false => None,
+    }
+}
+
+/// Identifiers, `:params`, operators and punctuation of a sql string, lowercased.
+/// String literals become a single `'` and quoted identifiers lose their quotes
+fn sql_tokens(sql: &str) -> Vec<String> {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '$';
+    let chars = sql.chars().collect::<Vec<_>>();
+    let mut tokens = vec![];
+    let mut ix = 0;
+    while ix < chars.len() {
+        let c = chars[ix];
+        let start = ix;
+        ix += 1;
+        match c {
+            c if c.is_whitespace() => {}
+            '\'' => {
+                while ix < chars.len() && chars[ix] != '\'' {
+                    ix += 1;
+                }
+                ix += 1;
+                tokens.push("'".to_string());
+            }
+            '"' | '`' | '[' => {
+                let close = match c {
+                    '[' => ']',
+                    c => c,
+                };
+                while ix < chars.len() && chars[ix] != close {
+                    ix += 1;
+                }
+                tokens.push(
+                    chars[start + 1..ix.min(chars.len())]
+                        .iter()
+                        .collect::<String>()
+                        .to_lowercase(),
+                );
+                ix += 1;
+            }
+            ':' | '@' | '$' if chars.get(ix).is_some_and(|c| is_ident(*c)) => {
+                while ix < chars.len() && is_ident(chars[ix]) {
+                    ix += 1;
+                }
+                tokens.push(chars[start..ix].iter().collect());
+            }
+            c if is_ident(c) => {
+                while ix < chars.len() && is_ident(chars[ix]) {
+                    ix += 1;
+                }
+                tokens.push(chars[start..ix].iter().collect::<String>().to_lowercase());
+            }
+            '=' | '!' | '<' | '>' if matches!(chars.get(ix), Some('=' | '>')) => {
+                ix += 1;
+                tokens.push(chars[start..ix].iter().collect());
+            }
+            c => tokens.push(c.to_string()),
+        }
+    }
+    tokens
+}
+
+/// Warns about each query that normalizes to the same sql as one declared before it,
+/// like two copies that only differ in whitespace or param names. Queries with their own
+/// `max_rows` or `paginate` read differently and aren't duplicates
+/// Two queries with the same key generate the same method
+#[derive(PartialEq)]
+struct QueryKey {
+    normalized_sql: String,
+    max_rows: Option<usize>,
+    paginate: bool,
+}
+
+fn warn_duplicate_queries(
+    db: &sqltight_core::Sqlite,
+    schema: &DatabaseSchema,
+) -> Result<(), Error> {
+    let mut seen: Vec<(QueryKey, &Ident)> = vec![];
+    for part in &schema.parts {
+        let SchemaPart::Query(select) = part else {
+            continue;
+        };
+        let key = QueryKey {
+            normalized_sql: db.prepare(&select.sql)?.normalized_sql(),
+            max_rows: select.max_rows,
+            paginate: select.paginate,
+        };
+        match seen.iter().find(|(seen_key, _fn_name)| *seen_key == key) {
+            Some((_key, first)) => Diagnostic::spanned(
+                select.fn_name.span(),
+                Level::Warning,
+                format!("{} is the same query as {first}", select.fn_name),
+            )
+            .emit(),
+            None => seen.push((key, &select.fn_name)),
+        }
+    }
+    Ok(())
+}
+
+fn check_defaults(db: &sqltight_core::Sqlite, tables: &[&Table]) -> Result<(), Error> {
+    for field in tables.iter().flat_map(|table| &table.fields) {
+        let Some(default) = &field.default else {
+            continue;
+        };
+        if let Err(sqltight_core::Error::Sqlite { text, .. }) =
+            db.prepare(&format!("select {default}"))
+        {
+            let err = format!("Invalid default for {}: {text}", field.name);
+            Diagnostic::spanned(field.name.span(), Level::Error, &err).emit();
+            return Err(Error::Generate(err));
+        }
+    }
+    Ok(())
+}
+
+/// Creating a trigger doesn't resolve column names, preparing the condition does
+fn check_conditions(db: &sqltight_core::Sqlite, schema: &DatabaseSchema) -> Result<(), Error> {
+    for part in &schema.parts {
+        let SchemaPart::Check(check) = part else {
+            continue;
+        };
+        let table = qualified_name(&check.schema, &check.name);
+        let sql = sql_text(check.condition.clone(), None);
+        if let Err(sqltight_core::Error::Sqlite { text, .. }) =
+            db.prepare(&format!("select 1 from {table} where {sql}"))
+        {
+            let err = format!("Invalid check for {table}: {text}");
+            Diagnostic::spanned(check.name.span(), Level::Error, &err).emit();
+            return Err(Error::Generate(err));
+        }
+    }
+    Ok(())
+}
+
+/// External content is read lazily, so a column missing from the `for` table would only fail on search
+fn check_fts_content(tables: &[&Table], schema: &DatabaseSchema) -> Result<(), Error> {
+    for part in &schema.parts {
+        let SchemaPart::Fts(Fts {
+            name,
+            content: Some(content),
+            columns,
+            ..
+        }) = part
+        else {
+            continue;
+        };
+        let table = tables.iter().find(|table| {
+            table
+                .name
+                .to_string()
+                .eq_ignore_ascii_case(&content.to_string())
+        });
+        let missing = columns.iter().find(|column| {
+            !table.is_some_and(|table| {
+                table.fields.iter().any(|field| {
+                    field
+                        .name
+                        .to_string()
+                        .eq_ignore_ascii_case(&column.to_string())
+                })
+            })
+        });
+        let err = match (table, missing) {
+            (None, _) => format!("fts table {name} is for {content}, which isn't declared"),
+            (Some(_table), Some(column)) => {
+                format!("fts table {name}: {content} has no column {column}")
+            }
+            (Some(_table), None) => continue,
+        };
+        Diagnostic::spanned(content.span(), Level::Error, &err).emit();
+        return Err(Error::Generate(err));
+    }
+    Ok(())
+}
+
+/// Sqlite only checks foreign keys when rows are written, this catches typos at compile time
+fn check_references(tables: &[&Table]) -> Result<(), Error> {
+    for field in tables.iter().flat_map(|table| &table.fields) {
+        let Some(References { table, column, .. }) = &field.references else {
+            continue;
+        };
+        let found = tables
+            .iter()
+            .filter(|t| t.name.to_string().eq_ignore_ascii_case(&table.to_string()))
+            .flat_map(|t| &t.fields)
+            .any(|f| f.name.to_string().eq_ignore_ascii_case(&column.to_string()));
+        if !found {
+            let err = format!(
+                "{} references {table}({column}), which isn't declared",
+                field.name
+            );
+            Diagnostic::spanned(table.span(), Level::Error, &err).emit();
+            return Err(Error::Generate(err));
+        }
+    }
+    Ok(())
+}
+
+fn table_field<'a>(
+    tables: &[&'a Table],
+    (table, column): (String, String),
+) -> Option<(&'a Table, &'a Field)> {
+    let table = tables
+        .iter()
+        .find(|t| t.name.to_string().eq_ignore_ascii_case(&table))?;
+    let field = table
+        .fields
+        .iter()
+        .find(|field| field.name.to_string().eq_ignore_ascii_case(&column))?;
+    Some((table, field))
+}
+
+/// The rust type of a field, `Enum(...)` columns are an optional generated enum
+/// and not null columns use plain rust types
+fn field_type(table: &Table, field: &Field) -> TokenStream {
+    if let Some(width) = &field.width {
+        return match field.not_null {
+            true => quote!($width),
+            false => quote!(Option<$width>),
+        };
+    }
+    match (field.variants.is_empty(), field.not_null) {
+        (false, true) => {
+            let name = enum_name(table, field);
+            quote!($name)
+        }
+        (false, false) => {
+            let name = enum_name(table, field);
+            quote!(Option<$name>)
+        }
+        (true, true) => match field.ty.to_string().as_str() {
+            "Text" => quote!(String),
+            "Int" => quote!(i64),
+            "Real" => quote!(f64),
+            _ => quote!(Vec<u8>),
+        },
+        (true, false) => {
+            let ty = &field.ty;
+            let args = &field.args;
+            quote!($ty $args)
+        }
+    }
+}
+
+/// `PostStatus` for `status: Enum(...)` in `table Post`
+fn enum_name(table: &Table, field: &Field) -> Ident {
+    Ident::new(
+        &format!("{}{}", table.name, pascal_case(&field.name.to_string())),
+        Span::call_site(),
+    )
+}
+
+/// Enums and not null columns go through `FromSql`, `Option<PostStatus>` and `String`
+/// can't implement `From<Value>` here
+fn from_row_field(name: &Ident, field: Option<&Field>) -> TokenStream {
+    let value = from_row_value(&name.to_string(), field);
+    quote! { $name: $value, }
+}
+
+/// Reads the column `key` of a `Row` or `RowRef`, a missing column is the field's default.
+/// `Int u8` columns panic on values out of range, like an overflowing `as` would in debug
+fn from_row_value(key: &str, field: Option<&Field>) -> TokenStream {
+    if let Some(width) = field.and_then(|field| field.width.as_ref()) {
+        let message = format!("{key} doesn't fit in {width}: {{:?}}");
+        return quote! {
+            match row.value_ref($key) {
+                Some(val) => match sqltight::FromSql::from_sql_ref(val) {
+                    Ok(val) => val,
+                    Err(err) => panic!($message, err),
+                },
+                None => Default::default(),
+            }
+        };
+    }
+    match field.is_some_and(|field| !field.variants.is_empty() || field.not_null) {
+        true => quote! {
+            match row.value_ref($key) {
+                Some(val) => match sqltight::FromSql::from_sql_ref(val) {
+                    Ok(val) => val,
+                    Err(_) => unreachable!(),
+                },
+                None => Default::default(),
+            }
+        },
+        false => quote! {
+            match row.value_ref($key) {
+                Some(val) => match sqltight::FromSql::from_sql_ref(val) {
+                    Ok(val) => val,
+                    Err(_) => unreachable!(),
+                },
+                None => None.into(),
+            }
+        },
+    }
+}
+
+fn generate_enum(table: &Table, field: &Field, vis: &TokenStream) -> TokenStream {
+    let name = enum_name(table, field);
+    let enum_name = name.to_string();
+    let serde = serde_derive();
+    // the first variant is also the migration's default for not null columns
+    let variants = field
+        .variants
+        .iter()
+        .enumerate()
+        .map(|(ix, variant)| {
+            let variant = &variant.name;
+            match ix {
+                0 => quote!(#[default] $variant,),
+                _ => quote!($variant,),
+            }
         })
         .collect::<TokenStream>();
-    let new_fn = Ident::new("new", name.span());
+    let as_str = field
+        .variants
+        .iter()
+        .map(|variant| {
+            let text = variant.name.to_string();
+            let variant = &variant.name;
+            quote!(Self::$variant => $text,)
+        })
+        .collect::<TokenStream>();
+    let from_str = field
+        .variants
+        .iter()
+        .map(|variant| {
+            let text = variant.name.to_string();
+            let variant = &variant.name;
+            quote!($text => Ok(Self::$variant),)
+        })
+        .collect::<TokenStream>();
+    let (to_value, from_sql, from_sql_ref) = match field
+        .variants
+        .iter()
+        .any(|variant| variant.value.is_some())
+    {
+        true => {
+            let to_value = field
+                .variants
+                .iter()
+                .map(|variant| {
+                    let value = Literal::i64_unsuffixed(variant.value.unwrap_or_default());
+                    let variant = &variant.name;
+                    quote!($name::$variant => sqltight::int($value),)
+                })
+                .collect::<TokenStream>();
+            let from_sql = field
+                .variants
+                .iter()
+                .map(|variant| {
+                    let value = Literal::i64_unsuffixed(variant.value.unwrap_or_default());
+                    let variant = &variant.name;
+                    quote!($value => Ok(Self::$variant),)
+                })
+                .collect::<TokenStream>();
+            (
+                quote!(sqltight::Value::from(match value { $to_value })),
+                quote! {
+                    let value: i64 = sqltight::FromSql::from_sql(value)?;
+                    match value {
+                        $from_sql
+                        value => Err(sqltight::Error::InvalidEnum(format!("{}: {}", $enum_name, value))),
+                    }
+                },
+                TokenStream::new(),
+            )
+        }
+        false => (
+            quote!(sqltight::Value::from(sqltight::text(value.as_str()))),
+            quote! {
+                let value: String = sqltight::FromSql::from_sql(value)?;
+                match value.as_str() {
+                    $from_str
+                    _ => Err(sqltight::Error::InvalidEnum(format!("{}: {}", $enum_name, value))),
+                }
+            },
+            // matched on the borrowed text, without copying it
+            quote! {
+                fn from_sql_ref(value: sqltight::ValueRef<'_>) -> sqltight::Result<Self> {
+                    match value {
+                        sqltight::ValueRef::Text(value) => match &*value {
+                            $from_str
+                            _ => Err(sqltight::Error::InvalidEnum(format!("{}: {}", $enum_name, value))),
+                        },
+                        value => Self::from_sql(&value.into()),
+                    }
+                }
+            },
+        ),
+    };
 
-    Ok(quote! {
-        #[derive(Default)]
-        pub struct $name {
-            $fields
+    quote! {
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+        $serde
+        $vis enum $name {
+            $variants
         }
+
         impl $name {
-            pub fn $new_fn($new_args) -> Self {
-                Self { $new_struct_fields ..Default::default() }
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $as_str
+                }
             }
         }
-        impl sqltight::Crud for $name {
-            fn save(self, db: &sqltight::Sqlite) -> sqltight::Result<Self> {
-                let sql = $upsert_sql;
-                let params = vec![$upsert_params];
-                let row = db.prepare(&sql)?
-                    .bind(&params)?
-                    .rows()?
-                    .into_iter()
-                    .nth(0)
-                    .ok_or(sqltight::Error::RowNotFound)?;
-                Ok(Self::from_row(&row))
+
+        impl From<$name> for sqltight::Value {
+            fn from(value: $name) -> Self {
+                $to_value
             }
+        }
 
-            fn delete(self, db: &sqltight::Sqlite) -> sqltight::Result<Self> {
-                let sql = $delete_sql;
-                let params = vec![self.$id.into()];
-                let row = db
-                    .prepare(&sql)?
-                    .bind(&params)?
-                    .rows()?
-                    .into_iter()
-                    .nth(0)
-                    .ok_or(sqltight::Error::RowNotFound)?;
-                Ok(Self::from_row(&row))
+        impl sqltight::FromSql for $name {
+            fn from_sql(value: &sqltight::Value) -> sqltight::Result<Self> {
+                $from_sql
             }
+
+            $from_sql_ref
+        }
+    }
+}
+
+/// Search results are `id`, the indexed columns as `Text` and the bm25 `rank`, lower is better.
+/// Fts tables without `for` are written to directly, so they also get `new` and `Crud`
+fn generate_fts_struct(fts: &Fts, vis: &TokenStream) -> TokenStream {
+    let vis = fts.vis.clone().map(generated_span).unwrap_or(vis.clone());
+    let name = &fts.name;
+    let id = Ident::new("id", Span::call_site());
+    let rank = Ident::new("rank", Span::call_site());
+    let fields = fts
+        .columns
+        .iter()
+        .map(|column| quote! { pub $column: sqltight::Text, })
+        .collect::<TokenStream>();
+    let from_row_fields = [&id]
+        .into_iter()
+        .chain(&fts.columns)
+        .chain([&rank])
+        .map(|column| from_row_field(column, None))
+        .collect::<TokenStream>();
+    let serde = serde_derive();
+    let crud = match &fts.content {
+        Some(_content) => TokenStream::new(),
+        None => generate_fts_crud(fts),
+    };
+    let debug_fields = [(id.clone(), false)]
+        .into_iter()
+        .chain(fts.columns.iter().map(|column| (column.clone(), true)))
+        .chain([(rank.clone(), false)])
+        .collect::<Vec<_>>();
+    let debug = debug_impl(name, &debug_fields, true);
+    quote! {
+        #[derive(Default, Clone, PartialEq)]
+        $serde
+        $vis struct $name {
+            pub $id: sqltight::Int,
+            $fields
+            pub $rank: sqltight::Real,
         }
 
+        $debug
+
         impl sqltight::FromRow for $name {
-            fn from_row(row: &std::collections::BTreeMap<String, sqltight::Value>) -> Self {
+            fn from_row(row: &sqltight::Row) -> Self {
+                Self {
+                    $from_row_fields
+                }
+            }
+
+            fn from_row_ref(row: &sqltight::RowRef) -> Self {
                 Self {
                     $from_row_fields
                 }
             }
         }
-    })
-}
 
-fn pascal_case(name: &str) -> String {
-    name.split("_")
-        .map(|x| {
-            format!(
-                "{}{}",
-                x.chars().nth(0).unwrap().to_uppercase(),
-                x.chars().skip(1).collect::<String>()
-            )
-        })
-        .collect::<Vec<_>>()
-        .join("")
+        $crud
+    }
 }
 
-fn generate_select(db: &sqltight_core::Sqlite, select: &Query) -> Result<TokenStream, Error> {
-    let sql = &select.sql;
-    let fn_name = &select.fn_name;
-    let return_ident = Ident::new(&pascal_case(&fn_name.to_string()), fn_name.span());
-    let (return_ty, return_val) = match sql.contains("limit 1") {
-        false => (quote!(Vec<$return_ident>), quote!(Ok(rows))),
-        true => (
-            quote!($return_ident),
-            quote!(rows.into_iter().nth(0).ok_or(sqltight::Error::RowNotFound)),
-        ),
-    };
-    let stmt = match db.prepare(&sql) {
-        Ok(stmt) => stmt,
-        Err(err) => match err {
-            sqltight_core::Error::Sqlite { text, .. } => {
-                Diagnostic::spanned(fn_name.span(), Level::Error, &text).emit();
-                return Err(Error::Generate(text));
-            }
-            _ => todo!(),
-        },
-    };
-    let param_names = stmt.parameter_names();
-    let param_names = param_names
+fn generate_fts_crud(fts: &Fts) -> TokenStream {
+    let name = &fts.name;
+    let columns = fts
+        .columns
         .iter()
-        .map(|x| x.trim_start_matches(":"))
+        .map(|column| column.to_string())
         .collect::<Vec<_>>();
-    let param_idents = param_names
+    let save_sql = format!(
+        "insert or replace into {name} (rowid, {}) values (:id, {})",
+        columns.join(", "),
+        columns
+            .iter()
+            .map(|column| format!(":{column}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let saved_sql = format!(
+        "select rowid as id, {}, 0.0 as rank from {name} where rowid = last_insert_rowid()",
+        columns.join(", ")
+    );
+    let delete_sql = format!("delete from {name} where rowid = :id");
+    let new_args = fts
+        .columns
         .iter()
-        .map(|name| Ident::new(name, fn_name.span()))
-        .collect::<Vec<_>>();
-    let fn_args = param_idents
+        .map(|column| quote! { $column: impl Into<sqltight::Text>, })
+        .collect::<TokenStream>();
+    let new_fields = fts
+        .columns
         .iter()
-        .map(|arg| quote!($arg: impl Into<sqltight::Value>,))
+        .map(|column| quote! { $column: $column.into(), })
         .collect::<TokenStream>();
-    let params = param_idents
+    let save_params = fts
+        .columns
         .iter()
-        .map(|arg| quote!($arg.into(),))
+        .map(|column| quote! { self.$column.into(), })
         .collect::<TokenStream>();
-    let params = quote!(&[$params]);
-    let fn_name_str = fn_name.to_string();
-    Ok(quote!(
-        #[doc = $sql]
-        pub fn $fn_name(&self, $fn_args) -> sqltight::Result<$return_ty> {
-            let rows = self.statements.get($fn_name_str).unwrap()
-                .bind($params)?
-                .rows()?
-                .iter()
-                .map($return_ident::from_row)
-                .collect::<Vec<$return_ident>>();
-            $return_val
+    let id = Ident::new("id", Span::call_site());
+    let new_fn = Ident::new("new", name.span());
+    let save_sql_const = Ident::new("SAVE_SQL", Span::call_site());
+    let delete_sql_const = Ident::new("DELETE_SQL", Span::call_site());
+    quote! {
+        impl $name {
+            #[doc = $save_sql]
+            pub const $save_sql_const: &'static str = $save_sql;
+            #[doc = $delete_sql]
+            pub const $delete_sql_const: &'static str = $delete_sql;
+
+            pub fn $new_fn($new_args) -> Self {
+                Self { $new_fields ..Default::default() }
+            }
         }
-    ))
+
+        impl sqltight::Crud for $name {
+            #[track_caller]
+            fn save(self, db: &sqltight::Sqlite) -> sqltight::Result<Self> {
+                let params = vec![self.$id.into(), $save_params];
+                let _changes = db.prepare(Self::$save_sql_const)?.bind(&params)?.changes()?;
+                let row = db
+                    .prepare($saved_sql)?
+                    .rows()?
+                    .into_iter()
+                    .nth(0)
+                    .ok_or(sqltight::Error::RowNotFound)?;
+                Ok(Self::from_row(&row))
+            }
+
+            #[track_caller]
+            fn delete(self, db: &sqltight::Sqlite) -> sqltight::Result<Self> {
+                let params = vec![self.$id.into()];
+                let _changes = db.prepare(Self::$delete_sql_const)?.bind(&params)?.changes()?;
+                Ok(self)
+            }
+        }
+    }
 }
 
 fn generate_select_struct(
     db: &sqltight_core::Sqlite,
+    tables: &[&Table],
     select: &Query,
+    vis: &TokenStream,
 ) -> Result<TokenStream, Error> {
+    let vis = select
+        .vis
+        .clone()
+        .map(generated_span)
+        .unwrap_or(vis.clone());
     let sql = &select.sql;
     let fn_name = &select.fn_name;
     let struct_ident = Ident::new(&pascal_case(&fn_name.to_string()), fn_name.span());
-    let stmt = match db.prepare(&sql) {
+    let stmt = match db.prepare(sql) {
         Ok(stmt) => stmt,
-        Err(err) => match err {
-            sqltight_core::Error::Sqlite { text, .. } => {
-                Diagnostic::spanned(fn_name.span(), Level::Error, &text).emit();
-                return Err(Error::Generate(text));
-            }
-            _ => todo!(),
-        },
+        Err(err) => {
+            let text = error_text(err);
+            Diagnostic::spanned(fn_name.span(), Level::Error, &text).emit();
+            return Err(Error::Generate(text));
+        }
     };
     let column_names = stmt.select_column_names();
     let column_types = stmt.select_column_types();
+    let origin_fields = stmt
+        .select_column_origins()
+        .into_iter()
+        .map(|origin| origin.and_then(|origin| table_field(tables, origin)))
+        .collect::<Vec<_>>();
     let columns = column_names
         .into_iter()
         .zip(column_types)
         .collect::<Vec<_>>();
-    let fields = columns
+    let rust_types = columns
         .iter()
-        .map(|(name, ty)| {
+        .zip(&origin_fields)
+        .map(|((name, ty), origin)| {
             if name.contains("(") {
                 let err = format!("{name} needs an alias. I don't make the rules");
                 Diagnostic::spanned(fn_name.span(), Level::Error, &err).emit();
                 return Err(Error::Generate(err));
             }
-            let name = Ident::new(name, fn_name.span());
+            // columns read straight from a table keep the type declared in the dsl
+            if let Some((table, field)) = origin {
+                return Ok(field_type(table, field));
+            }
             let ty = match ty.as_str() {
                 "INTEGER" | "INT" => "Int",
                 "TEXT" => "Text",
                 "BLOB" => "Blob",
                 "REAL" => "Real",
-                _ => match name.to_string().contains("count") {
+                _ => match name.contains("count") {
                     true => "Int",
                     false => "Blob",
                 },
             };
             let ty = Ident::new(ty, fn_name.span());
-            Ok(quote! { pub $name: $ty, })
+            Ok(quote!($ty))
         })
-        .collect::<Result<TokenStream, Error>>()?;
+        .collect::<Result<Vec<_>, Error>>()?;
+    let fields = columns
+        .iter()
+        .zip(&rust_types)
+        .map(|((name, _), ty)| {
+            let name = Ident::new(name, fn_name.span());
+            quote! { pub $name: $ty, }
+        })
+        .collect::<TokenStream>();
+    // a marker per column for `Database::{query}_fields::<(Id, Email)>`
+    let column_markers = columns
+        .iter()
+        .zip(&rust_types)
+        .zip(&origin_fields)
+        .map(|(((name, _), ty), origin)| {
+            let marker = Ident::new(&pascal_case(name), fn_name.span());
+            let value = from_row_value(name, origin.map(|(_, field)| field));
+            let marker_struct = quote! {
+                #[derive(Debug, Clone, Copy, PartialEq)]
+                pub struct $marker;
+            };
+            // implemented out here, a marker named like a type would shadow it in the module
+            let marker_impl = quote! {
+                impl sqltight::Column<$struct_ident> for $fn_name::$marker {
+                    const NAME: &'static str = $name;
+                    type Value = $ty;
+
+                    fn value(row: &sqltight::Row) -> Self::Value {
+                        $value
+                    }
+                }
+            };
+            (marker_struct, marker_impl)
+        })
+        .collect::<Vec<_>>();
+    let (column_markers, column_impls): (TokenStream, TokenStream) =
+        column_markers.into_iter().unzip();
     let from_row_fields = columns
         .iter()
-        .map(|(name, ..)| {
+        .zip(&origin_fields)
+        .map(|((name, _), origin)| {
             let ident = Ident::new(name, fn_name.span());
-            quote!($ident: match row.get($name) { Some(val) => val.clone().into(), None => None.into() },)
+            from_row_field(&ident, origin.map(|(_, field)| field))
         })
         .collect::<TokenStream>();
 
+    let debug_fields = columns
+        .iter()
+        .zip(&origin_fields)
+        .map(|((name, ty), origin)| {
+            let truncated = match origin {
+                Some((_table, field)) => is_truncated(field),
+                None => {
+                    !matches!(ty.as_str(), "INTEGER" | "INT" | "REAL") && !name.contains("count")
+                }
+            };
+            (Ident::new(name, fn_name.span()), truncated)
+        })
+        .collect::<Vec<_>>();
+    let debug = debug_impl(&struct_ident, &debug_fields, true);
+    let serde = serde_derive();
     Ok(quote!(
-        #[derive(Debug, Clone, PartialEq)]
-        pub struct $struct_ident {
+        #[derive(Clone, PartialEq)]
+        $serde
+        $vis struct $struct_ident {
             $fields
         }
 
+        $debug
+
         impl sqltight::FromRow for $struct_ident {
-            fn from_row(row: &std::collections::BTreeMap<String, sqltight::Value>) -> Self {
+            fn from_row(row: &sqltight::Row) -> Self {
+                Self {
+                    $from_row_fields
+                }
+            }
+
+            fn from_row_ref(row: &sqltight::RowRef) -> Self {
                 Self {
                     $from_row_fields
                 }
             }
         }
+
+        $vis mod $fn_name {
+            $column_markers
+        }
+
+        $column_impls
     ))
 }
 
+/// `Debug` like the derive's, except `truncated` fields go through `sqltight::Truncated`
+/// so logging a row with a big blob doesn't print all of it
+fn debug_impl(name: &Ident, fields: &[(Ident, bool)], exhaustive: bool) -> TokenStream {
+    let name_str = name.to_string();
+    let fields = fields
+        .iter()
+        .map(|(field, truncated)| {
+            let key = field.to_string();
+            match truncated {
+                true => quote!(.field($key, &sqltight::Truncated(&self.$field))),
+                false => quote!(.field($key, &self.$field)),
+            }
+        })
+        .collect::<TokenStream>();
+    let finish = match exhaustive {
+        true => quote!(finish()),
+        false => quote!(finish_non_exhaustive()),
+    };
+    quote! {
+        impl std::fmt::Debug for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.debug_struct($name_str)$fields.$finish
+            }
+        }
+    }
+}
+
+/// The fields of a table's struct in its `Debug` output, generic ones like `Json<Settings>`
+/// are left out since their argument may not be `Debug`
+fn table_debug_impl(table: &Table) -> TokenStream {
+    let fields = table
+        .fields
+        .iter()
+        .filter(|field| field.args.is_empty())
+        .map(|field| (field.name.clone(), is_truncated(field)))
+        .collect::<Vec<_>>();
+    debug_impl(&table.name, &fields, fields.len() == table.fields.len())
+}
+
+/// Text, blob and `Any` columns, the ones that can hold megabytes
+fn is_truncated(field: &Field) -> bool {
+    field.variants.is_empty()
+        && field.width.is_none()
+        && ["Text", "Blob", "Any"].contains(&field.ty.to_string().as_str())
+}
+
+/// Only emitted when sqltight is built with the serde feature
+fn serde_derive() -> TokenStream {
+    match cfg!(feature = "serde") {
+        true => quote! {
+            #[derive(sqltight::serde::Serialize, sqltight::serde::Deserialize)]
+            #[serde(crate = "sqltight::serde")]
+        },
+        false => TokenStream::new(),
+    }
+}
+
 fn upsert_sql(table: &Table) -> (String, TokenStream) {
     let columns: Vec<_> = table.fields.iter().map(|f| f.name.to_string()).collect();
     let column_names = columns.join(",");
-    let placeholders = columns
+    // null falls back to the column's default, the upsert always binds every column
+    let placeholders = table
+        .fields
         .iter()
-        .map(|c| format!(":{c}"))
+        .map(|field| match &field.default {
+            Some(default) => format!("coalesce(:{}, {default})", field.name),
+            None => format!(":{}", field.name),
+        })
         .collect::<Vec<_>>()
         .join(",");
-    let set_clause = columns
+    let sql = format!(
+        "insert into {} ({}) values ({}) {}",
+        qualified_name(&table.schema, &table.name),
+        column_names,
+        placeholders,
+        conflict_clause(table)
+    );
+
+    let params = table
+        .fields
         .iter()
-        .map(|c| format!("{c} = excluded.{c}"))
+        .map(|field| save_param(table, field, quote!(self)))
+        .collect::<TokenStream>();
+
+    (sql, params)
+}
+
+/// The value a save binds for `field`, `updated_at` of a `timestamps` table
+/// is bound as null so its default sets it to now
+fn save_param(table: &Table, field: &Field, row: TokenStream) -> TokenStream {
+    let name = &field.name;
+    match table.timestamps && field.name.to_string() == "updated_at" {
+        true => quote!(sqltight::Value::from(sqltight::Int::default()),),
+        false => quote!(sqltight::Value::from($row.$name),),
+    }
+}
+
+/// The upsert of `upsert_sql` split around its values with positional params,
+/// so `Sqlite::save_batch` can repeat the row once per saved row
+fn batch_sql(table: &Table) -> (String, String, String) {
+    let columns: Vec<_> = table.fields.iter().map(|f| f.name.to_string()).collect();
+    let insert = format!(
+        "insert into {} ({}) values",
+        qualified_name(&table.schema, &table.name),
+        columns.join(",")
+    );
+    let placeholders = table
+        .fields
+        .iter()
+        .map(|field| match &field.default {
+            Some(default) => format!("coalesce(?, {default})"),
+            None => "?".to_string(),
+        })
         .collect::<Vec<_>>()
         .join(",");
+    (insert, format!("({placeholders})"), conflict_clause(table))
+}
 
-    let sql = format!(
-        "insert into {} ({}) values ({}) on conflict (id) do update set {} returning *",
-        table.name, column_names, placeholders, set_clause
-    );
+/// `on conflict (email)` needs a unique index on email and `on conflict where` a valid
+/// condition, sqlite checks both when the upsert is prepared
+fn check_conflict_targets(
+    db: &sqltight_core::Sqlite,
+    schema: &DatabaseSchema,
+) -> Result<(), Error> {
+    for part in &schema.parts {
+        let SchemaPart::Table(table) = part else {
+            continue;
+        };
+        if table.conflict.is_empty() && table.conflict_where.is_none() {
+            continue;
+        }
+        let Err(err) = db.prepare(&upsert_sql(table).0) else {
+            continue;
+        };
+        let span = table.conflict.first().unwrap_or(&table.name).span();
+        let message = match (&table.conflict_where, err) {
+            (Some(condition), sqltight_core::Error::Sqlite { text, .. })
+                if !text.contains("ON CONFLICT clause does not match") =>
+            {
+                format!(
+                    "Invalid on conflict where for {}: {text} in \"{condition}\"",
+                    table.name
+                )
+            }
+            _ => {
+                let columns = table
+                    .conflict
+                    .iter()
+                    .map(|column| column.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!(
+                    "{} on conflict ({columns}) needs a unique index, e.g. index {} {{ ({columns}): Unique }}",
+                    table.name, table.name
+                )
+            }
+        };
+        Diagnostic::spanned(span, Level::Error, message.as_str()).emit();
+        return Err(Error::Generate(message));
+    }
+    Ok(())
+}
 
-    let params = table
+/// Upserts by id, or by the table's `on conflict` columns first, keeping the id
+/// of the row it updates. Each update only happens when the `on conflict where` holds
+/// A nullable column with a default, saving null inserts the default but sets an existing
+/// row's column to null. The timestamps are set on every save instead
+fn defaults_on_insert(table: &Table, field: &Field) -> bool {
+    field.default.is_some()
+        && !field.not_null
+        && !(table.timestamps
+            && matches!(field.name.to_string().as_str(), "created_at" | "updated_at"))
+}
+
+fn conflict_clause(table: &Table) -> String {
+    let condition = match &table.conflict_where {
+        Some(condition) => format!(" where {condition}"),
+        None => String::new(),
+    };
+    let set_clause = |skip_id: bool| {
+        let set = table
+            .fields
+            .iter()
+            .filter(|field| !(skip_id && field.name.to_string() == "id"))
+            .filter(|field| !(table.timestamps && field.name.to_string() == "created_at"))
+            // only delete sets it, saving a copy loaded before the delete keeps the row deleted
+            .filter(|field| !(table.soft_delete && field.name.to_string() == "deleted_at"))
+            .map(|field| match defaults_on_insert(table, field) {
+                // `excluded` has the default in place of a null
+                true => format!("{0} = :{0}", field.name),
+                false => format!("{0} = excluded.{0}", field.name),
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        format!("{set}{condition}")
+    };
+    let target = table
+        .conflict
+        .iter()
+        .map(|column| column.to_string())
+        .filter(|column| column != "id")
+        .collect::<Vec<_>>();
+    match target.is_empty() {
+        true => format!(
+            "on conflict (id) do update set {} returning *",
+            set_clause(false)
+        ),
+        false => format!(
+            "on conflict ({}) do update set {} on conflict (id) do update set {} returning *",
+            target.join(","),
+            set_clause(true),
+            set_clause(false)
+        ),
+    }
+}
+
+/// A plain insert for log tables, the id comes from the table and the hash from `append`
+fn append_sql(table: &Table) -> (String, TokenStream) {
+    let fields = table
         .fields
+        .iter()
+        .filter(|field| !matches!(field.name.to_string().as_str(), "id" | "hash"))
+        .collect::<Vec<_>>();
+    let columns = fields
+        .iter()
+        .map(|field| field.name.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    let placeholders = fields
+        .iter()
+        .map(|field| match &field.default {
+            Some(default) => format!("coalesce(:{}, {default})", field.name),
+            None => format!(":{}", field.name),
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    let sql = match fields.is_empty() {
+        true => format!(
+            "insert into {} default values returning *",
+            qualified_name(&table.schema, &table.name)
+        ),
+        false => format!(
+            "insert into {} ({columns}) values ({placeholders}) returning *",
+            qualified_name(&table.schema, &table.name)
+        ),
+    };
+    let params = fields
         .iter()
         .map(|Field { name, .. }| quote!(sqltight::Value::from(self.$name),))
         .collect::<TokenStream>();
-
     (sql, params)
 }
 
+/// sqlite's own message, or the error itself, e.g. a migration breaking a unique constraint
+fn error_text(err: sqltight_core::Error) -> String {
+    match err {
+        sqltight_core::Error::Sqlite { text, .. } => text,
+        err => format!("{err:?}"),
+    }
+}
+
 impl From<sqltight_core::Error> for Error {
     fn from(value: sqltight_core::Error) -> Self {
-        match value {
-            sqltight_core::Error::Sqlite { text, .. } => Self::Generate(text),
-            _ => todo!(),
-        }
+        Self::Generate(error_text(value))
     }
 }
 
-fn statement_from_part(part: &SchemaPart) -> TokenStream {
+fn statement_from_part(part: &SchemaPart, declared: &[String]) -> TokenStream {
     match part {
-        SchemaPart::Table(_table) => TokenStream::new(),
+        SchemaPart::Attach(_attach) => TokenStream::new(),
+        SchemaPart::Pragma(_pragma) => TokenStream::new(),
+        SchemaPart::Env(_env) => TokenStream::new(),
+        SchemaPart::DebugOutput(_debug_output) => TokenStream::new(),
+        SchemaPart::SchemaDiff(_schema_diff) => TokenStream::new(),
+        SchemaPart::DevDatabase(_dev_database) => TokenStream::new(),
+        SchemaPart::Docs(_docs) => TokenStream::new(),
+        SchemaPart::Visibility(_visibility) => TokenStream::new(),
+        SchemaPart::MaxRows(_max_rows) => TokenStream::new(),
+        SchemaPart::Table(table) => table_reads(table)
+            .into_iter()
+            .filter(|(name, _sql)| !declared.contains(&name.to_string()))
+            .map(|(name, sql)| {
+                let key = name.to_string();
+                quote! {
+                    ($key, connection.prepare($sql)?),
+                }
+            })
+            .collect(),
         SchemaPart::Index(_index) => TokenStream::new(),
+        SchemaPart::Check(_check) => TokenStream::new(),
         SchemaPart::Query(select) => statement_from_select(select),
+        // ops prepare on the connection saves run on, not on a pooled thread's
+        SchemaPart::Op(_op) => TokenStream::new(),
+        SchemaPart::Execute(_execute) => TokenStream::new(),
+        SchemaPart::View(view) => statement_from_select(&view_query(view)),
+        SchemaPart::Fts(fts) => statement_from_select(&fts_query(fts)),
+        SchemaPart::Timeseries(_timeseries) => TokenStream::new(),
+        SchemaPart::Migration(_migration) => TokenStream::new(),
+        SchemaPart::MigrationDir(_migration_dir) => TokenStream::new(),
     }
 }
 
 fn statement_from_select(select: &Query) -> TokenStream {
     let key = select.fn_name.to_string();
-    let sql = &select.sql;
+    match select.paginate {
+        true => {
+            let (page_sql, count_sql) = page_sql(&select.sql);
+            let count_key = format!("{key}_count");
+            quote! {
+                ($key, connection.prepare($page_sql)?),
+                ($count_key, connection.prepare($count_sql)?),
+            }
+        }
+        false => {
+            let sql = &select.sql;
+            quote! {
+                ($key, connection.prepare($sql)?),
+            }
+        }
+    }
+}
+
+/// The sql reading one page of a `paginate` query's rows and the sql counting all of them
+fn page_sql(sql: &str) -> (String, String) {
+    (
+        format!("select * from ({sql}) limit :sqltight_limit offset :sqltight_offset"),
+        format!("select count(*) from ({sql})"),
+    )
+}
+
+pub fn generate_from_row(input: &DeriveStruct) -> TokenStream {
+    let name = &input.name;
+    let fields = input
+        .fields
+        .iter()
+        .map(|field| {
+            let column = field.to_string();
+            quote! {
+                $field: match row.get($column) {
+                    Ok(value) => value,
+                    Err(sqltight::Error::ColumnNotFound(_)) => {
+                        match sqltight::FromSql::from_sql(&sqltight::Value::Null) {
+                            Ok(value) => value,
+                            Err(err) => panic!("{}: column {} is missing: {:?}", stringify!($name), $column, err),
+                        }
+                    }
+                    Err(err) => panic!("{}: {:?}", stringify!($name), err),
+                },
+            }
+        })
+        .collect::<TokenStream>();
+
     quote! {
-        ($key, connection.prepare($sql)?),
+        impl sqltight::FromRow for $name {
+            fn from_row(row: &sqltight::Row) -> Self {
+                Self {
+                    $fields
+                }
+            }
+
+            fn from_row_ref(row: &sqltight::RowRef) -> Self {
+                Self {
+                    $fields
+                }
+            }
+        }
     }
 }
@@ -1,10 +1,16 @@
-#![feature(proc_macro_quote, proc_macro_totokens, proc_macro_diagnostic)]
+#![feature(
+    proc_macro_quote,
+    proc_macro_totokens,
+    proc_macro_diagnostic,
+    proc_macro_span
+)]
 
+mod docs;
 mod generator;
 mod parser;
 
-use generator::generate;
-use parser::parse;
+use generator::{generate, generate_from_row};
+use parser::{parse, parse_derive};
 use proc_macro::{TokenStream, quote};
 
 #[proc_macro]
@@ -15,6 +21,15 @@ pub fn db(input: TokenStream) -> TokenStream {
     }
 }
 
+/// Implements `FromRow` by matching each named field to the column of the same name
+#[proc_macro_derive(FromRow)]
+pub fn derive_from_row(input: TokenStream) -> TokenStream {
+    match parse_derive(input) {
+        Ok(input) => generate_from_row(&input),
+        Err(err) => to_compile_error(err),
+    }
+}
+
 fn db_macro(input: TokenStream) -> Result<TokenStream, Error> {
     let schema = parse(input)?;
     let tokens = generate(&schema)?;
@@ -1,37 +1,263 @@
-use proc_macro::{Delimiter, Ident, TokenStream, TokenTree};
+use proc_macro::{Delimiter, Ident, Literal, Span, TokenStream, TokenTree};
 use std::iter::Peekable;
 
 use crate::Error;
 
 #[derive(Debug, Clone)]
 pub struct Field {
+    pub vis: Option<TokenStream>,
     pub name: Ident,
     pub ty: Ident,
+    /// Generic arguments including the angle brackets, e.g. `<Settings>` in `Json<Settings>`
+    pub args: TokenStream,
+    /// The variants of an `Enum(...)` column, empty for every other type
+    pub variants: Vec<Variant>,
+    /// The narrower rust type of `flags: Int u8` or `score: Real f32`,
+    /// the column keeps its sql type and reads are range checked
+    pub width: Option<Ident>,
+    /// `Text not null`, columns are nullable by default or with `Text?`
+    pub not_null: bool,
+    /// The sql of `default "unixepoch()"` or `default 0`
+    pub default: Option<String>,
+    /// `references User(id) on delete cascade`
+    pub references: Option<References>,
+    /// `email: Text renamed_from mail`, the name the column had before
+    pub renamed_from: Option<Ident>,
+    /// `legacy: Text dropped`, a column to drop from existing databases
+    pub dropped: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct References {
+    pub table: Ident,
+    pub column: Ident,
+    /// The sql after the column, e.g. `on delete cascade on update restrict`
+    pub actions: String,
+}
+
+/// `Draft` or `Low = 1` in `Enum(...)`, variants with values are stored as INTEGER
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub name: Ident,
+    pub value: Option<i64>,
 }
 
 #[derive(Debug)]
 pub struct Table {
+    pub vis: Option<TokenStream>,
+    pub schema: Option<Ident>,
     pub name: Ident,
     pub fields: Vec<Field>,
+    /// Columns marked `dropped`, they are left out of `fields`
+    pub dropped: Vec<Ident>,
+    /// `log table`, append-only with each row's `hash` chained to the row before
+    pub log: bool,
+    /// `table User on conflict (email) { ... }` upserts by email instead of by id
+    pub conflict: Vec<Ident>,
+    /// `on conflict (id) where "excluded.version > Doc.version"` only updates the stored row
+    /// when the condition holds, e.g. last write wins
+    pub conflict_where: Option<String>,
+    /// `table User soft_delete { ... }`, delete sets the added `deleted_at` column
+    /// and the generated reads skip rows that have it set
+    pub soft_delete: bool,
+    /// `table Post timestamps { ... }`, `created_at` is set on insert and `updated_at` on every save
+    pub timestamps: bool,
 }
 
 #[derive(Debug)]
 pub struct Index {
+    pub schema: Option<Ident>,
     pub name: Ident,
-    pub fields: Vec<Field>,
+    pub columns: Vec<IndexColumns>,
+}
+
+/// `email: Unique` or `(user_id, created_at): Index`, one index over one or more columns
+#[derive(Debug)]
+pub struct IndexColumns {
+    pub names: Vec<Ident>,
+    pub unique: bool,
 }
 
 #[derive(Debug)]
 pub struct Query {
+    pub vis: Option<TokenStream>,
+    pub fn_name: Ident,
+    pub max_rows: Option<usize>,
+    /// `query posts_page paginate "..."` reads one page at a time
+    pub paginate: bool,
+    /// `query posts_by_user uses_index "..."` generates a test that fails when its plan
+    /// searches no index, `uses_index = Post_user_id_ix` for one index in particular
+    pub uses_index: Option<Option<Ident>>,
+    pub sql: String,
+    /// `:user_id int` in the sql, the annotation is stripped from `sql`
+    pub param_types: Vec<(String, Ident)>,
+    /// The sql as written, to point diagnostics at its params. Views and fts tables have none
+    pub literal: Option<Literal>,
+    /// `query trash with_deleted "..."` reads the deleted rows of `soft_delete` tables too
+    pub with_deleted: bool,
+}
+
+/// `op transfer { "update ..." "insert ..." }`, statements run in order in one savepoint,
+/// sharing their named params
+#[derive(Debug)]
+pub struct Op {
+    pub vis: Option<TokenStream>,
     pub fn_name: Ident,
+    pub statements: Vec<String>,
+    /// `:amount int` in any of the statements, the annotations are stripped from `statements`
+    pub param_types: Vec<(String, Ident)>,
+    /// `op purge with_deleted { ... }`, see `Query::with_deleted`
+    pub with_deleted: bool,
+}
+
+/// `execute claim_job -> Job "update job ... returning *"`, one write statement, with the
+/// rows of its returning clause read into `Job`
+#[derive(Debug)]
+pub struct Execute {
+    pub vis: Option<TokenStream>,
+    pub fn_name: Ident,
+    pub returns: Option<Ident>,
+    pub sql: String,
+    /// `:worker text` in the sql, the annotation is stripped from `sql`
+    pub param_types: Vec<(String, Ident)>,
+    pub literal: Literal,
+    /// `execute restore with_deleted "..."`, see `Query::with_deleted`
+    pub with_deleted: bool,
+}
+
+/// `view active_users "select ..."`, created in the migrations and read like a query
+#[derive(Debug)]
+pub struct View {
+    pub vis: Option<TokenStream>,
+    pub name: Ident,
     pub sql: String,
+    /// `view trash with_deleted "..."`, see `Query::with_deleted`
+    pub with_deleted: bool,
+}
+
+/// `fts table PostSearch for Post { title, content }`, a full text index kept in sync
+/// with `Post`, or one written to directly without `for`
+#[derive(Debug)]
+pub struct Fts {
+    pub vis: Option<TokenStream>,
+    pub name: Ident,
+    pub content: Option<Ident>,
+    pub columns: Vec<Ident>,
+}
+
+/// `timeseries table Reading by at { at: Int not null, value: Real }`, rows go to one table
+/// per month of `at`, created on first insert
+#[derive(Debug)]
+pub struct Timeseries {
+    pub table: Table,
+    pub by: Ident,
+}
+
+/// `migration 2 up "alter table ..." down "alter table ..."`, run in version order
+#[derive(Debug, Clone)]
+pub struct Migration {
+    pub version: i64,
+    pub up: String,
+    pub down: String,
+}
+
+/// `migrations "./migrations"`, a dir of numbered .sql files read at compile time
+#[derive(Debug)]
+pub struct MigrationDir {
+    pub path: String,
+}
+
+/// `check Post { length(content) > 0 }`, the condition every row has to meet
+#[derive(Debug)]
+pub struct Check {
+    pub schema: Option<Ident>,
+    pub name: Ident,
+    pub condition: TokenStream,
+}
+
+#[derive(Debug)]
+pub struct Attach {
+    pub schema: Ident,
+}
+
+#[derive(Debug)]
+pub struct Pragma {
+    pub name: Ident,
+    pub value: String,
+}
+
+#[derive(Debug)]
+pub struct DebugOutput {
+    pub path: String,
+}
+
+/// `schema_diff = "dev.db"`, warns at compile time about what that database is missing
+#[derive(Debug)]
+pub struct SchemaDiffCheck {
+    pub path: String,
+    pub span: Span,
+}
+
+/// `path = "dev.sqlite3"`, queries are checked against that database instead of the dsl's
+#[derive(Debug)]
+pub struct DevDatabase {
+    pub path: String,
+    pub span: Span,
+}
+
+/// `docs = "docs/schema.md"`, markdown, html, mermaid or dot depending on the extension
+#[derive(Debug)]
+pub struct Docs {
+    pub path: String,
+}
+
+/// The default visibility of generated items, `pub` unless declared
+#[derive(Debug)]
+pub struct Visibility {
+    pub vis: TokenStream,
+}
+
+/// `max_rows = 1000`, the row limit of queries that don't declare their own
+#[derive(Debug)]
+pub struct MaxRows {
+    pub rows: usize,
+}
+
+#[derive(Debug)]
+pub struct Env {
+    pub name: Ident,
+    pub parts: Vec<SchemaPart>,
 }
 
 #[derive(Debug)]
 pub enum SchemaPart {
+    Attach(Attach),
+    Pragma(Pragma),
+    Env(Env),
+    DebugOutput(DebugOutput),
+    SchemaDiff(SchemaDiffCheck),
+    DevDatabase(DevDatabase),
+    Docs(Docs),
+    Visibility(Visibility),
+    MaxRows(MaxRows),
     Table(Table),
     Index(Index),
+    Check(Check),
     Query(Query),
+    Op(Op),
+    Execute(Execute),
+    View(View),
+    Fts(Fts),
+    Timeseries(Timeseries),
+    Migration(Migration),
+    MigrationDir(MigrationDir),
+}
+
+/// A plain struct with named fields, the input to `#[derive(FromRow)]`
+#[derive(Debug)]
+pub struct DeriveStruct {
+    pub name: Ident,
+    pub fields: Vec<Ident>,
 }
 
 #[derive(Debug)]
@@ -77,24 +303,563 @@ impl Parser<proc_macro::token_stream::IntoIter> {
         }
     }
 
-    fn parse_table(&mut self) -> Result<Table, Error> {
+    fn parse_schema_name(&mut self) -> Result<(Option<Ident>, Ident), Error> {
         let name = self.expect_ident()?;
-        let fields = self.parse_braced_fields()?;
-        Ok(Table { name, fields })
+        match self.tokens.peek() {
+            Some(TokenTree::Punct(p)) if p.as_char() == '.' => {
+                self.tokens.next();
+                Ok((Some(name), self.expect_ident()?))
+            }
+            _ => Ok((None, name)),
+        }
     }
 
-    fn parse_index(&mut self) -> Result<Index, Error> {
+    fn parse_attach(&mut self) -> Result<Attach, Error> {
+        let schema = self.expect_ident()?;
+        Ok(Attach { schema })
+    }
+
+    fn parse_pragma(&mut self) -> Result<Pragma, Error> {
         let name = self.expect_ident()?;
-        let fields = self.parse_braced_fields()?;
-        Ok(Index { name, fields })
+        match self.tokens.next() {
+            Some(TokenTree::Literal(lit)) => {
+                let value = lit.to_string().trim_matches('"').to_string();
+                Ok(Pragma { name, value })
+            }
+            Some(TokenTree::Ident(ident)) => Ok(Pragma {
+                name,
+                value: ident.to_string(),
+            }),
+            // negative numbers come through as a `-` punct before the literal
+            Some(TokenTree::Punct(punct)) if punct.as_char() == '-' => match self.tokens.next() {
+                Some(TokenTree::Literal(lit)) if lit.to_string().parse::<f64>().is_ok() => {
+                    Ok(Pragma {
+                        name,
+                        value: format!("-{lit}"),
+                    })
+                }
+                _ => Err(Error::Parse(format!(
+                    "Expected a number after - for pragma {name}, e.g. pragma {name} -2000"
+                ))),
+            },
+            _ => Err(Error::Parse(format!(
+                "Expected a value for pragma {name}, e.g. pragma {name} \"value\""
+            ))),
+        }
+    }
+
+    fn parse_debug_output(&mut self) -> Result<DebugOutput, Error> {
+        self.expect_punct('=')?;
+        match self.tokens.next() {
+            Some(TokenTree::Literal(lit)) => {
+                let path = lit.to_string().trim_matches('"').to_string();
+                Ok(DebugOutput { path })
+            }
+            _ => Err(Error::Parse(
+                "Expected a path for debug_output, e.g. debug_output = \"target/db.rs\""
+                    .to_string(),
+            )),
+        }
+    }
+
+    fn parse_schema_diff(&mut self) -> Result<SchemaDiffCheck, Error> {
+        self.expect_punct('=')?;
+        match self.tokens.next() {
+            Some(TokenTree::Literal(lit)) => {
+                let path = lit.to_string().trim_matches('"').to_string();
+                Ok(SchemaDiffCheck {
+                    path,
+                    span: lit.span(),
+                })
+            }
+            _ => Err(Error::Parse(
+                "Expected a database path for schema_diff, e.g. schema_diff = \"dev.db\""
+                    .to_string(),
+            )),
+        }
+    }
+
+    fn parse_dev_database(&mut self) -> Result<DevDatabase, Error> {
+        self.expect_punct('=')?;
+        match self.tokens.next() {
+            Some(TokenTree::Literal(lit)) => {
+                let path = lit.to_string().trim_matches('"').to_string();
+                Ok(DevDatabase {
+                    path,
+                    span: lit.span(),
+                })
+            }
+            _ => Err(Error::Parse(
+                "Expected a database path, e.g. path = \"dev.sqlite3\"".to_string(),
+            )),
+        }
+    }
+
+    fn parse_docs(&mut self) -> Result<Docs, Error> {
+        self.expect_punct('=')?;
+        match self.tokens.next() {
+            Some(TokenTree::Literal(lit)) => {
+                let path = lit.to_string().trim_matches('"').to_string();
+                Ok(Docs { path })
+            }
+            _ => Err(Error::Parse(
+                "Expected a path for docs, e.g. docs = \"docs/schema.md\"".to_string(),
+            )),
+        }
+    }
+
+    fn parse_migration_dir(&mut self) -> Result<MigrationDir, Error> {
+        match self.tokens.next() {
+            Some(TokenTree::Literal(lit)) => {
+                let path = lit.to_string().trim_matches('"').to_string();
+                Ok(MigrationDir { path })
+            }
+            _ => Err(Error::Parse(
+                "Expected a dir for migrations, e.g. migrations \"./migrations\"".to_string(),
+            )),
+        }
+    }
+
+    fn parse_env(&mut self) -> Result<Env, Error> {
+        let name = self.expect_ident()?;
+        let mut parts = Vec::new();
+        match self.tokens.next() {
+            Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Brace => {
+                let mut content_parser = Parser::new(group.stream());
+                while content_parser.tokens.peek().is_some() {
+                    let keyword = content_parser.expect_ident()?;
+                    match keyword.to_string().as_str() {
+                        "pragma" => parts.push(SchemaPart::Pragma(content_parser.parse_pragma()?)),
+                        "index" => parts.push(SchemaPart::Index(content_parser.parse_index()?)),
+                        _ => {
+                            return Err(Error::Parse(format!(
+                                "Unexpected keyword in env {name}: {keyword}. Expected 'pragma' or 'index'."
+                            )));
+                        }
+                    }
+                }
+            }
+            _ => {
+                return Err(Error::Parse(
+                    "Expected a braced block `{ ... }`".to_string(),
+                ));
+            }
+        }
+        Ok(Env { name, parts })
+    }
+
+    fn parse_visibility(&mut self) -> Option<TokenStream> {
+        match self.tokens.peek() {
+            Some(TokenTree::Ident(ident)) if ident.to_string() == "pub" => {
+                let mut vis = vec![self.tokens.next()?];
+                if let Some(TokenTree::Group(group)) = self.tokens.peek()
+                    && group.delimiter() == Delimiter::Parenthesis
+                {
+                    vis.push(self.tokens.next()?);
+                }
+                Some(vis.into_iter().collect())
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_default_visibility(&mut self) -> Result<Visibility, Error> {
+        self.expect_punct('=')?;
+        match self.parse_visibility() {
+            Some(vis) => Ok(Visibility { vis }),
+            None => Err(Error::Parse(
+                "Expected a visibility, e.g. visibility = pub(crate)".to_string(),
+            )),
+        }
+    }
+
+    fn parse_max_rows(&mut self) -> Result<MaxRows, Error> {
+        self.expect_punct('=')?;
+        match usize::try_from(self.parse_integer()?) {
+            Ok(rows) if rows > 0 => Ok(MaxRows { rows }),
+            _ => Err(Error::Parse(
+                "Expected a positive number of rows, e.g. max_rows = 1000".to_string(),
+            )),
+        }
+    }
+
+    /// Statements are prepared when the database opens, so only collations every
+    /// connection has are allowed
+    fn parse_collation(&mut self) -> Result<String, Error> {
+        let collation = self.expect_ident()?.to_string();
+        match collation.as_str() {
+            "binary" | "nocase" | "rtrim" => Ok(collation),
+            "unicode" if cfg!(feature = "unicode_collation") => Ok(collation),
+            "unicode" => Err(Error::Parse(
+                "order_by_collation = unicode needs the unicode_collation feature".to_string(),
+            )),
+            _ => Err(Error::Parse(format!(
+                "Expected binary, nocase, rtrim or unicode, but got: order_by_collation = {collation}"
+            ))),
+        }
+    }
+
+    fn parse_table(&mut self, vis: Option<TokenStream>) -> Result<Table, Error> {
+        let (schema, name) = self.parse_schema_name()?;
+        let mut conflict = vec![];
+        let mut conflict_where = None;
+        let mut soft_delete = false;
+        let mut timestamps = false;
+        while let Some(TokenTree::Ident(ident)) = self.tokens.peek() {
+            match ident.to_string().as_str() {
+                "on" => (conflict, conflict_where) = self.parse_conflict_target(&name)?,
+                "soft_delete" => {
+                    self.tokens.next();
+                    soft_delete = true;
+                }
+                "timestamps" => {
+                    self.tokens.next();
+                    timestamps = true;
+                }
+                option => {
+                    return Err(Error::Parse(format!(
+                        "Expected on conflict, soft_delete or timestamps, but got: table {name} {option}"
+                    )));
+                }
+            }
+        }
+        let (dropped, mut fields): (Vec<_>, Vec<_>) = self
+            .parse_braced_fields()?
+            .into_iter()
+            .partition(|field| field.dropped);
+        if timestamps {
+            for column in ["created_at", "updated_at"] {
+                match fields
+                    .iter_mut()
+                    .find(|field| field.name.to_string() == column)
+                {
+                    Some(field) if field.ty.to_string() != "Int" || field.width.is_some() => {
+                        return Err(Error::Parse(format!(
+                            "timestamps table {name} sets {column} to unix seconds, declare it as {column}: Int"
+                        )));
+                    }
+                    // a not null column is 0 and a default is kept, instead of the time of the save
+                    Some(field) if field.not_null || field.default.is_some() => {
+                        return Err(Error::Parse(format!(
+                            "timestamps table {name} sets {column} itself, declare it without not null or a default"
+                        )));
+                    }
+                    Some(field) => {
+                        field.default = Some("unixepoch()".to_string());
+                    }
+                    None => fields.push(Field {
+                        vis: None,
+                        name: Ident::new(column, name.span()),
+                        ty: Ident::new("Int", name.span()),
+                        args: TokenStream::new(),
+                        variants: vec![],
+                        width: None,
+                        not_null: false,
+                        default: Some("unixepoch()".to_string()),
+                        references: None,
+                        renamed_from: None,
+                        dropped: false,
+                    }),
+                }
+            }
+        }
+        if soft_delete {
+            if fields
+                .iter()
+                .any(|field| field.name.to_string() == "deleted_at")
+            {
+                return Err(Error::Parse(format!(
+                    "soft_delete table {name} declares deleted_at, it is added and set on delete"
+                )));
+            }
+            fields.push(Field {
+                vis: None,
+                name: Ident::new("deleted_at", name.span()),
+                ty: Ident::new("Int", name.span()),
+                args: TokenStream::new(),
+                variants: vec![],
+                width: None,
+                not_null: false,
+                default: None,
+                references: None,
+                renamed_from: None,
+                dropped: false,
+            });
+        }
+        if let Some(column) = conflict.iter().find(|column| {
+            !fields
+                .iter()
+                .any(|field| field.name.to_string() == column.to_string())
+        }) {
+            return Err(Error::Parse(format!(
+                "{name} conflicts on {column}, but it has no column {column}"
+            )));
+        }
+        Ok(Table {
+            vis,
+            schema,
+            name,
+            fields,
+            dropped: dropped.into_iter().map(|field| field.name).collect(),
+            log: false,
+            conflict,
+            conflict_where,
+            soft_delete,
+            timestamps,
+        })
+    }
+
+    /// `on conflict (email)` or `on conflict (team_id, email)`, either followed by an optional
+    /// `where "..."` condition, or `on conflict where "..."` to upsert by id on a condition
+    fn parse_conflict_target(
+        &mut self,
+        table: &Ident,
+    ) -> Result<(Vec<Ident>, Option<String>), Error> {
+        let expected = || {
+            Error::Parse(format!(
+                "Expected a conflict target, e.g. table {table} on conflict (email) {{ ... }}"
+            ))
+        };
+        match (self.tokens.next(), self.tokens.next()) {
+            (Some(TokenTree::Ident(on)), Some(TokenTree::Ident(conflict)))
+                if on.to_string() == "on" && conflict.to_string() == "conflict" => {}
+            _ => return Err(expected()),
+        }
+        let mut columns = vec![];
+        if let Some(TokenTree::Group(group)) = self.tokens.peek()
+            && group.delimiter() == Delimiter::Parenthesis
+        {
+            let mut parser = Parser::new(group.stream());
+            self.tokens.next();
+            while parser.tokens.peek().is_some() {
+                columns.push(parser.expect_ident()?);
+                if parser.tokens.peek().is_some() {
+                    parser.expect_punct(',')?;
+                }
+            }
+            if columns.is_empty() {
+                return Err(expected());
+            }
+        }
+        match self.tokens.peek() {
+            Some(TokenTree::Ident(ident)) if ident.to_string() == "where" => {
+                self.tokens.next();
+                match self.tokens.next() {
+                    Some(TokenTree::Literal(lit)) => {
+                        let condition = lit.to_string().trim_matches('"').to_string();
+                        Ok((columns, Some(condition)))
+                    }
+                    _ => Err(Error::Parse(format!(
+                        "Expected a condition, e.g. table {table} on conflict where \"excluded.version > {table}.version\" {{ ... }}"
+                    ))),
+                }
+            }
+            _ if columns.is_empty() => Err(expected()),
+            _ => Ok((columns, None)),
+        }
+    }
+
+    /// `log table AuditEvent { ... }` is a table with a `hash: Text` column added
+    fn parse_log_table(&mut self, vis: Option<TokenStream>) -> Result<Table, Error> {
+        match self.expect_ident()? {
+            keyword if keyword.to_string() == "table" => {}
+            keyword => {
+                return Err(Error::Parse(format!(
+                    "Expected log table, but got: log {keyword}"
+                )));
+            }
+        }
+        let mut table = self.parse_table(vis)?;
+        if !table.conflict.is_empty() || table.conflict_where.is_some() || table.soft_delete {
+            return Err(Error::Parse(format!(
+                "log table {} only appends, it can't upsert on conflict or soft delete",
+                table.name
+            )));
+        }
+        if table
+            .fields
+            .iter()
+            .any(|field| field.name.to_string() == "hash")
+        {
+            return Err(Error::Parse(format!(
+                "log table {} declares hash, it is added and computed on append",
+                table.name
+            )));
+        }
+        table.fields.push(Field {
+            vis: None,
+            name: Ident::new("hash", table.name.span()),
+            ty: Ident::new("Text", table.name.span()),
+            args: TokenStream::new(),
+            variants: vec![],
+            width: None,
+            not_null: false,
+            default: None,
+            references: None,
+            renamed_from: None,
+            dropped: false,
+        });
+        table.log = true;
+        Ok(table)
+    }
+
+    fn parse_index(&mut self) -> Result<Index, Error> {
+        let (schema, name) = self.parse_schema_name()?;
+        let mut parser = match self.tokens.next() {
+            Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Brace => {
+                Parser::new(group.stream())
+            }
+            _ => {
+                return Err(Error::Parse(
+                    "Expected a braced block `{ ... }`".to_string(),
+                ));
+            }
+        };
+        let mut columns = vec![];
+        while parser.tokens.peek().is_some() {
+            columns.push(parser.parse_index_columns()?);
+            match parser.tokens.next() {
+                Some(TokenTree::Punct(p)) if p.as_char() == ',' => {}
+                None => break,
+                Some(other) => {
+                    return Err(Error::Parse(format!(
+                        "Expected ',' between indexes, but got: {other}"
+                    )));
+                }
+            }
+        }
+        Ok(Index {
+            schema,
+            name,
+            columns,
+        })
+    }
+
+    fn parse_index_columns(&mut self) -> Result<IndexColumns, Error> {
+        let names = match self.tokens.next() {
+            Some(TokenTree::Ident(ident)) => vec![ident],
+            Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Parenthesis => {
+                let mut parser = Parser::new(group.stream());
+                let mut names = vec![];
+                while parser.tokens.peek().is_some() {
+                    names.push(parser.expect_ident()?);
+                    if parser.tokens.peek().is_some() {
+                        parser.expect_punct(',')?;
+                    }
+                }
+                names
+            }
+            other => {
+                return Err(Error::Parse(format!(
+                    "Expected a column or a list of columns, e.g. (user_id, created_at): Unique, but got: {}",
+                    other.map(|token| token.to_string()).unwrap_or_default()
+                )));
+            }
+        };
+        if names.is_empty() {
+            return Err(Error::Parse(
+                "Expected at least one column in ()".to_string(),
+            ));
+        }
+        self.expect_punct(':')?;
+        let kind = self.expect_ident()?;
+        let unique = match kind.to_string().as_str() {
+            "Unique" => true,
+            "Index" => false,
+            _ => {
+                return Err(Error::Parse(format!(
+                    "Expected Unique or Index, but got: {kind}"
+                )));
+            }
+        };
+        Ok(IndexColumns { names, unique })
+    }
+
+    fn parse_check(&mut self) -> Result<Check, Error> {
+        let (schema, name) = self.parse_schema_name()?;
+        let condition = match self.tokens.next() {
+            Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Brace => {
+                group.stream()
+            }
+            _ => {
+                return Err(Error::Parse(format!(
+                    "Expected a braced condition, e.g. check {name} {{ length(content) > 0 }}"
+                )));
+            }
+        };
+        match condition.is_empty() {
+            true => Err(Error::Parse(format!("check {name} is empty"))),
+            false => Ok(Check {
+                schema,
+                name,
+                condition,
+            }),
+        }
     }
 
-    fn parse_query(&mut self) -> Result<Query, Error> {
+    fn parse_query(&mut self, vis: Option<TokenStream>) -> Result<Query, Error> {
         let fn_name = self.expect_ident()?;
+        let mut max_rows = None;
+        let mut paginate = false;
+        let mut uses_index = None;
+        let mut collation = None;
+        let mut with_deleted = false;
+        while let Some(TokenTree::Ident(ident)) = self.tokens.peek() {
+            match ident.to_string().as_str() {
+                "max_rows" => {
+                    self.tokens.next();
+                    max_rows = Some(self.parse_max_rows()?.rows);
+                }
+                "paginate" => {
+                    self.tokens.next();
+                    paginate = true;
+                }
+                "uses_index" => {
+                    self.tokens.next();
+                    uses_index = match self.tokens.peek() {
+                        Some(TokenTree::Punct(punct)) if punct.as_char() == '=' => {
+                            self.tokens.next();
+                            Some(Some(self.expect_ident()?))
+                        }
+                        _ => Some(None),
+                    };
+                }
+                "order_by_collation" => {
+                    self.tokens.next();
+                    self.expect_punct('=')?;
+                    collation = Some(self.parse_collation()?);
+                }
+                "with_deleted" => {
+                    self.tokens.next();
+                    with_deleted = true;
+                }
+                _ => break,
+            }
+        }
         match self.tokens.next() {
             Some(TokenTree::Literal(lit)) => {
                 let sql = lit.to_string().trim_matches('"').to_string();
-                Ok(Query { fn_name, sql })
+                let (sql, param_types) = strip_param_types(&sql, lit.span());
+                // `query users order_by_collation = unicode "..."` collates each order by term
+                let sql = match &collation {
+                    Some(collation) => collate_order_by(&sql, collation).ok_or_else(|| {
+                        Error::Parse(format!(
+                            "{fn_name} has order_by_collation = {collation} but its sql has no order by"
+                        ))
+                    })?,
+                    None => sql,
+                };
+                Ok(Query {
+                    vis,
+                    fn_name,
+                    max_rows,
+                    paginate,
+                    uses_index,
+                    sql,
+                    param_types,
+                    literal: Some(lit),
+                    with_deleted,
+                })
             }
             _ => Err(Error::Parse(
                 "Expected a string literal for the SQL query inside the select parentheses."
@@ -103,6 +868,232 @@ impl Parser<proc_macro::token_stream::IntoIter> {
         }
     }
 
+    fn parse_execute(&mut self, vis: Option<TokenStream>) -> Result<Execute, Error> {
+        let fn_name = self.expect_ident()?;
+        let with_deleted = self.parse_with_deleted();
+        let returns = match self.tokens.peek() {
+            Some(TokenTree::Punct(punct)) if punct.as_char() == '-' => {
+                self.tokens.next();
+                self.expect_punct('>')?;
+                Some(self.expect_ident()?)
+            }
+            _ => None,
+        };
+        match self.tokens.next() {
+            Some(TokenTree::Literal(lit)) => {
+                let sql = lit.to_string().trim_matches('"').to_string();
+                let (sql, param_types) = strip_param_types(&sql, lit.span());
+                Ok(Execute {
+                    vis,
+                    fn_name,
+                    returns,
+                    sql,
+                    param_types,
+                    literal: lit,
+                    with_deleted,
+                })
+            }
+            _ => Err(Error::Parse(format!(
+                "Expected a string literal for the sql of {fn_name}, e.g. execute {fn_name} -> Job \"update ... returning *\""
+            ))),
+        }
+    }
+
+    fn parse_op(&mut self, vis: Option<TokenStream>) -> Result<Op, Error> {
+        let fn_name = self.expect_ident()?;
+        let with_deleted = self.parse_with_deleted();
+        let expected = || {
+            Error::Parse(format!(
+                "Expected a braced list of sql strings, e.g. op {fn_name} {{ \"update ...\" \"insert ...\" }}"
+            ))
+        };
+        let group = match self.tokens.next() {
+            Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Brace => group,
+            _ => return Err(expected()),
+        };
+        let mut statements = vec![];
+        let mut param_types: Vec<(String, Ident)> = vec![];
+        for token in group.stream() {
+            match token {
+                TokenTree::Literal(lit) => {
+                    let sql = lit.to_string().trim_matches('"').to_string();
+                    let (sql, types) = strip_param_types(&sql, lit.span());
+                    for (name, ty) in types {
+                        match param_types
+                            .iter()
+                            .find(|(annotated, _ty)| *annotated == name)
+                        {
+                            Some((_name, annotated)) if annotated.to_string() != ty.to_string() => {
+                                return Err(Error::Parse(format!(
+                                    "{fn_name} annotates :{name} as both {annotated} and {ty}"
+                                )));
+                            }
+                            Some(_) => {}
+                            None => param_types.push((name, ty)),
+                        }
+                    }
+                    statements.push(sql);
+                }
+                TokenTree::Punct(punct) if punct.as_char() == ',' => {}
+                _ => return Err(expected()),
+            }
+        }
+        if statements.is_empty() {
+            return Err(expected());
+        }
+        Ok(Op {
+            vis,
+            fn_name,
+            statements,
+            param_types,
+            with_deleted,
+        })
+    }
+
+    /// An optional `with_deleted` after the name of a view, op or execute
+    fn parse_with_deleted(&mut self) -> bool {
+        match self.tokens.peek() {
+            Some(TokenTree::Ident(ident)) if ident.to_string() == "with_deleted" => {
+                self.tokens.next();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn parse_view(&mut self, vis: Option<TokenStream>) -> Result<View, Error> {
+        let name = self.expect_ident()?;
+        let with_deleted = self.parse_with_deleted();
+        match self.tokens.next() {
+            Some(TokenTree::Literal(lit)) => {
+                let sql = lit.to_string().trim_matches('"').to_string();
+                Ok(View {
+                    vis,
+                    name,
+                    sql,
+                    with_deleted,
+                })
+            }
+            _ => Err(Error::Parse(format!(
+                "Expected a string literal for the view, e.g. view {name} \"select * from user\""
+            ))),
+        }
+    }
+
+    fn parse_migration(&mut self) -> Result<Migration, Error> {
+        let version = self.parse_integer()?;
+        if version <= 0 {
+            return Err(Error::Parse(format!(
+                "Expected a positive migration version, but got: migration {version}"
+            )));
+        }
+        let up = self.parse_migration_sql(version, "up")?;
+        let down = self.parse_migration_sql(version, "down")?;
+        Ok(Migration { version, up, down })
+    }
+
+    fn parse_migration_sql(&mut self, version: i64, keyword: &str) -> Result<String, Error> {
+        match (self.tokens.next(), self.tokens.next()) {
+            (Some(TokenTree::Ident(ident)), Some(TokenTree::Literal(lit)))
+                if ident.to_string() == keyword =>
+            {
+                Ok(lit.to_string().trim_matches('"').to_string())
+            }
+            _ => Err(Error::Parse(format!(
+                "Expected {keyword} sql, e.g. migration {version} up \"create table ...\" down \"drop table ...\""
+            ))),
+        }
+    }
+
+    fn parse_fts(&mut self, vis: Option<TokenStream>) -> Result<Fts, Error> {
+        match self.expect_ident()? {
+            keyword if keyword.to_string() == "table" => {}
+            keyword => {
+                return Err(Error::Parse(format!(
+                    "Expected fts table, but got: fts {keyword}"
+                )));
+            }
+        }
+        let name = self.expect_ident()?;
+        let content = match self.tokens.peek() {
+            Some(TokenTree::Ident(ident)) if ident.to_string() == "for" => {
+                self.tokens.next();
+                Some(self.expect_ident()?)
+            }
+            _ => None,
+        };
+        let mut parser = match self.tokens.next() {
+            Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Brace => {
+                Parser::new(group.stream())
+            }
+            _ => {
+                return Err(Error::Parse(format!(
+                    "Expected the indexed columns, e.g. fts table {name} {{ title, content }}"
+                )));
+            }
+        };
+        let mut columns = vec![];
+        while parser.tokens.peek().is_some() {
+            columns.push(parser.expect_ident()?);
+            if parser.tokens.peek().is_some() {
+                parser.expect_punct(',')?;
+            }
+        }
+        match columns.is_empty() {
+            true => Err(Error::Parse(format!("fts table {name} has no columns"))),
+            false => Ok(Fts {
+                vis,
+                name,
+                content,
+                columns,
+            }),
+        }
+    }
+
+    fn parse_timeseries(&mut self, vis: Option<TokenStream>) -> Result<Timeseries, Error> {
+        match self.expect_ident()? {
+            keyword if keyword.to_string() == "table" => {}
+            keyword => {
+                return Err(Error::Parse(format!(
+                    "Expected timeseries table, but got: timeseries {keyword}"
+                )));
+            }
+        }
+        let name = self.expect_ident()?;
+        let by = match self.tokens.next() {
+            Some(TokenTree::Ident(ident)) if ident.to_string() == "by" => self.expect_ident()?,
+            _ => {
+                return Err(Error::Parse(format!(
+                    "Expected the column to partition by, e.g. timeseries table {name} by at {{ ... }}"
+                )));
+            }
+        };
+        let fields = self.parse_braced_fields()?;
+        match fields
+            .iter()
+            .find(|field| field.name.to_string() == by.to_string())
+        {
+            Some(field) if field.ty.to_string() == "Int" => Ok(Timeseries {
+                table: Table {
+                    vis,
+                    schema: None,
+                    name,
+                    fields,
+                    dropped: vec![],
+                    log: false,
+                    conflict: vec![],
+                    conflict_where: None,
+                    soft_delete: false,
+                    timestamps: false,
+                },
+                by,
+            }),
+            _ => Err(Error::Parse(format!(
+                "timeseries table {name} is partitioned by {by}, declare it as unix seconds, e.g. {by}: Int not null"
+            ))),
+        }
+    }
+
     fn parse_braced_fields(&mut self) -> Result<Vec<Field>, Error> {
         match self.tokens.next() {
             Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Brace => {
@@ -115,13 +1106,337 @@ impl Parser<proc_macro::token_stream::IntoIter> {
         }
     }
 
+    fn skip_attributes_and_visibility(&mut self) {
+        loop {
+            match self.tokens.peek() {
+                Some(TokenTree::Punct(p)) if p.as_char() == '#' => {
+                    self.tokens.next();
+                    self.tokens.next();
+                }
+                Some(TokenTree::Ident(ident)) if ident.to_string() == "pub" => {
+                    self.tokens.next();
+                    if let Some(TokenTree::Group(group)) = self.tokens.peek()
+                        && group.delimiter() == Delimiter::Parenthesis
+                    {
+                        self.tokens.next();
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn parse_derive_fields(&mut self) -> Result<Vec<Ident>, Error> {
+        let mut fields = Vec::new();
+        while self.tokens.peek().is_some() {
+            self.skip_attributes_and_visibility();
+            fields.push(self.expect_ident()?);
+            self.expect_punct(':')?;
+            // the type is left to inference, skip to the next top level comma
+            let mut depth = 0;
+            for token in self.tokens.by_ref() {
+                match token {
+                    TokenTree::Punct(p) if p.as_char() == '<' => depth += 1,
+                    TokenTree::Punct(p) if p.as_char() == '>' => depth -= 1,
+                    TokenTree::Punct(p) if p.as_char() == ',' && depth == 0 => break,
+                    _ => {}
+                }
+            }
+        }
+        Ok(fields)
+    }
+
+    fn parse_generic_args(&mut self) -> TokenStream {
+        let mut args = vec![];
+        let mut depth = 0;
+        loop {
+            let opens =
+                matches!(self.tokens.peek(), Some(TokenTree::Punct(p)) if p.as_char() == '<');
+            if depth == 0 && !opens {
+                break;
+            }
+            let Some(token) = self.tokens.next() else {
+                break;
+            };
+            match &token {
+                TokenTree::Punct(p) if p.as_char() == '<' => depth += 1,
+                TokenTree::Punct(p) if p.as_char() == '>' => depth -= 1,
+                _ => {}
+            }
+            args.push(token);
+        }
+        args.into_iter().collect()
+    }
+
+    fn parse_variants(&mut self, field: &Ident) -> Result<Vec<Variant>, Error> {
+        let group = match self.tokens.next() {
+            Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Parenthesis => group,
+            _ => {
+                return Err(Error::Parse(format!(
+                    "Expected variants for {field}, e.g. {field}: Enum(Draft, Published)"
+                )));
+            }
+        };
+        let mut parser = Parser::new(group.stream());
+        let mut variants = vec![];
+        while parser.tokens.peek().is_some() {
+            let name = parser.expect_ident()?;
+            let value = match parser.tokens.peek() {
+                Some(TokenTree::Punct(p)) if p.as_char() == '=' => {
+                    parser.tokens.next();
+                    Some(parser.parse_integer()?)
+                }
+                _ => None,
+            };
+            variants.push(Variant { name, value });
+            match parser.tokens.next() {
+                Some(TokenTree::Punct(p)) if p.as_char() == ',' => {}
+                None => break,
+                Some(other) => {
+                    return Err(Error::Parse(format!(
+                        "Expected ',' between variants, but got: {other}"
+                    )));
+                }
+            }
+        }
+        if variants.is_empty() {
+            return Err(Error::Parse(format!(
+                "{field}: Enum needs at least one variant"
+            )));
+        }
+        let values = variants
+            .iter()
+            .filter(|variant| variant.value.is_some())
+            .count();
+        if values != 0 && values != variants.len() {
+            return Err(Error::Parse(format!(
+                "{field}: give every variant a value or none of them"
+            )));
+        }
+        Ok(variants)
+    }
+
+    fn parse_width(&mut self, field: &Ident, ty: &Ident) -> Result<Option<Ident>, Error> {
+        let Some(TokenTree::Ident(width)) = self.tokens.peek() else {
+            return Ok(None);
+        };
+        let widths: &[&str] = match ty.to_string().as_str() {
+            "Int" => &["i8", "i16", "i32", "u8", "u16", "u32"],
+            "Real" => &["f32"],
+            _ => &[],
+        };
+        let width = width.to_string();
+        let all = [
+            "i8", "i16", "i32", "u8", "u16", "u32", "f32", "i64", "u64", "f64",
+        ];
+        match (
+            widths.contains(&width.as_str()),
+            all.contains(&width.as_str()),
+        ) {
+            (true, _) => Ok(Some(self.expect_ident()?)),
+            (false, true) => Err(Error::Parse(format!(
+                "{field}: {ty} can't be stored as {width}, Int columns take i8, i16, i32, u8, u16 or u32 and Real columns take f32"
+            ))),
+            (false, false) => Ok(None),
+        }
+    }
+
+    fn parse_nullability(&mut self, field: &Ident, ty: &Ident) -> Result<bool, Error> {
+        let not_null = match self.tokens.peek() {
+            Some(TokenTree::Punct(p)) if p.as_char() == '?' => {
+                self.tokens.next();
+                false
+            }
+            Some(TokenTree::Ident(ident)) if ident.to_string() == "not" => {
+                self.tokens.next();
+                match self.expect_ident()?.to_string().as_str() {
+                    "null" => true,
+                    other => {
+                        return Err(Error::Parse(format!(
+                            "Expected 'not null' after {field}: {ty}, but got: not {other}"
+                        )));
+                    }
+                }
+            }
+            _ => false,
+        };
+        match (not_null, ty.to_string().as_str()) {
+            (true, "Text" | "Int" | "Real" | "Blob" | "Enum") => Ok(true),
+            (true, ty) => Err(Error::Parse(format!(
+                "{field}: {ty} can't be not null, only Text, Int, Real, Blob and Enum columns can"
+            ))),
+            (false, _) => Ok(false),
+        }
+    }
+
+    fn parse_default(&mut self, field: &Ident) -> Result<Option<String>, Error> {
+        match self.tokens.peek() {
+            Some(TokenTree::Ident(ident)) if ident.to_string() == "default" => {
+                self.tokens.next();
+            }
+            _ => return Ok(None),
+        }
+        let negative = match self.tokens.peek() {
+            Some(TokenTree::Punct(p)) if p.as_char() == '-' => {
+                self.tokens.next();
+                "-"
+            }
+            _ => "",
+        };
+        match self.tokens.next() {
+            Some(TokenTree::Literal(literal)) => {
+                let literal = literal.to_string();
+                match literal
+                    .strip_prefix('"')
+                    .and_then(|sql| sql.strip_suffix('"'))
+                {
+                    Some(sql) => Ok(Some(sql.replace("\\\"", "\""))),
+                    None => Ok(Some(format!("{negative}{literal}"))),
+                }
+            }
+            _ => Err(Error::Parse(format!(
+                "Expected a default for {field}, e.g. default 0 or default \"unixepoch()\""
+            ))),
+        }
+    }
+
+    fn parse_references(&mut self, field: &Ident) -> Result<References, Error> {
+        let _references = self.tokens.next();
+        let table = self.expect_ident()?;
+        let column = match self.tokens.next() {
+            Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Parenthesis => {
+                Parser::new(group.stream()).expect_ident()?
+            }
+            _ => {
+                return Err(Error::Parse(format!(
+                    "Expected a column for {field}, e.g. references {table}(id)"
+                )));
+            }
+        };
+        let mut actions = vec![];
+        while matches!(self.tokens.peek(), Some(TokenTree::Ident(ident)) if ident.to_string() == "on")
+        {
+            let _on = self.tokens.next();
+            let event = self.expect_ident()?.to_string();
+            if event != "delete" && event != "update" {
+                return Err(Error::Parse(format!(
+                    "Expected on delete or on update for {field}, but got: on {event}"
+                )));
+            }
+            let action = match self.expect_ident()?.to_string().as_str() {
+                "cascade" => "cascade",
+                "restrict" => "restrict",
+                "set" => match self.expect_ident()?.to_string().as_str() {
+                    "null" => "set null",
+                    "default" => "set default",
+                    other => {
+                        return Err(Error::Parse(format!(
+                            "Expected set null or set default for {field}, but got: set {other}"
+                        )));
+                    }
+                },
+                "no" => match self.expect_ident()?.to_string().as_str() {
+                    "action" => "no action",
+                    other => {
+                        return Err(Error::Parse(format!(
+                            "Expected no action for {field}, but got: no {other}"
+                        )));
+                    }
+                },
+                other => {
+                    return Err(Error::Parse(format!(
+                        "Expected cascade, restrict, set null, set default or no action for {field}, but got: {other}"
+                    )));
+                }
+            };
+            actions.push(format!("on {event} {action}"));
+        }
+        Ok(References {
+            table,
+            column,
+            actions: actions.join(" "),
+        })
+    }
+
+    fn parse_integer(&mut self) -> Result<i64, Error> {
+        let negative = match self.tokens.peek() {
+            Some(TokenTree::Punct(p)) if p.as_char() == '-' => {
+                self.tokens.next();
+                true
+            }
+            _ => false,
+        };
+        match self.tokens.next() {
+            Some(TokenTree::Literal(literal)) => match literal.to_string().parse::<i64>() {
+                Ok(value) if negative => Ok(-value),
+                Ok(value) => Ok(value),
+                Err(_) => Err(Error::Parse(format!(
+                    "Expected an integer, but got: {literal}"
+                ))),
+            },
+            Some(other) => Err(Error::Parse(format!(
+                "Expected an integer, but got: {other}"
+            ))),
+            None => Err(Error::Parse(
+                "Expected an integer, but found end of stream.".to_string(),
+            )),
+        }
+    }
+
     fn parse_fields(&mut self) -> Result<Vec<Field>, Error> {
         let mut fields = Vec::new();
         while self.tokens.peek().is_some() {
+            let vis = self.parse_visibility();
             let name = self.expect_ident()?;
             self.expect_punct(':')?;
             let ty = self.expect_ident()?;
-            fields.push(Field { name, ty });
+            let args = self.parse_generic_args();
+            let variants = match ty.to_string().as_str() {
+                "Enum" => self.parse_variants(&name)?,
+                _ => vec![],
+            };
+            let width = self.parse_width(&name, &ty)?;
+            let mut not_null = false;
+            let mut default = None;
+            let mut references = None;
+            let mut renamed_from = None;
+            let mut dropped = false;
+            // column constraints come in any order, like in sql
+            loop {
+                match self.tokens.peek() {
+                    Some(TokenTree::Punct(p)) if p.as_char() == '?' => {
+                        not_null = self.parse_nullability(&name, &ty)?
+                    }
+                    Some(TokenTree::Ident(ident)) => match ident.to_string().as_str() {
+                        "not" => not_null = self.parse_nullability(&name, &ty)?,
+                        "default" => default = self.parse_default(&name)?,
+                        "references" => references = Some(self.parse_references(&name)?),
+                        "renamed_from" => {
+                            let _renamed_from = self.tokens.next();
+                            renamed_from = Some(self.expect_ident()?);
+                        }
+                        "dropped" => {
+                            let _dropped = self.tokens.next();
+                            dropped = true;
+                        }
+                        _ => break,
+                    },
+                    _ => break,
+                }
+            }
+            fields.push(Field {
+                vis,
+                name,
+                ty,
+                args,
+                variants,
+                width,
+                not_null,
+                default,
+                references,
+                renamed_from,
+                dropped,
+            });
 
             if let Some(TokenTree::Punct(p)) = self.tokens.peek() {
                 if p.as_char() == ',' {
@@ -137,22 +1452,327 @@ impl Parser<proc_macro::token_stream::IntoIter> {
     }
 }
 
+/// Takes the type off of annotated params like `:user_id int`, which sqlite can't prepare.
+/// Types are `int`, `integer`, `real`, `text` or `blob`
+fn strip_param_types(sql: &str, span: Span) -> (String, Vec<(String, Ident)>) {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut out = String::with_capacity(sql.len());
+    let mut param_types = vec![];
+    let mut rest = sql;
+    let mut in_string = false;
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+        out.push(c);
+        match c {
+            '\'' => in_string = !in_string,
+            ':' if !in_string => {
+                let name_len = rest.find(|c| !is_ident(c)).unwrap_or(rest.len());
+                let (name, after_name) = rest.split_at(name_len);
+                let after_space = after_name.trim_start();
+                let word_len = after_space
+                    .find(|c| !is_ident(c))
+                    .unwrap_or(after_space.len());
+                let ty = match after_space[..word_len].to_lowercase().as_str() {
+                    "int" | "integer" => "Int",
+                    "real" => "Real",
+                    "text" => "Text",
+                    "blob" => "Blob",
+                    _ => continue,
+                };
+                if name.is_empty() || after_space.len() == after_name.len() {
+                    continue;
+                }
+                out.push_str(name);
+                param_types.push((name.to_string(), Ident::new(ty, span)));
+                rest = &after_space[word_len..];
+            }
+            _ => {}
+        }
+    }
+    (out, param_types)
+}
+
+/// Adds `collate {collation}` to each term of the last top level order by,
+/// before its `asc`, `desc` or `nulls first`. Terms with a collate already are left as is
+fn collate_order_by(sql: &str, collation: &str) -> Option<String> {
+    // top level words and commas, outside of parens and quotes
+    let mut words: Vec<(usize, usize)> = vec![];
+    let mut commas = vec![];
+    let mut depth = 0;
+    let mut quote = None;
+    let mut word_start = None;
+    for (ix, c) in sql.char_indices().chain([(sql.len(), ' ')]) {
+        if let Some(close) = quote {
+            if c == close {
+                quote = None;
+            }
+            continue;
+        }
+        let is_word = c.is_alphanumeric() || c == '_';
+        match (word_start, is_word) {
+            (None, true) => word_start = Some(ix),
+            (Some(start), false) => {
+                if depth == 0 {
+                    words.push((start, ix));
+                }
+                word_start = None;
+            }
+            _ => {}
+        }
+        match c {
+            '\'' | '"' | '`' => quote = Some(c),
+            '[' => quote = Some(']'),
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => commas.push(ix),
+            _ => {}
+        }
+    }
+    let word = |(start, end): (usize, usize)| sql[start..end].to_lowercase();
+    let by = words
+        .windows(2)
+        .rposition(|pair| word(pair[0]) == "order" && word(pair[1]) == "by")?
+        + 1;
+    let start = words[by].1;
+    let end = words[by + 1..]
+        .iter()
+        .find(|span| word(**span) == "limit")
+        .map(|span| span.0)
+        .unwrap_or(sql.len());
+    let mut bounds = vec![start];
+    bounds.extend(commas.iter().filter(|comma| (start..end).contains(*comma)));
+    bounds.push(end);
+    let mut out = sql[..start].to_string();
+    for (ix, pair) in bounds.windows(2).enumerate() {
+        let from = if ix == 0 { pair[0] } else { pair[0] + 1 };
+        let term = sql[from..pair[1]].trim();
+        if ix > 0 {
+            out.push(',');
+        }
+        out.push(' ');
+        let lower = term.to_lowercase();
+        if lower.split_whitespace().any(|word| word == "collate") {
+            out.push_str(term);
+            continue;
+        }
+        let term_words = term.split_whitespace().collect::<Vec<_>>();
+        let lower_words = lower.split_whitespace().collect::<Vec<_>>();
+        let mut expr_len = term_words.len();
+        if expr_len >= 3
+            && lower_words[expr_len - 2] == "nulls"
+            && matches!(lower_words[expr_len - 1], "first" | "last")
+        {
+            expr_len -= 2;
+        }
+        if expr_len >= 2 && matches!(lower_words[expr_len - 1], "asc" | "desc") {
+            expr_len -= 1;
+        }
+        out.push_str(&term_words[..expr_len].join(" "));
+        out.push_str(&format!(" collate {collation}"));
+        for word in &term_words[expr_len..] {
+            out.push(' ');
+            out.push_str(word);
+        }
+    }
+    if end < sql.len() {
+        out.push(' ');
+    }
+    out.push_str(&sql[end..]);
+    Some(out)
+}
+
 pub fn parse(input: TokenStream) -> Result<DatabaseSchema, Error> {
     let mut parser = Parser::new(input);
     let mut parts = Vec::new();
     while parser.tokens.peek().is_some() {
+        let vis = parser.parse_visibility();
         let keyword = parser.expect_ident()?;
+        if vis.is_some()
+            && !matches!(
+                keyword.to_string().as_str(),
+                "table" | "log" | "query" | "op" | "execute" | "view" | "fts" | "timeseries"
+            )
+        {
+            return Err(Error::Parse(format!(
+                "Unexpected visibility before {keyword}, only tables, log tables, queries, ops, executes, views, fts and timeseries tables take one."
+            )));
+        }
         match keyword.to_string().as_str() {
-            "table" => parts.push(SchemaPart::Table(parser.parse_table()?)),
+            "attach" => parts.push(SchemaPart::Attach(parser.parse_attach()?)),
+            "pragma" => parts.push(SchemaPart::Pragma(parser.parse_pragma()?)),
+            "env" => parts.push(SchemaPart::Env(parser.parse_env()?)),
+            "debug_output" => parts.push(SchemaPart::DebugOutput(parser.parse_debug_output()?)),
+            "schema_diff" => parts.push(SchemaPart::SchemaDiff(parser.parse_schema_diff()?)),
+            "path" => parts.push(SchemaPart::DevDatabase(parser.parse_dev_database()?)),
+            "docs" => parts.push(SchemaPart::Docs(parser.parse_docs()?)),
+            "visibility" => parts.push(SchemaPart::Visibility(parser.parse_default_visibility()?)),
+            "max_rows" => parts.push(SchemaPart::MaxRows(parser.parse_max_rows()?)),
+            "table" => parts.push(SchemaPart::Table(parser.parse_table(vis)?)),
+            "log" => parts.push(SchemaPart::Table(parser.parse_log_table(vis)?)),
             "index" => parts.push(SchemaPart::Index(parser.parse_index()?)),
-            "query" => parts.push(SchemaPart::Query(parser.parse_query()?)),
+            "check" => parts.push(SchemaPart::Check(parser.parse_check()?)),
+            "query" => parts.push(SchemaPart::Query(parser.parse_query(vis)?)),
+            "op" => parts.push(SchemaPart::Op(parser.parse_op(vis)?)),
+            "execute" => parts.push(SchemaPart::Execute(parser.parse_execute(vis)?)),
+            "view" => parts.push(SchemaPart::View(parser.parse_view(vis)?)),
+            "fts" => parts.push(SchemaPart::Fts(parser.parse_fts(vis)?)),
+            "timeseries" => parts.push(SchemaPart::Timeseries(parser.parse_timeseries(vis)?)),
+            "migration" => parts.push(SchemaPart::Migration(parser.parse_migration()?)),
+            "migrations" => parts.push(SchemaPart::MigrationDir(parser.parse_migration_dir()?)),
             _ => {
                 return Err(Error::Parse(format!(
-                    "Unexpected keyword: {}. Expected 'table', 'index', or 'query'.",
+                    "Unexpected keyword: {}. Expected 'attach', 'pragma', 'env', 'debug_output', 'schema_diff', 'path', 'docs', 'visibility', 'max_rows', 'table', 'log', 'index', 'check', 'query', 'op', 'execute', 'view', 'fts', 'timeseries', 'migration', or 'migrations'.",
                     keyword
                 )));
             }
         }
     }
+    hide_soft_deleted(&mut parts);
     Ok(DatabaseSchema { parts })
 }
+
+/// What follows `keyword` at the start of `sql`, when it starts with it
+fn strip_keyword<'a>(sql: &'a str, keyword: &str) -> Option<&'a str> {
+    let word_len = sql
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(sql.len());
+    match sql[..word_len].eq_ignore_ascii_case(keyword) {
+        true => Some(sql[word_len..].trim_start()),
+        false => None,
+    }
+}
+
+/// Queries, views, ops and executes read `soft_delete` tables through a cte of the same name
+/// without the deleted rows, unless they're `with_deleted`. `main.user` still reads all of them,
+/// and the table an update or delete writes to is never the cte
+fn hide_soft_deleted(parts: &mut [SchemaPart]) {
+    let tables = parts
+        .iter()
+        .filter_map(|part| match part {
+            SchemaPart::Table(table) if table.soft_delete && table.schema.is_none() => {
+                Some(table.name.to_string())
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if tables.is_empty() {
+        return;
+    }
+    for part in parts {
+        match part {
+            SchemaPart::Query(query) if !query.with_deleted => {
+                without_deleted(&mut query.sql, &tables);
+            }
+            SchemaPart::View(view) if !view.with_deleted => {
+                without_deleted(&mut view.sql, &tables);
+            }
+            SchemaPart::Execute(execute) if !execute.with_deleted => {
+                without_deleted(&mut execute.sql, &tables);
+            }
+            SchemaPart::Op(op) if !op.with_deleted => {
+                for sql in &mut op.statements {
+                    without_deleted(sql, &tables);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Puts the ctes of the `tables` that `sql` reads in front of it
+fn without_deleted(sql: &mut String, tables: &[String]) {
+    let trimmed = sql.trim_start();
+    let statement = [
+        "select", "insert", "replace", "update", "delete", "with", "values",
+    ]
+    .iter()
+    .any(|keyword| strip_keyword(trimmed, keyword).is_some());
+    if !statement {
+        return;
+    }
+    let words = table_words(trimmed);
+    let ctes = tables
+        .iter()
+        .filter(|table| words.contains(&table.to_lowercase()))
+        .map(|table| {
+            format!("\"{table}\" as (select * from main.\"{table}\" where deleted_at is null)")
+        })
+        .collect::<Vec<_>>();
+    if ctes.is_empty() {
+        return;
+    }
+    let ctes = ctes.join(", ");
+    *sql = match strip_keyword(trimmed, "with") {
+        Some(rest) => match strip_keyword(rest, "recursive") {
+            Some(rest) => format!("with recursive {ctes}, {rest}"),
+            None => format!("with {ctes}, {rest}"),
+        },
+        None => format!("with {ctes} {trimmed}"),
+    };
+}
+
+/// The lowercased names in `sql` that could be a table, without string literals
+/// and names after a `.` like `main.user` or `post.user`
+fn table_words(sql: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut chars = sql.chars().peekable();
+    let mut after_dot = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                }
+                after_dot = false;
+            }
+            '"' | '`' | '[' => {
+                let close = if c == '[' { ']' } else { c };
+                let word = chars
+                    .by_ref()
+                    .take_while(|c| *c != close)
+                    .collect::<String>();
+                if !after_dot {
+                    words.push(word.to_lowercase());
+                }
+                after_dot = false;
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+                    word.push(c);
+                }
+                if !after_dot {
+                    words.push(word.to_lowercase());
+                }
+                after_dot = false;
+            }
+            '.' => after_dot = true,
+            c if c.is_whitespace() => {}
+            _ => after_dot = false,
+        }
+    }
+    words
+}
+
+pub fn parse_derive(input: TokenStream) -> Result<DeriveStruct, Error> {
+    let mut parser = Parser::new(input);
+    parser.skip_attributes_and_visibility();
+    let keyword = parser.expect_ident()?;
+    if keyword.to_string() != "struct" {
+        return Err(Error::Parse(format!(
+            "FromRow can only be derived for structs, found: {keyword}"
+        )));
+    }
+    let name = parser.expect_ident()?;
+    match parser.tokens.next() {
+        Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Brace => {
+            let fields = Parser::new(group.stream()).parse_derive_fields()?;
+            Ok(DeriveStruct { name, fields })
+        }
+        _ => Err(Error::Parse(format!(
+            "FromRow needs a struct with named fields and no generics, e.g. struct {name} {{ id: i64 }}"
+        ))),
+    }
+}
@@ -1,9 +1,62 @@
 extern crate self as sqltight;
+#[cfg(feature = "serde")]
+pub use serde;
+pub use sqltight_core::bench;
 pub use sqltight_core::{
-    Blob, Crud, Error, FromRow, Int, Real, Result, Sqlite, Stmt, Text, Tx, Value, blob, int, real,
-    text,
+    Any, AuthAction, Authorization, AutoVacuum, BatchSql, Blob, CacheInvalidator, Col, Column,
+    ColumnIndex, ColumnProfile, Columns, ContentionStats, Crud, CsvImport, CsvOptions, CsvRowError,
+    DEBUG_MAX_LEN, DebugTruncated, Error, Filter, ForeignKeyViolation, FromRow, FromSql,
+    IndexSuggestion, Int, IntegrityError, InterruptGuard, InterruptHandle, InvalidUtf8,
+    InvalidateCache, LastError, LogTable, LongTransaction, MigrationRecord, OnLongTransaction,
+    OnMissing, OpenOptions, Order, Page, PlanNode, QueryPlan, Real, RecordedSql, RepeatedSql,
+    RepeatedSqlDetector, ReplayRate, ReplayedSql, Replica, ReplicaStats, Result, Row, RowRef,
+    STATEMENT_CACHE_CAPACITY, SchemaDiff, Select, SnapshotScheduler, SnapshotTable, SqlRecording,
+    Sqlite, StatementCache, Stmt, StorageStats, Table, Text, Timeseries, TraceEvent,
+    TransactionWatchdog, Truncated, Tx, UNICODE_COLLATION, VacuumPolicy, Value, ValueRef,
+    VersionedMigration, any, blob, changes_schema, data_dir, int, normalize_sql, path_str,
+    read_trace, real, text, write_trace,
 };
-pub use sqltight_macros::db;
+#[cfg(feature = "chrono")]
+pub use sqltight_core::{DateTime, datetime};
+#[cfg(feature = "dbstat")]
+pub use sqltight_core::{DbStat, Fragmentation, TableSize};
+#[cfg(feature = "json")]
+pub use sqltight_core::{Json, json};
+pub use sqltight_macros::{FromRow, db};
+
+pub struct Transaction<'a>(pub sqltight_core::Transaction<'a>);
+
+impl<'a> Transaction<'a> {
+    #[track_caller]
+    pub fn save<T: sqltight::Crud>(&self, row: T) -> Result<T> {
+        row.save(&self.0)
+    }
+
+    #[track_caller]
+    pub fn delete<T: sqltight::Crud>(&self, row: T) -> Result<T> {
+        row.delete(&self.0)
+    }
+
+    #[track_caller]
+    pub fn save_all<T: sqltight::Crud>(&self, rows: Vec<T>) -> Result<Vec<T>> {
+        T::save_all(rows, &self.0)
+    }
+
+    /// Saves what it can of a batch, see `sqltight_core::Transaction::try_each`
+    pub fn try_each<T, R>(
+        &self,
+        items: impl IntoIterator<Item = T>,
+        mut f: impl FnMut(&Self, T) -> Result<R>,
+    ) -> Result<Vec<Result<R>>> {
+        self.0.try_each(items, |_tx, item| f(self, item))
+    }
+}
+
+impl AsRef<Sqlite> for Transaction<'_> {
+    fn as_ref(&self) -> &Sqlite {
+        &self.0
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -21,6 +74,14 @@ mod tests {
             email: Unique
         }
 
+        env test {
+            pragma journal_mode "delete"
+            pragma cache_size -2000
+            index Post {
+                content: Index
+            }
+        }
+
         table Post {
             id: Int,
             user_id: Int,
@@ -37,9 +98,9 @@ mod tests {
             limit 2
         "
 
-        query user_by_id "select user.id from user where id = :id limit 1"
+        query user_by_id uses_index "select user.id from user where id = :id limit 1"
 
-        query posts_by_contents "
+        query posts_by_contents uses_index = Post_content_ix "
             select id, content, user_id, created_at
             from post
             where content = :content
@@ -85,6 +146,72 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn table_reads_work() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let user = db.save(User::new("email"))?;
+        let _post = db.save(Post::new(user.id, "one"))?;
+        let _post = db.save(Post::new(user.id, "two"))?;
+        assert_eq!(db.count_posts()?, 2);
+        assert!(db.user_exists(user.id)?);
+        assert!(!db.post_exists(3)?);
+        let found = db.find_user(user.id)?;
+        assert_eq!(found.map(|user| user.email), Some(text("email")));
+        assert!(db.find_post(3)?.is_none());
+        let posts = db.all_posts()?;
+        assert_eq!(posts.len(), 2);
+        assert_eq!(posts[1].content, text("two"));
+        Ok(())
+    }
+
+    #[test]
+    fn save_all_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let users = db.save_all(vec![User::new("a"), User::new("b"), User::new("c")])?;
+        assert_eq!(
+            users.iter().map(|user| user.id).collect::<Vec<_>>(),
+            vec![int(1), int(2), int(3)]
+        );
+        let mut updates = users;
+        updates[1].email = text("b2");
+        updates.push(User::new("a"));
+        assert!(db.save_all(updates).is_err());
+        let posts = {
+            let tx = db.transaction()?;
+            tx.save_all((0..1200).map(|ix| Post::new(1, ix.to_string())).collect())?
+        };
+        assert_eq!(posts.len(), 1200);
+        assert_eq!(posts[1199].content, text("1199"));
+        let users = db.all_users()?;
+        assert_eq!(users.len(), 3);
+        assert_eq!(users[1].email, text("b"));
+        let mut first = users.into_iter().next().ok_or(Error::RowNotFound)?;
+        first.email = text("a2");
+        let saved = db.save_all(vec![User::new("d"), first])?;
+        assert_eq!(
+            saved.iter().map(|user| user.id).collect::<Vec<_>>(),
+            vec![int(4), int(1)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn normalize_sql_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let stmt = db
+            .connection
+            .prepare("SELECT id\n  FROM User -- by email\n WHERE email = 'a@b.c' and id > :id")?;
+        assert_eq!(
+            stmt.normalized_sql(),
+            normalize_sql("select id from user where email = ? and id > 10;")
+        );
+        assert_eq!(
+            normalize_sql("select \"Id\", x'00' from user"),
+            "select \"Id\" , ? from user"
+        );
+        Ok(())
+    }
+
     #[test]
     fn readme() -> sqltight::Result<()> {
         let db = Database::open(":memory:")?;
@@ -117,16 +244,2919 @@ mod tests {
         assert_eq!(found_user.id, user.id);
         Ok(())
     }
-}
 
-pub struct Transaction<'a>(pub sqltight_core::Transaction<'a>);
+    #[test]
+    fn env_works() -> sqltight::Result<()> {
+        let sql = "select id from post indexed by Post_content_ix where content = 'content'";
+        let db = Database::open(":memory:")?;
+        assert!(db.execute(sql).is_ok());
+        let db = Database::open_env(":memory:", "default")?;
+        assert!(db.execute(sql).is_err());
+        assert!(matches!(
+            Database::open_env(":memory:", "staging"),
+            Err(Error::UnknownEnv(_))
+        ));
+        let db = Database::open_env(":memory:", "test")?;
+        let cache_size: i64 = db
+            .connection
+            .prepare("pragma cache_size")?
+            .rows()?
+            .first()
+            .ok_or(Error::RowNotFound)?
+            .get("cache_size")?;
+        assert_eq!(cache_size, -2000);
+        Ok(())
+    }
 
-impl<'a> Transaction<'a> {
-    pub fn save<T: sqltight::Crud>(&self, row: T) -> Result<T> {
-        row.save(&self.0)
+    #[test]
+    fn invalidate_statements_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let user = db.save(User::new("email"))?;
+        let _post = db.save(Post::new(user.id, "content"))?;
+        assert_eq!(db.posts_by_user_id(user.id)?.len(), 1);
+        db.execute("alter table post add column title text")?;
+        assert_eq!(db.posts_by_user_id(user.id)?.len(), 1);
+        db.invalidate_statements()?;
+        assert_eq!(db.user_by_id(user.id)?.id, user.id);
+        // execute only drops the cached statements after ddl
+        assert!(sqltight::changes_schema(
+            "insert into post (content) values ('x'); -- then\n drop index post_user_id"
+        ));
+        assert!(!sqltight::changes_schema(
+            "update post set content = 'drop table post'"
+        ));
+        Ok(())
     }
 
-    pub fn delete<T: sqltight::Crud>(&self, row: T) -> Result<T> {
-        row.delete(&self.0)
+    #[test]
+    fn connection_outlives_clones_and_statements() -> sqltight::Result<()> {
+        let sqlite = Sqlite::open(":memory:")?;
+        let stmt = sqlite.prepare("select 1 as one")?;
+        let clone = sqlite.clone();
+        drop(sqlite);
+        std::thread::spawn(move || clone.execute("select 1"))
+            .join()
+            .expect("thread panicked")?;
+        assert_eq!(stmt.rows()?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn statements_run_again() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let first = db.save(User::new("first"))?;
+        let second = db.save(User::new("second"))?;
+        let _post = db.save(Post::new(first.id, "hello"))?;
+        // the cached statement is reset and rebound, not prepared again
+        assert_eq!(db.posts_by_user_id(first.id)?.len(), 1);
+        assert_eq!(db.posts_by_user_id(second.id)?.len(), 0);
+        assert_eq!(db.posts_by_user_id(first.id)?.len(), 1);
+        let insert = |email: &str| {
+            db.connection
+                .prepare("insert into User (email) values (?)")?
+                .bind(&[email.into()])?
+                .changes()
+        };
+        assert_eq!(insert("third")?, 1);
+        assert!(matches!(
+            insert("third"),
+            Err(Error::UniqueConstraint { .. })
+        ));
+        assert_eq!(insert("fourth")?, 1);
+        assert_eq!(db.count_users()?, 4);
+        assert_eq!(db.count_users()?, 4);
+        Ok(())
+    }
+
+    #[test]
+    fn prepare_reuses_statements() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let sql = "select ? as value";
+        let value = |stmt: sqltight::Stmt| -> sqltight::Result<Option<i64>> {
+            stmt.rows()?.first().ok_or(Error::RowNotFound)?.get("value")
+        };
+        // a statement still held isn't handed out again
+        let first = db.connection.prepare(sql)?.bind(&[1.into()])?;
+        let second = db.connection.prepare(sql)?.bind(&[2.into()])?;
+        assert_eq!(value(first)?, Some(1));
+        assert_eq!(value(second)?, Some(2));
+        let cache = sqltight::StatementCache::new(vec![])?;
+        let first = cache.get(&db.connection, "value", sql)?.bind(&[1.into()])?;
+        let second = cache.get(&db.connection, "value", sql)?.bind(&[2.into()])?;
+        assert_eq!(value(first)?, Some(1));
+        assert_eq!(value(second)?, Some(2));
+        // nor are the params of one that was bound and dropped
+        drop(db.connection.prepare(sql)?.bind(&[3.into()])?);
+        assert_eq!(value(db.connection.prepare(sql)?)?, None);
+        db.connection.set_statement_cache_capacity(0)?;
+        assert_eq!(
+            value(db.connection.prepare(sql)?.bind(&[4.into()])?)?,
+            Some(4)
+        );
+        db.connection
+            .set_statement_cache_capacity(sqltight::STATEMENT_CACHE_CAPACITY)?;
+        let user = db.save(User::new("first"))?;
+        let user = db.save(User {
+            email: text("second"),
+            ..user
+        })?;
+        assert_eq!(user.email, text("second"));
+        Ok(())
+    }
+
+    #[test]
+    fn rows_share_column_names() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let rows = db
+            .connection
+            .prepare("select 1 as id, 'a' as name union all select 2, 'b'")?
+            .rows()?;
+        assert!(std::ptr::eq(rows[0].columns(), rows[1].columns()));
+        assert_eq!(rows[1].get::<String>("name")?, "b");
+        assert_eq!(rows[1].get::<i64>(0)?, 2);
+        let row = sqltight::Row::new(vec!["id".to_string()], vec![1.into()]);
+        assert_eq!(row.get::<i64>("id")?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn rows_borrow_text_and_blobs() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let mut names = vec![];
+        db.connection
+            .prepare("select 'a' as name, x'0102' as data union all select 'b', x''")?
+            .for_each_row(|row| {
+                match row.value_ref("name") {
+                    Some(sqltight::ValueRef::Text(std::borrow::Cow::Borrowed(name))) => {
+                        names.push(name.to_string())
+                    }
+                    value => panic!("{value:?}"),
+                }
+                assert_eq!(row.get::<Vec<u8>>("data")?.len() % 2, 0);
+                Ok(())
+            })?;
+        assert_eq!(names, ["a", "b"]);
+        let user = db.save(User::new("borrowed@example.com"))?;
+        let users = db
+            .connection
+            .prepare("select * from User")?
+            .rows_as::<User>()?;
+        assert_eq!(users[0].email, user.email);
+        Ok(())
+    }
+
+    #[test]
+    fn explain_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let plan = db
+            .connection
+            .prepare(
+                "select * from post where user_id in (select id from user where email = :email)",
+            )?
+            .explain()?;
+        assert_eq!(plan.full_scans(), ["post"]);
+        assert_eq!(plan.index_names(), ["User_email_ix"]);
+        assert!(plan.uses_index());
+        assert_eq!(plan.nodes[1].children[0].parent, plan.nodes[1].id);
+        assert_eq!(
+            plan.to_string(),
+            "QUERY PLAN\n|--SCAN post\n`--LIST SUBQUERY 1\n   `--SEARCH user USING COVERING INDEX User_email_ix (email=?)\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn values_roundtrip() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let huge = vec![7u8; 3 * 1024 * 1024];
+        // bound params are copied, the temporary slice is gone before the select steps
+        let stmt = db
+            .connection
+            .prepare("select ?1, ?2, ?3, ?4, ?5, ?6, cast(?7 as text)")?
+            .bind(&[
+                "a\0b".into(),
+                "".into(),
+                Vec::<u8>::new().into(),
+                huge.clone().into(),
+                f64::NAN.into(),
+                i64::MIN.into(),
+                vec![b'a', 0, 0xff].into(),
+            ])?;
+        let rows = stmt.rows()?;
+        assert_eq!(rows[0].get::<String>(0)?, "a\0b");
+        assert_eq!(rows[0].get::<String>(1)?, "");
+        assert_eq!(rows[0].get::<Vec<u8>>(2)?, Vec::<u8>::new());
+        assert_eq!(rows[0].get::<Vec<u8>>(3)?, huge);
+        assert_eq!(rows[0].get::<Option<f64>>(4)?, None);
+        assert_eq!(rows[0].get::<i64>(5)?, i64::MIN);
+        assert_eq!(rows[0].get::<String>(6)?, "a\0\u{fffd}");
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_utf8_policies_work() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let read = || {
+            db.connection
+                .prepare("select cast(x'610062ff' as text) as name")?
+                .rows()
+        };
+        assert_eq!(read()?[0].get::<String>("name")?, "a\0b\u{fffd}");
+        db.connection.set_invalid_utf8(sqltight::InvalidUtf8::Bytes);
+        assert_eq!(read()?[0].get::<Vec<u8>>("name")?, b"a\0b\xff");
+        db.connection.set_invalid_utf8(sqltight::InvalidUtf8::Error);
+        assert!(matches!(
+            read(),
+            Err(sqltight::Error::InvalidUtf8 { column, valid_up_to: 3 }) if column == "name"
+        ));
+        // valid text reads the same whatever the policy
+        let rows = db
+            .connection
+            .prepare("select 'a' || char(0) || 'b'")?
+            .rows()?;
+        assert_eq!(rows[0].get::<String>(0)?, "a\0b");
+        let result = db
+            .connection
+            .prepare("select :name")?
+            .bind(&["a".into(), "b".into()]);
+        assert!(matches!(result, Err(sqltight::Error::Bind { param, .. }) if param == "?2"));
+        Ok(())
+    }
+
+    #[test]
+    fn import_csv_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let csv = "email,created_at\r\na@example.com,1\nb@example.com,yesterday\n\"c,\"\"quoted\"\"\n@example.com\",3\na@example.com,4\n";
+        let options = sqltight::CsvOptions {
+            max_errors: 2,
+            ..Default::default()
+        };
+        let import = db.import_csv::<User>(csv.as_bytes(), options)?;
+        assert_eq!(import.rows, 2);
+        let lines = import
+            .errors
+            .iter()
+            .map(|error| error.line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [3, 6]);
+        assert!(matches!(
+            &import.errors[0].error,
+            Error::InvalidColumnType { column, .. } if column == "created_at"
+        ));
+        let users = db.query_as::<User>("select * from User order by id", &[])?;
+        assert_eq!(users[1].email, text("c,\"quoted\"\n@example.com"));
+        let result = db.import_csv::<User>(
+            "d@example.com,oops".as_bytes(),
+            sqltight::CsvOptions {
+                has_header: false,
+                ..Default::default()
+            },
+        );
+        assert!(matches!(result, Err(Error::CsvRow { line: 1, .. })));
+        assert_eq!(db.count_users()?, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn debug_truncates_long_fields() {
+        let user = User::new("a".repeat(1000));
+        let debug = format!("{user:?}");
+        assert!(debug.starts_with("User { id: Int(None), email: Text(Some(\"aaaa"));
+        assert!(debug.contains("\"… (1000 chars))), created_at: Int(None)"));
+        assert!(debug.len() < 200);
+        assert_eq!(
+            format!("{:?}", User::new("short")),
+            "User { id: Int(None), email: Text(Some(\"short\")), created_at: Int(None), updated_at: Int(None) }"
+        );
+        let bytes = vec![0u8; 1 << 20];
+        assert!(format!("{:?}", Truncated(&bytes)).ends_with("]… (1048576 bytes)"));
+    }
+
+    #[test]
+    fn integrity_check_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let _post = db.save(Post::new(1, "one"))?;
+        let _post = db.save(Post::new(1, "two"))?;
+        assert!(db.integrity_check(10)?.is_empty());
+        assert!(db.integrity_check(usize::MAX)?.is_empty());
+        // the index now claims to be on another column than the one it holds
+        let _result = db.execute(
+            "pragma writable_schema = on;
+            update sqlite_schema set sql = replace(sql, '(content)', '(created_at)')
+            where name = 'Post_content_ix';
+            pragma writable_schema = reset",
+        )?;
+        let errors = db.integrity_check(1)?;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].rowid, Some(1));
+        assert!(
+            errors[0]
+                .description
+                .starts_with("row 1 missing from index")
+        );
+        assert_eq!(errors[0].table.as_deref(), Some("Post_content_ix"));
+        Ok(())
+    }
+
+    #[test]
+    fn backup_to_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let _user = db.save(User::new("backup@example.com"))?;
+        let path = std::env::temp_dir().join(format!("sqltight_backup_{}.db", std::process::id()));
+        let path = path_str(&path)?;
+        let size = db.backup_to(&path)?;
+        assert_eq!(size, std::fs::metadata(&path)?.len());
+        assert!(matches!(db.backup_to(&path), Err(Error::Io(_))));
+        let copy = Database::open(&path)?;
+        assert_eq!(copy.count_users()?, 1);
+        drop(copy);
+        let _result = std::fs::remove_file(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn dump_and_restore_work() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let user = db.save(User::new("o'brien@example.com"))?;
+        let post = db.save(Post::new(user.id, "line one\nline two"))?;
+        let mut script = vec![];
+        db.dump(&mut script)?;
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.starts_with("PRAGMA foreign_keys=OFF;\nBEGIN TRANSACTION;\n"));
+        assert!(script.contains("'o''brien@example.com'"));
+        assert!(script.ends_with("COMMIT;\n"));
+        let path = std::env::temp_dir().join(format!("sqltight_dump_{}.db", std::process::id()));
+        let path = path_str(&path)?;
+        let restored = Sqlite::open(&path)?;
+        restored.restore(script.as_bytes())?;
+        // the tables are there already, nothing is left half restored
+        assert!(restored.restore(script.as_bytes()).is_err());
+        assert!(!restored.in_transaction());
+        drop(restored);
+        let restored = Database::open(&path)?;
+        let users = restored.query_as::<User>("select * from User", &[])?;
+        assert_eq!(users[0].email, user.email);
+        let posts = restored.query_as::<Post>("select * from Post", &[])?;
+        assert_eq!(posts[0].content, post.content);
+        assert_eq!(restored.count_users()?, 1);
+        drop(restored);
+        let _result = std::fs::remove_file(&path);
+        Ok(())
+    }
+
+    #[test]
+    fn exports_work() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let user = db.save(User::new("export@example.com"))?;
+        let _post = db.save(Post::new(user.id, "say \"hi\",\nthen go"))?;
+        let mut csv = vec![];
+        let rows = db.export_posts_by_user_id_csv(user.id, &mut csv)?;
+        assert_eq!(rows, 1);
+        assert_eq!(String::from_utf8(csv).unwrap(), "id\n1\n");
+        let mut json = vec![];
+        let _rows = db
+            .connection
+            .prepare("select id, content, null as missing, x'ff' as data from post")?
+            .write_json(&mut json)?;
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "[\n{\"id\":1,\"content\":\"say \\\"hi\\\",\\nthen go\",\"missing\":null,\"data\":\"FF\"}\n]\n"
+        );
+        let mut csv = vec![];
+        let _rows = db
+            .connection
+            .prepare("select content, '' as empty, null as missing from post")?
+            .write_csv(&mut csv)?;
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "content,empty,missing\n\"say \"\"hi\"\",\nthen go\",\"\",\n"
+        );
+        let mut empty = vec![];
+        assert_eq!(db.export_user_by_id_json(int(404), &mut empty)?, 0);
+        assert_eq!(empty, b"[]\n");
+        Ok(())
+    }
+
+    #[test]
+    fn datatype_mismatch_names_the_column() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let result = db
+            .connection
+            .prepare("insert into User (email, created_at) values (:email, :created_at)")?
+            .bind(&["a@example.com".into(), "yesterday".into()])?
+            .changes();
+        assert!(matches!(
+            result,
+            Err(Error::DatatypeMismatch { table, column }) if table == "User" && column == "created_at"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn estimate_rows_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        assert_eq!(db.estimate_rows::<User>()?, 0);
+        let _first = db.save(User::new("a"))?;
+        let second = db.save(User::new("b"))?;
+        let _third = db.save(User::new("c"))?;
+        let _fourth = db.save(User::new("d"))?;
+        // before analyze it's the largest id, deleted rows included
+        let _deleted = db.delete(second)?;
+        assert_eq!(db.estimate_rows::<User>()?, 4);
+        let _result = db.execute("analyze")?;
+        assert_eq!(db.estimate_rows::<User>()?, 3);
+        Ok(())
+    }
+
+    #[cfg(feature = "dbstat")]
+    #[test]
+    fn table_size_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let empty = db.table_size::<User>()?;
+        for ix in 0..500 {
+            let _user = db.save(User::new(format!("user{ix}@example.com")))?;
+        }
+        let size = db.table_size::<User>()?;
+        assert!(size.table_bytes > empty.table_bytes);
+        assert!(size.index_bytes > empty.index_bytes);
+        Ok(())
+    }
+
+    #[test]
+    fn rebuild_table_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        for ix in 0..200 {
+            let _user = db.save(User::new(format!("user{ix}@example.com")))?;
+        }
+        let _post = db.save(Post::new(200, "kept"))?;
+        let _deleted = db.connection.execute("delete from User where id < 200")?;
+        db.rebuild_table::<User>()?;
+        let users = db.all_users()?;
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].id, int(200));
+        assert_eq!(db.count_posts()?, 1);
+        assert!(db.save(User::new("user199@example.com")).is_err());
+        {
+            let tx = db.transaction()?;
+            assert!(matches!(
+                tx.0.rebuild_table::<User>(),
+                Err(Error::RebuildInTransaction)
+            ));
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "dbstat")]
+    #[test]
+    fn fragmentation_report_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        for ix in 0..1000 {
+            let _user = db.save(User::new(format!("{}{ix}@example.com", "user".repeat(40))))?;
+        }
+        // half empty pages, too full for sqlite to merge them
+        let _deleted = db.connection.execute("delete from User where id % 2 = 0")?;
+        let before = db.fragmentation_report()?.remove(0);
+        assert_eq!(before.table, "User");
+        assert!(before.ratio() > 0.3);
+        db.rebuild_table::<User>()?;
+        let report = db.fragmentation_report()?;
+        let after = report.iter().find(|table| table.table == "User").unwrap();
+        assert!(after.bytes < before.bytes);
+        assert!(after.ratio() < before.ratio());
+        Ok(())
+    }
+
+    #[cfg(feature = "dbstat")]
+    #[test]
+    fn table_stats_work() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        for ix in 0..500 {
+            let _user = db.save(User::new(format!("user{ix}@example.com")))?;
+        }
+        let stats = db.table_stats::<User>()?;
+        let table = stats.iter().find(|stat| !stat.is_index).unwrap();
+        assert_eq!(table.name, "User");
+        assert!(table.pages > 1 && table.depth > 1);
+        assert!(
+            stats
+                .iter()
+                .any(|stat| stat.is_index && stat.table == "User")
+        );
+        let _deleted = db.connection.execute("delete from User where id % 2 = 0")?;
+        let stats = db.table_stats::<User>()?;
+        let table = stats.iter().find(|stat| !stat.is_index).unwrap();
+        assert!(table.fragmentation() > 0.2);
+        Ok(())
+    }
+
+    #[test]
+    fn open_error_explains_why() {
+        let err = Sqlite::open("/does/not/exist/app.db").err();
+        assert!(matches!(
+            err,
+            Some(Error::Sqlite { text, .. }) if text.contains("/does/not/exist/app.db") && text.contains("os error")
+        ));
+    }
+
+    #[test]
+    fn open_options_work() -> sqltight::Result<()> {
+        let dir = std::env::temp_dir().join(format!("sqltight_open_{}", std::process::id()));
+        let path = dir.join("data").join("app.db");
+        let path = path.to_str().unwrap();
+        let options = OpenOptions {
+            on_missing: OnMissing::Error,
+            ..Default::default()
+        };
+        assert!(Database::open_with(path, options).is_err());
+        assert!(Database::open(path).is_err());
+        let options = OpenOptions {
+            create_parent_dirs: true,
+            ..Default::default()
+        };
+        let db = Database::open_with(path, options)?;
+        let _user = db.save(User::new("email"))?;
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn on_progress_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let sql = "with recursive c(x) as (select 1 union all select x + 1 from c where x < 100000) select count(*) from c";
+        let calls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = calls.clone();
+        db.connection.on_progress(100, move || {
+            counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed) < 10
+        })?;
+        assert!(db.execute(sql).is_err());
+        assert_eq!(calls.load(std::sync::atomic::Ordering::Relaxed), 11);
+        db.connection.clear_progress()?;
+        assert!(db.execute(sql).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn authorizer_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let _user = db.save(User::new("email"))?;
+        db.connection.authorize_read_only(&["user"])?;
+        assert_eq!(
+            db.connection
+                .prepare("select email from user")?
+                .rows()?
+                .len(),
+            1
+        );
+        assert!(db.connection.prepare("select content from post").is_err());
+        assert!(db.connection.prepare("delete from user").is_err());
+        db.connection.set_authorizer(|action| match action {
+            AuthAction::Read { column, .. } if column == "email" => Authorization::Ignore,
+            _ => Authorization::Allow,
+        })?;
+        let rows = db.connection.prepare("select email from user")?.rows()?;
+        assert!(matches!(rows[0].value("email"), Some(Value::Null)));
+        db.connection.clear_authorizer()?;
+        assert!(db.connection.prepare("delete from user").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn data_dir_works() -> sqltight::Result<()> {
+        let dir = data_dir("sqltight_test")?;
+        assert!(dir.ends_with("sqltight_test"));
+        Ok(())
+    }
+
+    #[test]
+    fn data_version_works() -> sqltight::Result<()> {
+        let path = std::env::temp_dir().join(format!("sqltight_dv_{}.db", std::process::id()));
+        let path = path_str(&path)?;
+        let writer = Database::open(&path)?;
+        let reader = Sqlite::open(&path)?;
+        let version = reader.data_version()?;
+        let _user = writer.save(User::new("email"))?;
+        assert_ne!(reader.data_version()?, version);
+        assert!(!reader.in_transaction());
+        drop((writer, reader));
+        for suffix in ["", "-wal", "-shm"] {
+            let _result = std::fs::remove_file(format!("{path}{suffix}"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn open_immutable_works() -> sqltight::Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("sqltight_immutable_{}.db", std::process::id()));
+        let path = path_str(&path)?;
+        let user = {
+            let db = Database::open(&path)?;
+            db.save(User::new("email"))?
+        };
+        let db = Database::open_immutable(&path)?;
+        let found = db.user_by_id(user.id)?;
+        assert_eq!(found.id, user.id);
+        assert!(matches!(
+            db.save(User::new("email2")),
+            Err(Error::Sqlite { .. })
+        ));
+        drop(db);
+        for suffix in ["", "-wal", "-shm"] {
+            let _result = std::fs::remove_file(format!("{path}{suffix}"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn open_replica_works() -> sqltight::Result<()> {
+        let path = std::env::temp_dir().join(format!("sqltight_primary_{}.db", std::process::id()));
+        let path = path_str(&path)?;
+        let primary = Database::open(&path)?;
+        let _user = primary.save(User::new("a"))?;
+        let (db, replica) = Database::open_replica(&path, std::time::Duration::from_millis(20))?;
+        assert_eq!(db.all_users()?.len(), 1);
+        assert!(db.save(User::new("b")).is_err());
+        let _user = primary.save(User::new("c"))?;
+        let started_at = std::time::Instant::now();
+        while replica.stats()?.refreshes < 3 && started_at.elapsed().as_secs() < 5 {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let stats = replica.stats()?;
+        assert!(stats.refreshes >= 3 && stats.last_error.is_none());
+        assert!(stats.staleness < std::time::Duration::from_secs(5));
+        let rows = db.connection.prepare("select count(*) from user")?.rows()?;
+        assert_eq!(rows[0].get::<i64>(0)?, 2);
+        drop((db, replica, primary));
+        for suffix in [
+            "",
+            "-wal",
+            "-shm",
+            "-replica",
+            "-replica-wal",
+            "-replica-shm",
+        ] {
+            let _result = std::fs::remove_file(format!("{path}{suffix}"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn open_bytes_works() -> sqltight::Result<()> {
+        let path = std::env::temp_dir().join(format!("sqltight_bytes_{}.db", std::process::id()));
+        let path = path_str(&path)?;
+        let user = {
+            let db = Database::open(&path)?;
+            db.save(User::new("email"))?
+        };
+        // saved in wal mode, checkpointed on close
+        let bytes = std::fs::read(&path)?;
+        for suffix in ["", "-wal", "-shm"] {
+            let _result = std::fs::remove_file(format!("{path}{suffix}"));
+        }
+        let db = Database::open_bytes(&bytes)?;
+        let found = db.user_by_id(user.id)?;
+        assert_eq!(found.id, user.id);
+        assert!(matches!(
+            db.save(User::new("email2")),
+            Err(Error::Sqlite { .. })
+        ));
+        let bytes: &'static [u8] = bytes.leak();
+        let db = Database::open_static_bytes(bytes)?;
+        assert_eq!(db.user_by_id(user.id)?.id, user.id);
+        Ok(())
+    }
+
+    #[test]
+    fn open_pooled_works() -> sqltight::Result<()> {
+        fn shareable<T: Send + Sync>(_value: &T) {}
+        assert!(matches!(
+            Database::open_pooled(":memory:"),
+            Err(Error::PooledInMemory)
+        ));
+        let path = std::env::temp_dir().join(format!("sqltight_pooled_{}.db", std::process::id()));
+        let path = path_str(&path)?;
+        let db = Database::open_pooled(&path)?;
+        shareable(&db);
+        let user = db.save(User::new("email"))?;
+        let found = std::thread::scope(|scope| {
+            let threads = (0..4)
+                .map(|_| scope.spawn(|| db.user_by_id(user.id)))
+                .collect::<Vec<_>>();
+            threads
+                .into_iter()
+                .map(|thread| thread.join().expect("thread panicked"))
+                .collect::<sqltight::Result<Vec<_>>>()
+        })?;
+        assert!(found.iter().all(|found| found.id == user.id));
+        drop(db);
+        for suffix in ["", "-wal", "-shm"] {
+            let _result = std::fs::remove_file(format!("{path}{suffix}"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn read_after_write_works() -> sqltight::Result<()> {
+        let path = std::env::temp_dir().join(format!("sqltight_raw_{}.db", std::process::id()));
+        let path = path_str(&path)?;
+        let db = Database::open_pooled(&path)?;
+        let user = db.save(User::new("before"))?;
+        assert_eq!(db.user_by_id(user.id)?.id, user.id);
+        let saved = {
+            let tx = db.transaction()?;
+            let saved = tx.save(User::new("in tx"))?;
+            // the pooled connection can't see the open transaction, the query runs on its connection
+            assert_eq!(db.user_by_id(saved.id)?.id, saved.id);
+            saved
+        };
+        let found = std::thread::scope(|scope| scope.spawn(|| db.user_by_id(saved.id)).join())
+            .expect("thread panicked")?;
+        assert_eq!(found.id, saved.id);
+        // a query still stepping keeps this thread's connection on the snapshot it started with,
+        // in WAL mode the writer commits all the same
+        let _mode = db.execute("pragma journal_mode = wal")?;
+        let reader_path = path.clone();
+        let cache = sqltight::StatementCache::pooled(move || Sqlite::open(&reader_path));
+        let count = || -> sqltight::Result<i64> {
+            cache
+                .get(&db.connection, "count", "select count(*) from User")?
+                .rows()?
+                .first()
+                .ok_or(Error::RowNotFound)?
+                .get(0)
+        };
+        let users = cache.get(&db.connection, "ids", "select id from User limit 1")?;
+        users.for_each_row(|_row| {
+            let _user = db.save(User::new("after"))?;
+            assert_eq!(count()?, 2);
+            cache.read_after_write();
+            assert_eq!(count()?, 3);
+            Ok(())
+        })?;
+        db.read_after_write();
+        assert_eq!(db.count_users()?, 3);
+        drop((users, cache, db));
+        for suffix in ["", "-wal", "-shm"] {
+            let _result = std::fs::remove_file(format!("{path}{suffix}"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn storage_stats_work() -> sqltight::Result<()> {
+        let memory = Database::open(":memory:")?.storage_stats()?;
+        assert_eq!((memory.file_size, memory.wal_size), (0, 0));
+        let path = std::env::temp_dir().join(format!("sqltight_stats_{}.db", std::process::id()));
+        let path = path_str(&path)?;
+        let db = Database::open(&path)?;
+        let _user = db.save(User::new("email"))?;
+        let stats = db.storage_stats()?;
+        assert!(stats.file_size + stats.wal_size > 0);
+        assert!(stats.page_count > 0 && stats.page_size > 0);
+        assert_eq!(stats.free_bytes(), stats.freelist_count * stats.page_size);
+        drop(db);
+        for suffix in ["", "-wal", "-shm"] {
+            let _result = std::fs::remove_file(format!("{path}{suffix}"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn query_fields_work() -> sqltight::Result<()> {
+        use posts_by_contents::{Content, Id};
+        let db = Database::open(":memory:")?;
+        let user = db.save(User::new("email"))?;
+        let post = db.save(Post::new(user.id, "content"))?;
+        let posts: Vec<(Int, Text)> =
+            db.posts_by_contents_fields::<(Id, Content)>("content", "")?;
+        assert_eq!(posts, vec![(post.id.into(), post.content)]);
+        let (email,) = db.count_posts_by_user_fields::<(count_posts_by_user::Email,)>()?;
+        assert_eq!(email, user.email);
+        Ok(())
+    }
+
+    #[test]
+    fn profile_table_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let _a = db.save(User::new("a@example.com"))?;
+        let _b = db.save(User::new("bb@example.com"))?;
+        let profiles = db.profile_table::<User>()?;
+        assert_eq!(
+            profiles
+                .iter()
+                .map(|p| p.column.as_str())
+                .collect::<Vec<_>>(),
+            User::COLUMNS
+        );
+        let email = &profiles[1];
+        assert_eq!((email.rows, email.nulls, email.distinct), (2, 0, 2));
+        assert_eq!(String::from_sql(&email.min)?, "a@example.com");
+        assert_eq!(String::from_sql(&email.max)?, "bb@example.com");
+        assert_eq!(email.avg_length, Some(13.5));
+        Ok(())
+    }
+
+    #[test]
+    fn snapshots_work() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let mut user = db.save(User::new("old@example.com"))?;
+        assert_eq!(db.snapshot::<User>(100)?, 1);
+        user.email = text("new@example.com");
+        let _user = db.save(user)?;
+        assert_eq!(db.snapshot::<User>(200)?, 1);
+        let users: Vec<User> = db.as_of(150)?;
+        assert_eq!(users[0].email, text("old@example.com"));
+        let users: Vec<User> = db.as_of(200)?;
+        assert_eq!(users[0].email, text("new@example.com"));
+        assert!(db.as_of::<User>(50)?.is_empty());
+        assert_eq!(db.delete_snapshots::<User>(150)?, 1);
+        assert!(db.as_of::<User>(150)?.is_empty());
+        assert!(
+            db.snapshot_every(std::time::Duration::from_secs(60), vec![], |_err| {})
+                .is_err()
+        );
+
+        let path =
+            std::env::temp_dir().join(format!("sqltight_snapshots_{}.db", std::process::id()));
+        let path = path_str(&path)?;
+        let db = Database::open(&path)?;
+        let _user = db.save(User::new("a@example.com"))?;
+        let scheduler = db.snapshot_every(
+            std::time::Duration::from_secs(3600),
+            vec![SnapshotTable::of::<User>()],
+            |err| panic!("{err:?}"),
+        )?;
+        let mut users: Vec<User> = vec![];
+        for _ in 0..100 {
+            users = db.as_of(i64::MAX)?;
+            if !users.is_empty() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        drop(scheduler);
+        drop(db);
+        let _result = std::fs::remove_file(&path);
+        assert_eq!(users.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn schema_diff_works() -> sqltight::Result<()> {
+        let path = std::env::temp_dir().join(format!("sqltight_diff_{}.db", std::process::id()));
+        let path = path_str(&path)?;
+        let _result = std::fs::remove_file(&path);
+        let live = Sqlite::open(&path)?;
+        let _result = live.execute("create table user (id integer primary key, email integer)")?;
+        drop(live);
+        let diffs = Database::schema_diff(&path)?;
+        let _result = std::fs::remove_file(&path);
+        assert!(diffs.contains(&SchemaDiff::TypeMismatch {
+            table: "User".into(),
+            column: "email".into(),
+            expected: "TEXT".into(),
+            found: "INTEGER".into(),
+        }));
+        assert!(diffs.contains(&SchemaDiff::MissingColumn {
+            table: "User".into(),
+            column: "created_at".into(),
+            ty: "INT".into(),
+        }));
+        assert!(diffs.contains(&SchemaDiff::MissingTable {
+            table: "Post".into()
+        }));
+        assert!(
+            diffs.iter().any(
+                |diff| matches!(diff, SchemaDiff::MissingIndex { table, .. } if table == "User")
+            )
+        );
+        assert!(Database::schema_diff("missing.db").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn try_each_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let results = {
+            let tx = db.transaction()?;
+            tx.try_each(["a", "b", "a"], |tx, email| tx.save(User::new(email)))?
+        };
+        assert!(results[0].is_ok() && results[1].is_ok());
+        assert!(matches!(results[2], Err(Error::UniqueConstraint { .. })));
+        let rows = db
+            .connection
+            .prepare("select count(*) as count from user")?
+            .rows()?;
+        assert_eq!(rows[0].get::<i64>("count")?, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let _user = db.save(User::new("seeded"))?;
+        let count = || -> sqltight::Result<i64> {
+            db.connection.prepare("select count(*) from user")?.rows()?[0].get(0)
+        };
+        let saved = db.test_transaction(|tx| {
+            let _user = tx.save(User::new("a"))?;
+            count()
+        })??;
+        assert_eq!((saved, count()?), (2, 1));
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            db.test_transaction(|tx| {
+                let _user = tx.save(User::new("b"));
+                panic!("test failed");
+            })
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(count()?, 1);
+        {
+            let tx = db.transaction()?;
+            let _user = tx.save(User::new("c"))?;
+            let _result = db.test_transaction(|tx| tx.save(User::new("d")))??;
+        }
+        assert_eq!(count()?, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn last_error_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        assert_eq!(db.last_error(), None);
+        let _user = db.save(User::new("a"))?;
+        assert!(db.save(User::new("a")).is_err());
+        let last_error = db.last_error().expect("the duplicate email fails");
+        assert_eq!(last_error.code, 19);
+        assert_eq!(last_error.extended_code, 2067);
+        assert_eq!(last_error.message, "UNIQUE constraint failed: User.email");
+        assert!(last_error.sql.starts_with("insert into User"));
+        assert!(db.connection.prepare("select nope from user").is_err());
+        assert_eq!(
+            db.last_error().map(|error| error.sql),
+            Some("select nope from user".into())
+        );
+        db.connection.clear_last_error();
+        assert_eq!(db.last_error(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn contention_stats_work() -> sqltight::Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("sqltight_contention_{}.db", std::process::id()));
+        let path = path_str(&path)?;
+        let db = Database::open(&path)?;
+        // the test env keeps the rollback journal
+        let _result = db.connection.execute("pragma journal_mode = wal")?;
+        let _user = db.save(User::new("a"))?;
+        let writer = Sqlite::open(&path)?;
+        let _result = writer.execute("begin immediate")?;
+        db.connection.set_busy_timeout(std::time::Duration::ZERO)?;
+        assert!(db.save(User::new("b")).is_err());
+        db.connection
+            .set_busy_timeout(std::time::Duration::from_secs(5))?;
+        let release = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            writer.execute("commit")
+        });
+        let _user = db.save(User::new("c"))?;
+        let _result = release.join().expect("the writer commits")?;
+        let stats = db.contention_stats()?;
+        assert_eq!(stats.busy_errors, 1);
+        assert!(stats.busy_retries > 0);
+        assert!(stats.lock_wait >= std::time::Duration::from_millis(20));
+        assert!(stats.wal_frames > 0);
+        drop(db);
+        for suffix in ["", "-wal", "-shm"] {
+            let _result = std::fs::remove_file(format!("{path}{suffix}"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn invalidate_caches_works() -> sqltight::Result<()> {
+        use std::{
+            collections::HashMap,
+            sync::{Arc, Mutex},
+        };
+        let db = Database::open(":memory:")?;
+        let cache = Arc::new(Mutex::new(HashMap::from([
+            ("users".to_string(), 0),
+            ("user:1".to_string(), 0),
+            ("user:2".to_string(), 0),
+            ("posts".to_string(), 0),
+        ])));
+        let invalidator = CacheInvalidator::new(cache.clone())
+            .table("user", ["users".to_string()])
+            .rows("user", |id| format!("user:{id}"));
+        db.invalidate_caches(invalidator)?;
+        let _user = db.save(User::new("email"))?;
+        let mut keys = cache.lock().unwrap().keys().cloned().collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, ["posts", "user:2"]);
+        let _result = cache.lock().unwrap().insert("users".to_string(), 0);
+        let _result = db.execute("begin")?;
+        let _result = db.execute("insert into user (email) values ('email1')")?;
+        let _result = db.execute("rollback")?;
+        assert!(cache.lock().unwrap().contains_key("users"));
+        Ok(())
+    }
+
+    #[test]
+    fn watch_transactions_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let _watchdog = db.watch_transactions(
+            std::time::Duration::from_millis(20),
+            OnLongTransaction::Rollback,
+            move |long_transaction| {
+                let _result = sender.send(long_transaction);
+            },
+        );
+        {
+            let tx = db.transaction()?;
+            let _user = tx.save(User::new("email"))?;
+            let long_transaction = receiver
+                .recv_timeout(std::time::Duration::from_secs(5))
+                .expect("no report");
+            assert!(long_transaction.rolled_back);
+            assert!(long_transaction.elapsed >= std::time::Duration::from_millis(20));
+            assert!(long_transaction.statements[0].starts_with("insert into User"));
+            assert!(matches!(
+                tx.save(User::new("email1")),
+                Err(Error::TransactionTimedOut)
+            ));
+        }
+        let rows = db
+            .connection
+            .prepare("select count(*) as count from user")?
+            .rows()?;
+        assert_eq!(rows[0].get::<i64>("count")?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn incremental_vacuum_works() -> sqltight::Result<()> {
+        let options = OpenOptions {
+            auto_vacuum: Some(AutoVacuum::Incremental),
+            ..Default::default()
+        };
+        let db = Database::open_with(":memory:", options)?;
+        assert_eq!(db.connection.auto_vacuum()?, AutoVacuum::Incremental);
+        let users = (0..20)
+            .map(|i| db.save(User::new(format!("{i}{}", "x".repeat(4000)))))
+            .collect::<sqltight::Result<Vec<_>>>()?;
+        for user in users {
+            let _user = db.delete(user)?;
+        }
+        assert!(db.storage_stats()?.freelist_count > 0);
+        let policy = VacuumPolicy {
+            min_free_ratio: 2.0,
+            max_pages: 0,
+        };
+        assert_eq!(db.reclaim_space(policy)?, 0);
+        assert_eq!(db.incremental_vacuum(1)?, 1);
+        let policy = VacuumPolicy {
+            min_free_ratio: 0.0,
+            max_pages: 0,
+        };
+        assert!(db.reclaim_space(policy)? > 0);
+        assert_eq!(db.storage_stats()?.freelist_count, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn suggest_indexes_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let user = db.save(User::new("email"))?;
+        let _post = db.save(Post::new(user.id, "content"))?;
+        let suggestions = db.suggest_indexes()?;
+        let user_id = suggestions
+            .iter()
+            .find(|suggestion| suggestion.column == "user_id")
+            .expect("an index on post.user_id");
+        assert_eq!(user_id.table, "Post");
+        assert_eq!(user_id.rows, 1);
+        assert!(
+            user_id
+                .queries
+                .contains(&Database::POSTS_BY_USER_ID_SQL.to_string())
+        );
+        // the test env already indexes post.content
+        assert!(
+            suggestions
+                .iter()
+                .all(|suggestion| suggestion.column != "content")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn on_trace_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let events = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let sink = events.clone();
+        db.connection
+            .on_trace(move |event| sink.lock().unwrap().push(event))?;
+        let user = db.save(User::new("email"))?;
+        let _user = db.user_by_id(user.id)?;
+        db.connection.clear_trace()?;
+        let _posts = db.posts_by_user_id(user.id)?;
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(events[0].sql.starts_with("insert into User"));
+        assert!(events[0].sql.contains("'email'"));
+        assert_eq!(
+            events[1].sql,
+            "select user.id from user where id = 1 limit 1"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn constraint_errors_work() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let _user = db.save(User::new("email"))?;
+        assert!(matches!(
+            db.save(User::new("email")),
+            Err(Error::UniqueConstraint { table, columns }) if table == "User" && columns == ["email"]
+        ));
+        db.execute("create table item (id integer primary key, name text not null check (length(name) > 1), user_id integer references user(id))")?;
+        assert!(matches!(
+            db.execute("insert into item (name) values (null)"),
+            Err(Error::NotNullConstraint { table, column }) if table == "item" && column == "name"
+        ));
+        assert!(matches!(
+            db.execute("insert into item (name) values ('a')"),
+            Err(Error::CheckConstraint(_))
+        ));
+        assert!(matches!(
+            db.execute("insert into item (name, user_id) values ('ab', 42)"),
+            Err(Error::ForeignKeyConstraint)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn interrupt_guard_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let guard = db.connection.interrupt_handle().guard();
+        let cancel = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            drop(guard);
+        });
+        let result = db.execute("with recursive c(x) as (select 1 union all select x + 1 from c) select count(*) from c");
+        cancel.join().expect("thread panicked");
+        assert!(matches!(result, Err(Error::Interrupted)));
+        db.connection.interrupt_handle().guard().disarm();
+        assert!(db.execute("select 1").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn record_sql_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let recording = db.record_sql();
+        let user = db.save(User::new("email"))?;
+        let _posts = db.posts_by_user_id(user.id)?;
+        db.execute("create table note (id integer primary key)")?;
+        let statements = recording.statements();
+        assert_eq!(recording.len(), 3);
+        assert!(statements[0].sql.starts_with("insert into User"));
+        assert!(matches!(&statements[1].params[..], [Value::Int(id)] if *id == user.id));
+        assert_eq!(
+            statements[2].sql,
+            "create table note (id integer primary key)"
+        );
+        drop(recording);
+        let recording = db.record_sql();
+        assert!(recording.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn replay_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let recording = db.record_sql();
+        let _user = db.save(User::new("tab\tand\nnewline"))?;
+        let _rows = db
+            .connection
+            .prepare("select * from user where id = ? and email is not ?")?
+            .bind(&[int(1).into(), Value::Null])?
+            .rows()?;
+        let path = std::env::temp_dir().join(format!("sqltight_trace_{}.txt", std::process::id()));
+        recording.export(&path)?;
+        let trace = read_trace(&path)?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(trace.len(), 2);
+        assert_eq!(trace[0].sql, User::SAVE_SQL);
+        assert!(matches!(&trace[1].params[..], [Value::Int(id), Value::Null] if *id == int(1)));
+
+        let replica = Database::open(":memory:")?;
+        let replayed = replica
+            .connection
+            .replay(&trace, ReplayRate::PerSecond(1000.0));
+        assert!(matches!(replayed[0].result, Ok(1)));
+        assert!(matches!(replayed[1].result, Ok(1)));
+        let replayed = replica
+            .connection
+            .replay(&trace[..1], ReplayRate::Unlimited);
+        assert!(matches!(
+            replayed[0].result,
+            Err(Error::UniqueConstraint { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn bench_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let insert = bench::bulk_insert(&db.connection, 100, |i| {
+            User::new(format!("{i}@example.com"))
+        })?;
+        let reads = bench::point_reads::<User>(&db.connection, "user", 1..=100)?;
+        let hot = bench::hot_query(10, || {
+            db.connection
+                .prepare("select count(*) as count from user")?
+                .rows()
+        })?;
+        assert_eq!(
+            (insert.iterations, reads.iterations, hot.iterations),
+            (100, 100, 10)
+        );
+        assert!(
+            insert
+                .to_string()
+                .starts_with("bulk insert: 100 iterations in")
+        );
+        assert!(matches!(
+            bench::point_reads::<User>(&db.connection, "user", [101]),
+            Err(Error::RowNotFound)
+        ));
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let user = db.save(User::new("email"))?;
+        let json = serde_json::to_string(&user).unwrap();
+        assert_eq!(
+            json,
+            r#"{"id":1,"email":"email","created_at":null,"updated_at":null}"#
+        );
+        let found = db.user_by_id(user.id)?;
+        assert_eq!(serde_json::to_string(&found).unwrap(), r#"{"id":1}"#);
+        let rows = db
+            .connection
+            .prepare("select id, email, 1.5 as score, x'01' as bytes from user")?
+            .rows()?;
+        assert_eq!(
+            serde_json::to_string(&rows).unwrap(),
+            r#"[{"id":1,"email":"email","score":1.5,"bytes":[1]}]"#
+        );
+        let values: Vec<Value> = serde_json::from_str(r#"[1, 1.5, "a", null]"#).unwrap();
+        assert!(matches!(
+            &values[..],
+            [Value::Int(_), Value::Real(_), Value::Text(_), Value::Null]
+        ));
+        let user: User = serde_json::from_str(&json).unwrap();
+        assert_eq!(user.email, text("email"));
+        Ok(())
+    }
+
+    #[test]
+    fn generated_modules_work() -> sqltight::Result<()> {
+        use db::prelude::*;
+        let db = Database::open(":memory:")?;
+        let user: db::user::User = db.save(db::user::User::new("email"))?;
+        let id: db::user::UserId = user.id;
+        assert_eq!(id, int(1));
+        let found: db::user::queries::UserById = db.user_by_id(id)?;
+        assert_eq!(found.id, id);
+        let _found: db::queries::UserById = found;
+        let post: db::post::Post = db.save(Post::new(id, "content"))?;
+        let _post_id: db::post::PostId = post.id;
+        let _posts: Vec<db::post::queries::PostsByUserId> = db.posts_by_user_id(id)?;
+        let _count: db::post::queries::CountPostsByUser = db.count_posts_by_user()?;
+        Ok(())
+    }
+
+    #[test]
+    fn generated_sql_consts_work() -> sqltight::Result<()> {
+        assert!(User::SAVE_SQL.starts_with("insert into User (id,email,created_at,updated_at)"));
+        assert_eq!(
+            Post::DELETE_SQL,
+            "delete from Post where id = :id returning *"
+        );
+        assert_eq!(
+            Database::USER_BY_ID_SQL,
+            "select user.id from user where id = :id limit 1"
+        );
+        let db = Database::open(":memory:")?;
+        let recording = db.record_sql();
+        let user = db.save(User::new("email"))?;
+        let _user = db.user_by_id(user.id)?;
+        let statements = recording.statements();
+        assert_eq!(statements[0].sql, User::SAVE_SQL);
+        assert_eq!(statements[1].sql, Database::USER_BY_ID_SQL);
+        Ok(())
+    }
+
+    #[test]
+    fn detect_repeated_sql_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let detector = db.detect_repeated_sql(2);
+        let user = db.save(User::new("email"))?;
+        let mut line = 0;
+        for content in ["a", "b", "c", "d"] {
+            line = line!() + 1;
+            let _post = db.save(Post::new(user.id, content))?;
+        }
+        let reports = detector.reports();
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0].sql.starts_with("insert into Post"));
+        assert_eq!(reports[0].count, 4);
+        assert_eq!(reports[0].location.file(), file!());
+        assert_eq!(reports[0].location.line(), line);
+        detector.reset();
+        assert!(detector.reports().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn migration_history_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let history = db.migration_history()?;
+        assert_eq!(
+            history[0].sql,
+            text("create table if not exists User ( id integer primary key ) strict")
+        );
+        assert_eq!(history[0].app_version, text(env!("CARGO_PKG_VERSION")));
+        assert_eq!(history[0].checksum.to_string().len(), 16);
+        assert!(
+            history
+                .iter()
+                .all(|record| record.applied_at != Int::default())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn row_get_works() -> sqltight::Result<()> {
+        let db = Database::open(":memory:")?;
+        let user = db.save(User::new("row@example.com"))?;
+        let rows = db
+            .connection
+            .prepare("select id, email, created_at from user")?
+            .rows()?;
+        let row = &rows[0];
+        assert_eq!(row.get::<Int>("id")?, user.id);
+        assert!(row.get::<i64>(0)? > 0);
+        assert_eq!(row.get::<String>(1)?, "row@example.com");
+        assert_eq!(row.get::<Option<i64>>("created_at")?, None);
+        assert!(matches!(
+            row.get::<String>("id"),
+            Err(Error::InvalidColumnType { column, expected: "text", found: "integer" }) if column == "id"
+        ));
+        assert!(matches!(
+            row.get::<i64>("missing"),
+            Err(Error::ColumnNotFound(_))
+        ));
+        assert!(matches!(row.get::<i64>(3), Err(Error::ColumnNotFound(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn derive_from_row_works() -> sqltight::Result<()> {
+        #[derive(FromRow, Debug, PartialEq)]
+        pub struct Account {
+            pub id: i64,
+            pub(crate) email: String,
+            created_at: Option<i64>,
+            nickname: Option<String>,
+        }
+
+        let db = Database::open(":memory:")?;
+        let _user = db.save(User::new("account@example.com"))?;
+        let accounts = db
+            .connection
+            .prepare("select id, email, created_at from user")?
+            .rows()?
+            .iter()
+            .map(Account::from_row)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            accounts,
+            vec![Account {
+                id: 1,
+                email: "account@example.com".into(),
+                created_at: None,
+                nickname: None,
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn query_as_works() -> sqltight::Result<()> {
+        #[derive(FromRow, Debug, PartialEq)]
+        struct Email {
+            id: i64,
+            email: String,
+        }
+
+        let db = Database::open(":memory:")?;
+        let _users = db.save_all(vec![User::new("a@example.com"), User::new("b@example.com")])?;
+        let sql = "select id, email from user where email like ? order by id desc";
+        let emails = db.query_as::<Email>(sql, &["%@example.com".into()])?;
+        assert_eq!(
+            emails,
+            vec![
+                Email {
+                    id: 2,
+                    email: "b@example.com".into()
+                },
+                Email {
+                    id: 1,
+                    email: "a@example.com".into()
+                }
+            ]
+        );
+        let literal: Vec<Email> = db
+            .connection
+            .query_as("select 1 as id, 'x' as email", &[])?;
+        assert_eq!(literal.len(), 1);
+        Ok(())
+    }
+
+    mod attached {
+        use crate::*;
+
+        db! {
+            attach archive
+            debug_output = "target/sqltight/attached.rs"
+            docs = "target/sqltight/attached.html"
+
+            table User {
+                id: Int,
+                email: Text
+            }
+
+            table archive.Event {
+                id: Int,
+                user_id: Int,
+                name: Text
+            }
+
+            index archive.Event {
+                user_id: Index
+            }
+
+            query events_by_user_id "
+                select event.id, event.name
+                from archive.event
+                join user on user.id = event.user_id
+                where event.user_id = :user_id
+            "
+        }
+
+        #[test]
+        fn attach_works() -> sqltight::Result<()> {
+            let db = Database::open(":memory:", ":memory:")?;
+            let user = db.save(User::new("email"))?;
+            let event = db.save(Event::new(user.id, "signup"))?;
+            assert_eq!(event.id, int(1));
+            let events = db.events_by_user_id(user.id)?;
+            assert_eq!(events.len(), 1);
+            assert_eq!(events[0].name, text("signup"));
+            db.attach(":memory:", "scratch")?;
+            db.execute("create table scratch.note (id integer primary key) strict")?;
+            db.detach("scratch")?;
+            assert!(db.execute("select * from scratch.note").is_err());
+            Ok(())
+        }
+
+        #[test]
+        fn debug_output_works() -> sqltight::Result<()> {
+            let output = std::fs::read_to_string(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/target/sqltight/attached.rs"
+            ))?;
+            assert!(output.contains("// create table if not exists archive.Event"));
+            assert!(output.contains("// PRAGMA journal_mode = WAL;"));
+            assert!(output.contains("pub struct Database"));
+            Ok(())
+        }
+
+        #[test]
+        fn docs_work() -> sqltight::Result<()> {
+            let markdown = Database::SCHEMA_MARKDOWN;
+            assert!(markdown.contains("### archive.Event"));
+            assert!(markdown.contains("| id | INTEGER | primary key |"));
+            assert!(markdown.contains("- `Event_user_id_ix (user_id)`"));
+            assert!(markdown.contains("### events_by_user_id\n\nParameters: `:user_id`"));
+            let html = std::fs::read_to_string(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/target/sqltight/attached.html"
+            ))?;
+            assert_eq!(html, Database::SCHEMA_HTML);
+            assert!(html.contains("<h3>events_by_user_id</h3>"));
+            Ok(())
+        }
+    }
+
+    mod query_builder {
+        use crate::*;
+
+        db! {
+            table Player soft_delete {
+                id: Int,
+                name: Text,
+                score: Int not null,
+                team: Text
+            }
+        }
+
+        #[test]
+        fn query_builder_works() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let players = db.save_all(vec![
+                Player::new("ann", 30, "red"),
+                Player::new("bob", 10, "blue"),
+                Player::new("cat", 20, "red"),
+                Player::new("dan", 5, None),
+            ])?;
+            let names = |players: Vec<Player>| -> Vec<Text> {
+                players.into_iter().map(|player| player.name).collect()
+            };
+            let top = Player::query()
+                .filter(Player::SCORE.ge(10))
+                .order_by(Player::SCORE.desc())
+                .limit(2);
+            assert_eq!(
+                top.sql(),
+                r#"select * from "Player" where ("score" >= ?) and deleted_at is null order by "score" desc limit ?"#
+            );
+            assert_eq!(names(top.fetch(&db)?), vec![text("ann"), text("cat")]);
+            let search = Player::query()
+                .filter(Player::TEAM.eq("red").or(Player::NAME.like("d%")))
+                .filter(!Player::NAME.is_in(["cat"]))
+                .order_by(Player::NAME);
+            assert_eq!(names(search.fetch(&db)?), vec![text("ann"), text("dan")]);
+            assert_eq!(search.count(&db)?, 2);
+            let no_team = Player::query().filter(Player::TEAM.is_null()).first(&db)?;
+            assert_eq!(no_team.map(|player| player.name), Some(text("dan")));
+            let _ann = db.delete(players.into_iter().next().expect("a player"))?;
+            {
+                let tx = db.transaction()?;
+                let rest = Player::query().order_by(Player::ID).offset(1).fetch(&tx)?;
+                assert_eq!(names(rest), vec![text("cat"), text("dan")]);
+            }
+            Ok(())
+        }
+    }
+
+    mod widths {
+        use crate::*;
+
+        db! {
+            table Reading {
+                id: Int,
+                sensor: Int u16 not null,
+                level: Int u8,
+                value: Real f32
+            }
+        }
+
+        #[test]
+        fn widths_work() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let reading = db.save(Reading::new(7u16, 200u8, 1.5f32))?;
+            assert_eq!(reading.sensor, 7u16);
+            assert_eq!(reading.level, Some(200u8));
+            assert_eq!(reading.value, Some(1.5f32));
+            let _result = db.execute("update Reading set level = 300, value = 1e300")?;
+            let row = db
+                .connection
+                .prepare("select level, value from Reading")?
+                .rows()?;
+            assert!(matches!(
+                row[0].get::<u8>("level"),
+                Err(Error::TryFromInt(_))
+            ));
+            assert!(matches!(
+                row[0].get::<f32>("value"),
+                Err(Error::RealOutOfRange(_))
+            ));
+            let find = std::panic::AssertUnwindSafe(|| db.find_reading(reading.id));
+            let panicked = std::panic::catch_unwind(find);
+            assert!(panicked.is_err());
+            Ok(())
+        }
+    }
+
+    mod any_columns {
+        use crate::*;
+
+        db! {
+            table Setting {
+                id: Int,
+                name: Text,
+                value: Any
+            }
+
+            query setting_values "select name, value from Setting where value is not null order by id"
+        }
+
+        #[test]
+        fn any_columns_work() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let _settings = db.save_all(vec![
+                Setting::new("retries", 3),
+                Setting::new("ratio", 0.5),
+                Setting::new("theme", "dark"),
+                Setting::new("key", vec![1, 2]),
+                Setting::new("unset", None::<Value>),
+            ])?;
+            let settings = db.all_settings()?;
+            assert_eq!(settings[0].value, any(3));
+            assert_eq!(settings[0].value.to::<i64>()?, 3);
+            assert_eq!(settings[1].value, any(0.5));
+            assert_eq!(settings[2].value, any("dark"));
+            assert!(matches!(
+                settings[2].value.to::<i64>(),
+                Err(Error::InvalidColumnType { .. })
+            ));
+            assert_eq!(settings[3].value, any(vec![1, 2]));
+            assert!(settings[4].value.is_null());
+            let values = db
+                .setting_values()?
+                .into_iter()
+                .map(|row| row.value)
+                .collect::<Vec<_>>();
+            assert_eq!(values.len(), 4);
+            assert_eq!(values[2], any("dark"));
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "chrono")]
+    mod datetime {
+        use crate::*;
+
+        db! {
+            table Event {
+                id: Int,
+                name: Text,
+                starts_at: DateTime
+            }
+        }
+
+        #[test]
+        fn datetime_works() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let starts_at = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+            let event = db.save(Event::new("launch", starts_at))?;
+            assert_eq!(event.starts_at, datetime(starts_at));
+            let rows = db
+                .connection
+                .prepare(
+                    "select typeof(starts_at) as ty, '2023-11-14 22:13:20' as text from event",
+                )?
+                .rows()?;
+            assert_eq!(rows[0].get::<String>("ty")?, "integer");
+            assert_eq!(rows[0].get::<DateTime>("text")?, datetime(starts_at));
+            assert!(matches!(
+                DateTime::from_sql(&text("yesterday").into()),
+                Err(Error::InvalidDateTime(_))
+            ));
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "json")]
+    mod json {
+        use crate::*;
+
+        #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+        pub struct Settings {
+            theme: String,
+            columns: Vec<i64>,
+        }
+
+        db! {
+            table Profile {
+                id: Int,
+                name: Text,
+                settings: Json<Settings>
+            }
+
+            query profile_by_id "select id, settings from profile where id = :id limit 1"
+        }
+
+        #[test]
+        fn json_works() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let settings = Settings {
+                theme: "dark".into(),
+                columns: vec![1, 2],
+            };
+            let profile = db.save(Profile::new("name", json(settings.clone())))?;
+            assert_eq!(profile.settings.get(), Some(&settings));
+            let found: ProfileById = db.profile_by_id(profile.id)?;
+            assert_eq!(found.settings, json(settings));
+            let rows = db
+                .connection
+                .prepare("select settings from profile")?
+                .rows()?;
+            assert_eq!(
+                rows[0].get::<String>("settings")?,
+                r#"{"theme":"dark","columns":[1,2]}"#
+            );
+            assert!(matches!(
+                Json::<Settings>::from_sql(&text("{").into()),
+                Err(Error::InvalidJson(_))
+            ));
+            Ok(())
+        }
+    }
+
+    mod visibility {
+        use crate::*;
+
+        db! {
+            visibility = pub(crate)
+
+            table Account {
+                id: Int,
+                name: Text,
+                pub(crate) token: Text
+            }
+
+            pub query account_names "select name from account"
+        }
+
+        #[test]
+        fn visibility_works() -> sqltight::Result<()> {
+            let db: db::Database = Database::open(":memory:")?;
+            let mut account = Account::new("name", "token");
+            account.token = text("secret");
+            let _account = db.save(account)?;
+            let names: Vec<db::queries::AccountNames> = db.account_names()?;
+            assert_eq!(names[0].name, text("name"));
+            Ok(())
+        }
+    }
+
+    mod enums {
+        use crate::*;
+
+        db! {
+            table Article {
+                id: Int,
+                title: Text,
+                status: Enum(Draft, Published, Archived),
+                priority: Enum(Low = 1, High = 2)
+            }
+
+            query article_statuses "select id, status from article"
+        }
+
+        #[test]
+        fn enums_work() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let article = db.save(Article::new(
+                "title",
+                ArticleStatus::Draft,
+                ArticlePriority::High,
+            ))?;
+            assert_eq!(article.status, Some(ArticleStatus::Draft));
+            assert_eq!(article.priority, Some(ArticlePriority::High));
+            let statuses: Vec<ArticleStatuses> = db.article_statuses()?;
+            assert_eq!(statuses[0].status, Some(ArticleStatus::Draft));
+            let rows = db
+                .connection
+                .prepare("select status, priority from article")?
+                .rows()?;
+            assert_eq!(rows[0].get::<String>("status")?, "Draft");
+            assert_eq!(rows[0].get::<i64>("priority")?, 2);
+            let result = db
+                .connection
+                .prepare("update article set status = 'Deleted'")?
+                .changes();
+            assert!(matches!(result, Err(Error::CheckConstraint(_))));
+            let empty = db.save(Article::new("title", None, None))?;
+            assert_eq!(empty.status, None);
+            Ok(())
+        }
+    }
+
+    mod not_null {
+        use crate::*;
+
+        db! {
+            table Member {
+                id: Int,
+                email: Text not null,
+                nickname: Text?,
+                score: Real not null,
+                role: Enum(Guest, Admin) not null
+            }
+
+            query member_emails "select email, nickname, role from member"
+        }
+
+        #[test]
+        fn not_null_works() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let member = db.save(Member::new("email", None, 1.5, MemberRole::Admin))?;
+            let email: String = member.email;
+            assert_eq!((email.as_str(), member.score), ("email", 1.5));
+            assert_eq!(member.nickname, Text::default());
+            let emails: Vec<MemberEmails> = db.member_emails()?;
+            assert_eq!(emails[0].role, MemberRole::Admin);
+            assert_eq!(Member::default().role, MemberRole::Guest);
+            let result = db.execute("insert into member (email) values (null)");
+            assert!(
+                matches!(result, Err(Error::NotNullConstraint { column, .. }) if column == "email")
+            );
+            Ok(())
+        }
+    }
+
+    mod defaults {
+        use crate::*;
+
+        db! {
+            table Task {
+                id: Int,
+                title: Text,
+                done: Int default 0,
+                added_at: Int default "unixepoch()",
+                label: Text not null default "'inbox'"
+            }
+        }
+
+        #[test]
+        fn defaults_work() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let task = db.save(Task::new("title", "label"))?;
+            assert_eq!(task.done, int(0));
+            assert_ne!(task.added_at, Int::default());
+            db.execute("insert into task (title) values ('raw')")?;
+            let rows = db
+                .connection
+                .prepare("select done, label from task where title = 'raw'")?
+                .rows()?;
+            assert_eq!(rows[0].get::<i64>("done")?, 0);
+            assert_eq!(rows[0].get::<String>("label")?, "inbox");
+            let task = db.save(Task {
+                done: Int::default(),
+                ..task
+            })?;
+            assert_eq!(task.done, Int::default());
+            let tasks = db.save_all(vec![Task {
+                done: int(1),
+                ..task
+            }])?;
+            assert_eq!(tasks[0].done, int(1));
+            let tasks = db.save_all(vec![Task {
+                done: Int::default(),
+                ..tasks.into_iter().next().ok_or(Error::RowNotFound)?
+            }])?;
+            assert_eq!(tasks[0].done, Int::default());
+            Ok(())
+        }
+    }
+
+    mod foreign_keys {
+        use crate::*;
+
+        db! {
+            docs = "target/sqltight/foreign_keys.mmd"
+
+            table Author {
+                id: Int,
+                name: Text
+            }
+
+            table Book {
+                id: Int,
+                author_id: Int references Author(id) on delete cascade,
+                title: Text
+            }
+        }
+
+        #[test]
+        fn foreign_keys_work() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let author = db.save(Author::new("name"))?;
+            let _book = db.save(Book::new(author.id, "title"))?;
+            assert!(matches!(
+                db.save(Book::new(999, "title")),
+                Err(Error::ForeignKeyConstraint)
+            ));
+            let _author = db.delete(author)?;
+            let rows = db.connection.prepare("select id from book")?.rows()?;
+            assert!(rows.is_empty());
+            Ok(())
+        }
+
+        #[test]
+        fn schema_graph_works() -> sqltight::Result<()> {
+            let mermaid = std::fs::read_to_string(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/target/sqltight/foreign_keys.mmd"
+            ))?;
+            assert_eq!(mermaid, Database::SCHEMA_MERMAID);
+            assert!(mermaid.starts_with("erDiagram\n    Author {\n        INTEGER id PK\n"));
+            assert!(mermaid.contains("        INT author_id FK\n"));
+            assert!(mermaid.contains("    Author |o--o{ Book : author_id\n"));
+            assert!(Database::SCHEMA_DOT.contains("\"Book\" -> \"Author\" [label=\"author_id\"];"));
+            Ok(())
+        }
+    }
+
+    mod relations {
+        use crate::*;
+
+        db! {
+            table Writer {
+                id: Int,
+                name: Text
+            }
+
+            table Story {
+                id: Int,
+                writer_id: Int references Writer(id),
+                editor_id: Int references Writer(id),
+                title: Text
+            }
+        }
+
+        #[test]
+        fn relations_work() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let ann = db.save(Writer::new("ann"))?;
+            let bob = db.save(Writer::new("bob"))?;
+            let first = db.save(Story::new(ann.id, bob.id, "first"))?;
+            let _second = db.save(Story::new(ann.id, None, "second"))?;
+            assert_eq!(
+                first.writer(&db)?.map(|writer| writer.name),
+                Some(text("ann"))
+            );
+            assert_eq!(
+                first.editor(&db)?.map(|writer| writer.name),
+                Some(text("bob"))
+            );
+            let titles = |stories: Vec<Story>| -> Vec<Text> {
+                stories.into_iter().map(|story| story.title).collect()
+            };
+            assert_eq!(
+                titles(ann.writer_storys(&db)?),
+                vec![text("first"), text("second")]
+            );
+            assert_eq!(titles(bob.editor_storys(&db)?), vec![text("first")]);
+
+            let recording = db.record_sql();
+            let writers = db.writers_with_writer_storys()?;
+            assert_eq!(recording.len(), 2);
+            let counts = writers
+                .iter()
+                .map(|(writer, stories)| (writer.name.clone(), stories.len()))
+                .collect::<Vec<_>>();
+            assert_eq!(counts, vec![(text("ann"), 2), (text("bob"), 0)]);
+            let stories = db.storys_with_editor()?;
+            assert_eq!(recording.len(), 4);
+            let editors = stories
+                .into_iter()
+                .map(|(story, editor)| (story.title, editor.map(|editor| editor.name)))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                editors,
+                vec![(text("first"), Some(text("bob"))), (text("second"), None)]
+            );
+            Ok(())
+        }
+    }
+
+    mod checks {
+        use crate::*;
+
+        db! {
+            table Comment {
+                id: Int,
+                body: Text,
+                kind: Text
+            }
+
+            check Comment { length(body) > 0 and kind != "spam" }
+        }
+
+        #[test]
+        fn checks_work() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let comment = db.save(Comment::new("body", "reply"))?;
+            assert!(matches!(
+                db.save(Comment::new("", "reply")),
+                Err(Error::CheckConstraint(check)) if check == "length (body) > 0 and kind != 'spam'"
+            ));
+            let mut comment = comment;
+            comment.kind = text("spam");
+            assert!(matches!(db.save(comment), Err(Error::CheckConstraint(_))));
+            Ok(())
+        }
+
+        mod changed {
+            use crate::*;
+
+            db! {
+                table Comment {
+                    id: Int,
+                    body: Text,
+                    kind: Text
+                }
+
+                check Comment { length(body) > 0 }
+            }
+        }
+
+        #[test]
+        fn changed_checks_work() -> sqltight::Result<()> {
+            let path =
+                std::env::temp_dir().join(format!("sqltight_checks_{}.db", std::process::id()));
+            let path = path.to_str().unwrap_or_default();
+            let db = Database::open(path)?;
+            assert!(matches!(
+                db.save(Comment::new("body", "spam")),
+                Err(Error::CheckConstraint(_))
+            ));
+            drop(db);
+            let db = changed::Database::open(path)?;
+            let _comment = db.save(changed::Comment::new("body", "spam"))?;
+            let triggers = db
+                .connection
+                .prepare("select name from sqlite_master where type = 'trigger'")?
+                .rows()?;
+            assert_eq!(triggers.len(), 2);
+            std::fs::remove_file(path).ok();
+            Ok(())
+        }
+    }
+
+    mod ops {
+        use crate::*;
+
+        db! {
+            table Account {
+                id: Int,
+                name: Text,
+                credits: Int
+            }
+
+            table Transfer {
+                id: Int,
+                from_id: Int references Account(id),
+                to_id: Int references Account(id),
+                amount: Int
+            }
+
+            check Account { credits >= 0 }
+
+            op transfer_credits {
+                "insert into Transfer (from_id, to_id, amount) values (:from_id, :to_id, :amount int)"
+                "update Account set credits = credits - :amount where id = :from_id"
+                "update Account set credits = credits + :amount where id = :to_id"
+            }
+        }
+
+        #[test]
+        fn ops_work() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let ann = db.save(Account::new("ann", 100))?;
+            let bob = db.save(Account::new("bob", 0))?;
+            let () = db.transfer_credits(ann.id, bob.id, 60)?;
+            let credits = |db: &Database| -> sqltight::Result<Vec<Int>> {
+                Ok(db
+                    .all_accounts()?
+                    .into_iter()
+                    .map(|account| account.credits)
+                    .collect())
+            };
+            assert_eq!(credits(&db)?, vec![int(40), int(60)]);
+            assert_eq!(db.count_transfers()?, 1);
+            // the overdraft fails the check and the transfer row before it is rolled back
+            assert!(matches!(
+                db.transfer_credits(ann.id, bob.id, 50),
+                Err(Error::CheckConstraint(_))
+            ));
+            assert_eq!(credits(&db)?, vec![int(40), int(60)]);
+            assert_eq!(db.count_transfers()?, 1);
+            Ok(())
+        }
+
+        #[test]
+        fn foreign_key_check_works() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let ann = db.save(Account::new("ann", 100))?;
+            assert!(db.foreign_key_check()?.is_empty());
+            let _result = db.execute("pragma foreign_keys = off")?;
+            let transfer = db.save(Transfer::new(ann.id, 404, 10))?;
+            let violations = db.foreign_key_check()?;
+            assert_eq!(violations.len(), 1);
+            assert_eq!(violations[0].table, "Transfer");
+            assert_eq!(violations[0].rowid, Some(1));
+            assert_eq!(transfer.id, int(1));
+            assert_eq!(violations[0].parent, "Account");
+            assert_eq!(violations[0].description, "to_id references Account(id)");
+            Ok(())
+        }
+    }
+
+    mod executes {
+        use crate::*;
+
+        db! {
+            table Job {
+                id: Int,
+                name: Text,
+                claimed_by: Text
+            }
+
+            execute claim_job -> Job "
+                update Job set claimed_by = :worker
+                where id = (select id from Job where claimed_by is null order by id limit 1)
+                returning *
+            "
+
+            execute release_jobs "update Job set claimed_by = null where claimed_by = :worker"
+        }
+
+        #[test]
+        fn executes_work() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let _jobs = db.save_all(vec![
+                Job::new("send email", None::<String>),
+                Job::new("resize image", None::<String>),
+            ])?;
+            let claimed = db.claim_job("one")?;
+            assert_eq!(claimed.len(), 1);
+            assert_eq!(claimed[0].id, int(1));
+            assert_eq!(claimed[0].claimed_by, text("one"));
+            assert_eq!(db.claim_job("two")?[0].id, int(2));
+            assert!(db.claim_job("three")?.is_empty());
+            assert_eq!(db.release_jobs("one")?, 1);
+            assert_eq!(db.claim_job("three")?[0].name, text("send email"));
+            Ok(())
+        }
+    }
+
+    mod conditional_upserts {
+        use crate::*;
+
+        db! {
+            table Note on conflict where "excluded.version > Note.version" {
+                id: Int,
+                body: Text,
+                version: Int
+            }
+        }
+
+        #[test]
+        fn conditional_upserts_work() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let note = db.save(Note::new("first", 1))?;
+            let note = db.save(Note {
+                body: text("second"),
+                version: int(2),
+                ..note
+            })?;
+            let stale = Note {
+                id: note.id,
+                body: text("stale"),
+                version: int(1),
+            };
+            assert!(matches!(db.save(stale), Err(Error::UpsertSkipped)));
+            let rows = vec![
+                Note::new("other", 1),
+                Note {
+                    id: note.id,
+                    body: text("stale"),
+                    version: int(2),
+                },
+            ];
+            assert!(matches!(db.save_all(rows), Err(Error::UpsertSkipped)));
+            assert_eq!(
+                db.find_note(note.id)?.map(|note| note.body),
+                Some(text("second"))
+            );
+            assert_eq!(db.count_notes()?, 1);
+            Ok(())
+        }
+    }
+
+    mod composite_indexes {
+        use crate::*;
+
+        db! {
+            table Vote {
+                id: Int,
+                user_id: Int,
+                post_id: Int
+            }
+
+            index Vote {
+                (user_id, post_id): Unique,
+                (post_id, user_id): Index
+            }
+        }
+
+        #[test]
+        fn composite_indexes_work() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let _vote = db.save(Vote::new(1, 1))?;
+            let _vote = db.save(Vote::new(1, 2))?;
+            assert!(matches!(
+                db.save(Vote::new(1, 1)),
+                Err(Error::UniqueConstraint { .. })
+            ));
+            let rows = db
+                .connection
+                .prepare("select name from sqlite_master where name = 'Vote_post_id_user_id_ix'")?
+                .rows()?;
+            assert_eq!(rows.len(), 1);
+            Ok(())
+        }
+    }
+
+    mod max_rows {
+        use crate::*;
+
+        db! {
+            max_rows = 2
+
+            table Note {
+                id: Int,
+                body: Text
+            }
+
+            query notes "select id, body from note order by id"
+
+            query all_notes max_rows = 10 "select id, body from note order by id"
+        }
+
+        #[test]
+        fn max_rows_works() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            for body in ["one", "two", "three"] {
+                let _note = db.save(Note::new(body))?;
+            }
+            assert!(matches!(
+                db.notes(),
+                Err(Error::TooManyRows { max_rows: 2, .. })
+            ));
+            assert_eq!(db.all_notes()?.len(), 3);
+            Ok(())
+        }
+    }
+
+    mod views {
+        use crate::*;
+
+        db! {
+            table Account {
+                id: Int,
+                email: Text,
+                deleted_at: Int
+            }
+
+            view active_accounts "select id, email from account where deleted_at is null"
+        }
+
+        #[test]
+        fn views_work() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let account = db.save(Account::new("email", None::<i64>))?;
+            let _deleted = db.save(Account::new("deleted", 1))?;
+            let accounts: Vec<ActiveAccounts> = db.active_accounts()?;
+            assert_eq!(accounts.len(), 1);
+            assert_eq!(accounts[0].id, account.id);
+            assert_eq!(accounts[0].email, account.email);
+            Ok(())
+        }
+    }
+
+    mod versioned_migrations {
+        use crate::*;
+
+        db! {
+            table Note {
+                id: Int,
+                body: Text
+            }
+
+            migration 1 up "create table legacy_note (id integer primary key, body text)"
+                down "drop table legacy_note"
+
+            migration 2 up "insert into note (body) select body from legacy_note; drop table legacy_note"
+                down "create table legacy_note (id integer primary key, body text)"
+        }
+
+        #[test]
+        fn versioned_migrations_work() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let table_count = |db: &Database| -> sqltight::Result<i64> {
+                let rows = db
+                    .connection
+                    .prepare(
+                        "select count(*) as count from sqlite_schema where name = 'legacy_note'",
+                    )?
+                    .rows()?;
+                rows[0].get("count")
+            };
+            assert_eq!(db.connection.migration_version()?, 2);
+            assert_eq!(table_count(&db)?, 0);
+            assert_eq!(db.rollback_to(1)?, 1);
+            assert_eq!(table_count(&db)?, 1);
+            assert_eq!(db.rollback_to(0)?, 0);
+            assert_eq!(table_count(&db)?, 0);
+            assert_eq!(db.migrate_to(1)?, 1);
+            assert_eq!(table_count(&db)?, 1);
+            let _result = db.execute("insert into legacy_note (body) values ('old')")?;
+            assert_eq!(db.migrate_to(2)?, 2);
+            let notes = db.connection.prepare("select body from note")?.rows()?;
+            assert_eq!(notes[0].get::<String>("body")?, "old");
+            Ok(())
+        }
+    }
+
+    mod migration_dir {
+        use crate::*;
+
+        db! {
+            migrations "tests/fixtures/migrations"
+
+            query tags "select id, name from tag order by id"
+        }
+
+        #[test]
+        fn migration_dir_works() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            assert_eq!(db.connection.migration_version()?, 2);
+            let tags: Vec<Tags> = db.tags()?;
+            assert_eq!(tags.len(), 2);
+            assert!(matches!(
+                db.rollback_to(0),
+                Err(Error::IrreversibleMigration(2))
+            ));
+            assert_eq!(db.connection.migration_version()?, 2);
+            Ok(())
+        }
+    }
+
+    mod column_changes {
+        use crate::*;
+
+        db! {
+            table Member {
+                id: Int,
+                email: Text renamed_from mail,
+                legacy: Text dropped
+            }
+
+            query member_by_id "select member.email from member where id = :id limit 1"
+        }
+
+        #[test]
+        fn column_changes_work() -> sqltight::Result<()> {
+            let path = std::env::temp_dir()
+                .join(format!("sqltight_column_changes_{}.db", std::process::id()));
+            let path = path_str(&path)?;
+            let _result = std::fs::remove_file(&path);
+            let old = Sqlite::open(&path)?;
+            let _result = old.execute(
+                "create table Member (id integer primary key, mail text, legacy text) strict",
+            )?;
+            let _result =
+                old.execute("insert into Member (mail, legacy) values ('a@example.com', 'x')")?;
+            drop(old);
+            for _ in 0..2 {
+                let db = Database::open(&path)?;
+                let member = db.member_by_id(1)?;
+                assert_eq!(member.email, text("a@example.com"));
+            }
+            let live = Sqlite::open(&path)?;
+            let columns = live
+                .prepare("select name from pragma_table_info('Member') order by cid")?
+                .rows()?
+                .iter()
+                .map(|row| row.get("name"))
+                .collect::<sqltight::Result<Vec<String>>>()?;
+            drop(live);
+            let _result = std::fs::remove_file(&path);
+            assert_eq!(columns, vec!["id", "email"]);
+            Ok(())
+        }
+    }
+
+    mod dev_database {
+        use crate::*;
+
+        db! {
+            path = "tests/fixtures/dev.sqlite3"
+
+            table Account {
+                id: Int,
+                email: Text,
+                score: Int
+            }
+
+            query account_by_email "select account.id, account.score from account where email = :email limit 1"
+        }
+
+        #[test]
+        fn dev_database_works() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let account = db.save(Account::new("a@example.com", 1))?;
+            let found = db.account_by_email("a@example.com")?;
+            assert_eq!(found.id, account.id);
+            assert_eq!(found.score, int(1));
+            Ok(())
+        }
+    }
+
+    mod typed_params {
+        use crate::*;
+
+        db! {
+            table Item {
+                id: Int,
+                name: Text not null,
+                price: Real
+            }
+
+            query items_cheaper_than "
+                select item.id, item.name
+                from item
+                where price < :price and item.name like :pattern
+                order by id
+                limit :count int
+            "
+        }
+
+        #[test]
+        fn typed_params_work() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let apple = db.save(Item {
+                name: "apple".into(),
+                price: real(1.5),
+                ..Default::default()
+            })?;
+            let _banana = db.save(Item {
+                name: "banana".into(),
+                price: real(20.5),
+                ..Default::default()
+            })?;
+            let items = db.items_cheaper_than(10.0, "%a%", 5)?;
+            assert_eq!(items.len(), 1);
+            assert_eq!(items[0].id, apple.id);
+            Ok(())
+        }
+    }
+
+    mod pagination {
+        use crate::*;
+
+        db! {
+            table Note {
+                id: Int,
+                body: Text not null
+            }
+
+            query notes_page paginate "
+                select note.id, note.body
+                from note
+                where note.body like :pattern
+                order by id
+            "
+        }
+
+        #[test]
+        fn pagination_works() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            for body in ["one", "two", "three", "four", "five"] {
+                let _note = db.save(Note {
+                    body: body.into(),
+                    ..Default::default()
+                })?;
+            }
+            let page = db.notes_page("%o%", 2, 2)?;
+            assert_eq!(page.total, 3);
+            assert_eq!(page.items.len(), 1);
+            assert_eq!(page.items[0].body, "four");
+            assert_eq!(page.next_offset, None);
+            assert_eq!(sqltight::Page::new(vec![1, 2], 3, 0).next_offset, Some(2));
+            Ok(())
+        }
+    }
+
+    mod conflict_targets {
+        use crate::*;
+
+        db! {
+            table Member on conflict (email) {
+                id: Int,
+                email: Text not null,
+                name: Text
+            }
+
+            index Member {
+                email: Unique
+            }
+        }
+
+        #[test]
+        fn conflict_target_works() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let member = db.save(Member::new("a@example.com", None))?;
+            let renamed = db.save(Member::new("a@example.com", "a"))?;
+            assert_eq!(renamed.id, member.id);
+            assert_eq!(renamed.name, text("a"));
+            let members = db.save_all(vec![
+                Member::new("b@example.com", "b"),
+                Member::new("a@example.com", "c"),
+            ])?;
+            assert_eq!(members[1].id, member.id);
+            let moved = db.save(Member {
+                email: "d@example.com".into(),
+                ..renamed
+            })?;
+            assert_eq!(moved.id, member.id);
+            assert_eq!(db.count_members()?, 2);
+            Ok(())
+        }
+    }
+
+    mod soft_delete {
+        use crate::*;
+
+        db! {
+            table Task soft_delete {
+                id: Int,
+                title: Text not null
+            }
+
+            query open_tasks "select task.id, task.title from task order by id"
+
+            query tasks_ever "select id, title, deleted_at from main.task order by id"
+
+            query trash with_deleted "select id, title from task where deleted_at is not null"
+
+            view task_titles "select title from task order by id"
+
+            execute shout_open "update task set title = title || '!' where id in (select id from task)"
+
+            execute restore_task with_deleted "update task set deleted_at = null where id = :id"
+        }
+
+        #[test]
+        fn soft_delete_works() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let mut tasks = vec![];
+            for title in ["one", "two", "three"] {
+                tasks.push(db.save(Task::new(title))?);
+            }
+            let deleted = db.delete(tasks.remove(1))?;
+            assert_ne!(deleted.deleted_at, Int::default());
+            assert_eq!(db.count_tasks()?, 2);
+            assert!(!db.task_exists(deleted.id)?);
+            assert!(db.find_task(deleted.id)?.is_none());
+            assert_eq!(db.all_tasks()?.len(), 2);
+            let titles = db
+                .open_tasks()?
+                .into_iter()
+                .map(|task| task.title)
+                .collect::<Vec<_>>();
+            assert_eq!(titles, ["one", "three"]);
+            let ever = db.tasks_ever()?;
+            assert_eq!(ever.len(), 3);
+            assert_eq!(ever[1].deleted_at, deleted.deleted_at);
+            assert_eq!(db.task_titles()?.len(), 2);
+            assert_eq!(db.trash()?.len(), 1);
+            assert_eq!(db.shout_open()?, 2);
+            let stale = Task {
+                deleted_at: Int::default(),
+                ..deleted
+            };
+            let stale = db.save(stale)?;
+            assert_eq!(stale.deleted_at, deleted.deleted_at);
+            assert_eq!(stale.title, "two");
+            assert_eq!(db.restore_task(deleted.id)?, 1);
+            assert!(db.task_exists(deleted.id)?);
+            Ok(())
+        }
+    }
+
+    mod timestamps {
+        use crate::*;
+
+        db! {
+            table Article timestamps {
+                id: Int,
+                title: Text not null
+            }
+        }
+
+        #[test]
+        fn timestamps_work() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let article = db.save(Article::new("draft"))?;
+            assert_ne!(article.created_at, Int::default());
+            assert_ne!(article.updated_at, Int::default());
+            let created_at = article.created_at;
+            let article = db.save(Article {
+                title: "published".into(),
+                created_at: int(1),
+                updated_at: int(2),
+                ..article
+            })?;
+            assert_eq!(article.created_at, created_at);
+            assert_ne!(article.updated_at, int(2));
+            let imported = db.save_all(vec![Article {
+                created_at: int(5),
+                ..Article::new("imported")
+            }])?;
+            assert_eq!(imported[0].created_at, int(5));
+            assert_ne!(imported[0].updated_at, Int::default());
+            Ok(())
+        }
+    }
+
+    mod collation {
+        use crate::*;
+
+        db! {
+            table Person {
+                id: Int,
+                name: Text not null
+            }
+
+            query people order_by_collation = nocase "
+                select person.id, person.name
+                from person
+                order by name desc, id
+                limit 20
+            "
+        }
+
+        #[test]
+        fn order_by_collation_works() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            for name in ["b", "A", "c", "a"] {
+                let _person = db.save(Person::new(name))?;
+            }
+            let names = db
+                .people()?
+                .into_iter()
+                .map(|person| person.name)
+                .collect::<Vec<_>>();
+            assert_eq!(names, ["c", "b", "A", "a"]);
+            db.connection
+                .create_collation("length", |a, b| a.len().cmp(&b.len()))?;
+            let rows = db
+                .connection
+                .prepare("select name from (select 'ccc' as name union all select 'a' union all select 'bb') order by name collate length")?
+                .rows()?;
+            let names = rows
+                .iter()
+                .map(|row| row.get::<String>(0))
+                .collect::<Result<Vec<_>>>()?;
+            assert_eq!(names, ["a", "bb", "ccc"]);
+            Ok(())
+        }
+
+        #[cfg(feature = "unicode_collation")]
+        #[test]
+        fn unicode_collation_works() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            for name in ["Peng", "Peña", "Ernie", "Émile"] {
+                let _person = db.save(Person::new(name))?;
+            }
+            let rows = db
+                .connection
+                .prepare("select name from person order by name collate unicode")?
+                .rows()?;
+            let names = rows
+                .iter()
+                .map(|row| row.get::<String>(0))
+                .collect::<Result<Vec<_>>>()?;
+            assert_eq!(names, ["Émile", "Ernie", "Peña", "Peng"]);
+            Ok(())
+        }
+    }
+
+    mod timeseries {
+        use crate::*;
+
+        db! {
+            timeseries table Reading by at {
+                at: Int not null,
+                sensor: Text not null,
+                value: Real
+            }
+        }
+
+        #[test]
+        fn timeseries_works() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            // 2026-09-15, 2026-10-01 and 2026-10-20
+            for at in [1789430400, 1790812800, 1792454400] {
+                db.insert_series(Reading {
+                    at,
+                    sensor: "kitchen".into(),
+                    value: real(20.5),
+                })?;
+            }
+            let partitions = db.connection.series_partitions::<Reading>()?;
+            assert_eq!(partitions.len(), 2);
+            assert_eq!(partitions[0].0, "Reading_2026_09");
+            assert_eq!(partitions[1].1, 1790812800);
+            // 2026-09-01 up to 2026-11-01
+            let readings = db.series_between::<Reading>(1788220800, 1793491200)?;
+            assert_eq!(readings.len(), 3);
+            assert_eq!(readings[2].at, 1792454400);
+            // everything before 2026-10-10
+            assert_eq!(db.sweep_series::<Reading>(1791590400)?, 2);
+            let readings = db.series_between::<Reading>(1788220800, 1793491200)?;
+            assert_eq!(readings.len(), 1);
+            assert_eq!(readings[0].sensor, "kitchen");
+            assert_eq!(db.connection.series_partitions::<Reading>()?.len(), 1);
+            Ok(())
+        }
+    }
+
+    mod log_tables {
+        use crate::*;
+
+        db! {
+            log table AuditEvent {
+                id: Int,
+                actor: Text not null,
+                action: Text not null
+            }
+        }
+
+        #[test]
+        fn log_tables_work() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let first = db.save(AuditEvent::new("ada", "login"))?;
+            let second = db.save(AuditEvent::new("ada", "export"))?;
+            assert!(first.hash.to_string().len() == 64);
+            // the same on every target, ints and lengths are hashed as big endian bytes
+            assert_eq!(
+                first.hash.to_string(),
+                "089c32000c41f63690e9fef752d0a41aedb7535e91cd0efc82c0816ade99d578"
+            );
+            assert_ne!(first.hash, second.hash);
+            assert_eq!(db.verify_chain::<AuditEvent>()?, 2);
+            assert!(db.delete(second).is_err());
+            assert!(
+                db.execute("update AuditEvent set action = 'logout'")
+                    .is_err()
+            );
+            let _result = db.execute("drop trigger AuditEvent_append_only_update")?;
+            let _result = db.execute("update AuditEvent set action = 'logout' where id = 1")?;
+            assert!(matches!(
+                db.verify_chain::<AuditEvent>(),
+                Err(Error::BrokenChain { id: 1, .. })
+            ));
+            Ok(())
+        }
+
+        #[test]
+        fn concurrent_appends_chain() -> sqltight::Result<()> {
+            let path = std::env::temp_dir().join(format!("sqltight_log_{}.db", std::process::id()));
+            let path = path_str(&path)?;
+            let migrated = Database::open(&path)?;
+            let start = std::sync::Arc::new(std::sync::Barrier::new(2));
+            let appenders = ["ada", "bob"].map(|actor| {
+                let path = path.clone();
+                let start = start.clone();
+                std::thread::spawn(move || -> sqltight::Result<()> {
+                    let db = Database::open(&path)?;
+                    start.wait();
+                    for _ in 0..50 {
+                        // one at a time, and ten in one savepoint
+                        match actor {
+                            "ada" => {
+                                for _ in 0..10 {
+                                    let _event = db.save(AuditEvent::new(actor, "login"))?;
+                                }
+                            }
+                            _ => {
+                                let events = (0..10).map(|_| AuditEvent::new(actor, "login"));
+                                let _events = db.save_all(events.collect())?;
+                            }
+                        }
+                    }
+                    Ok(())
+                })
+            });
+            for appender in appenders {
+                appender.join().expect("appender panicked")?;
+            }
+            // both chained to the row before theirs, neither forked the chain
+            let db = Database::open(&path)?;
+            assert_eq!(db.verify_chain::<AuditEvent>()?, 1000);
+            drop((migrated, db));
+            for suffix in ["", "-wal", "-shm"] {
+                let _result = std::fs::remove_file(format!("{path}{suffix}"));
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(target_pointer_width = "32")]
+    mod targets {
+        use crate::*;
+
+        #[test]
+        fn wide_values_on_32_bit() -> sqltight::Result<()> {
+            let db = Sqlite::open(":memory:")?;
+            let past_u32 = i64::from(u32::MAX) + 1;
+            let rows = db
+                .prepare("select ?1 as max, ?2 as past_u32, length(?3) as len")?
+                .bind(&[i64::MAX.into(), past_u32.into(), vec![0u8; 1 << 20].into()])?
+                .rows()?;
+            assert_eq!(rows[0].get::<i64>("max")?, i64::MAX);
+            assert_eq!(rows[0].get::<i64>("past_u32")?, past_u32);
+            assert!(matches!(
+                rows[0].get::<usize>("past_u32"),
+                Err(Error::TryFromInt(_))
+            ));
+            assert_eq!(rows[0].get::<usize>("len")?, 1 << 20);
+            assert!(db.integrity_check(usize::MAX)?.is_empty());
+            db.set_busy_timeout(std::time::Duration::MAX)?;
+            Ok(())
+        }
+    }
+
+    mod fts {
+        use crate::*;
+
+        db! {
+            table Article {
+                id: Int,
+                title: Text,
+                body: Text
+            }
+
+            fts table ArticleSearch for Article { title, body }
+
+            fts table NoteSearch { body }
+        }
+
+        #[test]
+        fn fts_works() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let rust = db.save(Article::new("rust", "memory safety without a collector"))?;
+            let mut sqlite = db.save(Article::new("sqlite", "a small fast database"))?;
+            sqlite.body = text("a small fast embedded database written in c");
+            let _sqlite = db.save(sqlite)?;
+            let results = db.article_search("embedded")?;
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].title, text("sqlite"));
+            let _rust = db.delete(rust)?;
+            let note = db.save(NoteSearch::new("remember the milk"))?;
+            assert_eq!(note.body, text("remember the milk"));
+            let results: Vec<NoteSearch> = db.note_search("milk")?;
+            assert_eq!(results[0].id, note.id);
+            Ok(())
+        }
+
+        #[test]
+        fn dump_rebuilds_fts() -> sqltight::Result<()> {
+            let db = Database::open(":memory:")?;
+            let _article = db.save(Article::new("sqlite", "a small embedded database"))?;
+            let _note = db.save(NoteSearch::new("remember the milk"))?;
+            let mut script = vec![];
+            db.dump(&mut script)?;
+            let restored = Sqlite::open(":memory:")?;
+            restored.restore(script.as_slice())?;
+            let matches =
+                |sql: &str| -> sqltight::Result<usize> { Ok(restored.prepare(sql)?.rows()?.len()) };
+            assert_eq!(
+                matches("select * from ArticleSearch where ArticleSearch match 'embedded'")?,
+                1
+            );
+            assert_eq!(
+                matches("select * from NoteSearch where NoteSearch match 'milk'")?,
+                1
+            );
+            Ok(())
+        }
     }
 }
@@ -0,0 +1 @@
+create table tag (id integer primary key, name text not null);
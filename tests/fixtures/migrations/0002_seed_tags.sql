@@ -0,0 +1 @@
+insert into tag (name) values ('rust'), ('sqlite');